@@ -1,13 +1,35 @@
 use std::fmt;
 
-use crate::vm::value::Value;
+use malachite::Rational;
 
-use super::{interner::Interned, symbol::Symbol};
+use crate::vm::value::{Merge, Value};
 
+use super::{
+    context::Context,
+    interner::Interned,
+    source::{BytePos, Span},
+    symbol::Symbol,
+};
+
+#[derive(Clone)]
 pub enum ExprKind {
     Literal(Value),
     Identifier(Identifier),
     Call(Call),
+    Operator(Operator),
+    Let(Let),
+    Record(Record),
+    List(List),
+    Import(Import),
+    Field(Field),
+    Annotated(Annotated),
+    /// `self`: The record the innermost record literal around it produces, once merged.
+    SelfRecord,
+    /// `super`: The record the one the innermost record literal around it produces is merged
+    /// into.
+    SuperRecord,
+    /// Placeholder for an expression that failed to parse.
+    Error,
     Todo,
 }
 
@@ -17,13 +39,28 @@ impl fmt::Debug for ExprKind {
             Self::Literal(v) => fmt::Debug::fmt(&v, f),
             Self::Identifier(v) => fmt::Debug::fmt(&v, f),
             Self::Call(v) => fmt::Debug::fmt(&v, f),
+            Self::Operator(v) => fmt::Debug::fmt(&v, f),
+            Self::Let(v) => fmt::Debug::fmt(&v, f),
+            Self::Record(v) => fmt::Debug::fmt(&v, f),
+            Self::List(v) => fmt::Debug::fmt(&v, f),
+            Self::Import(v) => fmt::Debug::fmt(&v, f),
+            Self::Field(v) => fmt::Debug::fmt(&v, f),
+            Self::Annotated(v) => fmt::Debug::fmt(&v, f),
+            Self::SelfRecord => write!(f, "self"),
+            Self::SuperRecord => write!(f, "super"),
+            Self::Error => write!(f, "Error"),
             Self::Todo => write!(f, "Todo"),
         }
     }
 }
 
+#[derive(Clone)]
 pub struct Expr {
     pub kind: ExprKind,
+
+    /// The span of the source code the expression was parsed from. Expressions built by other
+    /// means have an empty span at position 0.
+    pub span: Span,
 }
 
 impl fmt::Debug for Expr {
@@ -34,26 +71,92 @@ impl fmt::Debug for Expr {
 
 impl Expr {
     pub fn new(kind: ExprKind) -> Self {
-        Self { kind }
+        Self {
+            kind,
+            span: Span::new(BytePos::new(0), BytePos::new(0)),
+        }
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Self { span, ..self }
     }
 
     pub fn todo() -> Self {
         Self::new(ExprKind::Todo)
     }
 
+    pub fn error() -> Self {
+        Self::new(ExprKind::Error)
+    }
+
     pub fn literal(lit: Value) -> Self {
         Self::new(ExprKind::Literal(lit))
     }
 
+    pub fn identifier(ident: Identifier) -> Self {
+        Self::new(ExprKind::Identifier(ident))
+    }
+
+    pub fn operator(op: Operator) -> Self {
+        Self::new(ExprKind::Operator(op))
+    }
+
     pub fn call(fun: Expr, arg: Expr) -> Self {
         Self::new(ExprKind::Call(Call::new(fun, arg)))
     }
 
-    pub fn bin_op(fun: Expr, left: Expr, right: Expr) -> Self {
-        Expr::call(Expr::call(fun, left), right)
+    pub fn let_in(bindings: Vec<Binding>, body: Expr) -> Self {
+        Self::new(ExprKind::Let(Let {
+            types: Vec::new(),
+            bindings,
+            body: Box::new(body),
+        }))
+    }
+
+    pub fn record(fields: Vec<Binding>) -> Self {
+        Self::new(ExprKind::Record(Record {
+            fields,
+            merges: Vec::new(),
+        }))
+    }
+
+    pub fn list(items: Vec<Expr>) -> Self {
+        Self::new(ExprKind::List(List { items }))
+    }
+
+    pub fn import(path: String, span: Span) -> Self {
+        Self::new(ExprKind::Import(Import { path, span }))
+    }
+
+    pub fn field(record: Expr, name: Identifier) -> Self {
+        Self::new(ExprKind::Field(Field {
+            record: Box::new(record),
+            name,
+        }))
+    }
+
+    pub fn annotated(expr: Expr, ty: TypeExpr) -> Self {
+        Self::new(ExprKind::Annotated(Annotated {
+            expr: Box::new(expr),
+            ty,
+        }))
+    }
+
+    /// Format this expression like its `Debug` implementation, with names resolved.
+    pub(crate) fn resolved<'a>(&'a self, cx: &'a Context) -> Resolved<'a> {
+        Resolved { cx, expr: self }
+    }
+
+    pub fn unary_op(op: Operator, operand: Expr) -> Self {
+        Expr::call(Expr::operator(op), operand)
+    }
+
+    pub fn bin_op(op: Operator, left: Expr, right: Expr) -> Self {
+        Expr::call(Expr::call(Expr::operator(op), left), right)
     }
 }
 
+#[derive(Clone)]
 pub struct Call {
     pub fun: Box<Expr>,
     pub arg: Box<Expr>,
@@ -78,8 +181,287 @@ impl Call {
     }
 }
 
+/// The built-in operators. Operators are applied like any other function: Unary operators take a
+/// single argument, while binary operators are curried over their two operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// Prefix `-`.
+    Neg,
+    /// Prefix `not`.
+    Not,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    /// `//`, merging records.
+    Merge,
+}
+
+#[derive(Clone)]
+pub struct Let {
+    /// The type aliases, which are in scope in the whole expression, including each other's
+    /// definitions.
+    pub types: Vec<TypeAlias>,
+
+    pub bindings: Vec<Binding>,
+    pub body: Box<Expr>,
+}
+
+impl fmt::Debug for Let {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(let ")?;
+        f.debug_list()
+            .entries(&self.types)
+            .entries(&self.bindings)
+            .finish()?;
+        f.write_str(" ")?;
+        fmt::Debug::fmt(&self.body, f)?;
+        f.write_str(")")
+    }
+}
+
+#[derive(Clone)]
+pub struct Record {
+    pub fields: Vec<Binding>,
+
+    /// The fields bound with `:=` or `+=`, which merge into another record differently than by
+    /// default, and the fields deleted with `-name`, which have no binding.
+    pub merges: Vec<(Identifier, Merge)>,
+}
+
+impl Record {
+    pub fn merge(&self, name: Interned<Symbol>) -> Merge {
+        self.merges
+            .iter()
+            .find(|(field, _)| field.name == name)
+            .map_or(Merge::Deep, |(_, merge)| *merge)
+    }
+
+    /// The fields deleted from the record on the left of `//`.
+    pub fn deletions(&self) -> impl Iterator<Item = &Identifier> {
+        self.merges
+            .iter()
+            .filter(|(_, merge)| *merge == Merge::Delete)
+            .map(|(name, _)| name)
+    }
+}
+
+impl fmt::Debug for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for binding in &self.fields {
+            match self.merge(binding.name.name) {
+                Merge::Deep => set.entry(binding),
+                merge => set.entry(&format_args!(
+                    "{:?} {} {:?}",
+                    binding.name,
+                    merge.operator(),
+                    binding.value
+                )),
+            };
+        }
+        for name in self.deletions() {
+            set.entry(&format_args!("-{name:?}"));
+        }
+        set.finish()
+    }
+}
+
+#[derive(Clone)]
+pub struct List {
+    pub items: Vec<Expr>,
+}
+
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.items).finish()
+    }
+}
+
+/// `import "path"`: The contents of a data file, resolved relative to the importing source before
+/// evaluation.
+#[derive(Clone)]
+pub struct Import {
+    pub path: String,
+
+    /// The span of the path literal.
+    pub span: Span,
+}
+
+impl fmt::Debug for Import {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(import {:?})", self.path)
+    }
+}
+
+/// `record.name`: The value of a record's field.
+#[derive(Clone)]
+pub struct Field {
+    pub record: Box<Expr>,
+    pub name: Identifier,
+}
+
+impl fmt::Debug for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        fmt::Debug::fmt(&self.record, f)?;
+        f.write_str(".")?;
+        fmt::Debug::fmt(&self.name, f)?;
+        f.write_str(")")
+    }
+}
+
+/// `(expr : type)`: An expression whose value must have a type, and satisfy its contract. A
+/// binding annotated as in `name : type = value` binds an annotated value.
+#[derive(Clone)]
+pub struct Annotated {
+    pub expr: Box<Expr>,
+    pub ty: TypeExpr,
+}
+
+impl fmt::Debug for Annotated {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        fmt::Debug::fmt(&self.expr, f)?;
+        f.write_str(" : ")?;
+        fmt::Debug::fmt(&self.ty, f)?;
+        f.write_str(")")
+    }
+}
+
+/// `type name = type`: A name for a type, among the bindings of a `let`.
+#[derive(Clone)]
+pub struct TypeAlias {
+    pub name: Identifier,
+    pub ty: TypeExpr,
+}
+
+impl fmt::Debug for TypeAlias {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("type ")?;
+        fmt::Debug::fmt(&self.name, f)?;
+        f.write_str(" = ")?;
+        fmt::Debug::fmt(&self.ty, f)
+    }
+}
+
+/// A type, as written in annotations. Besides their static type, types describe contracts, which
+/// values are checked against during evaluation: Ranges, patterns and enumerations refine the
+/// values a type allows.
+#[derive(Clone)]
+pub struct TypeExpr {
+    pub kind: TypeExprKind,
+    pub span: Span,
+}
+
+impl fmt::Debug for TypeExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TypeExprKind::Name(name) => fmt::Debug::fmt(name, f),
+            TypeExprKind::List(item) => {
+                f.write_str("[")?;
+                fmt::Debug::fmt(item, f)?;
+                f.write_str("]")
+            }
+            TypeExprKind::Record(record) => {
+                let mut set = f.debug_set();
+                for field in &record.fields {
+                    set.entry(&format_args!("{:?}: {:?}", field.name, field.ty));
+                }
+                if record.open {
+                    set.entry(&format_args!(".."));
+                }
+                set.finish()
+            }
+            TypeExprKind::Range(range) => {
+                write!(f, "({:?} ", range.base)?;
+                if let Some(min) = &range.min {
+                    write!(f, "{min}")?;
+                }
+                f.write_str("..")?;
+                if let Some(max) = &range.max {
+                    write!(f, "{max}")?;
+                }
+                f.write_str(")")
+            }
+            TypeExprKind::Pattern(pattern) => {
+                write!(f, "({:?} ~ {:?})", pattern.base, pattern.regex)
+            }
+            TypeExprKind::Enum(values) => {
+                f.write_str("(")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(" | ")?;
+                    }
+                    fmt::Debug::fmt(value, f)?;
+                }
+                f.write_str(")")
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum TypeExprKind {
+    /// A built-in type, such as `Number`, or a type alias.
+    Name(Identifier),
+    List(Box<TypeExpr>),
+    Record(RecordTypeExpr),
+    Range(RangeTypeExpr),
+    Pattern(PatternTypeExpr),
+    /// Any of the given literal values.
+    Enum(Vec<Value>),
+}
+
+/// `{ name : type, ... }`: The type of records with the given fields, and any other fields if the
+/// record type is open, as written with a trailing `..`.
+#[derive(Clone)]
+pub struct RecordTypeExpr {
+    pub fields: Vec<TypeField>,
+    pub open: bool,
+}
+
+#[derive(Clone)]
+pub struct TypeField {
+    pub name: Identifier,
+    pub ty: TypeExpr,
+}
+
+/// `type min..max`: The numbers of a type within inclusive bounds, either of which may be left
+/// out.
+#[derive(Clone)]
+pub struct RangeTypeExpr {
+    pub base: Box<TypeExpr>,
+    pub min: Option<Rational>,
+    pub max: Option<Rational>,
+}
+
+/// `type ~ "regex"`: The strings of a type that match a regular expression.
+#[derive(Clone)]
+pub struct PatternTypeExpr {
+    pub base: Box<TypeExpr>,
+    pub regex: String,
+}
+
+/// A `name = value` pair, as found in `let` expressions and records.
+#[derive(Clone)]
+pub struct Binding {
+    pub name: Identifier,
+    pub value: Expr,
+}
+
+impl fmt::Debug for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.name, f)?;
+        f.write_str(" = ")?;
+        fmt::Debug::fmt(&self.value, f)
+    }
+}
+
+#[derive(Clone)]
 pub struct Identifier {
     pub name: Interned<Symbol>,
+    pub span: Span,
 }
 
 impl fmt::Debug for Identifier {
@@ -88,6 +470,157 @@ impl fmt::Debug for Identifier {
     }
 }
 
+/// An expression formatted like its `Debug` implementation, but with identifiers resolved through
+/// a [`Context`], rather than printed as opaque interned symbols.
+pub(crate) struct Resolved<'a> {
+    cx: &'a Context,
+    expr: &'a Expr,
+}
+
+impl Resolved<'_> {
+    fn with<'a>(&'a self, expr: &'a Expr) -> Resolved<'a> {
+        Resolved { cx: self.cx, expr }
+    }
+
+    fn name(&self, name: Interned<Symbol>) -> &str {
+        self.cx.symbol_interner.lookup(name).as_str()
+    }
+
+    fn ty(&self, f: &mut fmt::Formatter<'_>, ty: &TypeExpr) -> fmt::Result {
+        match &ty.kind {
+            TypeExprKind::Name(name) => f.write_str(self.name(name.name)),
+            TypeExprKind::List(item) => {
+                f.write_str("[")?;
+                self.ty(f, item)?;
+                f.write_str("]")
+            }
+            TypeExprKind::Record(record) => {
+                f.write_str("{")?;
+                for (i, field) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: ", self.name(field.name.name))?;
+                    self.ty(f, &field.ty)?;
+                }
+                if record.open {
+                    f.write_str(if record.fields.is_empty() {
+                        ".."
+                    } else {
+                        ", .."
+                    })?;
+                }
+                f.write_str("}")
+            }
+            TypeExprKind::Range(range) => {
+                f.write_str("(")?;
+                self.ty(f, &range.base)?;
+                f.write_str(" ")?;
+                if let Some(min) = &range.min {
+                    write!(f, "{min}")?;
+                }
+                f.write_str("..")?;
+                if let Some(max) = &range.max {
+                    write!(f, "{max}")?;
+                }
+                f.write_str(")")
+            }
+            TypeExprKind::Pattern(pattern) => {
+                f.write_str("(")?;
+                self.ty(f, &pattern.base)?;
+                write!(f, " ~ {:?})", pattern.regex)
+            }
+            TypeExprKind::Enum(_) => fmt::Debug::fmt(ty, f),
+        }
+    }
+
+    fn bindings(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        types: &[TypeAlias],
+        bindings: &[Binding],
+    ) -> fmt::Result {
+        for (i, alias) in types.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "type {} = ", self.name(alias.name.name))?;
+            self.ty(f, &alias.ty)?;
+        }
+
+        for (i, binding) in bindings.iter().enumerate() {
+            if i > 0 || !types.is_empty() {
+                f.write_str(", ")?;
+            }
+            write!(
+                f,
+                "{} = {:?}",
+                self.name(binding.name.name),
+                self.with(&binding.value)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Resolved<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.expr.kind {
+            ExprKind::Identifier(ident) => f.write_str(self.name(ident.name)),
+            ExprKind::Call(call) => {
+                write!(f, "({:?} {:?})", self.with(&call.fun), self.with(&call.arg))
+            }
+            ExprKind::Let(let_in) => {
+                f.write_str("(let [")?;
+                self.bindings(f, &let_in.types, &let_in.bindings)?;
+                write!(f, "] {:?})", self.with(&let_in.body))
+            }
+            ExprKind::Record(record) => {
+                f.write_str("{")?;
+                for (i, binding) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(
+                        f,
+                        "{} {} {:?}",
+                        self.name(binding.name.name),
+                        record.merge(binding.name.name).operator(),
+                        self.with(&binding.value)
+                    )?;
+                }
+                for (i, name) in record.deletions().enumerate() {
+                    if i > 0 || !record.fields.is_empty() {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "-{}", self.name(name.name))?;
+                }
+                f.write_str("}")
+            }
+            ExprKind::List(list) => {
+                f.write_str("[")?;
+                for (i, item) in list.items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    fmt::Debug::fmt(&self.with(item), f)?;
+                }
+                f.write_str("]")
+            }
+            ExprKind::Field(field) => {
+                let name = self.name(field.name.name);
+                write!(f, "({:?}.{name})", self.with(&field.record))
+            }
+            ExprKind::Annotated(annotated) => {
+                write!(f, "({:?} : ", self.with(&annotated.expr))?;
+                self.ty(f, &annotated.ty)?;
+                f.write_str(")")
+            }
+            kind => fmt::Debug::fmt(kind, f),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
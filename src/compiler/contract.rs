@@ -0,0 +1,607 @@
+//! Contracts: Types as checked against values during evaluation.
+//!
+//! An annotation such as `port : Integer 1..65535 = 80` gives its expression both a static type,
+//! which the [type checker](super::types) checks before evaluation, and a contract, which the
+//! value of the expression is checked against once it is evaluated. Refinements such as ranges,
+//! patterns and enumerations can only be checked on values, and so can the parts of values whose
+//! type is dynamic, such as imported data.
+//!
+//! Type aliases are resolved when contracts are: A contract refers to the parts of the type
+//! expressions it was resolved from, so that violations point at the annotation, or at the alias
+//! they are about.
+
+use indexmap::IndexMap;
+use malachite::Rational;
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use regex::Regex;
+use thiserror::Error;
+
+use crate::{
+    output::{Path, PathSegment},
+    vm::value::{Value, ValueKind},
+};
+
+use super::{
+    ast::{TypeAlias, TypeExpr, TypeExprKind},
+    context::Context,
+    interner::Interned,
+    source::{Source, Span},
+    symbol::Symbol,
+};
+
+/// The type aliases in scope, with the innermost ones last.
+pub type Aliases = Vec<(Interned<Symbol>, Contract)>;
+
+#[derive(Debug, Clone)]
+pub struct Contract {
+    pub kind: ContractKind,
+
+    /// The span of the type expression this contract was resolved from.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ContractKind {
+    /// Any value.
+    Dynamic,
+
+    Null,
+    Boolean,
+    Number,
+    Integer,
+    String,
+    List(Box<Contract>),
+    Record {
+        fields: IndexMap<String, Contract>,
+        open: bool,
+    },
+
+    /// The values of the base contract within inclusive bounds.
+    Range {
+        base: Box<Contract>,
+        min: Option<Rational>,
+        max: Option<Rational>,
+    },
+
+    /// The values of the base contract that match a regular expression anywhere, unless it is
+    /// anchored with `^` and `$`.
+    Pattern {
+        base: Box<Contract>,
+        regex: Regex,
+    },
+
+    /// Any of the given literal values.
+    Enum(Vec<Value>),
+}
+
+impl ContractKind {
+    /// The kind of the values a contract allows, without refinements.
+    fn base(&self) -> &Self {
+        match self {
+            Self::Range { base, .. } | Self::Pattern { base, .. } => base.kind.base(),
+            kind => kind,
+        }
+    }
+}
+
+/// A type expression that does not describe a contract.
+#[derive(Debug, Clone, Error)]
+pub enum InvalidType {
+    #[error("Unknown type `{name}`")]
+    Unknown { name: String, span: Span },
+
+    #[error("Recursive type alias `{name}`")]
+    Recursive { name: String, span: Span },
+
+    #[error("Ranges only apply to numbers")]
+    Range { span: Span },
+
+    #[error("Patterns only apply to strings")]
+    Pattern { span: Span },
+
+    #[error("Invalid pattern: {message}")]
+    Regex { message: String, span: Span },
+}
+
+impl InvalidType {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Unknown { span, .. }
+            | Self::Recursive { span, .. }
+            | Self::Range { span }
+            | Self::Pattern { span }
+            | Self::Regex { span, .. } => *span,
+        }
+    }
+}
+
+/// Resolve a type expression into a contract, with the given aliases in scope. Invalid parts of the
+/// type are resolved as [dynamic](ContractKind::Dynamic), and reported along with the contract.
+pub fn resolve(cx: &Context, aliases: &Aliases, ty: &TypeExpr) -> (Contract, Vec<InvalidType>) {
+    let mut resolver = Resolver::new(cx, aliases, &[]);
+    let contract = resolver.ty(ty);
+    (contract, resolver.errors)
+}
+
+/// Resolve the type aliases of a `let`, which may refer to each other in any order, with the given
+/// aliases in scope.
+pub fn resolve_aliases(
+    cx: &Context,
+    aliases: &Aliases,
+    group: &[TypeAlias],
+) -> (Aliases, Vec<InvalidType>) {
+    let mut resolver = Resolver::new(cx, aliases, group);
+    for index in 0..group.len() {
+        resolver.alias(index);
+    }
+
+    let resolved = group
+        .iter()
+        .zip(resolver.resolved)
+        .map(|(alias, contract)| (alias.name.name, contract.expect("resolved alias")))
+        .collect();
+    (resolved, resolver.errors)
+}
+
+struct Resolver<'a> {
+    cx: &'a Context,
+    aliases: &'a Aliases,
+
+    /// The aliases being resolved together, which are in scope besides `aliases`.
+    group: &'a [TypeAlias],
+    resolved: Vec<Option<Contract>>,
+    resolving: Vec<bool>,
+
+    errors: Vec<InvalidType>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(cx: &'a Context, aliases: &'a Aliases, group: &'a [TypeAlias]) -> Self {
+        Self {
+            cx,
+            aliases,
+            group,
+            resolved: vec![None; group.len()],
+            resolving: vec![false; group.len()],
+            errors: Vec::new(),
+        }
+    }
+
+    fn alias(&mut self, index: usize) -> Contract {
+        if let Some(contract) = &self.resolved[index] {
+            return contract.clone();
+        }
+
+        let alias = &self.group[index];
+        if self.resolving[index] {
+            self.errors.push(InvalidType::Recursive {
+                name: self.name(alias.name.name).to_owned(),
+                span: alias.ty.span,
+            });
+            return self.dynamic(alias.ty.span);
+        }
+
+        self.resolving[index] = true;
+        let contract = self.ty(&alias.ty);
+        self.resolving[index] = false;
+        // A recursive alias may have been resolved while it was being resolved, as dynamic.
+        self.resolved[index].get_or_insert(contract).clone()
+    }
+
+    fn ty(&mut self, ty: &TypeExpr) -> Contract {
+        let kind = match &ty.kind {
+            TypeExprKind::Name(name) => {
+                let group = self
+                    .group
+                    .iter()
+                    .position(|alias| alias.name.name == name.name);
+                if let Some(index) = group {
+                    return self.alias(index);
+                }
+
+                let alias = self.aliases.iter().rev().find(|(n, _)| *n == name.name);
+                if let Some((_, contract)) = alias {
+                    return contract.clone();
+                }
+
+                match self.name(name.name) {
+                    "Dynamic" => ContractKind::Dynamic,
+                    "Null" => ContractKind::Null,
+                    "Boolean" => ContractKind::Boolean,
+                    "Number" => ContractKind::Number,
+                    "Integer" => ContractKind::Integer,
+                    "String" => ContractKind::String,
+                    name => {
+                        self.errors.push(InvalidType::Unknown {
+                            name: name.to_owned(),
+                            span: ty.span,
+                        });
+                        ContractKind::Dynamic
+                    }
+                }
+            }
+            TypeExprKind::List(item) => ContractKind::List(Box::new(self.ty(item))),
+            TypeExprKind::Record(record) => ContractKind::Record {
+                fields: record
+                    .fields
+                    .iter()
+                    .map(|field| (self.name(field.name.name).to_owned(), self.ty(&field.ty)))
+                    .collect(),
+                open: record.open,
+            },
+            TypeExprKind::Range(range) => {
+                let base = self.ty(&range.base);
+                match base.kind.base() {
+                    ContractKind::Number | ContractKind::Integer => ContractKind::Range {
+                        base: Box::new(base),
+                        min: range.min.clone(),
+                        max: range.max.clone(),
+                    },
+                    _ => {
+                        self.errors.push(InvalidType::Range { span: ty.span });
+                        ContractKind::Dynamic
+                    }
+                }
+            }
+            TypeExprKind::Pattern(pattern) => {
+                let base = self.ty(&pattern.base);
+                match (base.kind.base(), Regex::new(&pattern.regex)) {
+                    (ContractKind::String, Ok(regex)) => ContractKind::Pattern {
+                        base: Box::new(base),
+                        regex,
+                    },
+                    (ContractKind::String, Err(err)) => {
+                        self.errors.push(InvalidType::Regex {
+                            message: regex_message(&err),
+                            span: ty.span,
+                        });
+                        ContractKind::Dynamic
+                    }
+                    _ => {
+                        self.errors.push(InvalidType::Pattern { span: ty.span });
+                        ContractKind::Dynamic
+                    }
+                }
+            }
+            TypeExprKind::Enum(values) => ContractKind::Enum(values.clone()),
+        };
+
+        Contract {
+            kind,
+            span: ty.span,
+        }
+    }
+
+    fn dynamic(&self, span: Span) -> Contract {
+        Contract {
+            kind: ContractKind::Dynamic,
+            span,
+        }
+    }
+
+    fn name(&self, name: Interned<Symbol>) -> &'a str {
+        self.cx.symbol_interner.lookup(name).as_str()
+    }
+}
+
+/// The last line of a regex error, which describes the error without repeating the pattern.
+fn regex_message(err: &regex::Error) -> String {
+    let message = err.to_string();
+    let last = message.lines().last().unwrap_or_default();
+    last.trim_start_matches("error: ").to_owned()
+}
+
+/// A value that does not satisfy a contract.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub message: String,
+
+    /// The span of the expression whose value violates the contract.
+    pub value: Span,
+
+    /// Where the violation is within the value of that expression.
+    pub path: Path,
+
+    /// The span of the part of the contract that is violated.
+    pub contract: Span,
+}
+
+impl Contract {
+    /// Check a value against this contract. The [value span](Violation::value) of violations is
+    /// left empty, for the caller to fill in.
+    pub fn check(&self, value: &Value) -> Result<(), Violation> {
+        self.check_at(value, &mut Path::root())
+    }
+
+    fn check_at(&self, value: &Value, path: &mut Path) -> Result<(), Violation> {
+        match (&self.kind, &*value.kind) {
+            (ContractKind::Dynamic, _)
+            | (ContractKind::Null, ValueKind::Null)
+            | (ContractKind::Boolean, ValueKind::Boolean(_))
+            | (ContractKind::Number, ValueKind::Number(_))
+            | (ContractKind::String, ValueKind::String(_)) => Ok(()),
+            (ContractKind::Integer, ValueKind::Number(number)) => {
+                if number.value.denominator_ref() == &1u32 {
+                    Ok(())
+                } else {
+                    Err(self.violation(path, format!("Expected an integer, found {value:?}")))
+                }
+            }
+            (ContractKind::List(item), ValueKind::List(list)) => {
+                for (index, value) in list.items().iter().enumerate() {
+                    path.push(PathSegment::Index(index));
+                    item.check_at(value, path)?;
+                    path.pop();
+                }
+                Ok(())
+            }
+            (ContractKind::Record { fields, open }, ValueKind::Record(record)) => {
+                for (name, contract) in fields {
+                    let Some(value) = record.fields.get(name) else {
+                        return Err(self.violation(path, format!("Missing field `{name}`")));
+                    };
+                    path.push(PathSegment::Field(name.clone()));
+                    contract.check_at(value, path)?;
+                    path.pop();
+                }
+
+                let unexpected = record
+                    .fields
+                    .keys()
+                    .find(|name| !fields.contains_key(*name));
+                match unexpected {
+                    Some(name) if !open => {
+                        Err(self.violation(path, format!("Unexpected field `{name}`")))
+                    }
+                    _ => Ok(()),
+                }
+            }
+            (ContractKind::Range { base, min, max }, _) => {
+                base.check_at(value, path)?;
+                let ValueKind::Number(number) = &*value.kind else {
+                    unreachable!("ranges only apply to numbers")
+                };
+
+                let within = min.as_ref().is_none_or(|min| &number.value >= min)
+                    && max.as_ref().is_none_or(|max| &number.value <= max);
+                if within {
+                    return Ok(());
+                }
+
+                let expected = match (min, max) {
+                    (Some(min), Some(max)) => format!("a number from {min} to {max}"),
+                    (Some(min), None) => format!("a number of at least {min}"),
+                    (None, Some(max)) => format!("a number of at most {max}"),
+                    (None, None) => unreachable!("numbers are within unbounded ranges"),
+                };
+                Err(self.violation(path, format!("Expected {expected}, found {value:?}")))
+            }
+            (ContractKind::Pattern { base, regex }, _) => {
+                base.check_at(value, path)?;
+                let ValueKind::String(string) = &*value.kind else {
+                    unreachable!("patterns only apply to strings")
+                };
+
+                if regex.is_match(&string.value) {
+                    Ok(())
+                } else {
+                    Err(self.violation(
+                        path,
+                        format!(
+                            "Expected a string matching `{}`, found {value:?}",
+                            regex.as_str()
+                        ),
+                    ))
+                }
+            }
+            (ContractKind::Enum(values), _) => {
+                if values.iter().any(|allowed| same(allowed, value)) {
+                    return Ok(());
+                }
+
+                let allowed: Vec<_> = values.iter().map(|value| format!("{value:?}")).collect();
+                Err(self.violation(
+                    path,
+                    format!("Expected one of {}, found {value:?}", allowed.join(", ")),
+                ))
+            }
+            (kind, found) => Err(self.violation(
+                path,
+                format!("Expected {}, found {}", describe(kind), found.describe()),
+            )),
+        }
+    }
+}
+
+impl Contract {
+    fn violation(&self, path: &Path, message: String) -> Violation {
+        Violation {
+            message,
+            value: Span::new(self.span.lo(), self.span.lo()),
+            path: path.clone(),
+            contract: self.span,
+        }
+    }
+}
+
+fn describe(kind: &ContractKind) -> &'static str {
+    match kind {
+        ContractKind::Null => "null",
+        ContractKind::Boolean => "a boolean",
+        ContractKind::Number => "a number",
+        ContractKind::Integer => "an integer",
+        ContractKind::String => "a string",
+        ContractKind::List(_) => "a list",
+        ContractKind::Record { .. } => "a record",
+        ContractKind::Dynamic
+        | ContractKind::Range { .. }
+        | ContractKind::Pattern { .. }
+        | ContractKind::Enum(_) => unreachable!("{kind:?} allows values of any kind"),
+    }
+}
+
+/// Whether two values are the same literal.
+fn same(a: &Value, b: &Value) -> bool {
+    match (&*a.kind, &*b.kind) {
+        (ValueKind::Null, ValueKind::Null) => true,
+        (ValueKind::Boolean(a), ValueKind::Boolean(b)) => a.value == b.value,
+        (ValueKind::Number(a), ValueKind::Number(b)) => a.value == b.value,
+        (ValueKind::String(a), ValueKind::String(b)) => a.value == b.value,
+        _ => false,
+    }
+}
+
+/// A contract violation found during evaluation, with the source code of the value and of the
+/// contract it violates.
+#[derive(Debug, Error, Diagnostic)]
+#[error("{message}")]
+pub struct ContractViolation {
+    message: String,
+
+    #[source_code]
+    source_code: NamedSource,
+
+    #[label("{value_label}")]
+    value: SourceSpan,
+    value_label: String,
+
+    #[label("Required by this contract")]
+    contract: Option<SourceSpan>,
+}
+
+impl ContractViolation {
+    /// Create a diagnostic for a violation whose value is in the given source. The contract is
+    /// only labelled if it is in the same source.
+    pub fn new(source: &Source, violation: Violation) -> Self {
+        let value_label = match violation.path.segments() {
+            [] => "This value".to_owned(),
+            _ => format!("At {} in this value", violation.path),
+        };
+
+        let local_span = |span: Span| source.local_span(span.lo().to_u32(), span.hi().to_u32());
+        let contract = (source.span().lo() <= violation.contract.lo()
+            && violation.contract.hi() <= source.span().hi())
+        .then(|| local_span(violation.contract));
+
+        Self {
+            message: violation.message,
+            source_code: NamedSource::new(source.name(), source.contents().to_owned()),
+            value: local_span(violation.value),
+            value_label,
+            contract,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{ast::ExprKind, eval, parser, source::Source};
+
+    /// Check the value of the annotated expression `source` against its annotation, without
+    /// checking the annotations within it.
+    fn check(source: &str) -> Result<(), String> {
+        let mut cx = Context::new();
+        let source = Source::at(0, source);
+        let parsed = parser::parse(&mut cx, &source);
+        assert!(
+            parsed.errors.is_empty(),
+            "cannot parse `{}`",
+            source.contents()
+        );
+
+        let ExprKind::Annotated(annotated) = &parsed.expr.kind else {
+            panic!("`{}` is not annotated", source.contents());
+        };
+        let value = eval::eval(&cx, Vec::new(), &annotated.expr).expect("value");
+
+        let (contract, errors) = resolve(&cx, &Vec::new(), &annotated.ty);
+        if let Some(err) = errors.first() {
+            return Err(err.to_string());
+        }
+        contract
+            .check(&value)
+            .map_err(|violation| match violation.path.segments() {
+                [] => violation.message,
+                _ => format!("{}: {}", violation.path, violation.message),
+            })
+    }
+
+    #[test]
+    fn kinds_are_checked() {
+        assert_eq!(check("(1 : Number)"), Ok(()));
+        assert_eq!(check("(null : Dynamic)"), Ok(()));
+        assert_eq!(
+            check(r#"("a" : Boolean)"#),
+            Err("Expected a boolean, found a string".to_owned())
+        );
+        assert_eq!(
+            check("(1.5 : Integer)"),
+            Err("Expected an integer, found 3/2".to_owned())
+        );
+        assert_eq!(
+            check("([1, true] : [Number])"),
+            Err("`[1]`: Expected a number, found a boolean".to_owned())
+        );
+    }
+
+    #[test]
+    fn refinements_are_checked() {
+        assert_eq!(check("(80 : Integer 1..65535)"), Ok(()));
+        assert_eq!(check("(-1 : Number ..0)"), Ok(()));
+        assert_eq!(
+            check("(70000 : Integer 1..65535)"),
+            Err("Expected a number from 1 to 65535, found 70000".to_owned())
+        );
+        assert_eq!(
+            check("(-2 : Number -1..)"),
+            Err("Expected a number of at least -1, found -2".to_owned())
+        );
+
+        assert_eq!(check(r#"("abc" : String ~ "^[a-z]+$")"#), Ok(()));
+        assert_eq!(
+            check(r#"("aBc" : String ~ "^[a-z]+$")"#),
+            Err(r#"Expected a string matching `^[a-z]+$`, found "aBc""#.to_owned())
+        );
+
+        assert_eq!(check(r#"("dev" : "dev" | "prod")"#), Ok(()));
+        assert_eq!(
+            check(r#"(1 : "dev" | 1.5 | null)"#),
+            Err(r#"Expected one of "dev", 3/2, null, found 1"#.to_owned())
+        );
+    }
+
+    #[test]
+    fn records_are_closed_unless_open() {
+        assert_eq!(check("({ a = 1 } : { a : Number })"), Ok(()));
+        assert_eq!(check("({ a = 1, b = 2 } : { a : Number, .. })"), Ok(()));
+        assert_eq!(
+            check("({ a = 1, b = 2 } : { a : Number })"),
+            Err("Unexpected field `b`".to_owned())
+        );
+        assert_eq!(
+            check("({ b = 2 } : { a : Number, .. })"),
+            Err("Missing field `a`".to_owned())
+        );
+        assert_eq!(
+            check(r#"({ a = { b = "c" } } : { a : { b : "d" } })"#),
+            Err(r#"`a.b`: Expected one of "d", found "c""#.to_owned())
+        );
+    }
+
+    #[test]
+    fn invalid_types_are_reported() {
+        assert_eq!(check("(1 : Port)"), Err("Unknown type `Port`".to_owned()));
+        assert_eq!(
+            check(r#"(1 : Number ~ "a")"#),
+            Err("Patterns only apply to strings".to_owned())
+        );
+        assert_eq!(
+            check(r#"("a" : String 1..2)"#),
+            Err("Ranges only apply to numbers".to_owned())
+        );
+        assert!(check(r#"("a" : String ~ "(")"#)
+            .unwrap_err()
+            .starts_with("Invalid pattern: "));
+    }
+}
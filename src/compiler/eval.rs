@@ -0,0 +1,950 @@
+use std::{rc::Rc, sync::Arc};
+
+use indexmap::{map::Entry, IndexMap};
+use malachite::{num::basic::traits::Zero, Rational};
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use crate::{
+    output::{Path, PathSegment},
+    vm::{
+        value::{Merge, Provenance, Record, Template, Value, ValueKind},
+        RuntimeError, Unresolved,
+    },
+};
+
+use super::{
+    ast::{self, Annotated, Binding, Call, Expr, ExprKind, Field, Let, Operator},
+    context::Context,
+    contract::{self, Aliases},
+    interner::Interned,
+    merge::{self, Definitions, Late, Operand},
+    source::{Source, Span},
+    symbol::Symbol,
+};
+
+/// Evaluate an expression, with the given global bindings in scope.
+///
+/// Evaluation is strict: `let` bindings, record fields and list items are evaluated in order, as
+/// soon as they are reached. A `let` binding is in scope for the bindings following it, and for the
+/// body of the expression.
+///
+/// Values are given the [provenance](crate::vm::value::Provenance) of the expression that produced
+/// them: Names, field accesses and annotations produce the values they refer to, with the
+/// provenance those were given, if any.
+///
+/// Record fields bound to values that depend on `self` or `super` are the exception: They are
+/// late-bound, and evaluated once the other fields of their record are, on demand, in the order
+/// they depend on each other. They are evaluated again whenever their record is merged into
+/// another, for `self` to be the merged record. See [`merge`] for how. Those that depend on fields
+/// no merge bound are an [error](RuntimeError::Unresolved) in the result.
+///
+/// The values of annotated expressions are checked against the contracts of their types, and a
+/// [violation](RuntimeError::Contract) stops the evaluation. Type annotations must have been
+/// checked before evaluation.
+///
+/// The items of lists that natives make may be evaluated on first use, but those of the result are
+/// all evaluated before it is returned, as are those of annotated values before they are checked.
+///
+/// Other errors are [located](RuntimeError::Located) at the innermost expression that raised them.
+pub fn eval(
+    cx: &Context,
+    globals: Vec<(Interned<Symbol>, Value)>,
+    expr: &Expr,
+) -> Result<Value, RuntimeError> {
+    let value = Evaluator::new(cx, globals).expr(expr)?;
+    value.force()?;
+    merge::resolved(&value)?;
+    Ok(value)
+}
+
+/// Evaluate the bindings of a `let` expression, with the given global bindings in scope, and return
+/// their values. The body of the expression is not evaluated.
+pub fn eval_bindings(
+    cx: &Context,
+    globals: Vec<(Interned<Symbol>, Value)>,
+    let_in: &Let,
+) -> Result<Vec<(Interned<Symbol>, Value)>, RuntimeError> {
+    let depth = globals.len();
+    let mut evaluator = Evaluator::new(cx, globals);
+    evaluator.aliases(let_in);
+    for Binding { name, value } in &let_in.bindings {
+        let value = evaluator.expr(value)?;
+        value.force()?;
+        evaluator.scope.push((name.name, value));
+    }
+
+    Ok(evaluator.scope.split_off(depth))
+}
+
+struct Evaluator<'cx> {
+    cx: &'cx Context,
+
+    /// The variables in scope, with the innermost ones last.
+    scope: Vec<(Interned<Symbol>, Value)>,
+
+    /// The type aliases in scope.
+    aliases: Aliases,
+
+    /// The records whose late-bound fields are being evaluated, with the innermost one last.
+    instances: Vec<Instance>,
+
+    /// What `self` and `super` are in the late-bound fields being evaluated, with the innermost
+    /// field last.
+    frames: Vec<Frame>,
+}
+
+/// A record whose late-bound fields are being evaluated.
+struct Instance {
+    /// The record, with a template.
+    record: Value,
+
+    /// Where the record is within the records being merged, if any.
+    path: Path,
+
+    /// The fields evaluated so far, or being evaluated.
+    values: IndexMap<String, State>,
+}
+
+enum State {
+    Evaluating,
+    Evaluated(Value),
+
+    /// The field depends on a field `self` or `super` does not have.
+    LeftOut(Unresolved),
+}
+
+/// The records `self` and `super` are, in a late-bound field.
+#[derive(Clone)]
+struct Frame {
+    /// The index of the instance `self` is.
+    instance: usize,
+
+    /// The record the one defining the field was merged into, if any.
+    supers: Option<Rc<Super>>,
+
+    /// The name of the field, and its span in the definition being evaluated.
+    field: String,
+    span: Span,
+}
+
+impl Frame {
+    fn unresolved(&self, dependency: String) -> RuntimeError {
+        RuntimeError::Unresolved(Unresolved {
+            field: self.field.clone(),
+            dependency,
+            span: self.span,
+        })
+    }
+}
+
+/// A record that another was merged into, as seen from a late-bound field of the other. Its own
+/// late-bound fields are evaluated with the same `self`, and their own `super`.
+struct Super {
+    record: Value,
+    outer: Option<Rc<Super>>,
+}
+
+impl<'cx> Evaluator<'cx> {
+    fn new(cx: &'cx Context, scope: Vec<(Interned<Symbol>, Value)>) -> Self {
+        Self {
+            cx,
+            scope,
+            aliases: Aliases::new(),
+            instances: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    /// Evaluate an expression. Errors that are not located yet are raised by it.
+    fn expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        self.eval_expr(expr).map_err(|err| err.at(expr.span))
+    }
+
+    fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match &expr.kind {
+            ExprKind::Literal(value) => Ok(value.clone().with_span(expr.span)),
+            ExprKind::Identifier(ident) => self
+                .scope
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == ident.name)
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| RuntimeError::UnboundVariable {
+                    name: self.name(ident.name).to_owned(),
+                }),
+            ExprKind::Call(call) => self.call(call, expr.span),
+            ExprKind::Let(let_in) => {
+                let (depth, aliases) = (self.scope.len(), self.aliases.len());
+                self.aliases(let_in);
+                let result = self.let_in(let_in);
+                self.scope.truncate(depth);
+                self.aliases.truncate(aliases);
+                result
+            }
+            ExprKind::Record(record) => self.record(record, expr.span),
+            ExprKind::List(list) => {
+                let items = list.items.iter().map(|item| self.expr(item));
+                Ok(Value::list(items.collect::<Result<_, _>>()?).with_span(expr.span))
+            }
+            ExprKind::Field(field) => {
+                let value = self.field(field)?;
+                // Fields produced along with their record, as imported ones are, were last
+                // produced by this expression.
+                match value.provenance {
+                    None => Ok(value.with_span(expr.span)),
+                    Some(_) => Ok(value),
+                }
+            }
+            ExprKind::Annotated(annotated) => self.annotated(annotated),
+            ExprKind::SelfRecord => {
+                let frame = self.frame("self")?;
+                let record = self.instances[frame.instance].record.clone();
+                let mut fields = IndexMap::new();
+                for name in merge::names(&record) {
+                    if let Some(value) = self.own_field(frame.instance, &name)? {
+                        fields.insert(name, value);
+                    }
+                }
+                Ok(Value::record(fields).with_span(expr.span))
+            }
+            ExprKind::SuperRecord => {
+                let frame = self.frame("super")?;
+                let supers = frame.supers.clone();
+                let supers = supers.ok_or_else(|| frame.unresolved("super".to_owned()))?;
+                let mut fields = IndexMap::new();
+                for name in merge::names(&supers.record) {
+                    if let Some(value) = self.super_field(&frame, &supers, &name)? {
+                        fields.insert(name, value);
+                    }
+                }
+                Ok(Value::record(fields).with_span(expr.span))
+            }
+            ExprKind::Import(_) => panic!("imports are resolved before evaluation"),
+            ExprKind::Operator(_) => panic!("operators are always applied"),
+            ExprKind::Error | ExprKind::Todo => panic!("cannot evaluate placeholders"),
+        }
+    }
+
+    fn record(&mut self, record: &ast::Record, span: Span) -> Result<Value, RuntimeError> {
+        let mut fields = IndexMap::with_capacity(record.fields.len());
+        let mut names = Vec::with_capacity(record.fields.len());
+        let mut late = IndexMap::new();
+        for binding @ Binding { name, value } in &record.fields {
+            let name = self.name(name.name).to_owned();
+            let value = if is_late(value) {
+                // Late-bound fields are evaluated once all the others are.
+                late.insert(name.clone(), binding.clone());
+                Value::null()
+            } else {
+                self.expr(value)?
+            };
+            names.push(name.clone());
+            match fields.entry(name) {
+                Entry::Vacant(entry) => entry.insert(value),
+                Entry::Occupied(entry) => {
+                    let name = entry.key().clone();
+                    return Err(RuntimeError::DuplicateField { name }.at(binding.name.span));
+                }
+            };
+        }
+
+        let mut merges = IndexMap::with_capacity(record.merges.len());
+        for (ident, merge) in &record.merges {
+            // A field cannot be both deleted and bound.
+            let name = self.name(ident.name);
+            let bound = *merge == Merge::Delete && fields.contains_key(name);
+            if bound || merges.insert(name.to_owned(), *merge).is_some() {
+                let name = name.to_owned();
+                return Err(RuntimeError::DuplicateField { name }.at(ident.span));
+            }
+        }
+
+        let is_late = !late.is_empty();
+        let template = is_late.then(|| {
+            let definitions = Definitions {
+                fields: late,
+                scope: self.scope.clone(),
+                aliases: self.aliases.clone(),
+            };
+            Arc::new(Template::Literal {
+                names,
+                definitions: Arc::new(definitions),
+            })
+        });
+        let record = Record {
+            fields,
+            merges,
+            template,
+            unresolved: Vec::new(),
+        };
+        let record = Value::new(ValueKind::Record(record)).with_span(span);
+        if is_late {
+            self.instantiate(&record, &mut Path::root())
+        } else {
+            Ok(record)
+        }
+    }
+
+    fn field(&mut self, field: &Field) -> Result<Value, RuntimeError> {
+        let name = self.name(field.name.name).to_owned();
+
+        match field.record.kind {
+            // Only the fields accessed through `self` and `super` are evaluated, so that fields
+            // can depend on each other.
+            ExprKind::SelfRecord => {
+                let frame = self.frame("self")?;
+                let value = self.own_field(frame.instance, &name)?;
+                return value.ok_or_else(|| frame.unresolved(format!("self.{name}")));
+            }
+            ExprKind::SuperRecord => {
+                let frame = self.frame("super")?;
+                let supers = frame.supers.clone();
+                let supers = supers.ok_or_else(|| frame.unresolved("super".to_owned()))?;
+                let value = self.super_field(&frame, &supers, &name)?;
+                return value.ok_or_else(|| frame.unresolved(format!("super.{name}")));
+            }
+            _ => {}
+        }
+
+        let record = self.expr(&field.record)?;
+        match &*record.kind {
+            ValueKind::Record(record) => record
+                .fields
+                .get(&name)
+                .cloned()
+                .ok_or(RuntimeError::MissingField { name }),
+            kind => Err(RuntimeError::TypeMismatch {
+                expected: "a record",
+                found: kind.describe(),
+            }),
+        }
+    }
+
+    /// What `self` and `super` are in the late-bound field being evaluated, if any.
+    fn frame(&self, keyword: &str) -> Result<Frame, RuntimeError> {
+        self.frames
+            .last()
+            .cloned()
+            .ok_or_else(|| RuntimeError::UnboundVariable {
+                name: keyword.to_owned(),
+            })
+    }
+
+    /// Evaluate the fields of a record with a template, at `path` in the records being merged.
+    /// Fields that depend on fields `self` or `super` do not have are left out of it, for merges to
+    /// bind those.
+    fn instantiate(&mut self, record: &Value, path: &mut Path) -> Result<Value, RuntimeError> {
+        let ValueKind::Record(Record {
+            merges,
+            template: Some(template),
+            ..
+        }) = &*record.kind
+        else {
+            unreachable!("only records with templates are instantiated");
+        };
+
+        let names = merge::names(record);
+        let instance = self.instances.len();
+        self.instances.push(Instance {
+            record: record.clone(),
+            path: path.clone(),
+            values: IndexMap::with_capacity(names.len()),
+        });
+        let capacity = names.len();
+        let result = names.into_iter().try_fold(
+            (IndexMap::with_capacity(capacity), Vec::new()),
+            |(mut fields, mut unresolved), name| {
+                match self.own_field(instance, &name) {
+                    Ok(Some(value)) => {
+                        fields.insert(name, value);
+                    }
+                    Ok(None) => {}
+                    Err(RuntimeError::Unresolved(dependency)) => unresolved.push(dependency),
+                    Err(err) => return Err(err),
+                }
+                Ok((fields, unresolved))
+            },
+        );
+        self.instances.pop();
+        let (fields, unresolved) = result?;
+
+        let (merges, provenance) = match &**template {
+            Template::Literal { .. } => (merges.clone(), record.provenance.clone()),
+            Template::Merge { left, right, span } => {
+                let (left, right) = (left.provenance.clone(), right.provenance.clone());
+                let provenance = Provenance::merging(*span, left, right);
+                (IndexMap::new(), Some(Arc::new(provenance)))
+            }
+        };
+        let record = Record {
+            fields,
+            merges,
+            template: Some(Arc::clone(template)),
+            unresolved,
+        };
+        Ok(Value {
+            kind: Arc::new(ValueKind::Record(record)),
+            provenance,
+        })
+    }
+
+    /// The value of a field of a record being instantiated, evaluated on first use. Returns `None`
+    /// if the record has no such field.
+    fn own_field(&mut self, instance: usize, name: &str) -> Result<Option<Value>, RuntimeError> {
+        match self.instances[instance].values.get(name) {
+            Some(State::Evaluated(value)) => return Ok(Some(value.clone())),
+            Some(State::LeftOut(dependency)) => {
+                return Err(RuntimeError::Unresolved(dependency.clone()))
+            }
+            Some(State::Evaluating) => {
+                // The innermost definition of the field being evaluated depends on itself.
+                let frame = self
+                    .frames
+                    .iter()
+                    .rev()
+                    .find(|frame| frame.instance == instance && frame.field == name);
+                return Err(RuntimeError::RecursiveField {
+                    name: name.to_owned(),
+                    span: frame.expect("fields are evaluated in frames").span,
+                });
+            }
+            None => {}
+        }
+
+        let Instance { record, path, .. } = &self.instances[instance];
+        let (record, mut path) = (record.clone(), path.clone());
+        self.instances[instance]
+            .values
+            .insert(name.to_owned(), State::Evaluating);
+
+        path.push(PathSegment::Field(name.to_owned()));
+        let value = self.late_field(instance, &record, name, None, &mut path);
+        let values = &mut self.instances[instance].values;
+        match value {
+            Ok(Some(value)) => {
+                values.insert(name.to_owned(), State::Evaluated(value.clone()));
+                Ok(Some(value))
+            }
+            Ok(None) => {
+                values.shift_remove(name);
+                Ok(None)
+            }
+            Err(RuntimeError::Unresolved(dependency)) => {
+                values.insert(name.to_owned(), State::LeftOut(dependency.clone()));
+                Err(RuntimeError::Unresolved(dependency))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// The value of a field of a record that another, being instantiated, was merged into.
+    fn super_field(
+        &mut self,
+        frame: &Frame,
+        supers: &Super,
+        name: &str,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let mut path = self.instances[frame.instance].path.clone();
+        path.push(PathSegment::Field(name.to_owned()));
+        let outer = supers.outer.clone();
+        self.late_field(frame.instance, &supers.record, name, outer, &mut path)
+    }
+
+    /// The value of a field of a record, with `self` the record being instantiated, and `super`
+    /// the given one. Returns `None` if the record has no such field.
+    fn late_field(
+        &mut self,
+        instance: usize,
+        record: &Value,
+        name: &str,
+        supers: Option<Rc<Super>>,
+        path: &mut Path,
+    ) -> Result<Option<Value>, RuntimeError> {
+        let ValueKind::Record(fields) = &*record.kind else {
+            unreachable!("only records are instantiated");
+        };
+
+        match fields.template.as_deref() {
+            Some(Template::Literal { definitions, .. }) => {
+                let definitions = definitions
+                    .downcast_ref::<Definitions>()
+                    .expect("templates of record literals are made by the evaluator");
+                let Some(binding) = definitions.fields.get(name) else {
+                    return Ok(fields.fields.get(name).cloned());
+                };
+
+                let scope = core::mem::replace(&mut self.scope, definitions.scope.clone());
+                let aliases = core::mem::replace(&mut self.aliases, definitions.aliases.clone());
+                self.frames.push(Frame {
+                    instance,
+                    supers,
+                    field: name.to_owned(),
+                    span: binding.name.span,
+                });
+                let value = self.expr(&binding.value);
+                self.frames.pop();
+                self.scope = scope;
+                self.aliases = aliases;
+                value.map(Some)
+            }
+            Some(Template::Merge { left, right, span }) => {
+                let ValueKind::Record(overrides) = &*right.kind else {
+                    unreachable!("only records are merged");
+                };
+                let merge = overrides.merge(name);
+                if merge == Merge::Delete {
+                    return Ok(None);
+                }
+
+                let inner = Rc::new(Super {
+                    record: left.clone(),
+                    outer: supers.clone(),
+                });
+                let left = self.late_field(instance, left, name, supers, path)?;
+                let right = self.late_field(instance, right, name, Some(inner), path)?;
+                match (left, right) {
+                    (Some(left), Some(right)) => {
+                        let (left, right) =
+                            (Operand::new(&left, *span), Operand::new(&right, *span));
+                        merge::field(merge, left, right, *span, path, self).map(Some)
+                    }
+                    (left, right) => Ok(right.or(left)),
+                }
+            }
+            None => Ok(fields.fields.get(name).cloned()),
+        }
+    }
+
+    fn let_in(&mut self, let_in: &Let) -> Result<Value, RuntimeError> {
+        for Binding { name, value } in &let_in.bindings {
+            let value = self.expr(value)?;
+            self.scope.push((name.name, value));
+        }
+        self.expr(&let_in.body)
+    }
+
+    /// Bring the type aliases of a `let` into scope.
+    fn aliases(&mut self, let_in: &Let) {
+        let (aliases, errors) = contract::resolve_aliases(self.cx, &self.aliases, &let_in.types);
+        assert!(errors.is_empty(), "types are checked before evaluation");
+        self.aliases.extend(aliases);
+    }
+
+    fn annotated(&mut self, annotated: &Annotated) -> Result<Value, RuntimeError> {
+        let value = self.expr(&annotated.expr)?;
+        value.force()?;
+
+        let (contract, errors) = contract::resolve(self.cx, &self.aliases, &annotated.ty);
+        assert!(errors.is_empty(), "types are checked before evaluation");
+        contract.check(&value).map_err(|mut violation| {
+            // Point at where the offending part of the value was defined, rather than at the
+            // expression that was annotated, where possible.
+            let (provenance, path) = violation.path.locate(&value);
+            violation.value = provenance.map_or(annotated.expr.span, |provenance| provenance.span);
+            violation.path = path;
+            RuntimeError::Contract(Box::new(violation))
+        })?;
+
+        Ok(value)
+    }
+
+    /// Evaluate a call, or the application of an operator, spanning `span`. Merged records keep
+    /// the provenance of their merge.
+    fn call(&mut self, call: &Call, span: Span) -> Result<Value, RuntimeError> {
+        // Unary operators
+        if let ExprKind::Operator(op @ (Operator::Neg | Operator::Not)) = call.fun.kind {
+            let operand = self.expr(&call.arg)?;
+            let result = match op {
+                Operator::Neg => Value::number(-number(&operand)?.clone()),
+                _ => Value::boolean(!boolean(&operand)?),
+            };
+            return Ok(result.with_span(span));
+        }
+
+        // Binary operators
+        if let ExprKind::Call(Call { fun, arg: left }) = &call.fun.kind {
+            if let ExprKind::Operator(Operator::Merge) = fun.kind {
+                let (left_span, right_span) = (left.span, call.arg.span);
+                let left = self.expr(left)?;
+                let right = self.expr(&call.arg)?;
+                let (left, right) = (
+                    Operand::new(&left, left_span),
+                    Operand::new(&right, right_span),
+                );
+                return merge::merge(left, right, span, self);
+            }
+
+            if let ExprKind::Operator(
+                op @ (Operator::Add | Operator::Sub | Operator::Mul | Operator::Div),
+            ) = fun.kind
+            {
+                let left = self.expr(left)?;
+                let right = self.expr(&call.arg)?;
+                let (left, right) = (number(&left)?, number(&right)?);
+
+                let result = match op {
+                    Operator::Add => left + right,
+                    Operator::Sub => left - right,
+                    Operator::Mul => left * right,
+                    _ if *right == Rational::ZERO => return Err(RuntimeError::DivisionByZero),
+                    _ => left / right,
+                };
+                return Ok(Value::number(result).with_span(span));
+            }
+        }
+
+        // Function application
+        let fun = self.expr(&call.fun)?;
+        let arg = self.expr(&call.arg)?;
+        match &*fun.kind {
+            ValueKind::Function(fun) => Ok((fun.body)(&arg)?.with_span(span)),
+            kind => Err(RuntimeError::TypeMismatch {
+                expected: "a function",
+                found: kind.describe(),
+            }),
+        }
+    }
+
+    fn name(&self, name: Interned<Symbol>) -> &str {
+        self.cx.symbol_interner.lookup(name).as_str()
+    }
+}
+
+impl Late for Evaluator<'_> {
+    fn instantiate(&mut self, record: &Value, path: &mut Path) -> Result<Value, RuntimeError> {
+        Evaluator::instantiate(self, record, path)
+    }
+}
+
+/// Whether the value of a field depends on `self` or `super`. Those in nested records refer to the
+/// nested records instead.
+fn is_late(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::SelfRecord | ExprKind::SuperRecord => true,
+        ExprKind::Call(call) => is_late(&call.fun) || is_late(&call.arg),
+        ExprKind::Let(let_in) => {
+            let_in
+                .bindings
+                .iter()
+                .any(|binding| is_late(&binding.value))
+                || is_late(&let_in.body)
+        }
+        ExprKind::List(list) => list.items.iter().any(is_late),
+        ExprKind::Field(field) => is_late(&field.record),
+        ExprKind::Annotated(annotated) => is_late(&annotated.expr),
+        ExprKind::Literal(_)
+        | ExprKind::Identifier(_)
+        | ExprKind::Operator(_)
+        | ExprKind::Record(_)
+        | ExprKind::Import(_)
+        | ExprKind::Error
+        | ExprKind::Todo => false,
+    }
+}
+
+fn number(value: &Value) -> Result<&Rational, RuntimeError> {
+    match &*value.kind {
+        ValueKind::Number(number) => Ok(&number.value),
+        kind => Err(RuntimeError::TypeMismatch {
+            expected: "a number",
+            found: kind.describe(),
+        }),
+    }
+}
+
+fn boolean(value: &Value) -> Result<bool, RuntimeError> {
+    match &*value.kind {
+        ValueKind::Boolean(boolean) => Ok(boolean.value),
+        kind => Err(RuntimeError::TypeMismatch {
+            expected: "a boolean",
+            found: kind.describe(),
+        }),
+    }
+}
+
+/// A runtime error, with the source code of the expression that raised it, or of the definition of
+/// the late-bound field it is about.
+#[derive(Debug, Error, Diagnostic)]
+#[error("{error}")]
+pub struct EvalError {
+    error: RuntimeError,
+
+    #[source_code]
+    source_code: NamedSource,
+
+    #[label("{label}")]
+    span: SourceSpan,
+    label: &'static str,
+}
+
+impl EvalError {
+    /// Create a diagnostic for an error located at `span`, in the given source.
+    pub fn new(source: &Source, error: RuntimeError, span: Span) -> Self {
+        let label = match error {
+            RuntimeError::RecursiveField { .. } | RuntimeError::Unresolved(_) => "Defined here",
+            _ => "Raised here",
+        };
+        Self {
+            error,
+            source_code: NamedSource::new(source.name(), source.contents().to_owned()),
+            span: source.local_span(span.lo().to_u32(), span.hi().to_u32()),
+            label,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{
+        parser,
+        source::{Source, Span},
+    };
+
+    /// Evaluate a source, with the errors that are not located elsewhere unwrapped.
+    fn eval(source: &str) -> Result<Value, RuntimeError> {
+        located(source).map_err(RuntimeError::unlocated)
+    }
+
+    fn located(source: &str) -> Result<Value, RuntimeError> {
+        let mut cx = Context::new();
+        let parsed = parser::parse(&mut cx, &Source::at(0, source));
+        assert!(parsed.errors.is_empty(), "cannot parse `{source}`");
+
+        super::eval(&cx, Vec::new(), &parsed.expr)
+    }
+
+    #[track_caller]
+    fn assert_evals(source: &str, expected: &str) {
+        match eval(source) {
+            Ok(value) => assert_eq!(format!("{value:?}"), expected, "source: `{source}`"),
+            Err(err) => panic!("cannot evaluate `{source}`: {err}"),
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_evals("1 + 2 * 3", "7");
+        assert_evals("-(1 - 3) / 4", "1/2");
+        assert_evals("not true", "false");
+        assert!(matches!(
+            eval("1 / (2 - 2)"),
+            Err(RuntimeError::DivisionByZero)
+        ));
+        assert!(matches!(
+            eval("1 + null"),
+            Err(RuntimeError::TypeMismatch {
+                expected: "a number",
+                found: "null"
+            })
+        ));
+    }
+
+    #[test]
+    fn let_bindings_are_sequential() {
+        assert_evals("let a = 1, b = a + 1 in [a, b]", "[1, 2]");
+        assert_evals("let a = 1 in let a = a + 1 in a", "2");
+        assert!(matches!(
+            eval("let a = b, b = 1 in a"),
+            Err(RuntimeError::UnboundVariable { name }) if name == "b"
+        ));
+        assert!(matches!(
+            eval("[let a = 1 in a, a]"),
+            Err(RuntimeError::UnboundVariable { name }) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn records_keep_their_order() {
+        assert_evals("{ b = 1, a = { c = null } }", "{b = 1, a = {c = null}}");
+        assert!(matches!(
+            eval("{ a = 1, a = 2 }"),
+            Err(RuntimeError::DuplicateField { name }) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn fields_are_accessed_by_name() {
+        assert_evals(r#"let a = { b = { "c d" = 1 } } in a.b."c d""#, "1");
+        assert!(matches!(
+            eval("{ a = 1 }.b"),
+            Err(RuntimeError::MissingField { name }) if name == "b"
+        ));
+        assert!(matches!(
+            eval("[1].a"),
+            Err(RuntimeError::TypeMismatch {
+                expected: "a record",
+                found: "a list"
+            })
+        ));
+    }
+
+    #[test]
+    fn values_keep_the_provenance_of_their_definition() {
+        let source = "let a = { b = [1, 2], c = { d = 2 } } in [a.b, a.c.d, 3]";
+        let value = eval(source).unwrap();
+        let spans: Vec<_> = match &*value.kind {
+            ValueKind::List(list) => list
+                .items()
+                .iter()
+                .map(|item| {
+                    let span = item.span().unwrap();
+                    &source[span.lo().to_u32() as usize..span.hi().to_u32() as usize]
+                })
+                .collect(),
+            _ => panic!("`{source}` is not a list"),
+        };
+        assert_eq!(spans, ["[1, 2]", "2", "3"]);
+    }
+
+    #[test]
+    fn annotated_values_are_checked() {
+        assert_evals(
+            "let type Port = Integer 1..65535 in { port : Port = 80 }",
+            "{port = 80}",
+        );
+
+        let source = "let type Port = Integer 1..65535 in [{ port : Port = 1 }, ({ a = [1, 70000] } : { a : [Port] })]";
+        let Err(RuntimeError::Contract(violation)) = eval(source) else {
+            panic!("`{source}` satisfies its contracts");
+        };
+        assert_eq!(
+            violation.message,
+            "Expected a number from 1 to 65535, found 70000"
+        );
+        let slice = |source: &'static str, span: Span| {
+            &source[span.lo().to_u32() as usize..span.hi().to_u32() as usize]
+        };
+        assert_eq!(slice(source, violation.value), "70000");
+        assert_eq!(slice(source, violation.contract), "Integer 1..65535");
+        assert!(violation.path.segments().is_empty());
+
+        let source = r#"let a = { b = "c" } in (a : { b : "d" })"#;
+        let Err(RuntimeError::Contract(violation)) = eval(source) else {
+            panic!("`{source}` satisfies its contracts");
+        };
+        assert_eq!(slice(source, violation.value), r#""c""#);
+        assert!(violation.path.segments().is_empty());
+    }
+
+    #[test]
+    fn late_bound_fields_follow_merges() {
+        let base =
+            "let base = { port = 80, next = self.port + 1, ports = { a = 1, b = self.a } } in";
+        assert_evals(
+            &format!("{base} base"),
+            "{port = 80, next = 81, ports = {a = 1, b = 1}}",
+        );
+        assert_evals(
+            &format!("{base} base // {{ port = 8000, ports = {{ a = 2 }} }}"),
+            "{port = 8000, next = 8001, ports = {a = 2, b = 2}}",
+        );
+        assert_evals(
+            &format!("{base} base // {{ next := self.port - 1 }} // {{ port = 1 }}"),
+            "{port = 1, next = 0, ports = {a = 1, b = 1}}",
+        );
+        assert_evals(
+            "{ a = self.b, b = self.c, c = 1 } // { c = 2 }",
+            "{a = 2, b = 2, c = 2}",
+        );
+        assert_evals(
+            "{ url = self.host } // { host = \"a\" }",
+            "{url = \"a\", host = \"a\"}",
+        );
+        assert!(matches!(
+            eval("{ port = 80, next = self.prot + 1 }"),
+            Err(RuntimeError::Unresolved(unresolved))
+                if unresolved.field == "next" && unresolved.dependency == "self.prot"
+        ));
+    }
+
+    #[test]
+    fn super_is_the_record_merged_into() {
+        let patch = "let patch = { port = super.port + 1, old = super.port } in";
+        assert!(matches!(
+            eval(&format!("{patch} patch.port")),
+            Err(RuntimeError::MissingField { name }) if name == "port"
+        ));
+        assert_evals(
+            &format!("{patch} {{ port = 1 }} // patch // patch"),
+            "{port = 3, old = 2}",
+        );
+        assert_evals(
+            "{ a = 1, b = { c = 2 } } // { b = { c = super.c * 10 }, d = super }",
+            "{a = 1, b = {c = 20}, d = {a = 1, b = {c = 2}}}",
+        );
+        assert!(matches!(
+            eval("{ a = 1 } // { b = super.b }"),
+            Err(RuntimeError::Unresolved(unresolved)) if unresolved.dependency == "super.b"
+        ));
+    }
+
+    #[test]
+    fn late_bound_errors_point_at_the_field_definition() {
+        let defined_at = |source: &'static str, err: RuntimeError| {
+            let span = match err {
+                RuntimeError::RecursiveField { span, .. } => span,
+                RuntimeError::Unresolved(unresolved) => unresolved.span,
+                err => panic!("`{source}` fails with {err:?}"),
+            };
+            span.lo().to_u32() as usize
+        };
+
+        let source = "{ a = 1 } // { b = 2, a = self.a + 1 }";
+        assert_eq!(defined_at(source, eval(source).unwrap_err()), 22);
+        let source = "{ a = 1, c = self.d }";
+        assert_eq!(defined_at(source, eval(source).unwrap_err()), 9);
+    }
+
+    #[test]
+    fn fields_cannot_depend_on_themselves() {
+        assert!(matches!(
+            eval("{ a = self.b, b = [self.a] }"),
+            Err(RuntimeError::RecursiveField { name, .. }) if name == "a"
+        ));
+        assert!(matches!(
+            eval("{ a = 1 } // { a = self.a + 1 }"),
+            Err(RuntimeError::RecursiveField { name, .. }) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn errors_are_raised_by_the_innermost_expression() {
+        let raised_by = |source: &'static str| {
+            let Err(RuntimeError::Located { error, span }) = located(source) else {
+                panic!("`{source}` has no located error");
+            };
+            let (lo, hi) = (span.lo().to_u32() as usize, span.hi().to_u32() as usize);
+            (error.to_string(), &source[lo..hi])
+        };
+
+        assert_eq!(
+            raised_by("{ a = [1, 2 / (1 - 1)] }"),
+            ("Division by zero".to_owned(), "2 / (1 - 1)")
+        );
+        assert_eq!(
+            raised_by("let r = { a = 1 } in { b = r.c }"),
+            ("Missing field `c`".to_owned(), "r.c")
+        );
+        assert_eq!(
+            raised_by("{ a = 1, b = 2, a = 3 }"),
+            ("Duplicate field `a`".to_owned(), "a")
+        );
+        assert!(matches!(
+            located("{ a = self.a }"),
+            Err(RuntimeError::RecursiveField { .. })
+        ));
+    }
+
+    #[test]
+    fn only_functions_can_be_called() {
+        assert!(matches!(
+            eval("1 2"),
+            Err(RuntimeError::TypeMismatch {
+                expected: "a function",
+                found: "a number"
+            })
+        ));
+    }
+}
@@ -0,0 +1,332 @@
+use core::ops::Range;
+
+use indexmap::{map::Entry, IndexMap};
+use logos::Logos;
+
+use crate::{compiler::source::Source, vm::value::Value};
+
+use super::{decimal, ParseError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Logos)]
+#[logos(skip r"[ \t\r\n]+")]
+enum Token {
+    #[token("{")]
+    LBrace,
+
+    #[token("}")]
+    RBrace,
+
+    #[token("[")]
+    LBracket,
+
+    #[token("]")]
+    RBracket,
+
+    #[token(":")]
+    Colon,
+
+    #[token(",")]
+    Comma,
+
+    #[token("true")]
+    True,
+
+    #[token("false")]
+    False,
+
+    #[token("null")]
+    Null,
+
+    #[regex(r"-?(0|[1-9][0-9]*)(\.[0-9]+)?([eE][+-]?[0-9]+)?")]
+    Number,
+
+    #[regex(r#""([^"\\\x00-\x1F]|\\(["\\/bfnrt]|u[0-9a-fA-F]{4}))*""#)]
+    String,
+}
+
+/// How deeply arrays and objects may be nested, so that parsing them does not overflow the stack.
+const MAX_DEPTH: usize = 256;
+
+/// Parse a JSON document. Objects keep the order of their keys, and numbers are read exactly.
+pub(super) fn parse(source: &Source) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        source,
+        lexer: Token::lexer(source.contents()),
+        peeked: None,
+        depth: 0,
+    };
+
+    let value = parser.value()?;
+    match parser.next()? {
+        None => Ok(value),
+        Some((_, span)) => Err(parser.error("Expected the end of the document", span)),
+    }
+}
+
+struct Parser<'s> {
+    source: &'s Source,
+    lexer: logos::Lexer<'s, Token>,
+    peeked: Option<(Token, Range<usize>)>,
+
+    /// The number of arrays and objects the parser is in.
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self) -> Result<Value, ParseError> {
+        let Some((token, span)) = self.next()? else {
+            return Err(self.eof("Expected a value"));
+        };
+
+        match token {
+            Token::Null => Ok(Value::null()),
+            Token::True => Ok(Value::boolean(true)),
+            Token::False => Ok(Value::boolean(false)),
+            Token::Number => match decimal(&self.source.contents()[span.clone()]) {
+                Ok(Some(number)) => Ok(Value::number(number)),
+                Ok(None) => Err(self.error("Invalid number", span)),
+                Err(message) => Err(self.error(message, span)),
+            },
+            Token::String => Ok(Value::string(self.string(span)?)),
+            Token::LBracket | Token::LBrace => {
+                if self.depth == MAX_DEPTH {
+                    let message =
+                        format!("Arrays and objects are nested more than {MAX_DEPTH} deep");
+                    return Err(self.error(message, span));
+                }
+
+                self.depth += 1;
+                let value = match token {
+                    Token::LBracket => self.array(),
+                    _ => self.object(),
+                };
+                self.depth -= 1;
+                value
+            }
+            _ => Err(self.error("Expected a value", span)),
+        }
+    }
+
+    /// The rest of an array, after its `[`.
+    fn array(&mut self) -> Result<Value, ParseError> {
+        let mut items = Vec::new();
+        if self.eat(Token::RBracket)? {
+            return Ok(Value::list(items));
+        }
+
+        loop {
+            items.push(self.value()?);
+            if !self.separator(Token::RBracket)? {
+                return Ok(Value::list(items));
+            }
+        }
+    }
+
+    /// The rest of an object, after its `{`.
+    fn object(&mut self) -> Result<Value, ParseError> {
+        let mut fields = IndexMap::new();
+        if self.eat(Token::RBrace)? {
+            return Ok(Value::record(fields));
+        }
+
+        loop {
+            let key_span = match self.next()? {
+                Some((Token::String, span)) => span,
+                Some((_, span)) => return Err(self.error("Expected a string key", span)),
+                None => return Err(self.eof("Expected a string key")),
+            };
+            let key = self.string(key_span.clone())?;
+
+            self.expect(Token::Colon, "Expected `:`")?;
+            let value = self.value()?;
+
+            match fields.entry(key) {
+                Entry::Vacant(entry) => entry.insert(value),
+                Entry::Occupied(entry) => {
+                    let message = format!("Duplicate key `{}`", entry.key());
+                    return Err(self.error(message, key_span));
+                }
+            };
+
+            if !self.separator(Token::RBrace)? {
+                return Ok(Value::record(fields));
+            }
+        }
+    }
+
+    /// Consume either a `,`, returning `true`, or the closing delimiter, returning `false`.
+    fn separator(&mut self, close: Token) -> Result<bool, ParseError> {
+        if self.eat(close)? {
+            return Ok(false);
+        }
+
+        let message = match close {
+            Token::RBracket => "Expected `,` or `]`",
+            _ => "Expected `,` or `}`",
+        };
+        self.expect(Token::Comma, message)?;
+        Ok(true)
+    }
+
+    /// Resolve the escape sequences of a string token.
+    fn string(&self, span: Range<usize>) -> Result<String, ParseError> {
+        let literal = &self.source.contents()[span.start + 1..span.end - 1];
+        let mut string = String::with_capacity(literal.len());
+
+        let mut chars = literal.char_indices();
+        while let Some((_, c)) = chars.next() {
+            if c != '\\' {
+                string.push(c);
+                continue;
+            }
+
+            let (i, escape) = chars.next().expect("escapes are complete");
+            match escape {
+                'b' => string.push('\u{8}'),
+                'f' => string.push('\u{c}'),
+                'n' => string.push('\n'),
+                'r' => string.push('\r'),
+                't' => string.push('\t'),
+                'u' => {
+                    let start = span.start + 1 + i - 1;
+                    let code = hex(chars.as_str());
+                    chars.nth(3);
+
+                    // Characters outside the BMP are escaped as surrogate pairs.
+                    let c = if (0xD800..0xDC00).contains(&code) {
+                        let rest = chars.as_str();
+                        let low = rest.strip_prefix("\\u").map(hex);
+                        match low {
+                            Some(low @ 0xDC00..0xE000) => {
+                                chars.nth(5);
+                                char::from_u32(0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00))
+                            }
+                            _ => None,
+                        }
+                    } else {
+                        char::from_u32(code)
+                    };
+
+                    let end = span.end - 1 - chars.as_str().len();
+                    string.push(c.ok_or_else(|| self.error("Invalid unicode escape", start..end))?);
+                }
+                c => string.push(c),
+            }
+        }
+
+        Ok(string)
+    }
+
+    fn expect(&mut self, expected: Token, message: &str) -> Result<(), ParseError> {
+        match self.next()? {
+            Some((token, _)) if token == expected => Ok(()),
+            Some((_, span)) => Err(self.error(message, span)),
+            None => Err(self.eof(message)),
+        }
+    }
+
+    /// Consume the next token if it is the expected one.
+    fn eat(&mut self, expected: Token) -> Result<bool, ParseError> {
+        match self.next()? {
+            Some((token, _)) if token == expected => Ok(true),
+            next => {
+                self.peeked = next;
+                Ok(false)
+            }
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<(Token, Range<usize>)>, ParseError> {
+        if let Some(peeked) = self.peeked.take() {
+            return Ok(Some(peeked));
+        }
+
+        match self.lexer.next() {
+            Some(Ok(token)) => Ok(Some((token, self.lexer.span()))),
+            Some(Err(())) => Err(self.error("Invalid token", self.lexer.span())),
+            None => Ok(None),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>, span: Range<usize>) -> ParseError {
+        ParseError::new(message, self.source.global_span(span))
+    }
+
+    fn eof(&self, message: &str) -> ParseError {
+        let end = self.source.contents().len();
+        self.error(
+            format!("{message}, found the end of the document"),
+            end..end,
+        )
+    }
+}
+
+/// Parse the four hex digits of a `\u` escape, which the lexer has already validated.
+fn hex(digits: &str) -> u32 {
+    u32::from_str_radix(&digits[..4], 16).expect("valid hex digits")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::import::tests::{assert_error, assert_imports};
+
+    use super::*;
+
+    #[test]
+    fn values() {
+        assert_imports(
+            parse,
+            r#"{"b": [1, -2.5, 1e-3, 12345678901234567890.123], "a": {"c": null, "d": true}}"#,
+            r#"{b = [1, -5/2, 1/1000, 12345678901234567890123/1000], a = {c = null, d = true}}"#,
+        );
+        assert_imports(parse, r#"  []  "#, "[]");
+    }
+
+    #[test]
+    fn strings() {
+        assert_imports(
+            parse,
+            r#"["a\"\\\/\b\f\n\r\t", "é😀", "ü"]"#,
+            r#"["a\"\\/\u{8}\u{c}\n\r\t", "é😀", "ü"]"#,
+        );
+        assert_error(parse, r#"["\ud83d"]"#, "Invalid unicode escape", 2..8);
+    }
+
+    #[test]
+    fn errors() {
+        assert_error(
+            parse,
+            "[1, 2",
+            "Expected `,` or `]`, found the end of the document",
+            5..5,
+        );
+        assert_error(parse, "{1: 2}", "Expected a string key", 1..2);
+        assert_error(parse, r#"{"a": 1 "b": 2}"#, "Expected `,` or `}`", 8..11);
+        assert_error(parse, r#"{"a": 1, "a": 2}"#, "Duplicate key `a`", 9..12);
+        assert_error(parse, "[01]", "Expected `,` or `]`", 2..3);
+        assert_error(parse, "1 2", "Expected the end of the document", 2..3);
+        assert_error(parse, "[nul]", "Invalid token", 1..2);
+        assert_error(
+            parse,
+            r#"{"a": 1e99999999999999999999}"#,
+            "Numbers cannot have an exponent larger than 1048576",
+            6..28,
+        );
+        assert_error(
+            parse,
+            "-1.5E-9999999999",
+            "Numbers cannot have an exponent larger than 1048576",
+            0..16,
+        );
+
+        let deep = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        assert!(parse(&Source::at(0, &deep)).is_ok());
+        let deeper = "[".repeat(100_000);
+        assert_error(
+            parse,
+            &deeper,
+            "Arrays and objects are nested more than 256 deep",
+            MAX_DEPTH as u32..MAX_DEPTH as u32 + 1,
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! Importing data files as values.
+//!
+//! `import "path"` expressions are resolved before evaluation: The file is loaded through the
+//! [`SourceMap`], relative to the importing source, and parsed according to its extension. Like
+//! dek sources, imported files are positioned in the global span space, so that errors point into
+//! them. Paths starting with `std:` refer to modules of the standard library instead, which are
+//! records of [native functions](crate::vm::builtins).
+
+use std::path::Path;
+
+use malachite::{num::conversion::traits::FromSciString, Rational};
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use crate::vm::{
+    builtins::{self, STD_PREFIX},
+    value::Value,
+};
+
+use super::{
+    ast::{Expr, ExprKind, Import},
+    query::{Dependency, Queries},
+    source::{EntryContext, Source, SourceContext, SourceMap, Span},
+    CompileError,
+};
+
+mod json;
+mod toml;
+mod yaml;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum ImportError {
+    #[error("Cannot import `{path}`")]
+    #[diagnostic(help("Only `.json`, `.yaml`, `.yml` and `.toml` files can be imported"))]
+    UnknownFormat {
+        path: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label("Unknown file type")]
+        span: SourceSpan,
+    },
+
+    #[error("There is no module `{path}` in the standard library")]
+    #[diagnostic(help("The modules are {}", module_names()))]
+    UnknownModule {
+        path: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label("Unknown module")]
+        span: SourceSpan,
+    },
+
+    #[error("Could not parse `{}` as {format}", .source_code.name())]
+    Parse {
+        format: &'static str,
+        message: String,
+
+        #[source_code]
+        source_code: NamedSource,
+
+        #[label("{message}")]
+        span: SourceSpan,
+    },
+}
+
+/// A parser for a data format.
+type Parse = fn(&Source) -> Result<Value, ParseError>;
+
+/// An error in an imported file.
+#[derive(Debug)]
+struct ParseError {
+    message: String,
+    span: Span,
+}
+
+impl ParseError {
+    fn new(message: impl Into<String>, span: Span) -> Self {
+        Self {
+            message: message.into(),
+            span,
+        }
+    }
+}
+
+/// Replace the imports within an expression with the values of the files they refer to, which are
+/// added to `deps`.
+pub(super) fn resolve(
+    source_map: &mut SourceMap,
+    queries: &mut Queries,
+    deps: &mut Vec<Dependency>,
+    expr: &mut Expr,
+) -> Result<(), CompileError> {
+    match &mut expr.kind {
+        ExprKind::Import(import) => {
+            let value = import_file(source_map, queries, deps, import)?;
+            expr.kind = ExprKind::Literal(value);
+        }
+        ExprKind::Call(call) => {
+            resolve(source_map, queries, deps, &mut call.fun)?;
+            resolve(source_map, queries, deps, &mut call.arg)?;
+        }
+        ExprKind::Let(let_in) => {
+            for binding in &mut let_in.bindings {
+                resolve(source_map, queries, deps, &mut binding.value)?;
+            }
+            resolve(source_map, queries, deps, &mut let_in.body)?;
+        }
+        ExprKind::Record(record) => {
+            for field in &mut record.fields {
+                resolve(source_map, queries, deps, &mut field.value)?;
+            }
+        }
+        ExprKind::List(list) => {
+            for item in &mut list.items {
+                resolve(source_map, queries, deps, item)?;
+            }
+        }
+        ExprKind::Field(field) => resolve(source_map, queries, deps, &mut field.record)?,
+        ExprKind::Annotated(annotated) => {
+            resolve(source_map, queries, deps, &mut annotated.expr)?;
+        }
+        ExprKind::Literal(_)
+        | ExprKind::Identifier(_)
+        | ExprKind::Operator(_)
+        | ExprKind::SelfRecord
+        | ExprKind::SuperRecord
+        | ExprKind::Error
+        | ExprKind::Todo => {}
+    }
+
+    Ok(())
+}
+
+fn import_file(
+    source_map: &mut SourceMap,
+    queries: &mut Queries,
+    deps: &mut Vec<Dependency>,
+    import: &Import,
+) -> Result<Value, CompileError> {
+    if let Some(name) = import.path.strip_prefix(STD_PREFIX) {
+        return builtins::module(name)
+            .map(|module| module.value())
+            .ok_or_else(|| {
+                let (source_code, span) = located(source_map, import);
+                ImportError::UnknownModule {
+                    path: import.path.clone(),
+                    source_code,
+                    span,
+                }
+                .into()
+            });
+    }
+
+    let Some((format, parse)) = format_of(&import.path) else {
+        let (source_code, span) = located(source_map, import);
+        return Err(ImportError::UnknownFormat {
+            path: import.path.clone(),
+            source_code,
+            span,
+        }
+        .into());
+    };
+
+    let source = source_map.load_relative(import.span.lo(), &import.path)?;
+    deps.push(Dependency {
+        pos: import.span.lo(),
+        path: import.path.clone(),
+        fingerprint: source.fingerprint(),
+    });
+
+    parse_file(queries, source, format, parse)
+}
+
+/// Import a data file or a module of the standard library from outside of any source, such as a
+/// file given on the command line. The path is relative to the base path, as for entry files.
+pub(super) fn import_entry(
+    source_map: &mut SourceMap,
+    queries: &mut Queries,
+    path: &str,
+) -> Result<Value, CompileError> {
+    // Errors about the path itself point at it, as there is no source to point into.
+    let source_code = || NamedSource::new("<import>", path.to_owned());
+    let span = SourceSpan::from((0, path.len()));
+
+    if let Some(name) = path.strip_prefix(STD_PREFIX) {
+        return builtins::module(name)
+            .map(|module| module.value())
+            .ok_or_else(|| {
+                ImportError::UnknownModule {
+                    path: path.to_owned(),
+                    source_code: source_code(),
+                    span,
+                }
+                .into()
+            });
+    }
+
+    let Some((format, parse)) = format_of(path) else {
+        return Err(ImportError::UnknownFormat {
+            path: path.to_owned(),
+            source_code: source_code(),
+            span,
+        }
+        .into());
+    };
+
+    let mut source_cx = SourceContext::new();
+    source_cx.extensions_mut().insert(EntryContext);
+    let source = source_map.load(&source_cx, path)?;
+    parse_file(queries, source, format, parse)
+}
+
+/// The name of the format of a data file, and its parser, by the extension of its path.
+fn format_of(path: &str) -> Option<(&'static str, Parse)> {
+    let extension = Path::new(path).extension()?.to_str()?;
+    match extension {
+        "json" => Some(("JSON", json::parse)),
+        "yaml" | "yml" => Some(("YAML", yaml::parse)),
+        "toml" => Some(("TOML", toml::parse)),
+        _ => None,
+    }
+}
+
+/// The value of a loaded data file.
+fn parse_file(
+    queries: &mut Queries,
+    source: &Source,
+    format: &'static str,
+    parse: Parse,
+) -> Result<Value, CompileError> {
+    queries.import(source, parse).map_err(|err| {
+        ImportError::Parse {
+            format,
+            message: err.message,
+            source_code: named_source(source),
+            span: source.local_span(err.span.lo().to_u32(), err.span.hi().to_u32()),
+        }
+        .into()
+    })
+}
+
+/// The source code of an import, and its span within it.
+fn located(source_map: &SourceMap, import: &Import) -> (NamedSource, SourceSpan) {
+    let importer = source_map
+        .lookup(import.span.lo())
+        .expect("imports are parsed from loaded sources");
+    let span = importer.local_span(import.span.lo().to_u32(), import.span.hi().to_u32());
+    (named_source(importer), span)
+}
+
+/// The names of the modules of the standard library, for messages.
+fn module_names() -> String {
+    let names: Vec<_> = builtins::MODULES
+        .iter()
+        .map(|module| format!("`{STD_PREFIX}{}`", module.name))
+        .collect();
+    names.join(", ")
+}
+
+fn named_source(source: &Source) -> NamedSource {
+    NamedSource::new(source.name(), source.contents().to_owned())
+}
+
+/// The largest exponent of decimal numbers in data files, so that a number that is short to write
+/// cannot exhaust memory once its digits are spelled out.
+const MAX_EXPONENT: u64 = 1 << 20;
+
+/// Parse a decimal number exactly, as written in data files: With an optional sign, fraction and
+/// exponent. Returns `None` if the text is not such a number, and the message of the error if its
+/// exponent is too large.
+fn decimal(text: &str) -> Result<Option<Rational>, String> {
+    let text = text.strip_prefix('+').unwrap_or(text);
+    if let Some((_, exponent)) = text.split_once(['e', 'E']) {
+        let digits = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+        // Digits that do not fit in a `u64` are too large as well.
+        let too_large = !digits.is_empty()
+            && digits.chars().all(|c| c.is_ascii_digit())
+            && digits
+                .parse()
+                .map_or(true, |exponent: u64| exponent > MAX_EXPONENT);
+        if too_large {
+            return Err(format!(
+                "Numbers cannot have an exponent larger than {MAX_EXPONENT}"
+            ));
+        }
+    }
+
+    Ok(Rational::from_sci_string(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use core::ops::Range;
+
+    use super::*;
+
+    /// The position sources are placed at, to check that spans are global.
+    const START: u32 = 1000;
+
+    #[track_caller]
+    pub(super) fn assert_imports(parse: Parse, contents: &str, expected: &str) {
+        match parse(&Source::at(START, contents)) {
+            Ok(value) => assert_eq!(format!("{value:?}"), expected, "contents: {contents:?}"),
+            Err(err) => panic!("cannot import {contents:?}: {err:?}"),
+        }
+    }
+
+    #[track_caller]
+    pub(super) fn assert_error(parse: Parse, contents: &str, message: &str, span: Range<u32>) {
+        match parse(&Source::at(START, contents)) {
+            Ok(value) => panic!("unexpected success for {contents:?}: {value:?}"),
+            Err(err) => {
+                assert_eq!(err.message, message, "contents: {contents:?}");
+                assert_eq!(
+                    err.span.lo().to_u32() - START..err.span.hi().to_u32() - START,
+                    span,
+                    "contents: {contents:?}"
+                );
+            }
+        }
+    }
+}
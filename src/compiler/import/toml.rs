@@ -0,0 +1,132 @@
+use malachite::Rational;
+use toml_edit::{ImDocument, Item, Table, Value as TomlValue};
+
+use crate::{compiler::source::Source, vm::value::Value};
+
+use super::{decimal, ParseError};
+
+/// Parse a TOML document into a record. Tables keep the order of their keys, floats are read
+/// exactly, and dates and times become strings.
+pub(super) fn parse(source: &Source) -> Result<Value, ParseError> {
+    let document = ImDocument::parse(source.contents()).map_err(|err| {
+        let span = err.span().unwrap_or(0..0);
+        // Floats too large for an `f64` are refused by the parser, which points at their first
+        // character. They are reported as for other formats if their exponent is too large for
+        // exact numbers too.
+        let rest = &source.contents()[span.start..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "+-._".contains(c)))
+            .unwrap_or(rest.len());
+        if let Err(message) = decimal(&rest[..len].replace('_', "")) {
+            return ParseError::new(message, source.global_span(span.start..span.start + len));
+        }
+
+        ParseError::new(err.message().trim_end(), source.global_span(span))
+    })?;
+
+    table(source, document.as_table())
+}
+
+fn table(source: &Source, table: &Table) -> Result<Value, ParseError> {
+    let fields = table
+        .iter()
+        .map(|(key, item)| Ok((key.to_owned(), self::item(source, item)?)))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Value::record(fields))
+}
+
+fn item(source: &Source, item: &Item) -> Result<Value, ParseError> {
+    match item {
+        Item::Value(value) => self::value(source, value),
+        Item::Table(table) => self::table(source, table),
+        Item::ArrayOfTables(tables) => {
+            let items = tables.iter().map(|table| self::table(source, table));
+            Ok(Value::list(items.collect::<Result<_, _>>()?))
+        }
+        Item::None => unreachable!("parsed tables hold no empty items"),
+    }
+}
+
+fn value(source: &Source, value: &TomlValue) -> Result<Value, ParseError> {
+    let value = match value {
+        TomlValue::String(string) => Value::string(string.value()),
+        TomlValue::Integer(integer) => Value::number(Rational::from(*integer.value())),
+        TomlValue::Float(float) => {
+            // The parsed `f64` is inexact, so the float is read again from the source.
+            let span = float.span().expect("parsed values have spans");
+            let text = source.contents()[span.clone()].replace('_', "");
+
+            match decimal(&text) {
+                Ok(Some(number)) => Value::number(number),
+                Ok(None) => {
+                    let message = "Infinite and NaN numbers cannot be imported";
+                    return Err(ParseError::new(message, source.global_span(span)));
+                }
+                Err(message) => return Err(ParseError::new(message, source.global_span(span))),
+            }
+        }
+        TomlValue::Boolean(boolean) => Value::boolean(*boolean.value()),
+        TomlValue::Datetime(datetime) => Value::string(datetime.value().to_string()),
+        TomlValue::Array(array) => {
+            let items = array.iter().map(|item| self::value(source, item));
+            Value::list(items.collect::<Result<_, _>>()?)
+        }
+        TomlValue::InlineTable(table) => {
+            let fields = table
+                .iter()
+                .map(|(key, value)| Ok((key.to_owned(), self::value(source, value)?)))
+                .collect::<Result<_, _>>()?;
+            Value::record(fields)
+        }
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::import::tests::{assert_error, assert_imports};
+
+    use super::*;
+
+    #[test]
+    fn tables() {
+        assert_imports(
+            parse,
+            "b = 1\na = \"x\"\n\n[t]\nc = [1, 2]\nd = { e = true }\n\n[[list]]\nf = 1\n\n[[list]]\n",
+            r#"{b = 1, a = "x", t = {c = [1, 2], d = {e = true}}, list = [{f = 1}, {}]}"#,
+        );
+    }
+
+    #[test]
+    fn numbers_are_exact() {
+        assert_imports(
+            parse,
+            "a = 0.1\nb = -1_000.5e-2\nc = 0xff\nd = 1979-05-27T07:32:00Z\n",
+            r#"{a = 1/10, b = -2001/200, c = 255, d = "1979-05-27T07:32:00Z"}"#,
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_error(
+            parse,
+            "a = nan\n",
+            "Infinite and NaN numbers cannot be imported",
+            4..7,
+        );
+        assert_error(
+            parse,
+            "a = 1\na = 2\n",
+            "duplicate key `a` in document root",
+            6..7,
+        );
+        assert_error(
+            parse,
+            "a = 1e9999999999\n",
+            "Numbers cannot have an exponent larger than 1048576",
+            4..16,
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use indexmap::{map::Entry, IndexMap};
+use malachite::{
+    num::conversion::{string::options::FromSciStringOptions, traits::FromSciString},
+    Rational,
+};
+use yaml_rust2::{
+    parser::{Event, MarkedEventReceiver, Parser},
+    scanner::{Marker, TScalarStyle},
+};
+
+use crate::{
+    compiler::source::{Source, Span},
+    vm::value::Value,
+};
+
+use super::{decimal, ParseError};
+
+/// Parse the first document of a YAML stream, or `null` if there is none.
+///
+/// Plain scalars are resolved with the YAML 1.2 core schema: They may be `null`, booleans or
+/// numbers, which are read exactly. Aliases are replaced with a copy of the anchored value. Keys
+/// must be scalars, and are always read as strings.
+pub(super) fn parse(source: &Source) -> Result<Value, ParseError> {
+    let mut events = Events::default();
+    Parser::new_from_str(source.contents())
+        .load(&mut events, false)
+        .map_err(|err| ParseError::new(err.info(), span_at(source, *err.marker())))?;
+
+    let mut builder = Builder {
+        source,
+        events: events.0.into_iter(),
+        anchors: HashMap::new(),
+    };
+
+    loop {
+        match builder.events.next() {
+            Some((Event::DocumentStart, _)) => return builder.node(),
+            Some((Event::StreamEnd, _)) | None => return Ok(Value::null()),
+            Some(_) => {}
+        }
+    }
+}
+
+#[derive(Default)]
+struct Events(Vec<(Event, Marker)>);
+
+impl MarkedEventReceiver for Events {
+    fn on_event(&mut self, event: Event, marker: Marker) {
+        self.0.push((event, marker));
+    }
+}
+
+struct Builder<'s> {
+    source: &'s Source,
+    events: std::vec::IntoIter<(Event, Marker)>,
+    anchors: HashMap<usize, Value>,
+}
+
+impl Builder<'_> {
+    fn node(&mut self) -> Result<Value, ParseError> {
+        let (event, marker) = self.events.next().expect("documents hold a node");
+
+        let (value, anchor) = match event {
+            Event::Scalar(text, style, anchor, tag) => {
+                let value = match (style, tag) {
+                    (TScalarStyle::Plain, None) => self.plain(&text, marker)?,
+                    _ => Value::string(text),
+                };
+                (value, anchor)
+            }
+            Event::SequenceStart(anchor, _) => {
+                let mut items = Vec::new();
+                while !matches!(self.peek(), Event::SequenceEnd) {
+                    items.push(self.node()?);
+                }
+                self.events.next();
+
+                (Value::list(items), anchor)
+            }
+            Event::MappingStart(anchor, _) => {
+                let mut fields = IndexMap::new();
+                while !matches!(self.peek(), Event::MappingEnd) {
+                    let (key, key_marker) = match self.events.next() {
+                        Some((Event::Scalar(key, ..), marker)) => (key, marker),
+                        Some((_, marker)) => {
+                            let message = "Only scalars can be used as keys";
+                            return Err(ParseError::new(message, span_at(self.source, marker)));
+                        }
+                        None => unreachable!("mappings are closed"),
+                    };
+
+                    let value = self.node()?;
+                    match fields.entry(key) {
+                        Entry::Vacant(entry) => entry.insert(value),
+                        Entry::Occupied(entry) => {
+                            let message = format!("Duplicate key `{}`", entry.key());
+                            let span = span_at(self.source, key_marker);
+                            return Err(ParseError::new(message, span));
+                        }
+                    };
+                }
+                self.events.next();
+
+                (Value::record(fields), anchor)
+            }
+            Event::Alias(anchor) => {
+                let value = self.anchors.get(&anchor).cloned();
+                (value.expect("aliases refer to known anchors"), 0)
+            }
+            event => unreachable!("unexpected {event:?} in place of a node"),
+        };
+
+        // Anchor 0 stands for no anchor.
+        if anchor != 0 {
+            self.anchors.insert(anchor, value.clone());
+        }
+
+        Ok(value)
+    }
+
+    /// Resolve a plain scalar according to the core schema.
+    fn plain(&self, text: &str, marker: Marker) -> Result<Value, ParseError> {
+        let value = match text {
+            "" | "~" | "null" | "Null" | "NULL" => Value::null(),
+            "true" | "True" | "TRUE" => Value::boolean(true),
+            "false" | "False" | "FALSE" => Value::boolean(false),
+            _ => match number(text) {
+                Ok(Some(Number::Finite(number))) => Value::number(number),
+                Ok(Some(Number::NonFinite)) => {
+                    let message = "Infinite and NaN numbers cannot be imported";
+                    return Err(ParseError::new(message, span_at(self.source, marker)));
+                }
+                Ok(None) => Value::string(text),
+                Err(message) => return Err(ParseError::new(message, span_at(self.source, marker))),
+            },
+        };
+
+        Ok(value)
+    }
+
+    fn peek(&self) -> &Event {
+        &self
+            .events
+            .as_slice()
+            .first()
+            .expect("collections are closed")
+            .0
+    }
+}
+
+enum Number {
+    Finite(Rational),
+    NonFinite,
+}
+
+/// Resolve a plain scalar as an integer or float of the core schema, if it is one. Returns the
+/// message of the error if its exponent is too large.
+fn number(text: &str) -> Result<Option<Number>, String> {
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", text.strip_prefix('+').unwrap_or(text)),
+    };
+
+    if matches!(unsigned, ".inf" | ".Inf" | ".INF") || matches!(text, ".nan" | ".NaN" | ".NAN") {
+        return Ok(Some(Number::NonFinite));
+    }
+
+    for (prefix, base) in [("0x", 16), ("0o", 8)] {
+        if let Some(digits) = text.strip_prefix(prefix) {
+            if digits.is_empty() || !digits.chars().all(|c| c.is_digit(base.into())) {
+                return Ok(None);
+            }
+
+            let mut options = FromSciStringOptions::default();
+            options.set_base(base);
+            let number = Rational::from_sci_string_with_options(digits, options);
+            return Ok(number.map(Number::Finite));
+        }
+    }
+
+    // Decimals may omit the digits on either side of the point, as in `1.` or `.5`, but not both.
+    let (mantissa, exponent) = unsigned.split_once(['e', 'E']).unwrap_or((unsigned, "0"));
+    let (integer, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let exponent_digits = exponent.strip_prefix(['-', '+']).unwrap_or(exponent);
+
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    let valid = (!integer.is_empty() || !fraction.is_empty())
+        && is_digits(integer)
+        && is_digits(fraction)
+        && !exponent_digits.is_empty()
+        && is_digits(exponent_digits);
+    if !valid {
+        return Ok(None);
+    }
+
+    let number = decimal(&format!("{sign}0{integer}.{fraction}0e{exponent}"))?;
+    Ok(number.map(Number::Finite))
+}
+
+/// The span of the character at a marker. Markers count characters rather than bytes.
+fn span_at(source: &Source, marker: Marker) -> Span {
+    let contents = source.contents();
+    let lo = contents
+        .char_indices()
+        .nth(marker.index())
+        .map_or(contents.len(), |(i, _)| i);
+    let hi = contents[lo..]
+        .chars()
+        .next()
+        .map_or(lo, |c| lo + c.len_utf8());
+
+    source.global_span(lo..hi)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::import::tests::{assert_error, assert_imports};
+
+    use super::*;
+
+    #[test]
+    fn block_and_flow_collections() {
+        assert_imports(
+            parse,
+            "b:\n  - 1\n  - [2, {c: d}]\na: {}\n",
+            r#"{b = [1, [2, {c = "d"}]], a = {}}"#,
+        );
+        assert_imports(parse, "", "null");
+        assert_imports(parse, "# only a comment\n", "null");
+        assert_imports(parse, "- 1\n---\n- 2\n", "[1]");
+    }
+
+    #[test]
+    fn core_schema_scalars() {
+        assert_imports(
+            parse,
+            "[~, null, '', True, FALSE, yes, 0x1f, 0o17, -12, +1.5, .5, 1., 2.5e-3, 1e3, 1_000, '1', \"true\", !!str 2, 1.2.3]",
+            r#"[null, null, "", true, false, "yes", 31, 15, -12, 3/2, 1/2, 1, 1/400, 1000, "1_000", "1", "true", "2", "1.2.3"]"#,
+        );
+    }
+
+    #[test]
+    fn anchors_and_aliases() {
+        assert_imports(
+            parse,
+            "base: &base {a: 1}\ncopy: *base\n",
+            "{base = {a = 1}, copy = {a = 1}}",
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_error(parse, "a: 1\na: 2\n", "Duplicate key `a`", 5..6);
+        assert_error(
+            parse,
+            "? [a]\n: 1\n",
+            "Only scalars can be used as keys",
+            2..3,
+        );
+        assert_error(
+            parse,
+            "a: .inf\n",
+            "Infinite and NaN numbers cannot be imported",
+            3..4,
+        );
+        assert_error(
+            parse,
+            "a: 1e9999999999\n",
+            "Numbers cannot have an exponent larger than 1048576",
+            3..4,
+        );
+        assert_error(
+            parse,
+            "a: [1\n",
+            "while parsing a flow sequence, expected ',' or ']'",
+            6..6,
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Lowering of the [syntax tree](super::syntax) into the [AST](super::ast).
+
+use logos::Logos;
+use malachite::Rational;
+
+use crate::vm::value::{Merge, Value};
+
+use super::{
+    ast::{
+        Binding, Expr, ExprKind, Identifier, Operator, PatternTypeExpr, RangeTypeExpr,
+        RecordTypeExpr, TypeAlias, TypeExpr, TypeExprKind, TypeField,
+    },
+    context::Context,
+    source::{lexer::Token, Source, Span},
+    symbol::Symbol,
+    syntax::{NodeKind, SyntaxNode, SyntaxToken, TokenKind},
+};
+
+/// Lower a syntax tree produced by the parser into an expression. Error nodes become
+/// [`ExprKind::Error`](super::ast::ExprKind::Error) placeholders. Expressions are given the spans
+/// of the nodes they are lowered from, and parentheses are left out of them.
+pub fn lower(cx: &mut Context, source: &Source, node: &SyntaxNode) -> Expr {
+    Lowering { cx, source }.expr(node)
+}
+
+struct Lowering<'a> {
+    cx: &'a mut Context,
+    source: &'a Source,
+}
+
+impl Lowering<'_> {
+    fn expr(&mut self, node: &SyntaxNode) -> Expr {
+        let mut nodes = node.nodes();
+        let mut tokens = node.tokens();
+
+        let expr = match node.kind {
+            NodeKind::Root | NodeKind::Paren => {
+                return self.expr(nodes.next().expect("inner expression"))
+            }
+            NodeKind::Literal => {
+                let token = tokens.next().expect("literal token");
+                let value = match token.kind {
+                    TokenKind::True => Value::boolean(true),
+                    TokenKind::False => Value::boolean(false),
+                    TokenKind::Null => Value::null(),
+                    TokenKind::Number => match self.relex(token) {
+                        Token::Number(number) => Value::number(number),
+                        _ => unreachable!("number tokens are valid numbers"),
+                    },
+                    TokenKind::String => Value::string(self.string(token)),
+                    kind => unreachable!("{kind:?} is not a literal"),
+                };
+                Expr::literal(value)
+            }
+            NodeKind::Name => Expr::identifier(self.identifier(tokens.next().expect("name"))),
+            NodeKind::SelfRecord => match tokens.next().expect("keyword").kind {
+                TokenKind::SelfValue => Expr::new(ExprKind::SelfRecord),
+                _ => Expr::new(ExprKind::SuperRecord),
+            },
+            NodeKind::Unary => {
+                let token = tokens.next().expect("operator");
+                let op = match token.kind {
+                    TokenKind::Dash => Operator::Neg,
+                    TokenKind::Not => Operator::Not,
+                    kind => unreachable!("{kind:?} is not a unary operator"),
+                };
+                let operator = Expr::operator(op).with_span(token.span);
+                Expr::call(operator, self.expr(nodes.next().expect("operand")))
+            }
+            NodeKind::Binary => {
+                let token = tokens.next().expect("operator");
+                let op = match token.kind {
+                    TokenKind::Plus => Operator::Add,
+                    TokenKind::Dash => Operator::Sub,
+                    TokenKind::Star => Operator::Mul,
+                    TokenKind::Slash => Operator::Div,
+                    TokenKind::SlashSlash => Operator::Merge,
+                    kind => unreachable!("{kind:?} is not a binary operator"),
+                };
+                let left = self.expr(nodes.next().expect("left operand"));
+                let right = self.expr(nodes.next().expect("right operand"));
+
+                // The operator applied to its left operand spans them both.
+                let span = Span::new(left.span.lo(), token.span.hi());
+                let operator = Expr::operator(op).with_span(token.span);
+                Expr::call(Expr::call(operator, left).with_span(span), right)
+            }
+            NodeKind::Apply => {
+                let fun = self.expr(nodes.next().expect("function"));
+                let arg = self.expr(nodes.next().expect("argument"));
+                Expr::call(fun, arg)
+            }
+            NodeKind::Let => {
+                let nodes: Vec<_> = nodes.collect();
+                let (body, bindings) = nodes.split_last().expect("let body");
+                let mut expr =
+                    Expr::let_in(self.bindings(bindings.iter().copied()), self.expr(body));
+                if let ExprKind::Let(let_in) = &mut expr.kind {
+                    let_in.types = self.aliases(bindings.iter().copied());
+                }
+                expr
+            }
+            NodeKind::Record => {
+                let nodes: Vec<_> = nodes.collect();
+                let mut expr = Expr::record(self.bindings(nodes.iter().copied()));
+                if let ExprKind::Record(record) = &mut expr.kind {
+                    record.merges = self.merges(nodes.iter().copied());
+                }
+                expr
+            }
+            NodeKind::List => Expr::list(nodes.map(|node| self.expr(node)).collect()),
+            NodeKind::Import => {
+                let path = tokens.nth(1).expect("import path");
+                Expr::import(self.string(path), path.span)
+            }
+            NodeKind::Field => {
+                let record = self.expr(nodes.next().expect("record"));
+                let name = self.identifier(tokens.nth(1).expect("field name"));
+                Expr::field(record, name)
+            }
+            NodeKind::Annotated => {
+                let expr = self.expr(nodes.next().expect("annotated expression"));
+                Expr::annotated(expr, self.ty(nodes.next().expect("annotation")))
+            }
+            NodeKind::Error => Expr::error(),
+            NodeKind::Binding | NodeKind::Deletion | NodeKind::TypeAlias => {
+                unreachable!("bindings are not expressions")
+            }
+            NodeKind::TypeName
+            | NodeKind::TypeList
+            | NodeKind::TypeRecord
+            | NodeKind::TypeField
+            | NodeKind::TypeRange
+            | NodeKind::TypePattern
+            | NodeKind::TypeEnum => unreachable!("types are not expressions"),
+        };
+
+        expr.with_span(node.span)
+    }
+
+    /// Lower the bindings among the given nodes. Errors in place of a binding are skipped, since
+    /// there is no name to bind a placeholder to. The value of an annotated binding is annotated.
+    fn bindings<'n>(&mut self, nodes: impl Iterator<Item = &'n SyntaxNode>) -> Vec<Binding> {
+        nodes
+            .filter(|node| node.kind == NodeKind::Binding)
+            .map(|node| {
+                let name = self.identifier(node.tokens().next().expect("binding name"));
+                let nodes: Vec<_> = node.nodes().collect();
+                let (value, annotation) = nodes.split_last().expect("binding value");
+                let mut value = self.expr(value);
+                if let Some(ty) = annotation.first() {
+                    let span = value.span;
+                    value = Expr::annotated(value, self.ty(ty)).with_span(span);
+                }
+                Binding { name, value }
+            })
+            .collect()
+    }
+
+    /// Lower the ways the fields of a record among the given nodes merge, for the bindings that do
+    /// not use `=`, and the deletions.
+    fn merges<'n>(
+        &mut self,
+        nodes: impl Iterator<Item = &'n SyntaxNode>,
+    ) -> Vec<(Identifier, Merge)> {
+        nodes
+            .filter_map(|node| {
+                let tokens: Vec<_> = node.tokens().collect();
+                let (name, merge) = match node.kind {
+                    NodeKind::Deletion => (tokens[1], Merge::Delete),
+                    NodeKind::Binding => {
+                        let merge = tokens.iter().find_map(|token| match token.kind {
+                            TokenKind::ColonAssign => Some(Merge::Replace),
+                            TokenKind::PlusAssign => Some(Merge::Append),
+                            _ => None,
+                        })?;
+                        (tokens[0], merge)
+                    }
+                    _ => return None,
+                };
+                Some((self.identifier(name), merge))
+            })
+            .collect()
+    }
+
+    /// Lower the type aliases among the given nodes.
+    fn aliases<'n>(&mut self, nodes: impl Iterator<Item = &'n SyntaxNode>) -> Vec<TypeAlias> {
+        nodes
+            .filter(|node| node.kind == NodeKind::TypeAlias)
+            .map(|node| TypeAlias {
+                name: self.identifier(node.tokens().nth(1).expect("alias name")),
+                ty: self.ty(node.nodes().next().expect("aliased type")),
+            })
+            .collect()
+    }
+
+    fn ty(&mut self, node: &SyntaxNode) -> TypeExpr {
+        let mut nodes = node.nodes();
+        let mut tokens = node.tokens();
+
+        let kind = match node.kind {
+            NodeKind::TypeName => TypeExprKind::Name(self.identifier(tokens.next().expect("name"))),
+            NodeKind::TypeList => {
+                TypeExprKind::List(Box::new(self.ty(nodes.next().expect("item"))))
+            }
+            NodeKind::TypeRecord => TypeExprKind::Record(RecordTypeExpr {
+                fields: nodes
+                    .map(|field| TypeField {
+                        name: self.identifier(field.tokens().next().expect("field name")),
+                        ty: self.ty(field.nodes().next().expect("field type")),
+                    })
+                    .collect(),
+                open: tokens.any(|token| token.kind == TokenKind::DotDot),
+            }),
+            NodeKind::TypeRange => {
+                let base = Box::new(self.ty(nodes.next().expect("base type")));
+                let (mut min, mut max) = (None, None);
+                let mut negative = false;
+                let mut bound = &mut min;
+                for token in tokens {
+                    match token.kind {
+                        TokenKind::Dash => negative = true,
+                        TokenKind::Number => {
+                            *bound = Some(self.number(token, negative));
+                            negative = false;
+                        }
+                        TokenKind::DotDot => bound = &mut max,
+                        kind => unreachable!("{kind:?} is not part of a range"),
+                    }
+                }
+                TypeExprKind::Range(RangeTypeExpr { base, min, max })
+            }
+            NodeKind::TypePattern => TypeExprKind::Pattern(PatternTypeExpr {
+                base: Box::new(self.ty(nodes.next().expect("base type"))),
+                regex: self.string(tokens.nth(1).expect("pattern")),
+            }),
+            NodeKind::TypeEnum => {
+                let mut values = Vec::new();
+                let mut negative = false;
+                for token in tokens {
+                    match token.kind {
+                        TokenKind::Dash => negative = true,
+                        TokenKind::Number => {
+                            values.push(Value::number(self.number(token, negative)));
+                            negative = false;
+                        }
+                        TokenKind::String => values.push(Value::string(self.string(token))),
+                        TokenKind::True => values.push(Value::boolean(true)),
+                        TokenKind::False => values.push(Value::boolean(false)),
+                        TokenKind::Null => values.push(Value::null()),
+                        TokenKind::Pipe => {}
+                        kind => unreachable!("{kind:?} is not part of an enumeration"),
+                    }
+                }
+                TypeExprKind::Enum(values)
+            }
+            kind => unreachable!("{kind:?} is not a type"),
+        };
+
+        TypeExpr {
+            kind,
+            span: node.span,
+        }
+    }
+
+    /// Lex a token again, to recover its value.
+    fn relex(&self, token: &SyntaxToken) -> Token<'_> {
+        match Token::lexer(self.source.slice(token.span)).next() {
+            Some(Ok(token)) => token,
+            _ => unreachable!("tokens in the syntax tree are valid"),
+        }
+    }
+
+    fn number(&self, token: &SyntaxToken, negative: bool) -> Rational {
+        match self.relex(token) {
+            Token::Number(number) if negative => -number,
+            Token::Number(number) => number,
+            _ => unreachable!("number tokens are valid numbers"),
+        }
+    }
+
+    fn string(&self, token: &SyntaxToken) -> String {
+        match self.relex(token) {
+            Token::String(string) => string,
+            _ => unreachable!("string tokens are valid strings"),
+        }
+    }
+
+    /// Lower an identifier, or a field name written as a string.
+    fn identifier(&mut self, token: &SyntaxToken) -> Identifier {
+        let name = match token.kind {
+            TokenKind::String => self.string(token),
+            _ => self.source.slice(token.span).to_owned(),
+        };
+
+        Identifier {
+            name: self.cx.symbol_interner.intern(Symbol::new(name)),
+            span: token.span,
+        }
+    }
+}
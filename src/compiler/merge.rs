@@ -0,0 +1,458 @@
+//! Merging records with `//`.
+//!
+//! `base // override` merges two records deeply. The fields of `base` come first, in order,
+//! followed by the fields only `override` has. A field both records have is merged according to
+//! how `override` binds it:
+//!
+//! - `name = value` merges records field by field, in the same way, and replaces any other value
+//!   with a value of the same kind: A number with a number, a list with a list, and so on. Values
+//!   of different kinds conflict.
+//! - `name := value` replaces the field, whatever its value is.
+//! - `name += value` appends a list to the list of the field.
+//! - `-name` deletes the field. Deleting a field that is not there does nothing.
+//!
+//! The result is a plain record: The ways the fields of `override` merge apply to this merge only,
+//! and are not kept.
+//!
+//! They have no effect anywhere else: In a record that is never merged, or only on the left of
+//! `//`, `name := value` and `name += value` bind the field as `name = value` does, and `-name`
+//! deletes nothing. `{ a := 1, -b }` outputs as `{ a = 1 }`.
+//!
+//! Fields bound to values that depend on `self` or `super` are late-bound: `self` is the record
+//! the field ends up in, once merged, and `super` the record the one defining the field was merged
+//! into. Merging a record with late-bound fields evaluates them again, so that a field such as
+//! `total = self.count * self.size` follows the overrides of `count` and `size`. Records with
+//! late-bound fields keep a [`Template`] for this, and the evaluator implements [`Late`] to
+//! evaluate it.
+//!
+//! A late-bound field that depends on a field `self` or `super` does not have is left out of its
+//! record, as a merge may bind it later: `{ url = self.host } // { host = "a" }` is
+//! `{ url = "a", host = "a" }`. A result with fields still left out is an error.
+//!
+//! Merged values keep their [provenance](crate::vm::value::Provenance): A replaced value is
+//! overridden by the value replacing it, and merged records and appended lists are merged by the
+//! `//` expression.
+
+use std::sync::Arc;
+
+use indexmap::{IndexMap, IndexSet};
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use crate::{
+    output::{Path, PathSegment},
+    vm::{
+        value::{Merge, Provenance, Template, Value, ValueKind},
+        RuntimeError,
+    },
+};
+
+use super::{
+    ast::Binding,
+    contract::Aliases,
+    interner::Interned,
+    source::{Source, Span},
+    symbol::Symbol,
+};
+
+/// The definitions of the late-bound fields of a record literal, with the names and type aliases
+/// in scope of it, as the evaluator keeps them in the [`Template`] of the record.
+pub(super) struct Definitions {
+    pub(super) fields: IndexMap<String, Binding>,
+    pub(super) scope: Vec<(Interned<Symbol>, Value)>,
+    pub(super) aliases: Aliases,
+}
+
+/// The names of the fields of a record, including those its template defines but that it does not
+/// have yet. The fields of the record on the left of a merge come first, but for those the record
+/// on the right deletes, followed by the fields only the record on the right has.
+pub fn names(record: &Value) -> Vec<String> {
+    let ValueKind::Record(fields) = &*record.kind else {
+        unreachable!("only records have fields");
+    };
+
+    match fields.template.as_deref() {
+        None => fields.fields.keys().cloned().collect(),
+        Some(Template::Literal { names, .. }) => names.clone(),
+        Some(Template::Merge { left, right, .. }) => {
+            let ValueKind::Record(overrides) = &*right.kind else {
+                unreachable!("only records are merged");
+            };
+            let mut all: IndexSet<_> = names(left)
+                .into_iter()
+                .filter(|name| overrides.merge(name) != Merge::Delete)
+                .collect();
+            all.extend(names(right));
+            all.into_iter().collect()
+        }
+    }
+}
+
+/// Evaluates late-bound fields.
+pub trait Late {
+    /// Evaluate the fields of a record that has a template, at `path` in the merged records.
+    fn instantiate(&mut self, record: &Value, path: &mut Path) -> Result<Value, RuntimeError>;
+}
+
+/// An operand of `//`, or a part of one.
+#[derive(Clone, Copy)]
+pub struct Operand<'a> {
+    pub value: &'a Value,
+
+    /// The span of the innermost definition of the value: Its own, if it has a provenance, and
+    /// otherwise the one of the innermost value around it that does.
+    pub span: Span,
+}
+
+impl<'a> Operand<'a> {
+    /// An operand produced by the expression at `span`, unless it has a provenance of its own.
+    pub fn new(value: &'a Value, span: Span) -> Self {
+        Self {
+            value,
+            span: value.span().unwrap_or(span),
+        }
+    }
+
+    fn part(self, value: &'a Value) -> Self {
+        Self::new(value, self.span)
+    }
+}
+
+/// Check that no record within a value has late-bound fields left out, as they depend on fields
+/// that no merge bound.
+pub fn resolved(value: &Value) -> Result<(), RuntimeError> {
+    match &*value.kind {
+        ValueKind::Record(record) => {
+            if let Some(unresolved) = record.unresolved.first() {
+                return Err(RuntimeError::Unresolved(unresolved.clone()));
+            }
+            record.fields.values().try_for_each(resolved)
+        }
+        ValueKind::List(list) => list.items().iter().try_for_each(resolved),
+        _ => Ok(()),
+    }
+}
+
+/// Two values that cannot be merged.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub message: String,
+
+    /// A suggestion to resolve the conflict, if there is one.
+    pub hint: Option<&'static str>,
+
+    /// Where the values are within the merged records.
+    pub path: Path,
+
+    /// The spans of the definitions of the values, on the left and on the right of `//`.
+    pub left: Span,
+    pub right: Span,
+}
+
+/// Merge the records on either side of the `//` expression at `span`.
+pub fn merge(
+    left: Operand,
+    right: Operand,
+    span: Span,
+    late: &mut dyn Late,
+) -> Result<Value, RuntimeError> {
+    for operand in [left, right] {
+        if !matches!(*operand.value.kind, ValueKind::Record(_)) {
+            return Err(RuntimeError::TypeMismatch {
+                expected: "a record",
+                found: operand.value.kind.describe(),
+            });
+        }
+    }
+
+    Merger { span, late }.records(left, right, &mut Path::root())
+}
+
+/// Merge the values of a field, at `path` in the records merged by the `//` expression at `span`,
+/// as the record on the right says.
+pub fn field(
+    merge: Merge,
+    left: Operand,
+    right: Operand,
+    span: Span,
+    path: &mut Path,
+    late: &mut dyn Late,
+) -> Result<Value, RuntimeError> {
+    Merger { span, late }.field(merge, left, right, path)
+}
+
+struct Merger<'l> {
+    /// The span of the `//` expression.
+    span: Span,
+
+    late: &'l mut dyn Late,
+}
+
+impl Merger<'_> {
+    fn records(
+        &mut self,
+        left: Operand,
+        right: Operand,
+        path: &mut Path,
+    ) -> Result<Value, RuntimeError> {
+        let (ValueKind::Record(a), ValueKind::Record(b)) = (&*left.value.kind, &*right.value.kind)
+        else {
+            unreachable!("only records are merged field by field");
+        };
+
+        if a.template.is_some() || b.template.is_some() {
+            let record = Template::merge(left.value, right.value, self.span);
+            return self.late.instantiate(&record, path);
+        }
+
+        let mut fields = IndexMap::with_capacity(a.fields.len() + b.fields.len());
+        for (name, value) in &a.fields {
+            let merge = b.merge(name);
+            let value = match b.fields.get(name) {
+                _ if merge == Merge::Delete => continue,
+                Some(other) => {
+                    path.push(PathSegment::Field(name.clone()));
+                    let value = self.field(merge, left.part(value), right.part(other), path)?;
+                    path.pop();
+                    value
+                }
+                None => value.clone(),
+            };
+            fields.insert(name.clone(), value);
+        }
+        for (name, value) in &b.fields {
+            if !a.fields.contains_key(name) {
+                fields.insert(name.clone(), value.clone());
+            }
+        }
+
+        Ok(Value::record(fields).with_provenance(self.provenance(left, right)))
+    }
+
+    fn field(
+        &mut self,
+        merge: Merge,
+        left: Operand,
+        right: Operand,
+        path: &mut Path,
+    ) -> Result<Value, RuntimeError> {
+        let conflict = |message, hint, path: &Path| {
+            RuntimeError::Merge(Box::new(Conflict {
+                message,
+                hint,
+                path: path.clone(),
+                left: left.span,
+                right: right.span,
+            }))
+        };
+
+        match (merge, &*left.value.kind, &*right.value.kind) {
+            (Merge::Deep, ValueKind::Record(_), ValueKind::Record(_)) => {
+                self.records(left, right, path)
+            }
+            (Merge::Deep, a, b) if core::mem::discriminant(a) == core::mem::discriminant(b) => {
+                Ok(replace(left, right, self.span))
+            }
+            (Merge::Deep, a, b) => Err(conflict(
+                format!("Cannot merge {} into {}", b.describe(), a.describe()),
+                Some("Bind the field with `:=` to replace its value instead"),
+                path,
+            )),
+            (Merge::Replace, _, _) => Ok(replace(left, right, self.span)),
+            (Merge::Append, ValueKind::List(a), ValueKind::List(b)) => {
+                let items = a.thunks().into_iter().chain(b.thunks()).collect();
+                Ok(Value::lazy_list(items).with_provenance(self.provenance(left, right)))
+            }
+            (Merge::Append, a, b) => Err(conflict(
+                match a {
+                    ValueKind::List(_) => format!("Cannot append {} to a list", b.describe()),
+                    _ => format!("Cannot append to {}", a.describe()),
+                },
+                None,
+                path,
+            )),
+            (Merge::Delete, _, _) => unreachable!("deleted fields have no value"),
+        }
+    }
+
+    /// The provenance of a value merged from two others by the `//` expression.
+    fn provenance(&self, left: Operand, right: Operand) -> Provenance {
+        let provenance = |operand: Operand| operand.value.provenance.clone();
+        Provenance::merging(self.span, provenance(left), provenance(right))
+    }
+}
+
+/// The value on the right, overriding the one on the left. A value with no provenance of its own
+/// overrides it at the `//` expression at `span`.
+fn replace(left: Operand, right: Operand, span: Span) -> Value {
+    let provenance = match (&left.value.provenance, &right.value.provenance) {
+        (Some(previous), Some(provenance)) => Some(provenance.after(previous.clone())),
+        (Some(previous), None) => Some(Provenance::overriding(span, Some(previous.clone()))),
+        (None, provenance) => provenance.as_deref().cloned(),
+    };
+
+    Value {
+        kind: Arc::clone(&right.value.kind),
+        provenance: provenance.map(Arc::new),
+    }
+}
+
+/// A merge conflict found during evaluation, with the source code of the conflicting values.
+#[derive(Debug, Error, Diagnostic)]
+#[error("{message}")]
+pub struct MergeConflict {
+    message: String,
+
+    #[source_code]
+    source_code: NamedSource,
+
+    #[label("Defined here")]
+    left: Option<SourceSpan>,
+
+    #[label("Conflicts with this definition")]
+    right: SourceSpan,
+
+    #[help]
+    help: Option<String>,
+}
+
+impl MergeConflict {
+    /// Create a diagnostic for a conflict whose value on the right is in the given source. The
+    /// value on the left is only labelled if it is in the same source.
+    pub fn new(source: &Source, conflict: Conflict) -> Self {
+        let local_span = |span: Span| source.local_span(span.lo().to_u32(), span.hi().to_u32());
+        let left = (source.span().lo() <= conflict.left.lo()
+            && conflict.left.hi() <= source.span().hi())
+        .then(|| local_span(conflict.left));
+
+        let at = (!conflict.path.segments().is_empty())
+            .then(|| format!("At {} in the merged records", conflict.path));
+        let help = match (at, conflict.hint) {
+            (Some(at), Some(hint)) => Some(format!("{at}. {hint}")),
+            (at, hint) => at.or(hint.map(str::to_owned)),
+        };
+
+        Self {
+            message: conflict.message,
+            source_code: NamedSource::new(source.name(), source.contents().to_owned()),
+            left,
+            right: local_span(conflict.right),
+            help,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compiler::{eval::eval, parser, Context},
+        vm::value::Origin,
+    };
+
+    fn merged(source: &str) -> Result<Value, RuntimeError> {
+        let mut cx = Context::new();
+        let parsed = parser::parse(&mut cx, &Source::at(0, source));
+        assert!(parsed.errors.is_empty(), "cannot parse `{source}`");
+
+        eval(&cx, Vec::new(), &parsed.expr).map_err(RuntimeError::unlocated)
+    }
+
+    #[track_caller]
+    fn assert_merges(source: &str, expected: &str) {
+        match merged(source) {
+            Ok(value) => assert_eq!(format!("{value:?}"), expected, "source: `{source}`"),
+            Err(err) => panic!("cannot evaluate `{source}`: {err}"),
+        }
+    }
+
+    #[track_caller]
+    fn conflict_in(source: &'static str) -> (Conflict, &'static str, &'static str) {
+        let Err(RuntimeError::Merge(conflict)) = merged(source) else {
+            panic!("`{source}` merges");
+        };
+        let slice = |span: Span| &source[span.lo().to_u32() as usize..span.hi().to_u32() as usize];
+        let (left, right) = (slice(conflict.left), slice(conflict.right));
+        (*conflict, left, right)
+    }
+
+    #[test]
+    fn records_are_merged_deeply() {
+        assert_merges(
+            "{ a = 1, b = { c = 2, d = 3 } } // { b = { d = 4, e = 5 }, f = 6 }",
+            "{a = 1, b = {c = 2, d = 4, e = 5}, f = 6}",
+        );
+        assert_merges("{ a = [1] } // { a = [2] } // {}", "{a = [2]}");
+        assert!(matches!(
+            merged("{} // [1]"),
+            Err(RuntimeError::TypeMismatch {
+                expected: "a record",
+                found: "a list"
+            })
+        ));
+    }
+
+    #[test]
+    fn fields_say_how_they_merge() {
+        assert_merges(
+            "{ a = { b = 1 }, c = [1], d = 2, e = 3 } // { a := { f = 2 }, c += [2], -d, -g }",
+            "{a = {f = 2}, c = [1, 2], e = 3}",
+        );
+        assert_merges("{ a = 1 } // { a := \"b\" }", "{a = \"b\"}");
+        assert_merges("{} // { a += [1], -b }", "{a = [1]}");
+        // Only the record on the right says how fields merge.
+        assert_merges("{ a := 1, -b } // { b = 2 }", "{a = 1, b = 2}");
+        assert!(matches!(
+            merged("{ a = 1, -a }"),
+            Err(RuntimeError::DuplicateField { name }) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn conflicts_point_at_both_definitions() {
+        let (conflict, left, right) =
+            conflict_in("let base = { a = { b = 1 } } in base // { a = { b = \"c\" } }");
+        assert_eq!(conflict.message, "Cannot merge a string into a number");
+        assert_eq!(conflict.path.to_string(), "`a.b`");
+        assert_eq!((left, right), ("1", "\"c\""));
+
+        let (conflict, left, right) = conflict_in("{ a = 1 } // { a += [2] }");
+        assert_eq!(conflict.message, "Cannot append to a number");
+        assert_eq!((left, right), ("1", "[2]"));
+    }
+
+    #[test]
+    fn merged_values_keep_their_history() {
+        let source = "let base = { a = 1, b = [1] } in base // { a = 2, b += [2] }";
+        let value = merged(source).unwrap();
+        let ValueKind::Record(record) = &*value.kind else {
+            panic!("`{source}` is not a record");
+        };
+        let history = |name: &str| {
+            let provenance = record.fields[name].provenance.as_ref().unwrap();
+            provenance
+                .history()
+                .into_iter()
+                .map(|step| {
+                    let kind = match step.origin {
+                        Origin::Defined => "defined",
+                        Origin::Override(_) => "overridden",
+                        Origin::Merge(..) => "merged",
+                    };
+                    let (lo, hi) = (step.span.lo().to_u32(), step.span.hi().to_u32());
+                    (kind, &source[lo as usize..hi as usize])
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(history("a"), [("defined", "1"), ("overridden", "2")]);
+        assert_eq!(
+            history("b"),
+            [
+                ("defined", "[1]"),
+                ("defined", "[2]"),
+                ("merged", "base // { a = 2, b += [2] }")
+            ]
+        );
+    }
+}
@@ -2,55 +2,698 @@ use lalrpop_util::lalrpop_mod;
 use miette::Diagnostic;
 use thiserror::Error;
 
-lalrpop_mod!(grammar, "/compiler/grammar.rs");
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    grammar,
+    "/compiler/grammar.rs"
+);
 
-use self::source::{EntryContext, FileLoader, SourceContext, SourceError, SourceMap};
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+
+use crate::vm::{
+    value::{Value, ValueKind},
+    RuntimeError, Unresolved,
+};
+
+use self::ast::{Expr, ExprKind};
+use self::context::Context;
+use self::contract::ContractViolation;
+use self::eval::EvalError;
+use self::import::ImportError;
+use self::interner::Interned;
+use self::merge::MergeConflict;
+use self::parser::{Parsed, SyntaxErrors};
+use self::printer::Trivia;
+use self::query::Queries;
+use self::source::{
+    BytePos, EntryContext, FileLoader, Fingerprint, Source, SourceContext, SourceError,
+    SourceLoader, SourceMap, Span,
+};
+use self::symbol::Symbol;
+use self::types::{Checker, DocumentTypes, Type, TypeErrors};
 
 mod interner;
 
 pub mod ast;
 mod context;
+pub mod contract;
+mod eval;
+pub mod import;
+mod lower;
+pub mod merge;
+pub mod parser;
+mod printer;
+mod query;
 pub mod source;
 mod symbol;
+pub mod syntax;
+pub mod types;
 
 pub struct Compiler {
     /// The source map.
     source_map: SourceMap,
+
+    /// The compilation context.
+    context: Context,
+
+    /// The external arguments supplied by the host.
+    args: IndexMap<String, Value>,
+
+    /// The names bound in the scope of every program, innermost last.
+    bindings: Vec<(Interned<Symbol>, Value)>,
+
+    /// The results of compiling each source, for incremental compilation.
+    queries: Queries,
 }
 
 #[derive(Debug, Error, Diagnostic)]
 pub enum CompileError {
     #[error("Error loading source")]
     Source(#[from] SourceError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Syntax(#[from] SyntaxErrors),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Import(#[from] ImportError),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Type(#[from] TypeErrors),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Contract(#[from] Box<ContractViolation>),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Merge(#[from] Box<MergeConflict>),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Eval(#[from] Box<EvalError>),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Runtime(#[from] RuntimeError),
+}
+
+/// The entry point of a program.
+#[derive(Debug, Clone, Copy)]
+pub enum Entry<'a> {
+    /// A file, loaded by name.
+    File(&'a str),
+
+    /// Source code given directly, such as an expression from the command line. The name is only
+    /// used in messages. Files are loaded relative to the base path, as for entry files.
+    Inline { name: &'a str, contents: &'a str },
+}
+
+impl<'a> From<&'a str> for Entry<'a> {
+    fn from(name: &'a str) -> Self {
+        Self::File(name)
+    }
+}
+
+impl<'a> From<&'a String> for Entry<'a> {
+    fn from(name: &'a String) -> Self {
+        Self::File(name)
+    }
 }
 
 impl Compiler {
     pub fn new() -> Self {
+        Self::with_loader(FileLoader::new("."))
+    }
+
+    /// Create a compiler loading sources with the given loader.
+    pub fn with_loader(source_loader: impl SourceLoader + Send + Sync + 'static) -> Self {
         Self {
-            source_map: SourceMap::new(FileLoader::new(".")),
+            source_map: SourceMap::new(source_loader),
+            context: Context::new(),
+            args: IndexMap::new(),
+            bindings: Vec::new(),
+            queries: Queries::default(),
+        }
+    }
+
+    /// Bind a name in the scope of the programs evaluated from now on, as if they were wrapped in
+    /// a `let` binding it. Later bindings shadow earlier ones.
+    pub fn bind(&mut self, name: impl Into<String>, value: Value) {
+        let name = self.context.symbol_interner.intern(Symbol::new(name));
+        self.bindings.push((name, value));
+        self.queries.invalidate_globals();
+    }
+
+    /// Evaluate the bindings of a `let` expression, and [bind](Self::bind) them for the programs
+    /// evaluated from now on. The body of the expression is ignored, and expressions other than
+    /// `let` define nothing.
+    ///
+    /// Returns the names and values that were bound.
+    pub fn define<'a>(
+        &mut self,
+        entry: impl Into<Entry<'a>>,
+    ) -> Result<Vec<(String, Value)>, CompileError> {
+        let entry = entry.into();
+        let (source, expr) = self.parse(entry)?;
+        let result = self.define_parsed(entry, source, &expr);
+        self.forget(entry, source);
+        result
+    }
+
+    fn define_parsed(
+        &mut self,
+        entry: Entry,
+        source: Fingerprint,
+        expr: &Expr,
+    ) -> Result<Vec<(String, Value)>, CompileError> {
+        let (expr, _) = self.check_parsed(source, expr)?;
+        let ExprKind::Let(let_in) = &expr.kind else {
+            return Ok(Vec::new());
+        };
+
+        let globals = self.globals();
+        let bound = eval::eval_bindings(&self.context, globals, let_in)
+            .map_err(|err| self.runtime_error(err))?;
+        let bound: Vec<_> = bound
+            .into_iter()
+            .map(|(name, value)| (name, detach(entry, value)))
+            .collect();
+        self.bindings.extend(bound.iter().cloned());
+        self.queries.invalidate_globals();
+
+        Ok(bound
+            .into_iter()
+            .map(|(name, value)| {
+                let name = self
+                    .context
+                    .symbol_interner
+                    .lookup(name)
+                    .as_str()
+                    .to_owned();
+                (name, value)
+            })
+            .collect())
+    }
+
+    /// Parse the given entry point, and print its AST for debugging.
+    pub fn ast<'a>(&mut self, entry: impl Into<Entry<'a>>) -> Result<String, CompileError> {
+        let entry = entry.into();
+        let (source, expr) = self.parse(entry)?;
+        self.forget(entry, source);
+
+        Ok(format!("{:?}", expr.resolved(&self.context)))
+    }
+
+    /// Supply an external argument, replacing any previous argument of the same name.
+    ///
+    /// Programs reach their arguments through the reserved `args` binding, a record with a field
+    /// per argument. When a program evaluates to a function, it is applied to that record instead.
+    pub fn arg(&mut self, name: impl Into<String>, value: Value) {
+        self.args.insert(name.into(), value);
+        self.queries.invalidate_globals();
+    }
+
+    /// Import a data file, relative to the base path, as `import "path"` would from an entry point.
+    pub fn import(&mut self, path: &str) -> Result<Value, CompileError> {
+        import::import_entry(&mut self.source_map, &mut self.queries, path)
+    }
+
+    /// Check the given entry point for errors, without evaluating it: Its imports are resolved,
+    /// and its types are checked.
+    pub fn check<'a>(&mut self, entry: impl Into<Entry<'a>>) -> Result<(), CompileError> {
+        self.type_of(entry).map(|_| ())
+    }
+
+    /// Infer the type of the given entry point, without evaluating it.
+    pub fn type_of<'a>(&mut self, entry: impl Into<Entry<'a>>) -> Result<Type, CompileError> {
+        let entry = entry.into();
+        let (source, expr) = self.parse(entry)?;
+        let result = self.check_parsed(source, &expr);
+        self.forget(entry, source);
+
+        result.map(|(_, ty)| ty)
+    }
+
+    /// Resolve the imports of a parsed source, and check its types.
+    fn check_parsed(
+        &mut self,
+        source: Fingerprint,
+        expr: &Expr,
+    ) -> Result<(Arc<Expr>, Type), CompileError> {
+        let expr = self.queries.resolve(&mut self.source_map, source, expr)?;
+        let code = self
+            .source_map
+            .get(source)
+            .expect("sources are loaded while they are compiled");
+
+        let args = self.context.symbol_interner.intern(Symbol::new("args"));
+        let (context, bindings) = (&self.context, &self.bindings);
+        let ty = self.queries.check(source, || {
+            checker(context, args, bindings, code)
+                .check(&expr)
+                .map_err(|errors| TypeErrors::new(code, errors))
+        })?;
+
+        Ok((expr, ty))
+    }
+
+    /// Evaluate the given entry point, with the external arguments supplied so far.
+    pub fn eval<'a>(&mut self, entry: impl Into<Entry<'a>>) -> Result<Value, CompileError> {
+        let entry = entry.into();
+        let (source, expr) = self.parse(entry)?;
+        let result = self.eval_parsed(source, &expr);
+        self.forget(entry, source);
+        result.map(|value| detach(entry, value))
+    }
+
+    fn eval_parsed(&mut self, source: Fingerprint, expr: &Expr) -> Result<Value, CompileError> {
+        let (expr, _) = self.check_parsed(source, expr)?;
+
+        let globals = self.globals();
+        let args = self.args_record();
+        let context = &self.context;
+        let value = self.queries.evaluate(source, || {
+            let value = eval::eval(context, globals, &expr)?;
+            match &*value.kind {
+                ValueKind::Function(fun) => {
+                    let value = (fun.body)(&args)?;
+                    value.force()?;
+                    Ok(value)
+                }
+                _ => Ok(value),
+            }
+        });
+
+        value.map_err(|err| self.runtime_error(err))
+    }
+
+    /// Give contract violations and merge conflicts the source code of the values they are about.
+    fn runtime_error(&self, err: RuntimeError) -> CompileError {
+        match err {
+            RuntimeError::Contract(violation) => {
+                let source = self
+                    .source_map
+                    .lookup(violation.value.lo())
+                    .expect("values are defined in loaded sources");
+                Box::new(ContractViolation::new(source, *violation)).into()
+            }
+            RuntimeError::Merge(conflict) => {
+                let source = self
+                    .source_map
+                    .lookup(conflict.right.lo())
+                    .expect("values are defined in loaded sources");
+                Box::new(MergeConflict::new(source, *conflict)).into()
+            }
+            RuntimeError::Located { error, span } => self.eval_error(*error, span),
+            RuntimeError::RecursiveField { span, .. } => self.eval_error(err, span),
+            RuntimeError::Unresolved(Unresolved { span, .. }) => self.eval_error(err, span),
+            err => err.into(),
         }
     }
 
-    pub fn compile(&mut self, entry: impl AsRef<str>) -> Result<(), CompileError> {
-        self._compile(entry.as_ref())
+    /// A runtime error located at `span`, with its source code if it is still loaded.
+    fn eval_error(&self, err: RuntimeError, span: Span) -> CompileError {
+        match self.source_map.lookup(span.lo()) {
+            Some(source) => Box::new(EvalError::new(source, err, span)).into(),
+            None => err.into(),
+        }
     }
 
-    fn _compile(&mut self, entry: &str) -> Result<(), CompileError> {
+    /// The loaded source containing a position, such as the start of the [span](Value::span) of a
+    /// value. Inline entry points are dropped once they are compiled, so the values they produce
+    /// have no provenance.
+    pub fn source_at(&self, pos: BytePos) -> Option<&Source> {
+        self.source_map.lookup(pos)
+    }
+
+    /// Load the files loaded so far again, so that the next compilations see their current
+    /// contents. Only the results that depend on files that changed are computed again.
+    ///
+    /// Returns the names of the files that changed, or could not be loaded anymore.
+    pub fn refresh(&mut self) -> Vec<String> {
+        self.source_map
+            .refresh()
+            .into_iter()
+            .map(|(source, name)| {
+                self.queries.evict(source);
+                name
+            })
+            .collect()
+    }
+
+    /// The names in scope at the top level of programs: The reserved `args`, then the names bound
+    /// by the host, which may shadow it.
+    fn globals(&mut self) -> Vec<(Interned<Symbol>, Value)> {
+        let args = self.context.symbol_interner.intern(Symbol::new("args"));
+
+        let mut globals = vec![(args, self.args_record())];
+        globals.extend(self.bindings.iter().cloned());
+        globals
+    }
+
+    fn args_record(&self) -> Value {
+        Value::record(self.args.clone())
+    }
+
+    /// Parse the given entry point, and print it back in its canonical format, with its comments
+    /// and blank lines.
+    /// Comments that the printer cannot place are an error, rather than dropped.
+    pub fn format<'a>(&mut self, entry: impl Into<Entry<'a>>) -> Result<String, CompileError> {
+        let entry = entry.into();
+        let (fingerprint, expr) = self.parse(entry)?;
+
+        let source = self
+            .source_map
+            .get(fingerprint)
+            .expect("parsed sources are loaded");
+        let syntax = parser::parse(&mut self.context, source).syntax;
+        let trivia = Trivia::new(source, &syntax);
+        let printed = printer::print(&self.context, &expr, &trivia);
+        self.forget(entry, fingerprint);
+
+        Ok(printed)
+    }
+
+    /// Parse source code into its syntax tree, keeping the tree and the errors found, for tools
+    /// that work on code being edited. Spans are in the global byte space, starting at the span of
+    /// the root node. The source is not kept, so other sources may later take the same spans.
+    pub fn parse_document(&mut self, name: &str, contents: &str) -> Result<Parsed, CompileError> {
+        let mut source = Source::new(contents.to_owned());
+        source.context_mut().extensions_mut().insert(EntryContext);
+
+        let source = self.source_map.add(name, source)?;
+        let parsed = parser::parse(&mut self.context, source);
+
+        let fingerprint = source.fingerprint();
+        self.source_map.remove(fingerprint);
+        Ok(parsed)
+    }
+
+    /// Check the types of source code being edited, which must parse without errors, and find the
+    /// types of its bindings. Imports are not resolved, so imported values are only checked during
+    /// evaluation.
+    pub fn check_document(
+        &mut self,
+        name: &str,
+        contents: &str,
+    ) -> Result<DocumentTypes, CompileError> {
+        let mut source = Source::new(contents.to_owned());
+        source.context_mut().extensions_mut().insert(EntryContext);
+        let source = self.source_map.add(name, source)?;
+        let parsed = parser::parse(&mut self.context, source);
+
+        let result = if parsed.errors.is_empty() {
+            let args = self.context.symbol_interner.intern(Symbol::new("args"));
+            checker(&self.context, args, &self.bindings, source)
+                .check_bindings(&parsed.expr)
+                .map(|(ty, bindings)| DocumentTypes {
+                    ty,
+                    bindings: bindings
+                        .into_iter()
+                        .map(|(span, ty)| (source.range(span), ty))
+                        .collect(),
+                })
+                .map_err(|errors| TypeErrors::new(source, errors).into())
+        } else {
+            Err(SyntaxErrors::new(name, source, parsed.errors).into())
+        };
+
+        let fingerprint = source.fingerprint();
+        self.source_map.remove(fingerprint);
+        result
+    }
+
+    /// Load and parse the given entry point.
+    fn parse(&mut self, entry: Entry) -> Result<(Fingerprint, Arc<Expr>), CompileError> {
         let mut source_cx = SourceContext::new();
         source_cx.extensions_mut().insert(EntryContext);
 
-        let source = self.source_map.load(&source_cx, entry)?;
+        let (name, source) = match entry {
+            Entry::File(name) => (name, self.source_map.load(&source_cx, name)?),
+            Entry::Inline { name, contents } => {
+                let mut source = Source::new(contents.to_owned());
+                *source.context_mut() = source_cx;
+                (name, self.source_map.add(name, source)?)
+            }
+        };
 
-        let ast = grammar::ExprParser::new().parse(source.lexer());
+        let fingerprint = source.fingerprint();
+        match self.queries.parse(&mut self.context, source) {
+            Ok(expr) => Ok((fingerprint, expr)),
+            Err(errors) => {
+                let errors = SyntaxErrors::new(name, source, errors);
+                self.forget(entry, fingerprint);
+                Err(errors.into())
+            }
+        }
+    }
 
-        let _ = dbg!(ast);
+    /// Drop an inline entry point, which is a new source every time, with its cached results,
+    /// once it is compiled.
+    fn forget(&mut self, entry: Entry, source: Fingerprint) {
+        if let Entry::Inline { .. } = entry {
+            self.queries.evict(source);
+            self.source_map.remove(source);
+        }
+    }
+}
 
-        Ok(())
+/// Drop the provenance and the record templates of a value produced by an inline entry point, which
+/// is forgotten once it is compiled, so that the value does not point into the span space of the
+/// sources loaded after it.
+fn detach(entry: Entry, value: Value) -> Value {
+    match entry {
+        Entry::Inline { .. } => value.detached(),
+        Entry::File(_) => value,
     }
 }
 
+/// A type checker for a source, with the names in scope at the top level of programs: The
+/// reserved `args`, whose fields are only known during evaluation, then the names bound by the
+/// host.
+fn checker<'a>(
+    cx: &'a Context,
+    args: Interned<Symbol>,
+    bindings: &[(Interned<Symbol>, Value)],
+    source: &'a Source,
+) -> Checker<'a> {
+    let mut checker = Checker::new(cx, source);
+    checker.bind_open_record(args);
+    checker.reserve(args);
+    for (name, value) in bindings {
+        checker.bind_value(*name, value);
+    }
+    checker
+}
+
 impl Default for Compiler {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    #[test]
+    fn inline_entries_load_files_from_the_base_path_or_lib_paths() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let golden = root.join("src/output/golden");
+        let entry = |contents| Entry::Inline {
+            name: "<test>",
+            contents,
+        };
+
+        let mut compiler = Compiler::with_loader(FileLoader::new(&golden));
+        let value = compiler.eval(entry(r#"import "data/defaults.json""#));
+        assert!(value.is_ok(), "{value:?}");
+
+        let mut compiler = Compiler::with_loader(FileLoader::new(root));
+        let value = compiler.eval(entry(r#"import "data/defaults.json""#));
+        assert!(matches!(value, Err(CompileError::Source(_))), "{value:?}");
+
+        let mut compiler = Compiler::with_loader(FileLoader::new(root).lib_path(&golden));
+        let value = compiler.eval(entry(r#"import "data/defaults.json""#));
+        assert!(value.is_ok(), "{value:?}");
+
+        // Each inline entry is a distinct source, even with the same name.
+        assert!(compiler.check(entry("1")).is_ok());
+        assert!(matches!(
+            compiler.check(entry("(")),
+            Err(CompileError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn std_modules_are_imported_without_files() {
+        let mut compiler = Compiler::new();
+        let entry = |contents| Entry::Inline {
+            name: "<test>",
+            contents,
+        };
+
+        let value = compiler.eval(entry(r#"(import "std:string").length "abc""#));
+        assert_eq!(format!("{:?}", value.unwrap()), "3");
+
+        let source = r#"import "std:nope""#;
+        let Err(err @ CompileError::Import(_)) = compiler.eval(entry(source)) else {
+            panic!("`{source}` imports a module");
+        };
+        assert_eq!(
+            err.to_string(),
+            "There is no module `std:nope` in the standard library"
+        );
+        assert_eq!(
+            err.help().unwrap().to_string(),
+            "The modules are `std:list`, `std:number`, `std:record`, `std:string`"
+        );
+    }
+
+    #[test]
+    fn arguments_are_bound_to_args() {
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/output/golden");
+        let mut compiler = Compiler::with_loader(FileLoader::new(golden));
+        let entry = |contents| Entry::Inline {
+            name: "<test>",
+            contents,
+        };
+
+        let value = compiler.eval(entry("args")).unwrap();
+        assert_eq!(format!("{value:?}"), "{}");
+
+        compiler.arg("env", Value::string("prod"));
+        let defaults = compiler.import("data/defaults.json").unwrap();
+        compiler.arg("defaults", defaults);
+        compiler.arg("env", Value::string("dev"));
+
+        let value = compiler.eval(entry("[args.env, args.defaults.replicas]"));
+        assert_eq!(format!("{:?}", value.unwrap()), r#"["dev", 2]"#);
+
+        let Err(CompileError::Type(errors)) = compiler.eval(entry("let args = 1 in args")) else {
+            panic!("`args` can be bound");
+        };
+        assert_eq!(
+            errors.errors()[0].to_string(),
+            "`args` is reserved, and cannot be bound"
+        );
+    }
+
+    #[test]
+    fn documents_have_the_types_of_their_bindings() {
+        let mut compiler = Compiler::new();
+        let contents = r#"let x = 1 + 2, r = { a = x, b = "b" } in r"#;
+        let types = compiler.check_document("<test>", contents).unwrap();
+        assert_eq!(types.ty.to_string(), "{a: Number, b: String}");
+
+        let bindings: Vec<_> = types
+            .bindings
+            .iter()
+            .map(|(range, ty)| format!("{} : {ty}", &contents[range.clone()]))
+            .collect();
+        assert_eq!(
+            bindings,
+            [
+                "x : Number",
+                "a : Number",
+                "b : String",
+                "r : {a: Number, b: String}"
+            ]
+        );
+    }
+
+    #[test]
+    fn contract_violations_point_at_the_value_and_the_contract() {
+        let mut compiler = Compiler::new();
+        let source = r#"let type Env = "dev" | "prod" in { env : Env = args.env }"#;
+        let entry = Entry::Inline {
+            name: "<test>",
+            contents: source,
+        };
+
+        compiler.arg("env", Value::string("dev"));
+        assert!(compiler.eval(entry).is_ok());
+
+        compiler.arg("env", Value::string("test"));
+        let Err(err @ CompileError::Contract(_)) = compiler.eval(entry) else {
+            panic!("`{source}` satisfies its contract");
+        };
+        assert_eq!(
+            err.to_string(),
+            r#"Expected one of "dev", "prod", found "test""#
+        );
+
+        let labels: Vec<_> = err
+            .labels()
+            .unwrap()
+            .map(|label| &source[label.offset()..label.offset() + label.len()])
+            .collect();
+        assert_eq!(labels, ["args.env", r#""dev" | "prod""#]);
+    }
+
+    #[test]
+    fn merge_conflicts_point_at_both_definitions() {
+        // Conflicts between values of known types are type errors, and values from `args` have
+        // none.
+        let mut compiler = Compiler::new();
+        compiler.arg("two", Value::number(2.into()));
+        let source = "let base = { a = { b = [1] } } in base // { a = { b = args.two } }";
+        let entry = Entry::Inline {
+            name: "<test>",
+            contents: source,
+        };
+
+        let Err(err @ CompileError::Merge(_)) = compiler.eval(entry) else {
+            panic!("`{source}` merges");
+        };
+        assert_eq!(err.to_string(), "Cannot merge a number into a list");
+        assert_eq!(
+            err.help().unwrap().to_string(),
+            "At `a.b` in the merged records. Bind the field with `:=` to replace its value instead"
+        );
+
+        let labels: Vec<_> = err
+            .labels()
+            .unwrap()
+            .map(|label| &source[label.offset()..label.offset() + label.len()])
+            .collect();
+        assert_eq!(labels, ["[1]", "args.two"]);
+    }
+
+    #[test]
+    fn runtime_errors_point_at_the_expression_that_raised_them() {
+        let cases = [
+            ("{ a = 1, b = { c = 1 / (2 - 2) } }", "1 / (2 - 2)"),
+            // Items of lists that natives make are evaluated later, on their behalf.
+            (
+                r#"let l = import "std:list", n = import "std:number" in
+                { a = l.map (n.div 1) [1, 0] }"#,
+                "l.map (n.div 1) [1, 0]",
+            ),
+        ];
+        for (source, expected) in cases {
+            let mut compiler = Compiler::new();
+            let entry = Entry::Inline {
+                name: "<test>",
+                contents: source,
+            };
+
+            let Err(err @ CompileError::Eval(_)) = compiler.eval(entry) else {
+                panic!("`{source}` evaluates without a located error");
+            };
+            assert_eq!(err.to_string(), "Division by zero");
+            let label = err.labels().unwrap().next().unwrap();
+            assert_eq!(
+                &source[label.offset()..label.offset() + label.len()],
+                expected
+            );
+        }
+    }
+}
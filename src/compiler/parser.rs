@@ -0,0 +1,421 @@
+use core::fmt;
+
+use lalrpop_util::{ErrorRecovery, ParseError};
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use super::{
+    ast::Expr,
+    context::Context,
+    grammar, lower,
+    source::{
+        lexer::{LexError, Token, TokenError},
+        Source,
+    },
+    syntax::{NodeKind, SyntaxNode},
+};
+
+/// The result of parsing a source.
+///
+/// Parsing always produces a syntax tree and an expression: Any part of the source that could not
+/// be parsed is replaced with an [`NodeKind::Error`] node in the tree, and an
+/// [`ExprKind::Error`](super::ast::ExprKind::Error) placeholder in the AST, so that both can still
+/// be inspected when there are errors.
+pub struct Parsed {
+    pub syntax: SyntaxNode,
+    pub expr: Expr,
+    pub errors: Vec<SyntaxError>,
+}
+
+pub fn parse(cx: &mut Context, source: &Source) -> Parsed {
+    let mut recovered = Vec::new();
+    let result = grammar::ExprParser::new().parse(&mut recovered, source.lexer());
+
+    let mut errors: Vec<_> = recovered
+        .into_iter()
+        .map(|ErrorRecovery { error, .. }| SyntaxError::new(source, error))
+        .collect();
+
+    let node = match result {
+        Ok(node) => node,
+        Err(error) => {
+            errors.push(SyntaxError::new(source, error));
+            SyntaxNode::empty(NodeKind::Error, source.span().lo())
+        }
+    };
+
+    let syntax = SyntaxNode::root(source, node);
+    let expr = lower::lower(cx, source, &syntax);
+
+    Parsed {
+        syntax,
+        expr,
+        errors,
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum SyntaxError {
+    #[error("Invalid token")]
+    InvalidToken {
+        #[label]
+        span: SourceSpan,
+    },
+
+    #[error("Unexpected end of file")]
+    #[diagnostic(help("Expected one of {expected}"))]
+    UnexpectedEof {
+        #[label]
+        span: SourceSpan,
+        expected: Expected,
+    },
+
+    #[error("Unexpected token")]
+    #[diagnostic(help("Expected one of {expected}"))]
+    UnexpectedToken {
+        #[label]
+        span: SourceSpan,
+        expected: Expected,
+    },
+
+    #[error("Extra token")]
+    ExtraToken {
+        #[label]
+        span: SourceSpan,
+    },
+
+    #[error("{error}")]
+    Token {
+        error: TokenError,
+
+        #[help]
+        help: Option<String>,
+
+        #[label]
+        span: SourceSpan,
+    },
+}
+
+impl SyntaxError {
+    fn new(source: &Source, error: ParseError<u32, Token<'_>, LexError>) -> Self {
+        match error {
+            ParseError::InvalidToken { location } => Self::InvalidToken {
+                span: source.local_span(location, location),
+            },
+            ParseError::UnrecognizedEof { location, expected } => Self::UnexpectedEof {
+                span: source.local_span(location, location),
+                expected: Expected(expected),
+            },
+            // Nothing is expected after the end of the expression.
+            ParseError::UnrecognizedToken {
+                token: (lo, _, hi),
+                expected,
+            } if expected.is_empty() => Self::ExtraToken {
+                span: source.local_span(lo, hi),
+            },
+            ParseError::UnrecognizedToken {
+                token: (lo, _, hi),
+                expected,
+            } => Self::UnexpectedToken {
+                span: source.local_span(lo, hi),
+                expected: Expected(expected),
+            },
+            ParseError::ExtraToken { token: (lo, _, hi) } => Self::ExtraToken {
+                span: source.local_span(lo, hi),
+            },
+            ParseError::User {
+                error: LexError { error, lo, hi },
+            } => Self::Token {
+                help: error.help().map(|help| help.to_string()),
+                error,
+                span: source.local_span(lo, hi),
+            },
+        }
+    }
+}
+
+/// The list of tokens the parser expected when encountering an error.
+#[derive(Debug)]
+pub struct Expected(Vec<String>);
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join(", "))
+    }
+}
+
+/// All the syntax errors found in a single source.
+#[derive(Debug, Error, Diagnostic)]
+#[error("Could not parse `{}`", .source_code.name())]
+pub struct SyntaxErrors {
+    #[source_code]
+    source_code: NamedSource,
+
+    #[related]
+    errors: Vec<SyntaxError>,
+}
+
+impl SyntaxErrors {
+    pub fn new(name: &str, source: &Source, errors: Vec<SyntaxError>) -> Self {
+        Self {
+            source_code: NamedSource::new(name, source.contents().to_owned()),
+            errors,
+        }
+    }
+
+    pub fn errors(&self) -> &[SyntaxError] {
+        &self.errors
+    }
+
+    /// Whether the source ended before the expression was complete, so that more input could
+    /// still make it valid.
+    pub fn is_incomplete(&self) -> bool {
+        self.errors
+            .iter()
+            .any(|error| matches!(error, SyntaxError::UnexpectedEof { .. }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::source::{SourceContext, SourceLoader, SourceMap};
+
+    /// Loads sources whose contents are their own name.
+    struct Verbatim;
+
+    #[derive(Debug, Error, Diagnostic)]
+    #[error("Unreachable")]
+    enum Never {}
+
+    impl SourceLoader for Verbatim {
+        type Key = String;
+        type Error = Never;
+
+        fn resolve(&mut self, _cx: &SourceContext, name: &str) -> Result<String, Never> {
+            Ok(name.to_owned())
+        }
+
+        fn load(&mut self, _cx: &SourceContext, key: &String) -> Result<Source, Never> {
+            Ok(Source::new(key.clone()))
+        }
+    }
+
+    fn parse_source(source: &str) -> (String, Vec<SyntaxError>) {
+        let mut source_map = SourceMap::new(Verbatim);
+        let source = source_map.load(&SourceContext::new(), source).unwrap();
+
+        let mut cx = Context::new();
+        let Parsed { expr, errors, .. } = super::parse(&mut cx, source);
+
+        (format!("{:?}", expr.resolved(&cx)), errors)
+    }
+
+    fn parse(source: &str) -> Result<String, Vec<SyntaxError>> {
+        match parse_source(source) {
+            (expr, errors) if errors.is_empty() => Ok(expr),
+            (_, errors) => Err(errors),
+        }
+    }
+
+    #[track_caller]
+    fn assert_parses(source: &str, expected: &str) {
+        assert_eq!(
+            parse(source)
+                .map_err(|errors| format!("{errors:?}"))
+                .as_deref(),
+            Ok(expected),
+            "source: `{source}`"
+        );
+    }
+
+    #[track_caller]
+    fn assert_recovers(source: &str, expected: &str, error_count: usize) {
+        let (expr, errors) = parse_source(source);
+        assert_eq!(expr, expected, "source: `{source}`");
+        assert_eq!(errors.len(), error_count, "source: `{source}`: {errors:?}");
+    }
+
+    #[test]
+    fn negation_is_a_prefix_operator() {
+        assert_parses("-1", "(Neg 1)");
+        assert_parses("- -a", "(Neg (Neg a))");
+        assert_parses("1-1", "((Sub 1) 1)");
+        assert_parses("a - -b", "((Sub a) (Neg b))");
+    }
+
+    #[test]
+    fn negation_binds_looser_than_application() {
+        assert_parses("-f 1", "(Neg (f 1))");
+        assert_parses("-f x y", "(Neg ((f x) y))");
+        assert_parses("f -1", "((Sub f) 1)");
+        assert_parses("f (-1)", "(f (Neg 1))");
+    }
+
+    #[test]
+    fn negation_binds_tighter_than_arithmetic() {
+        assert_parses("-a * b", "((Mul (Neg a)) b)");
+        assert_parses("a * -b", "((Mul a) (Neg b))");
+        assert_parses("-a + b", "((Add (Neg a)) b)");
+        assert_parses("a / -b + c", "((Add ((Div a) (Neg b))) c)");
+    }
+
+    #[test]
+    fn not_binds_looser_than_arithmetic() {
+        assert_parses("not a", "(Not a)");
+        assert_parses("not not a", "(Not (Not a))");
+        assert_parses("not a + b", "(Not ((Add a) b))");
+        assert_parses("not f x", "(Not (f x))");
+        assert_parses("not -a", "(Not (Neg a))");
+    }
+
+    #[test]
+    fn not_needs_parentheses_as_an_operand() {
+        assert!(parse("f not x").is_err());
+        assert!(parse("a + not b").is_err());
+        assert_parses("f (not x)", "(f (Not x))");
+        assert_parses("a + (not b)", "((Add a) (Not b))");
+    }
+
+    #[test]
+    fn compound_expressions() {
+        assert_parses("let a = 1, b = a in b", "(let [a = 1, b = a] b)");
+        assert_parses("{ a = 1, b = [1, 2], }", "{a = 1, b = [1, 2]}");
+        assert_parses("[]", "[]");
+        assert_parses(r#"{ "a/b.json" = 1 }"#, "{a/b.json = 1}");
+        assert!(parse(r#"let "a" = 1 in a"#).is_err());
+        assert_parses(
+            r#"["a\"b", import "c.json"]"#,
+            r#"["a\"b", (import "c.json")]"#,
+        );
+    }
+
+    #[test]
+    fn type_annotations() {
+        assert_parses(
+            "let type Port = Integer 1..65535, port : Port = 80 in port",
+            "(let [type Port = (Integer 1..65535), port = (80 : Port)] port)",
+        );
+        assert_parses(
+            r#"{ env : "dev" | -1 | null = "dev", type : String ~ "a" = "a" }"#,
+            r#"{env = ("dev" : ("dev" | -1 | null)), type = ("a" : (String ~ "a"))}"#,
+        );
+        assert_parses("(a : [{ b : Number, .. }])", "(a : [{b: Number, ..}])");
+        assert_parses("(a : Number ..-0.5)", "(a : (Number ..-1/2))");
+        assert!(parse("let type = 1 in 1").is_err());
+        assert!(parse("(a : 1..2)").is_err());
+    }
+
+    #[test]
+    fn merges() {
+        assert_parses("a // b // c", "((Merge ((Merge a) b)) c)");
+        assert_parses("a // b + c", "((Merge a) ((Add b) c))");
+        assert_parses("not a // b", "(Not ((Merge a) b))");
+        assert_parses(
+            "{ a := 1, b += [2], -c, d = 3 }",
+            "{a := 1, b += [2], d = 3, -c}",
+        );
+        assert!(parse("let a := 1 in a").is_err());
+        assert!(parse("[-a, 1]").is_ok());
+    }
+
+    #[test]
+    fn self_and_super() {
+        assert_parses(
+            "{ a = self.b, self = super.self }",
+            "{a = (self.b), self = (super.self)}",
+        );
+        assert_parses("f self super", "((f self) super)");
+        assert!(parse("let self = 1 in self").is_err());
+    }
+
+    #[test]
+    fn field_access_binds_tightest() {
+        assert_parses("a.b.c", "((a.b).c)");
+        assert_parses(r#"f a."b/c.json""#, "(f (a.b/c.json))");
+        assert_parses("-a.b", "(Neg (a.b))");
+        assert_parses("{ a = 1 }.a", "({a = 1}.a)");
+        assert_parses("(f x).a", "((f x).a)");
+        assert_parses("1.5.a", "(3/2.a)");
+    }
+
+    #[test]
+    fn invalid_escapes_are_errors() {
+        for source in [r#""\q""#, r#""\u{110000}""#, r#""\u{}""#, r#""\u{12""#] {
+            let (_, errors) = parse_source(source);
+            assert!(
+                matches!(
+                    errors[..],
+                    [SyntaxError::Token {
+                        error: TokenError::InvalidEscape,
+                        ..
+                    }]
+                ),
+                "source: `{source}`: {errors:?}"
+            );
+        }
+
+        let (_, errors) = parse_source(r#"[1, "a\qb"]"#);
+        let labels: Vec<_> = errors[0].labels().unwrap().collect();
+        assert_eq!(
+            (labels[0].offset(), labels[0].len()),
+            (4, 6),
+            "the label covers the string"
+        );
+        assert!(errors[0].help().is_some());
+    }
+
+    #[test]
+    fn recovers_within_lists() {
+        assert_recovers("[1, +, 3]", "[1, Error, 3]", 1);
+        assert_recovers("[*, 2, / 3]", "[Error, 2, Error]", 2);
+    }
+
+    #[test]
+    fn recovers_within_records() {
+        assert_recovers("{ a = 1, b = *, c = 3 }", "{a = 1, b = Error, c = 3}", 1);
+        assert_recovers("{ a 1, b = 2 }", "{b = 2}", 1);
+        assert_recovers(
+            "{ a = ), b = +, c = [1, *] }",
+            "{a = Error, b = Error, c = [1, Error]}",
+            3,
+        );
+    }
+
+    #[test]
+    fn recovers_within_let_bindings() {
+        assert_recovers("let a = +, b = 2 in b", "(let [a = Error, b = 2] b)", 1);
+        assert_recovers("let a = 1 2 3 in *", "Error", 1);
+    }
+
+    #[test]
+    fn unrecoverable_errors_produce_a_placeholder() {
+        let (expr, errors) = parse_source("1 +");
+        assert_eq!(expr, "Error");
+        assert!(matches!(errors[..], [SyntaxError::UnexpectedEof { .. }]));
+
+        let (expr, errors) = parse_source("{ a = + } }");
+        assert_eq!(expr, "Error");
+        assert!(matches!(
+            errors[..],
+            [
+                SyntaxError::UnexpectedToken { .. },
+                SyntaxError::ExtraToken { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn error_spans_are_local_to_the_source() {
+        let mut source_map = SourceMap::new(Verbatim);
+        let cx = &SourceContext::new();
+        source_map.load(cx, "first").unwrap();
+        let source = source_map.load(cx, "[1, +]").unwrap();
+
+        let errors = super::parse(&mut Context::new(), source).errors;
+        match &errors[..] {
+            [SyntaxError::UnexpectedToken { span, .. }] => assert_eq!(span, &(4..5).into()),
+            _ => panic!("unexpected errors: {errors:?}"),
+        }
+    }
+}
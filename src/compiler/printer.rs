@@ -0,0 +1,1101 @@
+use core::cell::RefCell;
+
+use logos::Logos;
+
+use crate::vm::value::{Value, ValueKind};
+
+use super::{
+    ast::{Binding, Call, Expr, ExprKind, Operator, TypeAlias, TypeExpr, TypeExprKind},
+    context::Context,
+    interner::Interned,
+    source::{lexer::Token, BytePos, Source, Span},
+    symbol::Symbol,
+    syntax::{SyntaxNode, TokenKind},
+};
+
+/// The line width the printer tries to fit its output in.
+const WIDTH: usize = 100;
+
+/// The number of spaces per indentation level.
+const INDENT: usize = 4;
+
+/// Print an expression as canonical dek source, with the comments and blank lines of the source it
+/// was parsed from.
+///
+/// Records, lists and `let` bindings are kept on a single line if they fit in the line width, and
+/// are otherwise split into one item per line, with a trailing comma. Their items keep their order
+/// in the source, and so do the comments between them: A comment at the end of the line an item
+/// ends on stays there, and other comments are put on their own lines, before the next item. Items
+/// with comments or blank lines between them are always split, and an item or comment keeps a
+/// blank line before it where the source has one. Comments before and after the whole expression
+/// are kept too. Comments within an item, or within the whole expression outside of its items, are
+/// attached to the innermost such item or expression that contains them: They are put on their
+/// own lines before it.
+///
+/// # Panics
+/// If the expression contains nodes with no source representation, which the parser never produces:
+/// Placeholders for syntax errors, non-scalar literals, and partially applied operators.
+pub fn print(cx: &Context, expr: &Expr, trivia: &Trivia) -> String {
+    let printer = Printer {
+        cx,
+        trivia,
+        printed: RefCell::new(vec![false; trivia.comments.len()]),
+    };
+
+    let mut docs = Vec::new();
+    let mut pos = None;
+    for comment in printer.take(BytePos::new(0), expr.span.lo()) {
+        printer.push(&mut docs, &mut pos, comment.span, Doc::comment(comment));
+    }
+    let doc = printer.expr(expr, Prec::Let);
+    printer.push_attached(&mut docs, &mut pos, expr.span, doc);
+    let mut trailing = printer
+        .take(expr.span.hi(), BytePos::new(u32::MAX))
+        .peekable();
+    if let Some(comment) = trailing.next_if(|comment| !comment.own_line) {
+        docs.push(Doc::trailing(comment));
+        pos = Some(comment.span.hi());
+    }
+    for comment in trailing {
+        printer.push(&mut docs, &mut pos, comment.span, Doc::comment(comment));
+    }
+
+    let mut out = render(&Doc::Concat(docs), WIDTH);
+    out.push('\n');
+    out
+}
+
+/// The comments and blank lines of a source, which the printer keeps.
+#[derive(Debug, Default)]
+pub struct Trivia {
+    /// The comments, in order.
+    pub comments: Vec<Comment>,
+
+    /// The start of each run of whitespace with a blank line in it, in order.
+    pub blank_lines: Vec<BytePos>,
+}
+
+/// A comment, without the line break that ends it.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub span: Span,
+    pub text: String,
+
+    /// Whether the comment is alone on its line, rather than at the end of a line of code.
+    pub own_line: bool,
+}
+
+impl Trivia {
+    /// The trivia of a source, from its syntax tree.
+    pub fn new(source: &Source, syntax: &SyntaxNode) -> Self {
+        let mut trivia = Self::default();
+        let mut own_line = true;
+        for token in syntax.descendant_tokens() {
+            let text = source.slice(token.span);
+            match token.kind {
+                TokenKind::Comment => trivia.comments.push(Comment {
+                    span: token.span,
+                    text: text.trim_end().to_owned(),
+                    own_line,
+                }),
+                TokenKind::Whitespace => {
+                    let breaks = text.matches('\n').count();
+                    if breaks > 1 {
+                        trivia.blank_lines.push(token.span.lo());
+                    }
+                    own_line |= breaks > 0;
+                }
+                _ => own_line = false,
+            }
+        }
+        trivia
+    }
+}
+
+/// The precedence levels of the grammar, from tightest to loosest binding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Atom,
+    Apply,
+    Neg,
+    Mul,
+    Add,
+    Merge,
+    Not,
+    Let,
+}
+
+impl Prec {
+    /// The loosest precedence that can appear on the right hand side of an operator of this
+    /// precedence, without parentheses.
+    fn tighter(self) -> Self {
+        match self {
+            Self::Atom | Self::Apply => Self::Atom,
+            Self::Neg => Self::Apply,
+            Self::Mul => Self::Neg,
+            Self::Add => Self::Mul,
+            Self::Merge => Self::Add,
+            Self::Not => Self::Merge,
+            Self::Let => Self::Not,
+        }
+    }
+}
+
+struct Printer<'cx> {
+    cx: &'cx Context,
+    trivia: &'cx Trivia,
+
+    /// Whether each comment was printed yet.
+    printed: RefCell<Vec<bool>>,
+}
+
+impl Printer<'_> {
+    /// Print an expression, wrapping it in parentheses if it binds looser than `max`.
+    fn expr(&self, expr: &Expr, max: Prec) -> Doc {
+        let (prec, doc) = self.expr_prec(expr);
+
+        if prec > max {
+            Doc::concat([Doc::text("("), doc, Doc::text(")")])
+        } else {
+            doc
+        }
+    }
+
+    fn expr_prec(&self, expr: &Expr) -> (Prec, Doc) {
+        match &expr.kind {
+            ExprKind::Literal(value) => (Prec::Atom, Doc::text(literal(value))),
+            ExprKind::Identifier(ident) => (Prec::Atom, Doc::text(self.name(ident.name))),
+            ExprKind::SelfRecord => (Prec::Atom, Doc::text("self")),
+            ExprKind::SuperRecord => (Prec::Atom, Doc::text("super")),
+            ExprKind::Call(call) => self.call(call),
+            ExprKind::Let(let_in) => {
+                let head = if let_in.bindings.is_empty() && let_in.types.is_empty() {
+                    Doc::text("let in")
+                } else {
+                    let aliases = let_in.types.iter().map(|alias| {
+                        let span = Span::new(alias.name.span.lo(), alias.ty.span.hi());
+                        (span, self.alias(alias))
+                    });
+                    let bindings = let_in
+                        .bindings
+                        .iter()
+                        .map(|binding| (binding_span(binding), self.binding(binding)));
+                    // Comments between the bindings and the body are kept before `in`.
+                    let span = Span::new(expr.span.lo(), let_in.body.span.lo());
+                    Doc::group([
+                        Doc::text("let"),
+                        Doc::nest([Doc::Line, self.items(span, aliases.chain(bindings), true)]),
+                        Doc::Line,
+                        Doc::text("in"),
+                    ])
+                };
+
+                let body = self.expr(&let_in.body, Prec::Let);
+                (Prec::Let, Doc::concat([head, Doc::text(" "), body]))
+            }
+            ExprKind::Record(record) => {
+                let fields = record.fields.iter().map(|binding| {
+                    let merge = record.merge(binding.name.name);
+                    let doc = self.binding_with(binding, merge.operator());
+                    (binding_span(binding), doc)
+                });
+                let deletions = record.deletions().map(|name| {
+                    let doc = Doc::text(format!("-{}", self.field_name(name.name)));
+                    (name.span, doc)
+                });
+                let fields = self.items(expr.span, fields.chain(deletions), true);
+                (Prec::Atom, delimited("{", fields, "}", Doc::Line))
+            }
+            ExprKind::List(list) => {
+                let items = list
+                    .items
+                    .iter()
+                    .map(|item| (item.span, self.expr(item, Prec::Let)));
+                let items = self.items(expr.span, items, true);
+                (Prec::Atom, delimited("[", items, "]", Doc::SoftLine))
+            }
+            ExprKind::Import(import) => {
+                let text = format!("import {}", quote(&import.path));
+                (Prec::Atom, Doc::text(text))
+            }
+            ExprKind::Field(field) => {
+                // A number followed by `.` could be read as a decimal point, as in `1._a`.
+                let record = match &field.record.kind {
+                    ExprKind::Literal(value) if matches!(*value.kind, ValueKind::Number(_)) => {
+                        Doc::concat([
+                            Doc::text("("),
+                            self.expr(&field.record, Prec::Let),
+                            Doc::text(")"),
+                        ])
+                    }
+                    _ => self.expr(&field.record, Prec::Atom),
+                };
+                let name = self.field_name(field.name.name);
+                (
+                    Prec::Atom,
+                    Doc::concat([record, Doc::text("."), Doc::text(name)]),
+                )
+            }
+            ExprKind::Annotated(annotated) => {
+                let doc = Doc::concat([
+                    Doc::text("("),
+                    self.expr(&annotated.expr, Prec::Let),
+                    Doc::text(" : "),
+                    self.ty(&annotated.ty),
+                    Doc::text(")"),
+                ]);
+                (Prec::Atom, doc)
+            }
+            ExprKind::Operator(_) => panic!("partially applied operators cannot be printed"),
+            ExprKind::Error | ExprKind::Todo => panic!("placeholders cannot be printed"),
+        }
+    }
+
+    fn call(&self, call: &Call) -> (Prec, Doc) {
+        // Unary operators
+        if let ExprKind::Operator(op @ (Operator::Neg | Operator::Not)) = call.fun.kind {
+            let (prec, op) = match op {
+                Operator::Neg => (Prec::Neg, "-"),
+                _ => (Prec::Not, "not "),
+            };
+
+            let operand = self.expr(&call.arg, prec);
+            return (prec, Doc::concat([Doc::text(op), operand]));
+        }
+
+        // Binary operators
+        if let ExprKind::Call(Call { fun, arg: left }) = &call.fun.kind {
+            if let ExprKind::Operator(
+                op @ (Operator::Add
+                | Operator::Sub
+                | Operator::Mul
+                | Operator::Div
+                | Operator::Merge),
+            ) = fun.kind
+            {
+                let (prec, op) = match op {
+                    Operator::Add => (Prec::Add, " + "),
+                    Operator::Sub => (Prec::Add, " - "),
+                    Operator::Mul => (Prec::Mul, " * "),
+                    Operator::Div => (Prec::Mul, " / "),
+                    _ => (Prec::Merge, " // "),
+                };
+
+                let left = self.expr(left, prec);
+                let right = self.expr(&call.arg, prec.tighter());
+                return (prec, Doc::concat([left, Doc::text(op), right]));
+            }
+        }
+
+        // Function application
+        let fun = self.expr(&call.fun, Prec::Apply);
+        let arg = self.expr(&call.arg, Prec::Atom);
+        (Prec::Apply, Doc::concat([fun, Doc::text(" "), arg]))
+    }
+
+    /// Print a binding, with the annotation of an annotated value before the `=`.
+    fn binding(&self, binding: &Binding) -> Doc {
+        self.binding_with(binding, "=")
+    }
+
+    /// Print a binding with the given operator in place of `=`, as record fields may have.
+    fn binding_with(&self, binding: &Binding, operator: &str) -> Doc {
+        let name = Doc::text(self.field_name(binding.name.name));
+        let operator = Doc::text(format!(" {operator} "));
+        match &binding.value.kind {
+            ExprKind::Annotated(annotated) => Doc::concat([
+                name,
+                Doc::text(" : "),
+                self.ty(&annotated.ty),
+                operator,
+                self.expr(&annotated.expr, Prec::Let),
+            ]),
+            _ => Doc::concat([name, operator, self.expr(&binding.value, Prec::Let)]),
+        }
+    }
+
+    fn alias(&self, alias: &TypeAlias) -> Doc {
+        Doc::concat([
+            Doc::text(format!("type {} = ", self.name(alias.name.name))),
+            self.ty(&alias.ty),
+        ])
+    }
+
+    fn ty(&self, ty: &TypeExpr) -> Doc {
+        match &ty.kind {
+            TypeExprKind::Name(name) => Doc::text(self.name(name.name)),
+            TypeExprKind::List(item) => {
+                Doc::concat([Doc::text("["), self.ty(item), Doc::text("]")])
+            }
+            TypeExprKind::Record(record) => {
+                let fields = record.fields.iter().map(|field| {
+                    let doc = Doc::concat([
+                        Doc::text(self.field_name(field.name.name)),
+                        Doc::text(" : "),
+                        self.ty(&field.ty),
+                    ]);
+                    (Span::new(field.name.span.lo(), field.ty.span.hi()), doc)
+                });
+                // `..` has no span of its own: It is the last thing in the record, and cannot be
+                // followed by a comma.
+                let end = Span::new(ty.span.hi(), ty.span.hi());
+                let rest = record.open.then(|| (end, Doc::text("..")));
+                let fields = self.items(ty.span, fields.chain(rest), !record.open);
+                delimited("{", fields, "}", Doc::Line)
+            }
+            TypeExprKind::Range(range) => {
+                let bound = |bound: &Option<_>| match bound {
+                    Some(bound) => literal(&Value::number(Clone::clone(bound))),
+                    None => String::new(),
+                };
+                Doc::concat([
+                    self.ty(&range.base),
+                    Doc::text(format!(" {}..{}", bound(&range.min), bound(&range.max))),
+                ])
+            }
+            TypeExprKind::Pattern(pattern) => Doc::concat([
+                self.ty(&pattern.base),
+                Doc::text(format!(" ~ {}", quote(&pattern.regex))),
+            ]),
+            TypeExprKind::Enum(values) => {
+                let values: Vec<_> = values.iter().map(literal).collect();
+                Doc::text(values.join(" | "))
+            }
+        }
+    }
+
+    /// Print the items of a record, list, record type or `let`, which are within `span`, in source
+    /// order, with the comments between them. Items are followed by a comma if they are split
+    /// over several lines, except for the last one if `last_comma` is false.
+    fn items(
+        &self,
+        span: Span,
+        items: impl IntoIterator<Item = (Span, Doc)>,
+        last_comma: bool,
+    ) -> Doc {
+        let mut items: Vec<_> = items.into_iter().collect();
+        items.sort_by_key(|(span, _)| span.lo());
+
+        let mut out = Vec::new();
+        let mut pos = None;
+        let mut gap = span.lo();
+        for (i, (span, doc)) in items.iter().enumerate() {
+            let mut comments = self.take(gap, span.lo()).peekable();
+            if i > 0 {
+                out.push(Doc::text(","));
+                if let Some(comment) = comments.next_if(|comment| !comment.own_line) {
+                    out.push(Doc::trailing(comment));
+                    pos = Some(comment.span.hi());
+                }
+            }
+            for comment in comments {
+                self.push(&mut out, &mut pos, comment.span, Doc::comment(comment));
+            }
+            self.push_attached(&mut out, &mut pos, *span, doc.clone());
+            gap = span.hi();
+        }
+
+        let mut comments = self.take(gap, span.hi()).peekable();
+        if !items.is_empty() {
+            if last_comma {
+                out.push(Doc::IfBreak(","));
+            }
+            if let Some(comment) = comments.next_if(|comment| !comment.own_line) {
+                out.push(Doc::trailing(comment));
+                pos = Some(comment.span.hi());
+            }
+        }
+        for comment in comments {
+            self.push(&mut out, &mut pos, comment.span, Doc::comment(comment));
+        }
+        Doc::Concat(out)
+    }
+
+    /// Push an item, a comment or the whole expression spanning `span`, on a new line if something
+    /// was pushed before, ending at `pos`. A blank line in the source between them is kept.
+    fn push(&self, out: &mut Vec<Doc>, pos: &mut Option<BytePos>, span: Span, doc: Doc) {
+        if let Some(pos) = *pos {
+            let blank_lines = &self.trivia.blank_lines;
+            if blank_lines
+                .iter()
+                .any(|&blank| pos <= blank && blank < span.lo())
+            {
+                out.push(Doc::BlankLine);
+            }
+            out.push(Doc::Line);
+        }
+        out.push(doc);
+        *pos = Some(span.hi());
+    }
+
+    /// Push an item or the whole expression spanning `span` like [`Self::push`], after the comments
+    /// within it that were not printed with its parts.
+    fn push_attached(&self, out: &mut Vec<Doc>, pos: &mut Option<BytePos>, span: Span, doc: Doc) {
+        for comment in self.take(span.lo(), span.hi()) {
+            self.push(out, pos, comment.span, Doc::comment(comment));
+        }
+        self.push(out, pos, span, doc);
+    }
+
+    /// The comments from `lo` to `hi` that were not printed yet, which are printed by the caller.
+    fn take(&self, lo: BytePos, hi: BytePos) -> impl Iterator<Item = &Comment> {
+        let mut printed = self.printed.borrow_mut();
+        let mut taken = Vec::new();
+        for (i, comment) in self.trivia.comments.iter().enumerate() {
+            if !printed[i] && lo <= comment.span.lo() && comment.span.hi() <= hi {
+                printed[i] = true;
+                taken.push(comment);
+            }
+        }
+        taken.into_iter()
+    }
+
+    fn name(&self, name: Interned<Symbol>) -> String {
+        self.cx.symbol_interner.lookup(name).as_str().to_owned()
+    }
+
+    fn field_name(&self, name: Interned<Symbol>) -> String {
+        field_name(&self.name(name))
+    }
+}
+
+/// The span of a binding, from its name to the end of its value.
+fn binding_span(binding: &Binding) -> Span {
+    Span::new(binding.name.span.lo(), binding.value.span.hi())
+}
+
+/// A scalar value, as written in literals.
+fn literal(value: &Value) -> String {
+    match &*value.kind {
+        ValueKind::Null => "null".to_owned(),
+        ValueKind::Boolean(boolean) => boolean.value.to_string(),
+        ValueKind::Number(number) => number
+            .to_decimal()
+            .expect("number literals have a decimal representation"),
+        ValueKind::String(string) => quote(&string.value),
+        ValueKind::Record(_) | ValueKind::List(_) | ValueKind::Function(_) => {
+            panic!("literals are scalars")
+        }
+    }
+}
+
+/// A binding name, quoted if it is not an identifier, as record fields may be. The `type`, `self`
+/// and `super` keywords are valid field names.
+pub(super) fn field_name(name: &str) -> String {
+    let mut tokens = Token::lexer(name);
+    let is_identifier = match tokens.next() {
+        Some(Ok(Token::Ident(ident))) => ident == name,
+        Some(Ok(Token::Type)) => name == "type",
+        Some(Ok(Token::SelfValue)) => name == "self",
+        Some(Ok(Token::Super)) => name == "super",
+        _ => false,
+    };
+
+    if is_identifier {
+        name.to_owned()
+    } else {
+        quote(name)
+    }
+}
+
+/// Surround the items with delimiters, in a group that breaks after the opening delimiter.
+fn delimited(open: &'static str, items: Doc, close: &'static str, padding: Doc) -> Doc {
+    if matches!(&items, Doc::Concat(items) if items.is_empty()) {
+        return Doc::concat([Doc::text(open), Doc::text(close)]);
+    }
+
+    Doc::group([
+        Doc::text(open),
+        Doc::nest([padding.clone(), items]),
+        padding,
+        Doc::text(close),
+    ])
+}
+
+/// A document to be laid out by the printer, in the style of Wadler's "prettier printer".
+#[derive(Debug, Clone)]
+enum Doc {
+    Text(String),
+    /// A space if the enclosing group fits on the line, otherwise a line break.
+    Line,
+    /// Nothing if the enclosing group fits on the line, otherwise a line break.
+    SoftLine,
+    /// Text that is only printed if the enclosing group does not fit on the line.
+    IfBreak(&'static str),
+    /// An empty line, which does not fit on the line of the enclosing groups.
+    BlankLine,
+    /// Nothing, but the enclosing groups do not fit on the line, as something in them must be
+    /// followed by a line break, such as a comment.
+    BreakParent,
+    Concat(Vec<Doc>),
+    /// Indent line breaks within the document by one level.
+    Nest(Box<Doc>),
+    /// A document that is laid out on a single line, if it fits.
+    Group(Box<Doc>),
+}
+
+impl Doc {
+    fn text(text: impl Into<String>) -> Self {
+        Self::Text(text.into())
+    }
+
+    fn concat(docs: impl IntoIterator<Item = Doc>) -> Self {
+        Self::Concat(docs.into_iter().collect())
+    }
+
+    fn nest(docs: impl IntoIterator<Item = Doc>) -> Self {
+        Self::Nest(Box::new(Self::concat(docs)))
+    }
+
+    fn group(docs: impl IntoIterator<Item = Doc>) -> Self {
+        Self::Group(Box::new(Self::concat(docs)))
+    }
+
+    /// A comment on its own line.
+    fn comment(comment: &Comment) -> Self {
+        Self::concat([Self::text(&comment.text), Self::BreakParent])
+    }
+
+    /// A comment at the end of a line.
+    fn trailing(comment: &Comment) -> Self {
+        Self::concat([
+            Self::text(" "),
+            Self::text(&comment.text),
+            Self::BreakParent,
+        ])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+fn render(doc: &Doc, width: usize) -> String {
+    let mut out = String::new();
+    let mut column = 0;
+
+    let mut stack = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => {
+                out.push_str(text);
+                column += text.chars().count();
+            }
+            Doc::Line | Doc::SoftLine if mode == Mode::Break => {
+                out.push('\n');
+                out.extend(core::iter::repeat_n(' ', indent));
+                column = indent;
+            }
+            Doc::Line => {
+                out.push(' ');
+                column += 1;
+            }
+            Doc::BlankLine if mode == Mode::Break => out.push('\n'),
+            Doc::SoftLine | Doc::BlankLine | Doc::BreakParent => {}
+            Doc::IfBreak(text) => {
+                if mode == Mode::Break {
+                    out.push_str(text);
+                    column += text.chars().count();
+                }
+            }
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            Doc::Nest(doc) => stack.push((indent + INDENT, mode, doc)),
+            Doc::Group(doc) => {
+                let flat = mode == Mode::Flat
+                    || fits(width.saturating_sub(column), doc, stack.iter().rev());
+                let mode = if flat { Mode::Flat } else { Mode::Break };
+                stack.push((indent, mode, doc));
+            }
+        }
+    }
+
+    out
+}
+
+/// Whether `doc` fits in the remaining width when laid out flat, along with whatever follows it up
+/// to the next line break.
+fn fits<'d>(
+    mut remaining: usize,
+    doc: &'d Doc,
+    mut rest: impl Iterator<Item = &'d (usize, Mode, &'d Doc)>,
+) -> bool {
+    let mut stack = vec![(Mode::Flat, doc)];
+    let mut in_rest = false;
+
+    loop {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => {
+                    in_rest = true;
+                    (mode, doc)
+                }
+                None => return true,
+            },
+        };
+
+        let text = match doc {
+            Doc::Text(text) => text.as_str(),
+            Doc::Line | Doc::SoftLine | Doc::BlankLine if mode == Mode::Break => return true,
+            Doc::Line => " ",
+            Doc::SoftLine => "",
+            Doc::IfBreak(text) if mode == Mode::Break => text,
+            Doc::IfBreak(_) => "",
+            // What follows the document starts on a new line.
+            Doc::BlankLine | Doc::BreakParent => return in_rest,
+            Doc::Concat(docs) => {
+                stack.extend(docs.iter().rev().map(|doc| (mode, doc)));
+                continue;
+            }
+            Doc::Nest(doc) | Doc::Group(doc) => {
+                stack.push((mode, doc));
+                continue;
+            }
+        };
+
+        match remaining.checked_sub(text.chars().count()) {
+            Some(left) => remaining = left,
+            None => return false,
+        }
+    }
+}
+
+/// Quote a string as a string literal, escaping it as needed.
+pub(super) fn quote(string: &str) -> String {
+    let mut out = String::with_capacity(string.len() + 2);
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use malachite::{num::arithmetic::traits::Pow, Rational};
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        compiler::{
+            ast::Identifier,
+            parser,
+            source::{BytePos, Source, Span},
+        },
+        vm::value::Value,
+    };
+
+    fn parse(cx: &mut Context, source: &str) -> Expr {
+        let parsed = parser::parse(cx, &Source::at(0, source));
+        assert!(
+            parsed.errors.is_empty(),
+            "cannot parse `{source}`: {:?}",
+            parsed.errors
+        );
+        parsed.expr
+    }
+
+    fn format(source: &str) -> String {
+        let mut cx = Context::new();
+        let source = Source::at(0, source);
+        let parsed = parser::parse(&mut cx, &source);
+        assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+        print(&cx, &parsed.expr, &Trivia::new(&source, &parsed.syntax))
+    }
+
+    #[test]
+    fn canonical_spacing() {
+        assert_eq!(format("{a=1,b=[1,2,],}"), "{ a = 1, b = [1, 2] }\n");
+        assert_eq!(format("let a=1,in f a(b)"), "let a = 1 in f a b\n");
+        assert_eq!(format("{ }"), "{}\n");
+        assert_eq!(format("0x10 + 1e-2"), "16 + 0.01\n");
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        assert_eq!(
+            format(r#"["a\"b\\c\n\u{1}\u{e9}é", import "data.json"]"#),
+            "[\"a\\\"b\\\\c\\n\\u{1}éé\", import \"data.json\"]\n"
+        );
+        assert_eq!(
+            format(r#"{ "a" = 1, "b c" = 2, "let" = 3, "" = 4 }"#),
+            "{ a = 1, \"b c\" = 2, \"let\" = 3, \"\" = 4 }\n"
+        );
+    }
+
+    #[test]
+    fn parentheses_follow_precedence() {
+        assert_eq!(format("((a + b)) * (c)"), "(a + b) * c\n");
+        assert_eq!(format("a - (b - c)"), "a - (b - c)\n");
+        assert_eq!(format("(a - b) - c"), "a - b - c\n");
+        assert_eq!(format("f (-1) (g x)"), "f (-1) (g x)\n");
+        assert_eq!(format("-(f x)"), "-f x\n");
+        assert_eq!(format("(not a) + 1"), "(not a) + 1\n");
+        assert_eq!(format("(let a = 1 in a) + 1"), "(let a = 1 in a) + 1\n");
+        assert_eq!(format("(f x).a.\"b c\""), "(f x).a.\"b c\"\n");
+        assert_eq!(format("(1)._a + (-1).b"), "(1)._a + (-1).b\n");
+    }
+
+    #[test]
+    fn long_records_are_broken() {
+        let long = "a_very_long_identifier_name";
+        let source = format!("{{ a = [{long}, {long}, {long}, {long}], b = {{ c = {long} }} }}");
+
+        assert_eq!(
+            format(&source),
+            "{\n    a = [\n        a_very_long_identifier_name,\n        a_very_long_identifier_name,\n        \
+            a_very_long_identifier_name,\n        a_very_long_identifier_name,\n    ],\n    b = { c = a_very_long_identifier_name },\n}\n",
+        );
+    }
+
+    #[test]
+    fn long_let_bindings_are_broken() {
+        let long = "a_very_long_identifier_name";
+        let source = format!("let a = {long}, b = {long}, c = {long} in [a, b, c]");
+
+        assert_eq!(
+            format(&source),
+            "let\n    a = a_very_long_identifier_name,\n    b = a_very_long_identifier_name,\n    \
+            c = a_very_long_identifier_name,\nin [a, b, c]\n",
+        );
+    }
+
+    #[test]
+    fn annotations_are_kept() {
+        assert_eq!(
+            format(r#"let type Env="dev"|"prod"|-1,port:Integer 1..65535=80 in {env:Env="dev"}"#),
+            "let type Env = \"dev\" | \"prod\" | -1, port : Integer 1..65535 = 80 in { env : Env = \"dev\" }\n",
+        );
+        assert_eq!(
+            format(r#"[(a : {b:[String ~ "^a"],"c d":Number ..0.5,..}), { type = 1 }]"#),
+            "[(a : { b : [String ~ \"^a\"], \"c d\" : Number ..0.5, .. }), { type = 1 }]\n",
+        );
+    }
+
+    #[test]
+    fn merges_are_kept() {
+        assert_eq!(
+            format("a//{b:={c=1},d+=[2],-e}//c"),
+            "a // { b := { c = 1 }, d += [2], -e } // c\n",
+        );
+        assert_eq!(format("(a // b) + (c // d)"), "(a // b) + (c // d)\n");
+        assert_eq!(format("a // (b // c)"), "a // (b // c)\n");
+        assert_eq!(
+            format("{self=self.a,\"super\"=super}"),
+            "{ self = self.a, super = super }\n"
+        );
+    }
+
+    #[test]
+    fn bindings_keep_their_order() {
+        assert_eq!(
+            format("a // { -b, c = 1, -d, e := 2 }"),
+            "a // { -b, c = 1, -d, e := 2 }\n"
+        );
+        assert_eq!(
+            format("let a = 1, type T = Number, b : T = a in b"),
+            "let a = 1, type T = Number, b : T = a in b\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = r#"# Leading
+let
+    type T = { b : Number, # After a field type
+    .. },
+    # Before a binding
+    a = 1, # After a binding
+    # Before `in`
+in
+{
+    b = [
+        1, # After an item
+        # Before an item
+        2,
+    ],
+    d = {
+        # Alone
+    },
+    # Before a deletion
+    -c,
+} # Trailing
+# Last
+"#;
+        let expected = r#"# Leading
+let
+    type T = {
+        b : Number, # After a field type
+        ..
+    },
+    # Before a binding
+    a = 1, # After a binding
+    # Before `in`
+in {
+    b = [
+        1, # After an item
+        # Before an item
+        2,
+    ],
+    d = {
+        # Alone
+    },
+    # Before a deletion
+    -c,
+} # Trailing
+# Last
+"#;
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn blank_lines_are_kept() {
+        let source = r#"# Header
+
+
+let
+
+    a = 1,
+
+
+    b = 2,
+in {
+
+    a = a,
+
+    # About b
+
+    b = b, c = [1,
+
+    2],
+}
+"#;
+        let expected = r#"# Header
+
+let
+    a = 1,
+
+    b = 2,
+in {
+    a = a,
+
+    # About b
+
+    b = b,
+    c = [
+        1,
+
+        2,
+    ],
+}
+"#;
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+    }
+
+    #[test]
+    fn comments_within_expressions_are_attached() {
+        let source = r#"{
+    a = 1 + # Within a field
+        2,
+    b = [(# Within an item
+        1)],
+}
+"#;
+        let expected = r#"{
+    # Within a field
+    a = 1 + 2,
+    b = [
+        # Within an item
+        1,
+    ],
+}
+"#;
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected), expected);
+
+        assert_eq!(format("1 + # Within\n2"), "# Within\n1 + 2\n");
+    }
+
+    /// Structural equality of expressions.
+    fn same(a: &Expr, b: &Expr) -> bool {
+        let same_bindings = |a: &[Binding], b: &[Binding]| {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|(a, b)| a.name.name == b.name.name && same(&a.value, &b.value))
+        };
+
+        match (&a.kind, &b.kind) {
+            (ExprKind::Literal(a), ExprKind::Literal(b)) => format!("{a:?}") == format!("{b:?}"),
+            (ExprKind::Identifier(a), ExprKind::Identifier(b)) => a.name == b.name,
+            (ExprKind::Call(a), ExprKind::Call(b)) => same(&a.fun, &b.fun) && same(&a.arg, &b.arg),
+            (ExprKind::Operator(a), ExprKind::Operator(b)) => a == b,
+            (ExprKind::Import(a), ExprKind::Import(b)) => a.path == b.path,
+            (ExprKind::Field(a), ExprKind::Field(b)) => {
+                a.name.name == b.name.name && same(&a.record, &b.record)
+            }
+            (ExprKind::Let(a), ExprKind::Let(b)) => {
+                same_bindings(&a.bindings, &b.bindings) && same(&a.body, &b.body)
+            }
+            (ExprKind::Record(a), ExprKind::Record(b)) => {
+                same_bindings(&a.fields, &b.fields)
+                    && a.merges.len() == b.merges.len()
+                    && a.merges
+                        .iter()
+                        .zip(&b.merges)
+                        .all(|((a, x), (b, y))| a.name == b.name && x == y)
+            }
+            (ExprKind::List(a), ExprKind::List(b)) => {
+                a.items.len() == b.items.len()
+                    && a.items.iter().zip(&b.items).all(|(a, b)| same(a, b))
+            }
+            _ => false,
+        }
+    }
+
+    /// A description of an expression, from which a strategy can be derived without a [`Context`].
+    #[derive(Debug, Clone)]
+    enum Tree {
+        Number(u64, u64),
+        Boolean(bool),
+        Null,
+        String(String),
+        Import(String),
+        Identifier(String),
+        Unary(Operator, Box<Tree>),
+        Binary(Operator, Box<Tree>, Box<Tree>),
+        Apply(Box<Tree>, Box<Tree>),
+        Let(Vec<(String, Tree)>, Box<Tree>),
+        Record(Vec<(String, Tree)>),
+        List(Vec<Tree>),
+        Field(Box<Tree>, String),
+    }
+
+    impl Tree {
+        fn build(self, cx: &mut Context) -> Expr {
+            let bindings = |cx: &mut Context, bindings: Vec<(String, Tree)>| {
+                bindings
+                    .into_iter()
+                    .map(|(name, value)| Binding {
+                        name: Identifier {
+                            name: cx.symbol_interner.intern(Symbol::new(name)),
+                            span: Span::new(BytePos::new(0), BytePos::new(0)),
+                        },
+                        value: value.build(cx),
+                    })
+                    .collect()
+            };
+
+            match self {
+                Tree::Number(mantissa, scale) => Expr::literal(Value::number(
+                    Rational::from(mantissa) / Rational::from(10).pow(scale),
+                )),
+                Tree::Boolean(value) => Expr::literal(Value::boolean(value)),
+                Tree::Null => Expr::literal(Value::null()),
+                Tree::String(value) => Expr::literal(Value::string(value)),
+                Tree::Import(path) => {
+                    Expr::import(path, Span::new(BytePos::new(0), BytePos::new(0)))
+                }
+                Tree::Identifier(name) => Expr::identifier(Identifier {
+                    name: cx.symbol_interner.intern(Symbol::new(name)),
+                    span: Span::new(BytePos::new(0), BytePos::new(0)),
+                }),
+                Tree::Unary(op, operand) => Expr::unary_op(op, operand.build(cx)),
+                Tree::Binary(op, l, r) => Expr::bin_op(op, l.build(cx), r.build(cx)),
+                Tree::Apply(fun, arg) => Expr::call(fun.build(cx), arg.build(cx)),
+                Tree::Let(b, body) => {
+                    let b = bindings(cx, b);
+                    Expr::let_in(b, body.build(cx))
+                }
+                Tree::Record(fields) => Expr::record(bindings(cx, fields)),
+                Tree::List(items) => Expr::list(items.into_iter().map(|i| i.build(cx)).collect()),
+                Tree::Field(record, name) => {
+                    let record = record.build(cx);
+                    Expr::field(
+                        record,
+                        Identifier {
+                            name: cx.symbol_interner.intern(Symbol::new(name)),
+                            span: Span::new(BytePos::new(0), BytePos::new(0)),
+                        },
+                    )
+                }
+            }
+        }
+    }
+
+    fn tree() -> impl Strategy<Value = Tree> {
+        let name = "[a-z_][a-z0-9_]{0,8}".prop_filter("keywords are not identifiers", |name| {
+            [
+                "let", "in", "import", "not", "and", "or", "true", "false", "null", "type", "self",
+                "super",
+            ]
+            .iter()
+            .all(|keyword| name != keyword)
+        });
+
+        let leaf = prop_oneof![
+            (any::<u64>(), 0..6u64).prop_map(|(m, s)| Tree::Number(m, s)),
+            any::<bool>().prop_map(Tree::Boolean),
+            Just(Tree::Null),
+            any::<String>().prop_map(Tree::String),
+            any::<String>().prop_map(Tree::Import),
+            name.clone().prop_map(Tree::Identifier),
+        ];
+
+        leaf.prop_recursive(6, 64, 8, move |inner| {
+            let bindings = prop::collection::vec((name.clone(), inner.clone()), 0..6);
+            let field_name = prop_oneof![name.clone(), any::<String>()];
+            let fields = prop::collection::vec((field_name.clone(), inner.clone()), 0..6);
+            let unary = prop_oneof![Just(Operator::Neg), Just(Operator::Not)];
+            let binary = prop_oneof![
+                Just(Operator::Add),
+                Just(Operator::Sub),
+                Just(Operator::Mul),
+                Just(Operator::Div),
+                Just(Operator::Merge),
+            ];
+
+            prop_oneof![
+                (unary, inner.clone()).prop_map(|(op, e)| Tree::Unary(op, Box::new(e))),
+                (binary, inner.clone(), inner.clone()).prop_map(|(op, l, r)| Tree::Binary(
+                    op,
+                    Box::new(l),
+                    Box::new(r)
+                )),
+                (inner.clone(), inner.clone())
+                    .prop_map(|(f, x)| Tree::Apply(Box::new(f), Box::new(x))),
+                (bindings.clone(), inner.clone()).prop_map(|(b, e)| Tree::Let(b, Box::new(e))),
+                fields.prop_map(Tree::Record),
+                (inner.clone(), field_name).prop_map(|(e, name)| Tree::Field(Box::new(e), name)),
+                prop::collection::vec(inner, 0..8).prop_map(Tree::List),
+            ]
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn printing_round_trips(tree in tree()) {
+            let mut cx = Context::new();
+            let expr = tree.build(&mut cx);
+
+            let printed = print(&cx, &expr, &Trivia::default());
+            let reparsed = parse(&mut cx, &printed);
+
+            prop_assert!(same(&expr, &reparsed), "{expr:?} printed as:\n{printed}");
+            prop_assert_eq!(print(&cx, &reparsed, &Trivia::default()), printed);
+        }
+    }
+}
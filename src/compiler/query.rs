@@ -0,0 +1,426 @@
+//! Incremental compilation.
+//!
+//! Compiling a source goes through stages: Parsing, resolving its imports, type checking, and
+//! evaluation. The result of each stage is cached by the [`Fingerprint`] of the source it was
+//! computed from, along with the fingerprints of the sources it depends on. A source changes its
+//! fingerprint when [`SourceMap::refresh`] finds that its contents changed, so cached results are
+//! used for as long as everything they depend on is unchanged: When an imported file changes, only
+//! that file is parsed again, and only the sources importing it are resolved and evaluated again.
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::vm::{value::Value, RuntimeError};
+
+use super::{
+    ast::Expr,
+    context::Context,
+    import,
+    parser::{self, SyntaxError},
+    source::{BytePos, Fingerprint, Source, SourceMap},
+    types::{Type, TypeErrors},
+    CompileError,
+};
+
+/// The cached results of each stage of the pipeline.
+#[derive(Default)]
+pub(super) struct Queries {
+    /// The ASTs of dek sources.
+    parsed: HashMap<Fingerprint, Arc<Expr>>,
+
+    /// The values of imported data files.
+    imported: HashMap<Fingerprint, Value>,
+
+    /// The ASTs of dek sources, with their imports resolved.
+    resolved: HashMap<Fingerprint, Memo<Arc<Expr>>>,
+
+    /// The types of dek sources.
+    checked: HashMap<Fingerprint, Memo<Type>>,
+
+    /// The values of dek sources.
+    evaluated: HashMap<Fingerprint, Memo<Value>>,
+
+    /// The revision of the names in scope at the top level of programs, which type checking and
+    /// evaluation depend on.
+    globals: u64,
+
+    /// The number of times each stage was run, rather than cached.
+    #[cfg(test)]
+    pub(super) runs: Runs,
+}
+
+#[cfg(test)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Runs {
+    pub parsed: usize,
+    pub imported: usize,
+    pub resolved: usize,
+    pub checked: usize,
+    pub evaluated: usize,
+}
+
+/// A cached result, with what it depends on besides its own source.
+struct Memo<T> {
+    /// The imports of the source.
+    deps: Vec<Dependency>,
+
+    /// The revision of the globals, for type checking and evaluation.
+    globals: u64,
+
+    value: T,
+}
+
+/// A source imported by another one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Dependency {
+    /// The position of the import, from which the path is resolved.
+    pub pos: BytePos,
+    pub path: String,
+
+    /// The fingerprint of the imported source, when it was imported.
+    pub fingerprint: Fingerprint,
+}
+
+impl Dependency {
+    /// Whether the import still refers to the same source, with the same contents.
+    fn is_current(&self, source_map: &mut SourceMap) -> bool {
+        match source_map.load_relative(self.pos, &self.path) {
+            Ok(source) => source.fingerprint() == self.fingerprint,
+            Err(_) => false,
+        }
+    }
+}
+
+impl Queries {
+    /// Forget the results computed from a source that was replaced or removed. The results that
+    /// depend on it are recomputed when they are next needed, since its replacement has a
+    /// different fingerprint.
+    pub fn evict(&mut self, source: Fingerprint) {
+        self.parsed.remove(&source);
+        self.imported.remove(&source);
+        self.resolved.remove(&source);
+        self.checked.remove(&source);
+        self.evaluated.remove(&source);
+    }
+
+    /// Invalidate the cached types and evaluations, after the names in scope at the top level
+    /// changed.
+    pub fn invalidate_globals(&mut self) {
+        self.globals += 1;
+    }
+
+    pub fn parse(
+        &mut self,
+        cx: &mut Context,
+        source: &Source,
+    ) -> Result<Arc<Expr>, Vec<SyntaxError>> {
+        if let Some(expr) = self.parsed.get(&source.fingerprint()) {
+            return Ok(expr.clone());
+        }
+
+        #[cfg(test)]
+        {
+            self.runs.parsed += 1;
+        }
+
+        let parsed = parser::parse(cx, source);
+        if !parsed.errors.is_empty() {
+            return Err(parsed.errors);
+        }
+
+        let expr = Arc::new(parsed.expr);
+        self.parsed.insert(source.fingerprint(), expr.clone());
+        Ok(expr)
+    }
+
+    /// The value of an imported data file, parsed by `parse`.
+    pub fn import<E>(
+        &mut self,
+        source: &Source,
+        parse: impl FnOnce(&Source) -> Result<Value, E>,
+    ) -> Result<Value, E> {
+        if let Some(value) = self.imported.get(&source.fingerprint()) {
+            return Ok(value.clone());
+        }
+
+        #[cfg(test)]
+        {
+            self.runs.imported += 1;
+        }
+
+        let value = parse(source)?;
+        self.imported.insert(source.fingerprint(), value.clone());
+        Ok(value)
+    }
+
+    /// The AST of a source, parsed as `expr`, with its imports resolved.
+    pub fn resolve(
+        &mut self,
+        source_map: &mut SourceMap,
+        source: Fingerprint,
+        expr: &Expr,
+    ) -> Result<Arc<Expr>, CompileError> {
+        if let Some(memo) = self.resolved.get(&source) {
+            if memo.deps.iter().all(|dep| dep.is_current(source_map)) {
+                return Ok(memo.value.clone());
+            }
+        }
+
+        #[cfg(test)]
+        {
+            self.runs.resolved += 1;
+        }
+
+        let mut expr = expr.clone();
+        let mut deps = Vec::new();
+        import::resolve(source_map, self, &mut deps, &mut expr)?;
+
+        let expr = Arc::new(expr);
+        let memo = Memo {
+            deps,
+            globals: self.globals,
+            value: expr.clone(),
+        };
+        self.resolved.insert(source, memo);
+        Ok(expr)
+    }
+
+    /// The type of a source, inferred by `check`. The source must have been
+    /// [resolved](Self::resolve) just before.
+    pub fn check(
+        &mut self,
+        source: Fingerprint,
+        check: impl FnOnce() -> Result<Type, TypeErrors>,
+    ) -> Result<Type, TypeErrors> {
+        let deps = self.resolved_deps(source);
+        if let Some(memo) = self.checked.get(&source) {
+            if memo.globals == self.globals && memo.deps == *deps {
+                return Ok(memo.value.clone());
+            }
+        }
+
+        let deps = deps.clone();
+
+        #[cfg(test)]
+        {
+            self.runs.checked += 1;
+        }
+
+        let ty = check()?;
+        let memo = Memo {
+            deps,
+            globals: self.globals,
+            value: ty.clone(),
+        };
+        self.checked.insert(source, memo);
+        Ok(ty)
+    }
+
+    /// The value of a source, computed by `eval`. The source must have been
+    /// [resolved](Self::resolve) just before.
+    pub fn evaluate(
+        &mut self,
+        source: Fingerprint,
+        eval: impl FnOnce() -> Result<Value, RuntimeError>,
+    ) -> Result<Value, RuntimeError> {
+        let deps = self.resolved_deps(source);
+
+        if let Some(memo) = self.evaluated.get(&source) {
+            if memo.globals == self.globals && memo.deps == *deps {
+                return Ok(memo.value.clone());
+            }
+        }
+
+        let deps = deps.clone();
+
+        #[cfg(test)]
+        {
+            self.runs.evaluated += 1;
+        }
+
+        let value = eval()?;
+        let memo = Memo {
+            deps,
+            globals: self.globals,
+            value: value.clone(),
+        };
+        self.evaluated.insert(source, memo);
+        Ok(value)
+    }
+
+    /// The imports of a source, as of the last time it was resolved.
+    fn resolved_deps(&self, source: Fingerprint) -> &Vec<Dependency> {
+        &self
+            .resolved
+            .get(&source)
+            .expect("sources are resolved before they are checked or evaluated")
+            .deps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use miette::Diagnostic;
+    use thiserror::Error;
+
+    use super::*;
+    use crate::compiler::{
+        source::{SourceContext, SourceLoader},
+        Compiler, Entry,
+    };
+
+    /// Loads files from memory, where the test can change them.
+    #[derive(Clone, Default)]
+    struct Files(Arc<Mutex<HashMap<String, String>>>);
+
+    #[derive(Debug, Error, Diagnostic)]
+    #[error("No file `{0}`")]
+    struct Missing(String);
+
+    impl Files {
+        fn write(&self, name: &str, contents: &str) {
+            let mut files = self.0.lock().unwrap();
+            files.insert(name.to_owned(), contents.to_owned());
+        }
+
+        fn remove(&self, name: &str) {
+            self.0.lock().unwrap().remove(name);
+        }
+    }
+
+    impl SourceLoader for Files {
+        type Key = String;
+        type Error = Missing;
+
+        fn resolve(&mut self, _cx: &SourceContext, name: &str) -> Result<String, Missing> {
+            Ok(name.to_owned())
+        }
+
+        fn load(&mut self, _cx: &SourceContext, key: &String) -> Result<Source, Missing> {
+            match self.0.lock().unwrap().get(key) {
+                Some(contents) => Ok(Source::new(contents.clone())),
+                None => Err(Missing(key.clone())),
+            }
+        }
+    }
+
+    #[track_caller]
+    fn assert_eval(compiler: &mut Compiler, name: &str, expected: &str, runs: [usize; 5]) {
+        let before = compiler.queries.runs;
+        let value = compiler.eval(name).unwrap();
+        assert_eq!(format!("{value:?}"), expected, "entry: {name}");
+
+        let after = compiler.queries.runs;
+        let ran = [
+            after.parsed - before.parsed,
+            after.imported - before.imported,
+            after.resolved - before.resolved,
+            after.checked - before.checked,
+            after.evaluated - before.evaluated,
+        ];
+        assert_eq!(
+            ran, runs,
+            "stages run for {name}: parse, import, resolve, check, evaluate"
+        );
+    }
+
+    #[test]
+    fn only_what_depends_on_changes_is_recomputed() {
+        let files = Files::default();
+        files.write(
+            "main.dek",
+            r#"{ a = import "a.json", b = import "b.json" }"#,
+        );
+        files.write("other.dek", r#"[import "a.json"]"#);
+        files.write("a.json", "1");
+        files.write("b.json", "2");
+
+        let mut compiler = Compiler::with_loader(files.clone());
+        assert_eval(&mut compiler, "main.dek", "{a = 1, b = 2}", [1, 2, 1, 1, 1]);
+        assert_eval(&mut compiler, "main.dek", "{a = 1, b = 2}", [0, 0, 0, 0, 0]);
+        assert_eval(&mut compiler, "other.dek", "[1]", [1, 0, 1, 1, 1]);
+
+        // Changes are only seen after a refresh.
+        files.write("b.json", "3");
+        assert_eval(&mut compiler, "main.dek", "{a = 1, b = 2}", [0, 0, 0, 0, 0]);
+        assert_eq!(compiler.refresh(), ["b.json"]);
+        assert_eval(&mut compiler, "main.dek", "{a = 1, b = 3}", [0, 1, 1, 1, 1]);
+        assert_eval(&mut compiler, "other.dek", "[1]", [0, 0, 0, 0, 0]);
+
+        // Rewriting a file with the same contents changes nothing.
+        files.write("a.json", "1");
+        assert_eq!(compiler.refresh(), Vec::<String>::new());
+
+        files.write("main.dek", r#"{ b = import "b.json" }"#);
+        assert_eq!(compiler.refresh(), ["main.dek"]);
+        assert_eval(&mut compiler, "main.dek", "{b = 3}", [1, 0, 1, 1, 1]);
+
+        // Evaluations depend on the names in scope.
+        compiler.arg("env", Value::string("dev"));
+        assert_eval(&mut compiler, "main.dek", "{b = 3}", [0, 0, 0, 1, 1]);
+
+        files.remove("a.json");
+        assert_eq!(compiler.refresh(), ["a.json"]);
+        assert!(matches!(
+            compiler.eval("other.dek"),
+            Err(CompileError::Source(_))
+        ));
+
+        files.write("a.json", "4");
+        assert_eval(&mut compiler, "other.dek", "[4]", [0, 1, 1, 1, 1]);
+    }
+
+    #[test]
+    fn inline_entries_are_not_cached() {
+        let files = Files::default();
+        files.write("a.json", "1");
+
+        let mut compiler = Compiler::with_loader(files);
+        for _ in 0..3 {
+            compiler
+                .eval(Entry::Inline {
+                    name: "<test>",
+                    contents: r#"import "a.json""#,
+                })
+                .unwrap();
+        }
+
+        let queries = &compiler.queries;
+        assert_eq!(queries.runs.imported, 1);
+        assert_eq!(queries.parsed.len(), 0);
+        assert_eq!(queries.resolved.len(), 0);
+        assert_eq!(queries.checked.len(), 0);
+        assert_eq!(queries.evaluated.len(), 0);
+    }
+
+    #[test]
+    fn reloading_does_not_accumulate_sources_or_results() {
+        let files = Files::default();
+        files.write("main.dek", r#"{ a = import "a.json" }"#);
+        files.write("a.json", "0");
+
+        let mut compiler = Compiler::with_loader(files.clone());
+        for i in 1..=2000 {
+            files.write("a.json", &i.to_string());
+            compiler.refresh();
+
+            let value = compiler.eval("main.dek").unwrap();
+            assert_eq!(format!("{value:?}"), format!("{{a = {i}}}"));
+
+            let inline = Entry::Inline {
+                name: "<test>",
+                contents: r#"import "a.json""#,
+            };
+            assert!(compiler.check(inline).is_ok());
+            assert!(compiler.parse_document("<document>", "[").is_ok());
+        }
+
+        assert_eq!(compiler.source_map.source_count(), 2);
+        let queries = &compiler.queries;
+        assert_eq!(queries.imported.len(), 1);
+        assert_eq!(queries.resolved.len(), 1);
+        assert_eq!(queries.checked.len(), 1);
+        assert_eq!(queries.evaluated.len(), 1);
+    }
+}
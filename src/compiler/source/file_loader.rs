@@ -13,17 +13,28 @@ use super::{Source, SourceContext, SourceLoader};
 pub struct FileLoader {
     /// The base path from where all file accesses will be resolved.
     base_path: PathBuf,
+
+    /// The directories searched for files that are not found relative to the file loading them.
+    lib_paths: Vec<PathBuf>,
 }
 
 impl FileLoader {
     pub fn new(base_path: impl AsRef<Path>) -> Self {
         Self {
             base_path: base_path.as_ref().to_owned(),
+            lib_paths: Vec::new(),
         }
     }
+
+    /// Add a library directory. Library directories are searched in the order they were added.
+    pub fn lib_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.lib_paths.push(path.as_ref().to_owned());
+        self
+    }
 }
 
-/// The context attached to files loaded with a [`FileLoader`]
+/// The context attached to files loaded with a [`FileLoader`]. Files loaded from the context of
+/// another file are resolved relative to the directory containing it.
 #[derive(Debug)]
 pub struct FileContext {
     path: PathBuf,
@@ -52,15 +63,27 @@ impl SourceLoader for FileLoader {
         let mut base = None;
 
         if let Some(cx) = cx.extensions().get::<FileContext>() {
-            base = Some(&cx.path);
+            base = cx.path.parent();
         }
 
         if let Some(_cx) = cx.extensions().get::<EntryContext>() {
-            base = Some(&self.base_path);
+            base = Some(self.base_path.as_path());
         }
 
         let base = base.ok_or(FileLoaderError::InvalidContext)?;
-        Ok(base.join(name))
+        let path = base.join(name);
+        if path.exists() {
+            return Ok(path);
+        }
+
+        // Fall back to the library directories, but report missing files relative to the base.
+        let lib_path = self
+            .lib_paths
+            .iter()
+            .map(|lib_path| lib_path.join(name))
+            .find(|path| path.exists());
+
+        Ok(lib_path.unwrap_or(path))
     }
 
     fn load(&mut self, _cx: &SourceContext, key: &Self::Key) -> Result<Source, Self::Error> {
@@ -69,6 +92,12 @@ impl SourceLoader for FileLoader {
             path: key.clone(),
         })?;
 
-        Ok(Source::new(contents))
+        let mut source = Source::new(contents);
+        source
+            .context_mut()
+            .extensions_mut()
+            .insert(FileContext { path: key.clone() });
+
+        Ok(source)
     }
 }
@@ -9,6 +9,7 @@ use thiserror::Error;
 #[derive(Debug, Clone, Logos)]
 #[logos(error = TokenError)]
 #[logos(skip r"[ \t\r\n\f]+")]
+#[logos(skip r"#[^\n]*")]
 pub enum Token<'s> {
     #[token("=")]
     Assign,
@@ -16,6 +17,12 @@ pub enum Token<'s> {
     #[token("==")]
     Equals,
 
+    #[token(":=")]
+    ColonAssign,
+
+    #[token("+=")]
+    PlusAssign,
+
     #[token("+")]
     Plus,
 
@@ -28,9 +35,24 @@ pub enum Token<'s> {
     #[token("/")]
     Slash,
 
+    #[token("//")]
+    SlashSlash,
+
     #[token(".")]
     Dot,
 
+    #[token("..")]
+    DotDot,
+
+    #[token(":")]
+    Colon,
+
+    #[token("|")]
+    Pipe,
+
+    #[token("~")]
+    Tilde,
+
     #[token(",")]
     Comma,
 
@@ -58,6 +80,18 @@ pub enum Token<'s> {
     #[token("in")]
     In,
 
+    #[token("import")]
+    Import,
+
+    #[token("type")]
+    Type,
+
+    #[token("self")]
+    SelfValue,
+
+    #[token("super")]
+    Super,
+
     #[token("not")]
     Not,
 
@@ -92,16 +126,30 @@ pub enum Token<'s> {
         ignore(ascii_case)
     )]
     #[regex( // Decimal (with scientific notation)
-        r#"[+-]?[0-9][0-9]*(\.[0-9_]+)?([eE][+-]?[0-9_]+)?"#,
+        r#"[0-9][0-9]*(\.[0-9_]+)?([eE][+-]?[0-9_]+)?"#,
         |lex| parse_number(lex.slice(), 10),
         ignore(ascii_case)
     )]
     Number(Rational),
 
+    #[regex(r#""([^"\\\n]|\\[^\n])*""#, |lex| parse_string(lex.slice()))]
+    String(String),
+
     #[regex(r#"[a-z_][a-z0-9_]*"#, ignore(ascii_case))]
     Ident(&'s str),
 }
 
+/// The text skipped by the [`Lexer`] between tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Logos)]
+pub enum Trivia {
+    #[regex(r"[ \t\r\n\f]+")]
+    Whitespace,
+
+    /// A line comment, starting with `#`.
+    #[regex(r"#[^\n]*")]
+    Comment,
+}
+
 #[derive(Debug, Clone, Error, Diagnostic, Default)]
 pub enum TokenError {
     #[default]
@@ -110,6 +158,12 @@ pub enum TokenError {
 
     #[error("Invalid number literal")]
     InvalidNumber,
+
+    #[error("Invalid escape sequence in string literal")]
+    #[diagnostic(help(
+        r#"The supported escapes are `\"`, `\\`, `\n`, `\r`, `\t` and `\u{{...}}`"#
+    ))]
+    InvalidEscape,
 }
 
 impl PartialEq for TokenError {
@@ -120,13 +174,21 @@ impl PartialEq for TokenError {
 
 impl Eq for TokenError {}
 
+/// A [`TokenError`], with the span of the text it is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexError {
+    pub error: TokenError,
+    pub lo: u32,
+    pub hi: u32,
+}
+
 pub struct Lexer<'s> {
     span_offset: u32,
     inner: logos::Lexer<'s, Token<'s>>,
 }
 
 impl<'s> Lexer<'s> {
-    pub(super) fn new(span_offset: u32, source: &'s str) -> Self {
+    pub(crate) fn new(span_offset: u32, source: &'s str) -> Self {
         Self {
             span_offset,
             inner: Token::lexer(source),
@@ -135,19 +197,20 @@ impl<'s> Lexer<'s> {
 }
 
 impl<'s> Iterator for Lexer<'s> {
-    type Item = Result<(u32, Token<'s>, u32), TokenError>;
+    type Item = Result<(u32, Token<'s>, u32), LexError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let token = self.inner.next()?;
         let span = self.inner.span();
-
-        Some(token.map(|token| {
-            (
-                self.span_offset + span.start as u32,
-                token,
-                self.span_offset + span.end as u32,
-            )
-        }))
+        let (lo, hi) = (
+            self.span_offset + span.start as u32,
+            self.span_offset + span.end as u32,
+        );
+
+        Some(match token {
+            Ok(token) => Ok((lo, token, hi)),
+            Err(error) => Err(LexError { error, lo, hi }),
+        })
     }
 }
 
@@ -159,3 +222,41 @@ fn parse_number(number: &str, base: u8) -> Result<Rational, TokenError> {
 
     Rational::from_sci_string_with_options(&number, options).ok_or(TokenError::InvalidNumber)
 }
+
+/// Parse a string literal, quotes included, resolving its escape sequences.
+fn parse_string(literal: &str) -> Result<String, TokenError> {
+    let mut string = String::with_capacity(literal.len());
+
+    let mut chars = literal[1..literal.len() - 1].chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            string.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('"') => string.push('"'),
+            Some('\\') => string.push('\\'),
+            Some('n') => string.push('\n'),
+            Some('r') => string.push('\r'),
+            Some('t') => string.push('\t'),
+            Some('u') => {
+                let rest = chars.as_str();
+                let (code, rest) = rest
+                    .strip_prefix('{')
+                    .and_then(|rest| rest.split_once('}'))
+                    .ok_or(TokenError::InvalidEscape)?;
+
+                let c = u32::from_str_radix(code, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or(TokenError::InvalidEscape)?;
+                string.push(c);
+                chars = rest.chars();
+            }
+            _ => return Err(TokenError::InvalidEscape),
+        }
+    }
+
+    Ok(string)
+}
@@ -5,22 +5,57 @@ pub use file_loader::FileLoader;
 mod source_map;
 pub use source_map::SourceMap;
 
-use core::{fmt, hash::Hash};
-use miette::Diagnostic;
+use core::{
+    fmt,
+    hash::{Hash, Hasher},
+    ops::Range,
+};
+use miette::{Diagnostic, SourceSpan};
+use std::hash::DefaultHasher;
 use thiserror::Error;
 use type_map::concurrent::TypeMap;
 
 use self::lexer::Lexer;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+/// A position in the global byte space shared by all the sources of a [`SourceMap`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BytePos(u32);
 
-#[derive(PartialEq, Eq)]
+impl BytePos {
+    pub const fn new(pos: u32) -> Self {
+        Self(pos)
+    }
+
+    pub const fn to_u32(self) -> u32 {
+        self.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
 pub struct Span {
     lo: BytePos,
     hi: BytePos,
 }
 
+impl Span {
+    pub fn new(lo: BytePos, hi: BytePos) -> Self {
+        debug_assert!(lo <= hi);
+        Self { lo, hi }
+    }
+
+    pub fn lo(&self) -> BytePos {
+        self.lo
+    }
+
+    pub fn hi(&self) -> BytePos {
+        self.hi
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo == self.hi
+    }
+}
+
 impl fmt::Debug for Span {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}..{}", self.lo.0, self.hi.0)
@@ -43,27 +78,60 @@ pub enum SourceError {
     Loader(#[from] Box<dyn Diagnostic + Send + Sync>),
 }
 
+/// The identity of a version of a source: Sources are never modified, but when the contents of a
+/// loaded source change, the [`SourceMap`] replaces it with a new source, at a new position.
+///
+/// Positions are reused once sources are removed, and a source with the same contents, loaded from
+/// elsewhere, may take the same position as one that was removed. The generation of the source
+/// tells them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    start: BytePos,
+    generation: u64,
+    hash: u64,
+}
+
 #[derive(Debug)]
 pub struct Source {
+    /// The name the source was loaded by.
+    name: String,
+
     /// The contents of the source.
     contents: String,
 
+    /// A hash of the contents, to tell whether they changed when the source is loaded again.
+    hash: u64,
+
     /// The starting position for measuring spans.
     start_pos: BytePos,
 
+    /// The number of sources inserted in the source map before this one.
+    generation: u64,
+
     /// The context this source was loaded under.
     context: SourceContext,
 }
 
 impl Source {
     pub fn new(contents: String) -> Self {
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+
         Self {
+            name: String::new(),
+            hash: hasher.finish(),
             contents,
             start_pos: BytePos(u32::MAX),
+            generation: 0,
             context: SourceContext::new(),
         }
     }
 
+    /// The name the source was first loaded by, as given to [`SourceMap::load`].
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn context(&self) -> &SourceContext {
         &self.context
     }
@@ -76,9 +144,66 @@ impl Source {
         &self.contents
     }
 
+    /// Identifies this source, with its current contents, among all the sources ever loaded in its
+    /// source map.
+    pub fn fingerprint(&self) -> Fingerprint {
+        Fingerprint {
+            start: self.start_pos,
+            generation: self.generation,
+            hash: self.hash,
+        }
+    }
+
+    /// The span covering the whole source.
+    pub fn span(&self) -> Span {
+        let len = self.contents.len() as u32;
+        Span::new(self.start_pos, BytePos(self.start_pos.0 + len))
+    }
+
+    /// The text of a span within this source.
+    ///
+    /// # Panics
+    /// If the span is not part of this source.
+    pub fn slice(&self, span: Span) -> &str {
+        &self.contents[self.range(span)]
+    }
+
+    /// Convert a global span within this source into a byte range within its contents.
+    pub fn range(&self, span: Span) -> Range<usize> {
+        (span.lo.0 - self.start_pos.0) as usize..(span.hi.0 - self.start_pos.0) as usize
+    }
+
     pub fn lexer(&self) -> Lexer<'_> {
         Lexer::new(self.start_pos.0, self.contents.as_str())
     }
+
+    /// Convert a byte range within the contents of this source into a global span.
+    pub fn global_span(&self, range: Range<usize>) -> Span {
+        Span::new(
+            BytePos(self.start_pos.0 + range.start as u32),
+            BytePos(self.start_pos.0 + range.end as u32),
+        )
+    }
+
+    /// Convert a pair of locations, as produced by [`Source::lexer`], into a span relative to the
+    /// start of this source.
+    pub fn local_span(&self, lo: u32, hi: u32) -> SourceSpan {
+        let lo = (lo - self.start_pos.0) as usize;
+        let hi = (hi - self.start_pos.0) as usize;
+
+        SourceSpan::from(lo..hi)
+    }
+}
+
+#[cfg(test)]
+impl Source {
+    /// Create a source positioned as if it had been loaded in a [`SourceMap`].
+    pub(crate) fn at(start_pos: u32, contents: impl Into<String>) -> Self {
+        Self {
+            start_pos: BytePos(start_pos),
+            ..Self::new(contents.into())
+        }
+    }
 }
 
 impl AsRef<str> for Source {
@@ -1,18 +1,38 @@
 use core::{
     any::{Any, TypeId},
     hash::{Hash, Hasher},
+    mem,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
 };
-use indexmap::{map::Entry, IndexMap};
 
-use super::{BytePos, Source, SourceContext, SourceError, SourceLoader};
+use super::{BytePos, Fingerprint, Source, SourceContext, SourceError, SourceLoader};
 
 /// A source map handles storage of the loaded sources, as well as mapping between spans and
 /// meaningful spans of code.
+///
+/// Each source takes a range of the global byte space. Sources can be removed, or replaced when
+/// their contents change, and the ranges they leave free are reused by later sources, so that a
+/// long-running process can reload sources indefinitely.
 pub struct SourceMap {
-    /// The list of loaded sources.
-    ///
-    /// Note: Preserving insertion order is vital for span lookups.
-    sources: IndexMap<SourceMapKey, Source>,
+    /// The loaded sources by start position, with the keys they were loaded by. Sources never
+    /// overlap, so the source containing a position is the last one starting at or before it.
+    sources: BTreeMap<BytePos, (SourceMapKey, Source)>,
+
+    /// The start positions of the loaded sources, by key.
+    positions: HashMap<SourceMapKey, BytePos>,
+
+    /// The end of the byte space.
+    limit: u32,
+
+    /// The number of sources [added](Self::add) so far, which tells them apart.
+    added: usize,
+
+    /// The number of sources inserted so far, added or loaded, which tells apart sources that took
+    /// the same range in turn.
+    generation: u64,
 
     /// The method for loading sources.
     source_loader: Box<dyn SourceLoaderDyn + Send + Sync>,
@@ -21,7 +41,11 @@ pub struct SourceMap {
 impl SourceMap {
     pub fn new(source_loader: impl SourceLoader + Send + Sync + 'static) -> Self {
         Self {
-            sources: IndexMap::new(),
+            sources: BTreeMap::new(),
+            positions: HashMap::new(),
+            limit: u32::MAX,
+            added: 0,
+            generation: 0,
             source_loader: Box::new(source_loader),
         }
     }
@@ -33,50 +57,175 @@ impl SourceMap {
             key: self.source_loader.resolve(context, name)?,
         };
 
-        // Compute the start position of the source span if possible
-        let start_pos = match self.sources.last() {
-            Some((_, source)) => {
-                let last_len = u32::try_from(source.contents.len())
-                    .expect("no source already in the sourcemap should be too large");
-                source.start_pos.0.checked_add(last_len)
-            }
-            None => Some(0),
+        // Return early if the source is already loaded, otherwise continue the loading process.
+        if let Some(start_pos) = self.positions.get(&source_key) {
+            return Ok(&self.sources[start_pos].1);
+        }
+
+        let source = self.source_loader.load(context, &*source_key.key)?;
+        self.insert(source_key, name, source)
+    }
+
+    /// Add a source that was not loaded from anywhere, such as an expression given on the command
+    /// line. Each added source is distinct, even if another one has the same name.
+    pub fn add(&mut self, name: &str, source: Source) -> Result<&Source, SourceError> {
+        let source_key = SourceMapKey {
+            loader: TypeId::of::<Self>(),
+            key: Arc::new(self.added),
         };
 
-        // Return early if the source is already loaded, otherwise continue the loading process.
-        match self.sources.entry(source_key) {
-            Entry::Occupied(entry) => Ok(entry.into_mut()),
-            Entry::Vacant(entry) => {
-                let mut source = self.source_loader.load(context, &*entry.key().key)?;
-
-                // If both start_pos and (start_pos + source_len) are valid `u32`s, we know that no span
-                // computation will overflow. So we're safe to assign the source's start_pos and continue.
-                if let Err(()) = match start_pos {
-                    Some(start_pos) => match start_pos.checked_add(
-                        u32::try_from(source.contents.len())
-                            .map_err(|_| SourceError::SourceTooLarge)?,
-                    ) {
-                        Some(_) => {
-                            source.start_pos = BytePos(start_pos);
-                            Ok(())
-                        }
-                        None => Err(()),
-                    },
-                    None => Err(()),
-                } {
-                    return Err(SourceError::SourceTooLarge);
-                }
+        self.added += 1;
+        self.insert(source_key, name, source)
+    }
+
+    fn insert(
+        &mut self,
+        source_key: SourceMapKey,
+        name: &str,
+        mut source: Source,
+    ) -> Result<&Source, SourceError> {
+        let len = u32::try_from(source.contents.len()).map_err(|_| SourceError::SourceTooLarge)?;
+        let start_pos = self.allocate(len)?;
+
+        source.start_pos = start_pos;
+        source.generation = self.generation;
+        source.name = name.to_owned();
+        self.generation += 1;
+
+        // At last, insert the loaded source into the source map
+        self.positions.insert(source_key.clone(), start_pos);
+        self.sources.insert(start_pos, (source_key, source));
+        Ok(&self.sources[&start_pos].1)
+    }
 
-                // At last, insert the loaded source into the source map
-                Ok(entry.insert(source))
+    /// Find room for a source of `len` bytes: The first range left free between sources that is
+    /// large enough, or else the space after the last source.
+    ///
+    /// A source takes one more position than its length, for the position at its end, so that
+    /// positions at the end of a source are never at the start of another.
+    fn allocate(&self, len: u32) -> Result<BytePos, SourceError> {
+        let size = len.checked_add(1).ok_or(SourceError::SourceTooLarge)?;
+
+        let mut free = 0;
+        for (start_pos, (_, source)) in &self.sources {
+            if start_pos.0 - free >= size {
+                return Ok(BytePos(free));
             }
+            // Sources are allocated within the limit, so this cannot overflow.
+            free = source.span().hi().0 + 1;
+        }
+
+        match free.checked_add(size) {
+            Some(end) if end <= self.limit => Ok(BytePos(free)),
+            _ => Err(SourceError::SourceTooLarge),
         }
     }
+
+    /// The source with the given fingerprint, if it is still loaded.
+    pub fn get(&self, source: Fingerprint) -> Option<&Source> {
+        match self.sources.get(&source.start) {
+            Some((_, loaded)) if loaded.fingerprint() == source => Some(loaded),
+            _ => None,
+        }
+    }
+
+    /// Remove a source, and free its range of the byte space for other sources. Spans into the
+    /// source cannot be looked up anymore, and may later point into another source.
+    ///
+    /// Returns the source, unless it was already removed or replaced.
+    pub fn remove(&mut self, source: Fingerprint) -> Option<Source> {
+        self.get(source)?;
+
+        let (source_key, source) = self.sources.remove(&source.start)?;
+        self.positions.remove(&source_key);
+        Some(source)
+    }
+
+    /// Load the sources loaded so far again, to pick up changes to their contents. Sources whose
+    /// contents changed are replaced with new sources, and sources that cannot be loaded anymore
+    /// are removed, so that loading them again reports why. Sources that were
+    /// [added](Self::add) are left as they are.
+    ///
+    /// Returns the fingerprints of the sources that were replaced or removed, with their names.
+    pub fn refresh(&mut self) -> Vec<(Fingerprint, String)> {
+        let loader = self.source_loader.loader_id();
+
+        let mut changed = Vec::new();
+        for (start_pos, (key, source)) in &self.sources {
+            if key.loader != loader {
+                continue;
+            }
+
+            match self.source_loader.load(&source.context, &*key.key) {
+                Ok(reloaded) if reloaded.contents == source.contents => {}
+                reloaded => changed.push((*start_pos, reloaded.ok())),
+            }
+        }
+
+        let mut stale = Vec::new();
+        for (start_pos, reloaded) in changed {
+            let (key, source) = self.sources.remove(&start_pos).expect("source is loaded");
+            self.positions.remove(&key);
+
+            if let Some(reloaded) = reloaded {
+                // A source too large to be placed is removed, like sources that cannot be loaded.
+                let _ = self.insert(key, &source.name, reloaded);
+            }
+            stale.push((source.fingerprint(), source.name));
+        }
+
+        stale
+    }
+
+    /// Load a source, with a name relative to the source containing the given position. This is
+    /// how sources refer to other sources.
+    ///
+    /// # Panics
+    /// If no loaded source contains the position.
+    pub fn load_relative(&mut self, pos: BytePos, name: &str) -> Result<&Source, SourceError> {
+        let start_pos = self
+            .lookup(pos)
+            .expect("position in a loaded source")
+            .start_pos;
+        let (_, source) = self.sources.get_mut(&start_pos).expect("source is loaded");
+
+        // The context is moved out for the duration of the load, since the source map is borrowed
+        // mutably in the meantime.
+        let context = mem::take(&mut source.context);
+        let loaded = self.load(&context, name).map(|source| source.start_pos);
+        let (_, source) = self.sources.get_mut(&start_pos).expect("source is loaded");
+        source.context = context;
+
+        Ok(&self.sources[&loaded?].1)
+    }
+
+    /// Find the source containing a position.
+    pub fn lookup(&self, pos: BytePos) -> Option<&Source> {
+        let (_, (_, source)) = self.sources.range(..=pos).next_back()?;
+        (pos <= source.span().hi()).then_some(source)
+    }
 }
 
+#[cfg(test)]
+impl SourceMap {
+    /// The number of sources loaded.
+    pub(crate) fn source_count(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// Create a source map with a smaller byte space.
+    fn with_limit(source_loader: impl SourceLoader + Send + Sync + 'static, limit: u32) -> Self {
+        Self {
+            limit,
+            ..Self::new(source_loader)
+        }
+    }
+}
+
+#[derive(Clone)]
 struct SourceMapKey {
     loader: TypeId,
-    key: Box<dyn DynKey>,
+    key: Arc<dyn DynKey>,
 }
 
 impl PartialEq for SourceMapKey {
@@ -97,7 +246,7 @@ impl Eq for SourceMapKey {}
 /// Object-safe wrapper for [`SourceLoader`]
 trait SourceLoaderDyn: Send + Sync {
     /// Resolve the supplied name into a DynKey.
-    fn resolve(&mut self, cx: &SourceContext, name: &str) -> Result<Box<dyn DynKey>, SourceError>;
+    fn resolve(&mut self, cx: &SourceContext, name: &str) -> Result<Arc<dyn DynKey>, SourceError>;
 
     /// Load the source corresponding to the relevant DynKey
     ///
@@ -112,9 +261,9 @@ impl<T> SourceLoaderDyn for T
 where
     T: SourceLoader + Send + Sync,
 {
-    fn resolve(&mut self, cx: &SourceContext, name: &str) -> Result<Box<dyn DynKey>, SourceError> {
+    fn resolve(&mut self, cx: &SourceContext, name: &str) -> Result<Arc<dyn DynKey>, SourceError> {
         match self.resolve(cx, name) {
-            Ok(key) => Ok(Box::new(key)),
+            Ok(key) => Ok(Arc::new(key)),
             Err(err) => Err(SourceError::Loader(Box::new(err))),
         }
     }
@@ -180,6 +329,11 @@ impl Eq for dyn DynKey {}
 
 #[cfg(test)]
 mod test {
+    use std::sync::Mutex;
+
+    use miette::Diagnostic;
+    use thiserror::Error;
+
     use super::*;
 
     /// Ensure various types are Send + Sync. It's easy to forget when dealing with `dyn`s
@@ -189,4 +343,170 @@ mod test {
         is_send_sync::<SourceMap>();
         is_send_sync::<Source>();
     };
+
+    /// Loads files from memory, where the test can change them.
+    #[derive(Clone, Default)]
+    struct Files(Arc<Mutex<HashMap<String, String>>>);
+
+    #[derive(Debug, Error, Diagnostic)]
+    #[error("No file `{0}`")]
+    struct Missing(String);
+
+    impl Files {
+        fn write(&self, name: &str, contents: String) {
+            self.0.lock().unwrap().insert(name.to_owned(), contents);
+        }
+    }
+
+    impl SourceLoader for Files {
+        type Key = String;
+        type Error = Missing;
+
+        fn resolve(&mut self, _cx: &SourceContext, name: &str) -> Result<String, Missing> {
+            Ok(name.to_owned())
+        }
+
+        fn load(&mut self, _cx: &SourceContext, key: &String) -> Result<Source, Missing> {
+            match self.0.lock().unwrap().get(key) {
+                Some(contents) => Ok(Source::new(contents.clone())),
+                None => Err(Missing(key.clone())),
+            }
+        }
+    }
+
+    /// Check that every position of every source maps back to it.
+    #[track_caller]
+    fn assert_lookups(source_map: &SourceMap) {
+        for (_, source) in source_map.sources.values() {
+            let span = source.span();
+            for pos in [
+                span.lo(),
+                BytePos((span.lo().0 + span.hi().0) / 2),
+                span.hi(),
+            ] {
+                let found = source_map.lookup(pos).map(Source::fingerprint);
+                assert_eq!(found, Some(source.fingerprint()), "position {pos:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn positions_map_to_the_source_containing_them() {
+        let mut source_map = SourceMap::new(Files::default());
+        let a = source_map
+            .add("a", Source::new("abc".into()))
+            .unwrap()
+            .span();
+        let empty = source_map
+            .add("empty", Source::new("".into()))
+            .unwrap()
+            .span();
+        let b = source_map
+            .add("b", Source::new("de".into()))
+            .unwrap()
+            .span();
+
+        // The position at the end of a source is not the start of the next.
+        assert_eq!((a.lo().0, a.hi().0), (0, 3));
+        assert_eq!((empty.lo().0, empty.hi().0), (4, 4));
+        assert_eq!((b.lo().0, b.hi().0), (5, 7));
+        assert_lookups(&source_map);
+        assert!(source_map.lookup(BytePos(8)).is_none());
+
+        let fingerprint = source_map.lookup(empty.lo()).unwrap().fingerprint();
+        assert!(source_map.remove(fingerprint).is_some());
+        assert!(source_map.remove(fingerprint).is_none());
+        assert!(source_map.lookup(empty.lo()).is_none());
+        assert_lookups(&source_map);
+    }
+
+    #[test]
+    fn removed_ranges_are_reused() {
+        let mut source_map = SourceMap::new(Files::default());
+        let fingerprints: Vec<_> = ["aaaa", "bb", "cccc"]
+            .into_iter()
+            .map(|contents| {
+                let source = Source::new(contents.into());
+                source_map.add(contents, source).unwrap().fingerprint()
+            })
+            .collect();
+
+        source_map.remove(fingerprints[0]);
+        source_map.remove(fingerprints[1]);
+
+        // The first range that fits: `aaaa` took 0..=4, and `bb` took 5..=7.
+        let source = source_map.add("d", Source::new("ddddddd".into())).unwrap();
+        assert_eq!(source.span().lo().0, 0);
+        let source = source_map.add("e", Source::new("eeee".into())).unwrap();
+        assert_eq!(source.span().lo().0, 13);
+        assert_lookups(&source_map);
+    }
+
+    #[test]
+    fn sources_at_reused_ranges_have_new_fingerprints() {
+        let mut source_map = SourceMap::new(Files::default());
+        let first = source_map
+            .add("a/main.dek", Source::new("1".into()))
+            .unwrap();
+        let (span, fingerprint) = (first.span(), first.fingerprint());
+        source_map.remove(fingerprint);
+
+        // The same contents, from elsewhere, at the same position.
+        let second = source_map
+            .add("b/main.dek", Source::new("1".into()))
+            .unwrap();
+        assert_eq!(second.span(), span);
+        assert_ne!(second.fingerprint(), fingerprint);
+        let second = second.fingerprint();
+        assert!(source_map.get(fingerprint).is_none());
+        assert!(source_map.get(second).is_some());
+    }
+
+    #[test]
+    fn thousands_of_reloads_fit_in_a_small_space() {
+        let files = Files::default();
+        files.write("stable", "s".repeat(1000));
+        files.write("changing", "-".repeat(1000));
+
+        // Without reuse, the reloads below would take more than a hundred times this space.
+        let mut source_map = SourceMap::with_limit(files.clone(), 16 * 1024);
+        let cx = SourceContext::new();
+        let stable = source_map.load(&cx, "stable").unwrap().fingerprint();
+        source_map.load(&cx, "changing").unwrap();
+
+        for i in 0..5000 {
+            // Sources of different sizes, which fragment the space.
+            files.write("changing", format!("{i}").repeat(1000 / (i % 7 + 1)));
+            let stale = source_map.refresh();
+            assert_eq!(stale.len(), 1, "reload {i}");
+            assert_eq!(stale[0].1, "changing");
+
+            let added = Source::new("x".repeat(i % 500));
+            let added = source_map.add("added", added).unwrap().fingerprint();
+            if i % 3 != 0 {
+                source_map.remove(added);
+            }
+            if i % 3 == 0 && i > 0 {
+                // Remove the ones kept earlier, as a process done with them would.
+                let kept: Vec<_> = source_map
+                    .sources
+                    .values()
+                    .filter(|(key, _)| key.loader == TypeId::of::<SourceMap>())
+                    .map(|(_, source)| source.fingerprint())
+                    .collect();
+                for fingerprint in kept {
+                    source_map.remove(fingerprint);
+                }
+            }
+
+            let changing = source_map.load(&cx, "changing").unwrap();
+            assert!(changing.contents().starts_with(&i.to_string()));
+            assert_eq!(
+                source_map.load(&cx, "stable").unwrap().fingerprint(),
+                stable
+            );
+        }
+
+        assert_lookups(&source_map);
+    }
 }
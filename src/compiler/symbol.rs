@@ -2,3 +2,13 @@
 pub struct Symbol {
     name: String,
 }
+
+impl Symbol {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self { name: name.into() }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.name
+    }
+}
@@ -0,0 +1,511 @@
+//! The concrete syntax tree.
+//!
+//! Unlike the [AST](super::ast), the syntax tree is lossless: It holds every token of the source,
+//! including whitespace and comments, each with its span in the global byte space of the
+//! [`SourceMap`](super::source::SourceMap). Concatenating the text of all the tokens of a tree
+//! reproduces the source it was parsed from exactly, which is what tools that edit source code
+//! need. The AST is [lowered](super::lower) from the syntax tree.
+
+use core::fmt;
+
+use lalrpop_util::{ErrorRecovery, ParseError};
+use logos::Logos;
+
+use super::source::{
+    lexer::{LexError, Token, Trivia},
+    BytePos, Source, Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Assign,
+    Equals,
+    ColonAssign,
+    PlusAssign,
+    Plus,
+    Dash,
+    Star,
+    Slash,
+    SlashSlash,
+    Dot,
+    DotDot,
+    Colon,
+    Pipe,
+    Tilde,
+    Comma,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Let,
+    In,
+    Import,
+    Type,
+    SelfValue,
+    Super,
+    Not,
+    And,
+    Or,
+    True,
+    False,
+    Null,
+    Number,
+    String,
+    Ident,
+
+    // Trivia
+    Whitespace,
+    Comment,
+
+    /// Text that could not be parsed, and was skipped by the parser.
+    Unknown,
+}
+
+impl TokenKind {
+    pub fn is_trivia(self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment)
+    }
+}
+
+impl From<&Token<'_>> for TokenKind {
+    fn from(token: &Token<'_>) -> Self {
+        match token {
+            Token::Assign => Self::Assign,
+            Token::Equals => Self::Equals,
+            Token::ColonAssign => Self::ColonAssign,
+            Token::PlusAssign => Self::PlusAssign,
+            Token::Plus => Self::Plus,
+            Token::Dash => Self::Dash,
+            Token::Star => Self::Star,
+            Token::Slash => Self::Slash,
+            Token::SlashSlash => Self::SlashSlash,
+            Token::Dot => Self::Dot,
+            Token::DotDot => Self::DotDot,
+            Token::Colon => Self::Colon,
+            Token::Pipe => Self::Pipe,
+            Token::Tilde => Self::Tilde,
+            Token::Comma => Self::Comma,
+            Token::LParen => Self::LParen,
+            Token::RParen => Self::RParen,
+            Token::LBrace => Self::LBrace,
+            Token::RBrace => Self::RBrace,
+            Token::LBracket => Self::LBracket,
+            Token::RBracket => Self::RBracket,
+            Token::Let => Self::Let,
+            Token::In => Self::In,
+            Token::Import => Self::Import,
+            Token::Type => Self::Type,
+            Token::SelfValue => Self::SelfValue,
+            Token::Super => Self::Super,
+            Token::Not => Self::Not,
+            Token::And => Self::And,
+            Token::Or => Self::Or,
+            Token::True => Self::True,
+            Token::False => Self::False,
+            Token::Null => Self::Null,
+            Token::Number(_) => Self::Number,
+            Token::String(_) => Self::String,
+            Token::Ident(_) => Self::Ident,
+        }
+    }
+}
+
+impl From<Trivia> for TokenKind {
+    fn from(trivia: Trivia) -> Self {
+        match trivia {
+            Trivia::Whitespace => Self::Whitespace,
+            Trivia::Comment => Self::Comment,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    /// The whole source: An expression, surrounded by trivia.
+    Root,
+    /// `null`, `true`, `false`, a number or a string.
+    Literal,
+    /// An identifier, used as an expression.
+    Name,
+    /// `self` or `super`, in a record.
+    SelfRecord,
+    /// `( expr )`
+    Paren,
+    /// `op expr`
+    Unary,
+    /// `expr op expr`
+    Binary,
+    /// `expr expr`
+    Apply,
+    /// `let binding, ... in expr`
+    Let,
+    /// `ident = expr`, or `ident : type = expr`. In records, `:=` or `+=` may take the place of
+    /// `=`.
+    Binding,
+    /// `-ident`, in a record.
+    Deletion,
+    /// `( expr : type )`
+    Annotated,
+    /// `type ident = type`, among the bindings of a `let`
+    TypeAlias,
+    /// `{ binding, ... }`, where deletions may take the place of bindings.
+    Record,
+    /// `[ expr, ... ]`
+    List,
+    /// `import "path"`
+    Import,
+    /// `expr.name`
+    Field,
+    /// A type named by an identifier, such as `Number`.
+    TypeName,
+    /// `[ type ]`
+    TypeList,
+    /// `{ name : type, ... }`, ending with `..` for open records.
+    TypeRecord,
+    /// `name : type`, in a record type.
+    TypeField,
+    /// `type min..max`, where either bound may be left out.
+    TypeRange,
+    /// `type ~ "regex"`
+    TypePattern,
+    /// `literal | ...`
+    TypeEnum,
+    /// Tokens skipped while recovering from a syntax error.
+    Error,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SyntaxToken {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+impl fmt::Debug for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{:?}", self.kind, self.span)
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct SyntaxNode {
+    pub kind: NodeKind,
+    pub span: Span,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{:?} ", self.kind, self.span)?;
+        f.debug_list().entries(&self.children).finish()
+    }
+}
+
+impl SyntaxNode {
+    /// Create a node spanning its children.
+    ///
+    /// # Panics
+    /// If there are no children.
+    pub fn new(kind: NodeKind, children: Vec<SyntaxElement>) -> Self {
+        let lo = children.first().expect("node has children").span().lo();
+        let hi = children.last().expect("node has children").span().hi();
+
+        Self {
+            kind,
+            span: Span::new(lo, hi),
+            children,
+        }
+    }
+
+    /// Create an error node from the tokens dropped while recovering from a syntax error.
+    pub fn error(recovery: &ErrorRecovery<u32, Token<'_>, LexError>) -> Self {
+        let children: Vec<_> = recovery
+            .dropped_tokens
+            .iter()
+            .map(|(lo, token, hi)| SyntaxElement::token(token, *lo, *hi))
+            .collect();
+
+        if !children.is_empty() {
+            return Self::new(NodeKind::Error, children);
+        }
+
+        // Nothing was dropped: Place an empty node where the error was found.
+        let pos = match recovery.error {
+            ParseError::InvalidToken { location } => location,
+            ParseError::UnrecognizedEof { location, .. } => location,
+            ParseError::UnrecognizedToken {
+                token: (lo, ..), ..
+            } => lo,
+            ParseError::ExtraToken { token: (lo, ..) } => lo,
+            ParseError::User { .. } => unreachable!("lexer errors are not recoverable"),
+        };
+
+        Self::empty(NodeKind::Error, BytePos::new(pos))
+    }
+
+    /// Create a node with no children at the given position.
+    pub fn empty(kind: NodeKind, pos: BytePos) -> Self {
+        Self {
+            kind,
+            span: Span::new(pos, pos),
+            children: Vec::new(),
+        }
+    }
+
+    /// The child nodes, in order.
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// The child tokens that are not trivia, in order.
+    pub fn tokens(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Token(token) if !token.kind.is_trivia() => Some(token),
+            _ => None,
+        })
+    }
+
+    /// All the tokens of the tree, including trivia, in source order.
+    pub fn descendant_tokens(&self) -> impl Iterator<Item = &SyntaxToken> {
+        let mut stack = vec![self.children.iter()];
+
+        core::iter::from_fn(move || loop {
+            let child = match stack.last_mut()?.next() {
+                Some(child) => child,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
+
+            match child {
+                SyntaxElement::Token(token) => return Some(token),
+                SyntaxElement::Node(node) => stack.push(node.children.iter()),
+            }
+        })
+    }
+
+    /// Wrap a parsed expression into a [`NodeKind::Root`] node, filling the gaps between its tokens
+    /// with trivia, so that the tree covers every byte of the source.
+    pub(super) fn root(source: &Source, expr: SyntaxNode) -> Self {
+        let span = source.span();
+
+        let mut children = Vec::new();
+        let mut pos = span.lo();
+        fill(source, &mut pos, &mut children, SyntaxElement::Node(expr));
+        if pos < span.hi() {
+            push_trivia(source, Span::new(pos, span.hi()), &mut children);
+        }
+
+        Self {
+            kind: NodeKind::Root,
+            span,
+            children,
+        }
+    }
+}
+
+/// Push `element` into `children`, after the trivia preceding it, and recursively fill the gaps
+/// within `element`. `pos` is the end of the last token pushed so far.
+fn fill(
+    source: &Source,
+    pos: &mut BytePos,
+    children: &mut Vec<SyntaxElement>,
+    element: SyntaxElement,
+) {
+    if *pos < element.span().lo() {
+        push_trivia(source, Span::new(*pos, element.span().lo()), children);
+        *pos = element.span().lo();
+    }
+
+    match element {
+        SyntaxElement::Token(token) => {
+            *pos = token.span.hi();
+            children.push(SyntaxElement::Token(token));
+        }
+        SyntaxElement::Node(mut node) => {
+            let mut inner = Vec::with_capacity(node.children.len());
+            for child in node.children {
+                fill(source, pos, &mut inner, child);
+            }
+
+            node.children = inner;
+            *pos = node.span.hi().max(*pos);
+            children.push(SyntaxElement::Node(node));
+        }
+    }
+}
+
+fn push_trivia(source: &Source, span: Span, children: &mut Vec<SyntaxElement>) {
+    let mut lexer = Trivia::lexer(source.slice(span));
+    while let Some(trivia) = lexer.next() {
+        let lo = BytePos::new(span.lo().to_u32() + lexer.span().start as u32);
+        let hi = BytePos::new(span.lo().to_u32() + lexer.span().end as u32);
+
+        let kind = trivia.map_or(TokenKind::Unknown, TokenKind::from);
+
+        // Merge runs of unknown text into a single token.
+        if let Some(SyntaxElement::Token(last)) = children.last_mut() {
+            if kind == TokenKind::Unknown && last.kind == kind && last.span.hi() == lo {
+                last.span = Span::new(last.span.lo(), hi);
+                continue;
+            }
+        }
+
+        children.push(SyntaxElement::Token(SyntaxToken {
+            kind,
+            span: Span::new(lo, hi),
+        }));
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl fmt::Debug for SyntaxElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Node(v) => fmt::Debug::fmt(v, f),
+            Self::Token(v) => fmt::Debug::fmt(v, f),
+        }
+    }
+}
+
+impl SyntaxElement {
+    pub fn node(kind: NodeKind, children: impl IntoIterator<Item = SyntaxElement>) -> Self {
+        Self::Node(SyntaxNode::new(kind, children.into_iter().collect()))
+    }
+
+    pub fn token(token: &Token<'_>, lo: u32, hi: u32) -> Self {
+        Self::Token(SyntaxToken {
+            kind: token.into(),
+            span: Span::new(BytePos::new(lo), BytePos::new(hi)),
+        })
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Node(node) => node.span,
+            Self::Token(token) => token.span,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::compiler::{context::Context, parser};
+
+    fn parse(start_pos: u32, text: &str) -> (Source, SyntaxNode) {
+        let source = Source::at(start_pos, text);
+        let syntax = parser::parse(&mut Context::new(), &source).syntax;
+        (source, syntax)
+    }
+
+    fn tokens(source: &Source, syntax: &SyntaxNode) -> Vec<(TokenKind, String)> {
+        syntax
+            .descendant_tokens()
+            .map(|token| (token.kind, source.slice(token.span).to_owned()))
+            .collect()
+    }
+
+    #[track_caller]
+    fn assert_lossless(text: &str) {
+        let (source, syntax) = parse(0, text);
+        let tokens: String = tokens(&source, &syntax)
+            .into_iter()
+            .map(|(_, t)| t)
+            .collect();
+        assert_eq!(tokens, text);
+    }
+
+    #[test]
+    fn trivia_is_kept() {
+        let (source, syntax) = parse(0, "# leading\n{ a = 1, # field\n} # trailing");
+
+        use TokenKind::*;
+        let expected = [
+            (Comment, "# leading"),
+            (Whitespace, "\n"),
+            (LBrace, "{"),
+            (Whitespace, " "),
+            (Ident, "a"),
+            (Whitespace, " "),
+            (Assign, "="),
+            (Whitespace, " "),
+            (Number, "1"),
+            (Comma, ","),
+            (Whitespace, " "),
+            (Comment, "# field"),
+            (Whitespace, "\n"),
+            (RBrace, "}"),
+            (Whitespace, " "),
+            (Comment, "# trailing"),
+        ];
+        let expected: Vec<_> = expected.map(|(k, t)| (k, t.to_owned())).into();
+        assert_eq!(tokens(&source, &syntax), expected);
+    }
+
+    #[test]
+    fn trivia_is_placed_between_nodes() {
+        let (_, syntax) = parse(0, " ( 1 ) ");
+
+        assert_eq!(
+            format!("{syntax:?}"),
+            "Root@0..7 [Whitespace@0..1, Paren@1..6 [LParen@1..2, Whitespace@2..3, \
+            Literal@3..4 [Number@3..4], Whitespace@4..5, RParen@5..6], Whitespace@6..7]"
+        );
+    }
+
+    #[test]
+    fn spans_are_global() {
+        let (source, syntax) = parse(100, "a + 1");
+
+        assert_eq!(syntax.span, source.span());
+        assert_eq!(syntax.span.lo(), BytePos::new(100));
+        assert_eq!(
+            format!("{syntax:?}"),
+            "Root@100..105 [Binary@100..105 [Name@100..101 [Ident@100..101], Whitespace@101..102, \
+            Plus@102..103, Whitespace@103..104, Literal@104..105 [Number@104..105]]]"
+        );
+    }
+
+    #[test]
+    fn errors_are_kept() {
+        let (source, syntax) = parse(0, "[1, + *, 3]");
+        assert_lossless("[1, + *, 3]");
+
+        let list = syntax.nodes().next().unwrap();
+        let error = list.nodes().nth(1).unwrap();
+        assert_eq!(error.kind, NodeKind::Error);
+        assert_eq!(source.slice(error.span), "+ *");
+
+        // Unrecoverable errors keep the source as unknown tokens.
+        let (source, syntax) = parse(0, "1 + ");
+        assert_eq!(
+            tokens(&source, &syntax),
+            [
+                (TokenKind::Unknown, "1".to_owned()),
+                (TokenKind::Whitespace, " ".to_owned()),
+                (TokenKind::Unknown, "+".to_owned()),
+                (TokenKind::Whitespace, " ".to_owned()),
+            ]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn any_source_is_lossless(text in r#"[a-z0-9 \n#+*/(){}\[\]=,.-]*"#) {
+            assert_lossless(&text);
+        }
+    }
+}
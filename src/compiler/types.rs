@@ -0,0 +1,1156 @@
+//! Static type checking.
+//!
+//! Types are inferred by unification, in the style of Hindley-Milner: Every expression is given a
+//! type, which may contain type variables standing for types that are not known yet, and using an
+//! expression where some type is expected unifies the two, binding variables as needed. Type errors
+//! are found before evaluation, with the spans of the expressions they are about.
+//!
+//! Record types are row-polymorphic: A record type may be open, with a row variable standing for
+//! the fields it has besides the ones listed. Accessing a field only requires a record to have that
+//! field, so a value can be used as a record before anything tells which fields it has: `args`,
+//! whose fields are only known during evaluation, is an open record, and `let a = args.a in [a.b,
+//! a.c]` types `a` as a record with at least the fields `b` and `c`.
+//!
+//! There are no function literals, so no binding is ever polymorphic, and `let` bindings are not
+//! generalized. Values whose type cannot be known statically, such as lists of items of different
+//! types, have the [dynamic](Type::Dynamic) type, which unifies with any type, and is only checked
+//! during evaluation.
+
+use core::{fmt, mem, ops::Range};
+
+use indexmap::IndexMap;
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use thiserror::Error;
+
+use crate::vm::value::{Merge, Value, ValueKind};
+
+use super::{
+    ast::{Call, Expr, ExprKind, Operator, Record},
+    context::Context,
+    contract::{self, Aliases, Contract, ContractKind, InvalidType},
+    interner::Interned,
+    printer,
+    source::{Source, Span},
+    symbol::Symbol,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    /// A type that is not known yet.
+    Var(TypeVar),
+
+    /// The type of values that are only checked during evaluation.
+    Dynamic,
+
+    Null,
+    Boolean,
+    Number,
+    String,
+    List(Box<Type>),
+    Record(RecordType),
+    Function(Box<Type>, Box<Type>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypeVar(u32);
+
+/// The types of source code being edited, as found by
+/// [`Compiler::check_document`](super::Compiler::check_document).
+#[derive(Debug)]
+pub struct DocumentTypes {
+    /// The type of the whole document.
+    pub ty: Type,
+
+    /// The types of the `let` bindings and record fields of the document, by the byte range of
+    /// their name in it.
+    pub bindings: Vec<(Range<usize>, Type)>,
+}
+
+/// The type of records with the given fields. An open record may have other fields, represented
+/// by a row variable, which is bound to the type of a record with the other fields once they are
+/// known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordType {
+    pub fields: IndexMap<String, Type>,
+    pub rest: Option<TypeVar>,
+}
+
+impl Type {
+    fn function(param: Type, result: Type) -> Self {
+        Self::Function(Box::new(param), Box::new(result))
+    }
+
+    fn fmt_with(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        vars: &mut Vec<TypeVar>,
+        in_param: bool,
+    ) -> fmt::Result {
+        match self {
+            Self::Var(var) => {
+                let index = match vars.iter().position(|v| v == var) {
+                    Some(index) => index,
+                    None => {
+                        vars.push(*var);
+                        vars.len() - 1
+                    }
+                };
+                match u8::try_from(index) {
+                    Ok(index @ 0..=25) => write!(f, "{}", char::from(b'a' + index)),
+                    _ => write!(f, "t{index}"),
+                }
+            }
+            Self::Dynamic => f.write_str("Dynamic"),
+            Self::Null => f.write_str("Null"),
+            Self::Boolean => f.write_str("Boolean"),
+            Self::Number => f.write_str("Number"),
+            Self::String => f.write_str("String"),
+            Self::List(item) => {
+                f.write_str("[")?;
+                item.fmt_with(f, vars, false)?;
+                f.write_str("]")
+            }
+            Self::Record(record) => {
+                f.write_str("{")?;
+                for (i, (name, ty)) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "{}: ", printer::field_name(name))?;
+                    ty.fmt_with(f, vars, false)?;
+                }
+                match (record.rest, record.fields.is_empty()) {
+                    (Some(_), true) => f.write_str("..")?,
+                    (Some(_), false) => f.write_str(", ..")?,
+                    (None, _) => {}
+                }
+                f.write_str("}")
+            }
+            Self::Function(param, result) => {
+                if in_param {
+                    f.write_str("(")?;
+                }
+                param.fmt_with(f, vars, true)?;
+                f.write_str(" -> ")?;
+                result.fmt_with(f, vars, false)?;
+                if in_param {
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Types are written as `Number`, `[Number]` for lists, `{a: Number, ..}` for records, where `..`
+/// stands for the other fields of open records, and `Number -> Number` for functions. Type
+/// variables are named with letters, in order of appearance.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_with(f, &mut Vec::new(), false)
+    }
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum TypeError {
+    #[error("Expected {expected}, found {found}")]
+    Mismatch {
+        expected: String,
+        found: String,
+
+        #[label("This is {found}")]
+        span: SourceSpan,
+
+        #[label("Expected because of this annotation")]
+        annotation: Option<SourceSpan>,
+
+        /// The whole types, when the mismatch is between parts of them.
+        #[help]
+        help: Option<String>,
+    },
+
+    #[error("Missing field `{name}`")]
+    MissingField {
+        name: String,
+        found: String,
+
+        #[label("This is {found}")]
+        span: SourceSpan,
+
+        #[label("Expected because of this annotation")]
+        annotation: Option<SourceSpan>,
+    },
+
+    /// Fields of different kinds merged by default, which fails during evaluation.
+    #[error("Cannot merge {found} into {expected}")]
+    Merge {
+        expected: String,
+        found: String,
+
+        #[label("This is {found}")]
+        span: SourceSpan,
+
+        #[help]
+        help: String,
+    },
+
+    #[error("Unbound variable `{name}`")]
+    UnboundVariable {
+        name: String,
+
+        #[label("Not in scope")]
+        span: SourceSpan,
+    },
+
+    #[error("`{name}` is reserved, and cannot be bound")]
+    Reserved {
+        name: String,
+
+        #[label("Bound here")]
+        span: SourceSpan,
+    },
+
+    #[error("`{keyword}` is only bound within records")]
+    OutsideRecord {
+        keyword: &'static str,
+
+        #[label("Not in a record")]
+        span: SourceSpan,
+    },
+
+    #[error("{error}")]
+    InvalidType {
+        error: InvalidType,
+
+        #[label]
+        span: SourceSpan,
+    },
+
+    #[error("Infinite type")]
+    Recursive {
+        #[label("This would have a type containing itself")]
+        span: SourceSpan,
+    },
+}
+
+/// All the type errors found in a single source.
+#[derive(Debug, Error, Diagnostic)]
+#[error("Type errors in `{}`", .source_code.name())]
+pub struct TypeErrors {
+    #[source_code]
+    source_code: NamedSource,
+
+    #[related]
+    errors: Vec<TypeError>,
+}
+
+impl TypeErrors {
+    pub fn new(source: &Source, errors: Vec<TypeError>) -> Self {
+        Self {
+            source_code: NamedSource::new(source.name(), source.contents().to_owned()),
+            errors,
+        }
+    }
+
+    pub fn errors(&self) -> &[TypeError] {
+        &self.errors
+    }
+}
+
+/// Why two types do not unify.
+enum Conflict {
+    /// The innermost types that differ, expected first.
+    Types(Box<Type>, Box<Type>),
+
+    /// A field the expected record type has, but not the found one.
+    MissingField(String),
+
+    /// A type variable would be bound to a type containing it.
+    Recursive,
+}
+
+/// The types of the `let` bindings and record fields within an expression, by the span of their
+/// name.
+type BindingTypes = Vec<(Span, Type)>;
+
+/// Infers the type of an expression parsed from a source.
+pub(super) struct Checker<'a> {
+    cx: &'a Context,
+    source: &'a Source,
+
+    /// The types that type variables were bound to, by index.
+    vars: Vec<Option<Type>>,
+
+    /// The types of the names in scope, with the innermost ones last.
+    scope: Vec<(Interned<Symbol>, Type)>,
+
+    /// The type aliases in scope.
+    aliases: Aliases,
+
+    /// The names that programs may not bind.
+    reserved: Vec<Interned<Symbol>>,
+
+    /// The types of `self` and `super` within the record literals around the expression being
+    /// checked, with the innermost ones last.
+    records: Vec<(Type, Type)>,
+
+    /// The types of the `let` bindings and record fields checked so far.
+    bindings: BindingTypes,
+
+    errors: Vec<TypeError>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(cx: &'a Context, source: &'a Source) -> Self {
+        Self {
+            cx,
+            source,
+            vars: Vec::new(),
+            scope: Vec::new(),
+            aliases: Aliases::new(),
+            reserved: Vec::new(),
+            records: Vec::new(),
+            bindings: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Bring a name in scope, with the type of its value.
+    pub fn bind_value(&mut self, name: Interned<Symbol>, value: &Value) {
+        let ty = value_type(value);
+        self.scope.push((name, ty));
+    }
+
+    /// Bring a name in scope, as a record whose fields are only known during evaluation.
+    pub fn bind_open_record(&mut self, name: Interned<Symbol>) {
+        let ty = Type::Record(RecordType {
+            fields: IndexMap::new(),
+            rest: Some(self.fresh_var()),
+        });
+        self.scope.push((name, ty));
+    }
+
+    /// Forbid binding a name, which is then only bound by the host.
+    pub fn reserve(&mut self, name: Interned<Symbol>) {
+        self.reserved.push(name);
+    }
+
+    /// Infer the type of an expression, with the names bound so far in scope.
+    pub fn check(self, expr: &Expr) -> Result<Type, Vec<TypeError>> {
+        self.check_bindings(expr).map(|(ty, _)| ty)
+    }
+
+    /// Infer the type of an expression like [`Self::check`], along with the types of the `let`
+    /// bindings and record fields within it.
+    pub fn check_bindings(mut self, expr: &Expr) -> Result<(Type, BindingTypes), Vec<TypeError>> {
+        let ty = self.expr(expr);
+        if !self.errors.is_empty() {
+            return Err(self.errors);
+        }
+
+        let bindings = self
+            .bindings
+            .iter()
+            .map(|(span, ty)| (*span, self.zonk(ty)))
+            .collect();
+        Ok((self.zonk(&ty), bindings))
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Literal(value) => value_type(value),
+            ExprKind::Identifier(ident) => {
+                let ty = self
+                    .scope
+                    .iter()
+                    .rev()
+                    .find(|(name, _)| *name == ident.name);
+                match ty {
+                    Some((_, ty)) => ty.clone(),
+                    None => {
+                        self.errors.push(TypeError::UnboundVariable {
+                            name: self.name(ident.name).to_owned(),
+                            span: self.span(expr.span),
+                        });
+                        Type::Dynamic
+                    }
+                }
+            }
+            ExprKind::Call(call) => self.call(call),
+            ExprKind::Operator(op) => operator_type(*op),
+            ExprKind::Let(let_in) => {
+                let (depth, aliases) = (self.scope.len(), self.aliases.len());
+                let (resolved, errors) =
+                    contract::resolve_aliases(self.cx, &self.aliases, &let_in.types);
+                self.invalid_types(errors);
+                self.aliases.extend(resolved);
+
+                for binding in &let_in.bindings {
+                    if self.reserved.contains(&binding.name.name) {
+                        self.errors.push(TypeError::Reserved {
+                            name: self.name(binding.name.name).to_owned(),
+                            span: self.span(binding.name.span),
+                        });
+                    }
+                    let ty = self.expr(&binding.value);
+                    self.bindings.push((binding.name.span, ty.clone()));
+                    self.scope.push((binding.name.name, ty));
+                }
+
+                let ty = self.expr(&let_in.body);
+                self.scope.truncate(depth);
+                self.aliases.truncate(aliases);
+                ty
+            }
+            ExprKind::Record(record) => {
+                // `self` has the fields of the record, with the types they are bound to, and those
+                // of the records it is merged with. Those of `super` are unknown.
+                let own: IndexMap<_, _> = record
+                    .fields
+                    .iter()
+                    .map(|binding| (self.name(binding.name.name).to_owned(), self.fresh()))
+                    .collect();
+                let this = Type::Record(RecordType {
+                    fields: own.clone(),
+                    rest: Some(self.fresh_var()),
+                });
+                let parent = Type::Record(RecordType {
+                    fields: IndexMap::new(),
+                    rest: Some(self.fresh_var()),
+                });
+                self.records.push((this, parent));
+
+                let mut fields = IndexMap::with_capacity(record.fields.len());
+                for binding in &record.fields {
+                    let ty = self.expr(&binding.value);
+                    let name = self.name(binding.name.name);
+                    self.expect(&own[name], &ty, binding.value.span);
+                    self.bindings.push((binding.name.span, ty.clone()));
+                    fields.insert(name.to_owned(), ty);
+                }
+
+                self.records.pop();
+                Type::Record(RecordType { fields, rest: None })
+            }
+            ExprKind::List(list) => {
+                let items: Vec<_> = list.items.iter().map(|item| self.expr(item)).collect();
+                Type::List(Box::new(self.join(&items)))
+            }
+            ExprKind::Field(field) => {
+                let record = self.expr(&field.record);
+                match self.resolve(&record) {
+                    Type::Var(_) | Type::Record(_) => {
+                        let ty = self.fresh();
+                        let name = self.name(field.name.name).to_owned();
+                        let expected = Type::Record(RecordType {
+                            fields: IndexMap::from([(name, ty.clone())]),
+                            rest: Some(self.fresh_var()),
+                        });
+                        self.expect(&expected, &record, field.record.span);
+                        ty
+                    }
+                    Type::Dynamic => Type::Dynamic,
+                    found => {
+                        self.mismatch("a record", &found, field.record.span);
+                        Type::Dynamic
+                    }
+                }
+            }
+            ExprKind::Annotated(annotated) => {
+                let (contract, errors) = contract::resolve(self.cx, &self.aliases, &annotated.ty);
+                self.invalid_types(errors);
+
+                let expected = self.contract_type(&contract);
+                let found = self.expr(&annotated.expr);
+                self.expect_annotated(&expected, &found, annotated.expr.span, annotated.ty.span);
+                expected
+            }
+            ExprKind::SelfRecord | ExprKind::SuperRecord => {
+                let (keyword, record) = match expr.kind {
+                    ExprKind::SelfRecord => ("self", self.records.last().map(|(this, _)| this)),
+                    _ => ("super", self.records.last().map(|(_, parent)| parent)),
+                };
+                match record {
+                    Some(record) => record.clone(),
+                    None => {
+                        self.errors.push(TypeError::OutsideRecord {
+                            keyword,
+                            span: self.span(expr.span),
+                        });
+                        Type::Dynamic
+                    }
+                }
+            }
+            // Imports are not resolved when checking source code being edited.
+            ExprKind::Import(_) | ExprKind::Error | ExprKind::Todo => Type::Dynamic,
+        }
+    }
+
+    fn call(&mut self, call: &Call) -> Type {
+        if let ExprKind::Call(Call { fun, arg: left }) = &call.fun.kind {
+            if let ExprKind::Operator(Operator::Merge) = fun.kind {
+                return self.merge(left, &call.arg);
+            }
+        }
+
+        let fun = self.expr(&call.fun);
+        let arg = self.expr(&call.arg);
+
+        match self.resolve(&fun) {
+            Type::Function(param, result) => {
+                self.expect(&param, &arg, call.arg.span);
+                *result
+            }
+            Type::Var(_) => {
+                let result = self.fresh();
+                let expected = Type::function(arg, result.clone());
+                self.expect(&expected, &fun, call.fun.span);
+                result
+            }
+            Type::Dynamic => Type::Dynamic,
+            found => {
+                self.mismatch("a function", &found, call.fun.span);
+                Type::Dynamic
+            }
+        }
+    }
+
+    /// The type of merging two records with `//`. The ways the fields of the record on the right
+    /// merge are only known if it is written in place. Fields of different kinds cannot be merged
+    /// unless they are replaced, and other fields whose values conflict, such as lists of different
+    /// types, have the dynamic type: They are only checked during evaluation.
+    fn merge(&mut self, left: &Expr, right: &Expr) -> Type {
+        let types = [(self.expr(left), left.span), (self.expr(right), right.span)];
+        for (ty, span) in &types {
+            match self.resolve(ty) {
+                Type::Record(_) | Type::Dynamic => {}
+                Type::Var(_) => {
+                    let record = Type::Record(RecordType {
+                        fields: IndexMap::new(),
+                        rest: Some(self.fresh_var()),
+                    });
+                    self.expect(&record, ty, *span);
+                }
+                found => {
+                    self.mismatch("a record", &found, *span);
+                    return Type::Dynamic;
+                }
+            }
+        }
+
+        let literal = match &right.kind {
+            ExprKind::Record(record) => Some(record),
+            _ => None,
+        };
+        let [(left, _), (right_type, _)] = types;
+        self.merge_records(&left, &right_type, literal, right.span, &[])
+    }
+
+    /// The type of merging two records, at `path` within the records merged by `//`. Conflicts are
+    /// reported at `span`, or at the field on the right if it is written in place.
+    fn merge_records(
+        &mut self,
+        left: &Type,
+        right: &Type,
+        literal: Option<&Record>,
+        span: Span,
+        path: &[&str],
+    ) -> Type {
+        let (Type::Record(left), Type::Record(right)) = (self.resolve(left), self.resolve(right))
+        else {
+            return Type::Dynamic;
+        };
+        let (left, right) = (self.flatten(left), self.flatten(right));
+
+        let mut fields = IndexMap::with_capacity(left.fields.len() + right.fields.len());
+        for (name, ty) in &left.fields {
+            let merge = literal.map_or(Merge::Deep, |record| {
+                let field = record
+                    .merges
+                    .iter()
+                    .find(|(field, _)| self.name(field.name) == name);
+                field.map_or(Merge::Deep, |(_, merge)| *merge)
+            });
+            let ty = match (merge, right.fields.get(name)) {
+                (Merge::Delete, _) => continue,
+                (_, None) => ty.clone(),
+                (Merge::Replace, Some(other)) => other.clone(),
+                (Merge::Append, Some(other)) => match (self.resolve(ty), self.resolve(other)) {
+                    (Type::List(a), Type::List(b)) => Type::List(Box::new(self.join(&[*a, *b]))),
+                    _ => Type::Dynamic,
+                },
+                (Merge::Deep, Some(other)) => {
+                    let field = literal.and_then(|record| {
+                        record
+                            .fields
+                            .iter()
+                            .find(|b| self.name(b.name.name) == name)
+                    });
+                    let literal = field.and_then(|field| match &field.value.kind {
+                        ExprKind::Record(record) => Some(record),
+                        _ => None,
+                    });
+                    let span = field.map_or(span, |field| field.value.span);
+                    let path = [path, &[name.as_str()]].concat();
+                    self.merge_fields(ty, other, literal, span, &path)
+                }
+            };
+            fields.insert(name.clone(), ty);
+        }
+        for (name, ty) in &right.fields {
+            if !left.fields.contains_key(name) {
+                fields.insert(name.clone(), ty.clone());
+            }
+        }
+
+        let rest = (left.rest.is_some() || right.rest.is_some()).then(|| self.fresh_var());
+        Type::Record(RecordType { fields, rest })
+    }
+
+    /// The type of merging two fields by default: Records are merged, and other values are
+    /// replaced by values of the same type. Values of different kinds are an error, as they are
+    /// during evaluation, if both types are known.
+    fn merge_fields(
+        &mut self,
+        left: &Type,
+        right: &Type,
+        literal: Option<&Record>,
+        span: Span,
+        path: &[&str],
+    ) -> Type {
+        let (resolved_left, resolved_right) = (self.resolve(left), self.resolve(right));
+        if let (Type::Record(_), Type::Record(_)) = (&resolved_left, &resolved_right) {
+            return self.merge_records(left, right, literal, span, path);
+        }
+
+        let vars = self.vars.clone();
+        if self.unify(left, right).is_ok() {
+            return right.clone();
+        }
+        self.vars = vars;
+
+        let known = |ty: &Type| !matches!(ty, Type::Var(_) | Type::Dynamic);
+        if known(&resolved_left)
+            && known(&resolved_right)
+            && mem::discriminant(&resolved_left) != mem::discriminant(&resolved_right)
+        {
+            self.errors.push(TypeError::Merge {
+                expected: self.zonk(left).to_string(),
+                found: self.zonk(right).to_string(),
+                span: self.span(span),
+                help: format!(
+                    "At `{}` in the merged records. Bind the field with `:=` to replace its value \
+                     instead",
+                    path.join(".")
+                ),
+            });
+        }
+        Type::Dynamic
+    }
+
+    /// The type of the items of a list: The type all of them have, or the dynamic type if they
+    /// have different types.
+    fn join(&mut self, items: &[Type]) -> Type {
+        let item = self.fresh();
+        let vars = self.vars.clone();
+        for ty in items {
+            if self.unify(&item, ty).is_err() {
+                self.vars = vars;
+                return Type::Dynamic;
+            }
+        }
+        item
+    }
+
+    /// The static type of the values a contract allows: Refinements are only checked during
+    /// evaluation.
+    fn contract_type(&mut self, contract: &Contract) -> Type {
+        match &contract.kind {
+            ContractKind::Dynamic => Type::Dynamic,
+            ContractKind::Null => Type::Null,
+            ContractKind::Boolean => Type::Boolean,
+            ContractKind::Number | ContractKind::Integer => Type::Number,
+            ContractKind::String => Type::String,
+            ContractKind::List(item) => Type::List(Box::new(self.contract_type(item))),
+            ContractKind::Record { fields, open } => Type::Record(RecordType {
+                fields: fields
+                    .iter()
+                    .map(|(name, field)| (name.clone(), self.contract_type(field)))
+                    .collect(),
+                rest: open.then(|| self.fresh_var()),
+            }),
+            ContractKind::Range { base, .. } | ContractKind::Pattern { base, .. } => {
+                self.contract_type(base)
+            }
+            ContractKind::Enum(values) => {
+                let types: Vec<_> = values.iter().map(value_type).collect();
+                self.join(&types)
+            }
+        }
+    }
+
+    fn invalid_types(&mut self, errors: Vec<InvalidType>) {
+        for error in errors {
+            let span = self.span(error.span());
+            self.errors.push(TypeError::InvalidType { error, span });
+        }
+    }
+
+    /// Unify the type of an expression with the type expected of it, or report an error at its
+    /// span.
+    fn expect(&mut self, expected: &Type, found: &Type, span: Span) {
+        self.expect_at(expected, found, span, None);
+    }
+
+    /// Unify the type of an annotated expression with the type of its annotation, or report an
+    /// error pointing at both.
+    fn expect_annotated(&mut self, expected: &Type, found: &Type, span: Span, annotation: Span) {
+        self.expect_at(expected, found, span, Some(annotation));
+    }
+
+    fn expect_at(&mut self, expected: &Type, found: &Type, span: Span, annotation: Option<Span>) {
+        let annotation = annotation.map(|span| self.span(span));
+        let error = match self.unify(expected, found) {
+            Ok(()) => return,
+            Err(Conflict::Types(inner_expected, inner_found)) => {
+                let (expected, found) = (self.zonk(expected), self.zonk(found));
+                let (inner_expected, inner_found) =
+                    (self.zonk(&inner_expected), self.zonk(&inner_found));
+                let help = (inner_expected != expected || inner_found != found)
+                    .then(|| format!("Expected {expected}, found {found}"));
+
+                TypeError::Mismatch {
+                    expected: inner_expected.to_string(),
+                    found: inner_found.to_string(),
+                    span: self.span(span),
+                    annotation,
+                    help,
+                }
+            }
+            Err(Conflict::MissingField(name)) => TypeError::MissingField {
+                name,
+                found: self.zonk(found).to_string(),
+                span: self.span(span),
+                annotation,
+            },
+            Err(Conflict::Recursive) => TypeError::Recursive {
+                span: self.span(span),
+            },
+        };
+        self.errors.push(error);
+    }
+
+    /// Report an expression of the given type where a kind of type was expected.
+    fn mismatch(&mut self, expected: &str, found: &Type, span: Span) {
+        self.errors.push(TypeError::Mismatch {
+            expected: expected.to_owned(),
+            found: self.zonk(found).to_string(),
+            span: self.span(span),
+            annotation: None,
+            help: None,
+        });
+    }
+
+    fn unify(&mut self, expected: &Type, found: &Type) -> Result<(), Conflict> {
+        match (self.resolve(expected), self.resolve(found)) {
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(var), ty) | (ty, Type::Var(var)) => self.bind(var, ty),
+            (Type::Dynamic, _) | (_, Type::Dynamic) => Ok(()),
+            (Type::List(expected), Type::List(found)) => self.unify(&expected, &found),
+            (Type::Function(expected_param, expected), Type::Function(found_param, found)) => {
+                self.unify(&expected_param, &found_param)?;
+                self.unify(&expected, &found)
+            }
+            (Type::Record(expected), Type::Record(found)) => self.unify_records(expected, found),
+            (expected, found) if expected == found => Ok(()),
+            (expected, found) => Err(Conflict::Types(Box::new(expected), Box::new(found))),
+        }
+    }
+
+    /// Unify two record types: The fields they share must have the same types, and the fields
+    /// only one of them has are added to the other one, if it is open.
+    fn unify_records(&mut self, expected: RecordType, found: RecordType) -> Result<(), Conflict> {
+        let expected = self.flatten(expected);
+        let found = self.flatten(found);
+
+        for (name, ty) in &expected.fields {
+            if let Some(found) = found.fields.get(name) {
+                self.unify(ty, found)?;
+            }
+        }
+
+        let only_in = |a: &RecordType, b: &RecordType| -> IndexMap<_, _> {
+            let fields = a.fields.iter();
+            fields
+                .filter(|(name, _)| !b.fields.contains_key(*name))
+                .map(|(name, ty)| (name.clone(), ty.clone()))
+                .collect()
+        };
+        let missing = only_in(&expected, &found);
+        let extra = only_in(&found, &expected);
+        let rows = |fields, rest| Type::Record(RecordType { fields, rest });
+
+        match (expected.rest, found.rest) {
+            (_, None) if !missing.is_empty() => {
+                let name = missing.keys().next().expect("a missing field");
+                Err(Conflict::MissingField(name.clone()))
+            }
+            (None, _) if !extra.is_empty() => Err(Conflict::Types(
+                Box::new(Type::Record(expected)),
+                Box::new(Type::Record(found)),
+            )),
+            (None, None) => Ok(()),
+            (Some(rest), None) => self.bind(rest, rows(extra, None)),
+            (None, Some(rest)) => self.bind(rest, rows(missing, None)),
+            (Some(a), Some(b)) if a == b => match missing.is_empty() && extra.is_empty() {
+                true => Ok(()),
+                false => Err(Conflict::Types(
+                    Box::new(Type::Record(expected)),
+                    Box::new(Type::Record(found)),
+                )),
+            },
+            (Some(expected_rest), Some(found_rest)) => {
+                let rest = Some(self.fresh_var());
+                self.bind(expected_rest, rows(extra, rest))?;
+                self.bind(found_rest, rows(missing, rest))
+            }
+        }
+    }
+
+    fn bind(&mut self, var: TypeVar, ty: Type) -> Result<(), Conflict> {
+        if self.occurs(var, &ty) {
+            return Err(Conflict::Recursive);
+        }
+        self.vars[var.0 as usize] = Some(ty);
+        Ok(())
+    }
+
+    /// Whether a type variable occurs in a type.
+    fn occurs(&self, var: TypeVar, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => other == var,
+            Type::List(item) => self.occurs(var, &item),
+            Type::Function(param, result) => self.occurs(var, &param) || self.occurs(var, &result),
+            Type::Record(record) => {
+                record.fields.values().any(|ty| self.occurs(var, ty))
+                    || record
+                        .rest
+                        .is_some_and(|rest| self.occurs(var, &Type::Var(rest)))
+            }
+            _ => false,
+        }
+    }
+
+    /// Follow the bindings of a type variable, until a type that is not a bound variable.
+    fn resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty;
+        while let Type::Var(var) = ty {
+            match &self.vars[var.0 as usize] {
+                Some(bound) => ty = bound,
+                None => break,
+            }
+        }
+        ty.clone()
+    }
+
+    /// Collect the fields a record type has through the bindings of its row variable.
+    fn flatten(&self, mut record: RecordType) -> RecordType {
+        while let Some(rest) = record.rest {
+            match &self.vars[rest.0 as usize] {
+                Some(Type::Record(more)) => {
+                    for (name, ty) in &more.fields {
+                        record.fields.insert(name.clone(), ty.clone());
+                    }
+                    record.rest = more.rest;
+                }
+                Some(_) => unreachable!("row variables are only bound to records"),
+                None => break,
+            }
+        }
+        record
+    }
+
+    /// Replace the bound type variables within a type with their types.
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::List(item) => Type::List(Box::new(self.zonk(&item))),
+            Type::Function(param, result) => Type::function(self.zonk(&param), self.zonk(&result)),
+            Type::Record(record) => {
+                let mut record = self.flatten(record);
+                for ty in record.fields.values_mut() {
+                    *ty = self.zonk(ty);
+                }
+                Type::Record(record)
+            }
+            ty => ty,
+        }
+    }
+
+    fn fresh_var(&mut self) -> TypeVar {
+        self.vars.push(None);
+        TypeVar(self.vars.len() as u32 - 1)
+    }
+
+    fn fresh(&mut self) -> Type {
+        Type::Var(self.fresh_var())
+    }
+
+    fn span(&self, span: Span) -> SourceSpan {
+        self.source
+            .local_span(span.lo().to_u32(), span.hi().to_u32())
+    }
+
+    fn name(&self, name: Interned<Symbol>) -> &'a str {
+        self.cx.symbol_interner.lookup(name).as_str()
+    }
+}
+
+fn operator_type(op: Operator) -> Type {
+    match op {
+        Operator::Neg => Type::function(Type::Number, Type::Number),
+        Operator::Not => Type::function(Type::Boolean, Type::Boolean),
+        Operator::Add | Operator::Sub | Operator::Mul | Operator::Div => {
+            Type::function(Type::Number, Type::function(Type::Number, Type::Number))
+        }
+        // Merges are typed from their operands, when they are applied.
+        Operator::Merge => {
+            Type::function(Type::Dynamic, Type::function(Type::Dynamic, Type::Dynamic))
+        }
+    }
+}
+
+/// The type of a value. Lists have the type of their items if they all have the same type, and
+/// functions, whose types are not known, have the dynamic type.
+pub fn value_type(value: &Value) -> Type {
+    match &*value.kind {
+        ValueKind::Null => Type::Null,
+        ValueKind::Boolean(_) => Type::Boolean,
+        ValueKind::Number(_) => Type::Number,
+        ValueKind::String(_) => Type::String,
+        ValueKind::Record(record) => Type::Record(RecordType {
+            fields: record
+                .fields
+                .iter()
+                .map(|(name, value)| (name.clone(), value_type(value)))
+                .collect(),
+            rest: None,
+        }),
+        ValueKind::List(list) => {
+            let mut items = list.items().iter().map(value_type);
+            let first = items.next().unwrap_or(Type::Dynamic);
+            let item = match items.all(|item| item == first) {
+                true => first,
+                false => Type::Dynamic,
+            };
+            Type::List(Box::new(item))
+        }
+        ValueKind::Function(_) => Type::Dynamic,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::{parser, symbol::Symbol};
+
+    fn check(source: &str) -> Result<Type, Vec<TypeError>> {
+        let mut cx = Context::new();
+        let source = Source::at(0, source);
+        let parsed = parser::parse(&mut cx, &source);
+        assert!(
+            parsed.errors.is_empty(),
+            "cannot parse `{}`",
+            source.contents()
+        );
+
+        let args = cx.symbol_interner.intern(Symbol::new("args"));
+        let data = cx.symbol_interner.intern(Symbol::new("data"));
+        let mut checker = Checker::new(&cx, &source);
+        checker.bind_open_record(args);
+        checker.reserve(args);
+        checker.bind_value(
+            data,
+            &Value::list(vec![Value::null(), Value::number(1.into())]),
+        );
+        checker.check(&parsed.expr)
+    }
+
+    #[track_caller]
+    fn assert_type(source: &str, expected: &str) {
+        match check(source) {
+            Ok(ty) => assert_eq!(ty.to_string(), expected, "source: `{source}`"),
+            Err(errors) => panic!("cannot type `{source}`: {errors:?}"),
+        }
+    }
+
+    /// Check that a source has a single error, with the given message, at the given text.
+    #[track_caller]
+    fn assert_error(source: &str, message: &str, at: &str) {
+        match check(source) {
+            Ok(ty) => panic!("unexpected type for `{source}`: {ty}"),
+            Err(errors) => {
+                assert_eq!(errors.len(), 1, "errors: {errors:?}");
+                assert_eq!(errors[0].to_string(), message, "source: `{source}`");
+
+                let label = errors[0].labels().unwrap().next().unwrap();
+                let text = &source[label.offset()..label.offset() + label.len()];
+                assert_eq!(text, at, "source: `{source}`");
+            }
+        }
+    }
+
+    #[test]
+    fn literals_and_operators() {
+        assert_type("1 + 2 * -3", "Number");
+        assert_type("not true", "Boolean");
+        assert_type(
+            r#"{ a = "b", "c d" = null }"#,
+            r#"{a: String, "c d": Null}"#,
+        );
+        assert_type("[[1], []]", "[[Number]]");
+        assert_type("[]", "[a]");
+
+        assert_error("1 + true", "Expected Number, found Boolean", "true");
+        assert_error("not (1 - 2)", "Expected Boolean, found Number", "1 - 2");
+        assert_error("1 2", "Expected a function, found Number", "1");
+    }
+
+    #[test]
+    fn lists_of_different_types_are_dynamic() {
+        assert_type(r#"[1, "a"]"#, "[Dynamic]");
+        assert_type("data", "[Dynamic]");
+        assert_type("[data, [1]]", "[[Dynamic]]");
+    }
+
+    #[test]
+    fn bindings() {
+        assert_type("let a = 1, b = { c = a } in b", "{c: Number}");
+        assert_error("let a = b in a", "Unbound variable `b`", "b");
+        assert_error("[let a = 1 in a, a]", "Unbound variable `a`", "a");
+        assert_error(
+            "let a = 1, args = {} in a",
+            "`args` is reserved, and cannot be bound",
+            "args",
+        );
+        assert_type("{ args = 1 }.args", "Number");
+    }
+
+    #[test]
+    fn records_are_row_polymorphic() {
+        assert_type("{ a = 1 }.a", "Number");
+        assert_type("args.a", "a");
+        assert_type("args.a.b", "a");
+        assert_type("let a = args.a in [a.b + 1, a.c]", "[Number]");
+        assert_type("let a = args.a, b = a.b + 1 in a", "{b: Number, ..}");
+        assert_type(
+            "let a = args.a in [a, { b = 1, c = true }]",
+            "[{b: Number, c: Boolean}]",
+        );
+        assert_type("let a = args.a in [{ b = 1 }, a.b]", "[{b: Number}]");
+
+        assert_error("{ a = 1 }.b", "Missing field `b`", "{ a = 1 }");
+        assert_error("[1].a", "Expected a record, found [Number]", "[1]");
+        assert_error(
+            "let a = args.a, b = a.b + 1 in a.b.c",
+            "Expected a record, found Number",
+            "a.b",
+        );
+        assert_error(
+            "let a = args.a in [a.b + 1, not a.b]",
+            "Expected Boolean, found Number",
+            "a.b",
+        );
+    }
+
+    #[test]
+    fn merged_records() {
+        assert_type(
+            "{ a = 1, b = { c = true } } // { b = { d = null }, e = \"f\" }",
+            "{a: Number, b: {c: Boolean, d: Null}, e: String}",
+        );
+        assert_type(
+            "{ a = 1, b = [1], c = null } // { a := \"a\", b += [2], -c }",
+            "{a: String, b: [Number]}",
+        );
+        assert_type(r#"{ a = [1] } // { a = ["b"] }"#, "{a: Dynamic}");
+        assert_type(r#"{ a = args.b } // { a = "c" }"#, "{a: String}");
+        assert_error(
+            r#"{ a = 1 } // { a = "b" }"#,
+            "Cannot merge String into Number",
+            r#""b""#,
+        );
+        assert_error(
+            r#"let base = { a = { b = 1 } } in base // { a = { b = "c" } }"#,
+            "Cannot merge String into Number",
+            r#""c""#,
+        );
+        assert_error(
+            "let base = { a = 1 }, other = { a = true } in base // other",
+            "Cannot merge Boolean into Number",
+            "other",
+        );
+        assert_type("args.a // { b = 1 }", "{b: Number, ..}");
+        assert_error("{} // 1", "Expected a record, found Number", "1");
+    }
+
+    #[test]
+    fn self_and_super() {
+        assert_type(
+            "{ a = 1, b = self.a + 1, c = super.c }",
+            "{a: Number, b: Number, c: a}",
+        );
+        assert_type("{ a = { b = self.c }, c = true }.a", "{b: a}");
+        assert_error(
+            r#"{ a = "b", c = self.a + 1 }"#,
+            "Expected Number, found String",
+            "self.a",
+        );
+        assert_error("self.a", "`self` is only bound within records", "self");
+    }
+
+    #[test]
+    fn annotations() {
+        assert_type("(1 : Integer 1..10)", "Number");
+        assert_type(
+            r#"let type Env = "dev" | "prod" in (args.env : Env)"#,
+            "String",
+        );
+        assert_type("(args.a : { b : [Number], .. })", "{b: [Number], ..}");
+        assert_type("let a : { b : Number, .. } = args.a in a.c", "a");
+        assert_type(
+            "let type A = [B], type B = Boolean in ([] : A)",
+            "[Boolean]",
+        );
+
+        assert_error(
+            r#"let a : Number 1..2 = "a" in a"#,
+            "Expected Number, found String",
+            "\"a\"",
+        );
+        assert_error("(1 : Port)", "Unknown type `Port`", "Port");
+        assert_error(
+            "let type A = [A] in ([] : A)",
+            "Recursive type alias `A`",
+            "[A]",
+        );
+        assert_error(
+            r#"(1 : String ~ "(")"#,
+            "Invalid pattern: unclosed group",
+            r#"String ~ "(""#,
+        );
+    }
+
+    #[test]
+    fn all_errors_are_reported() {
+        let errors = check("[1 + null, x, {}.a]").unwrap_err();
+        let messages: Vec<_> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            [
+                "Expected Number, found Null",
+                "Unbound variable `x`",
+                "Missing field `a`"
+            ]
+        );
+    }
+}
@@ -1,2 +1,5 @@
 pub mod compiler;
+pub mod lsp;
+pub mod output;
+pub mod repl;
 pub mod vm;
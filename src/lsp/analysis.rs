@@ -0,0 +1,606 @@
+//! Queries over the syntax tree of a document, for the language server.
+//!
+//! The queries work on the lossless syntax tree rather than the AST, so that they keep working on
+//! documents with syntax errors, and can find comments. Offsets are byte offsets into the text of
+//! the document.
+
+use core::ops::Range;
+
+use logos::Logos;
+
+use crate::{
+    compiler::{
+        source::{lexer::Token, Span},
+        syntax::{NodeKind, SyntaxElement, SyntaxNode, SyntaxToken, TokenKind},
+    },
+    vm::builtins::{self, Module, Native, STD_PREFIX},
+};
+
+/// The name every document can refer to, besides its own bindings.
+const ARGS: &str = "args";
+
+/// An identifier inserted at the cursor before parsing a document for completion, so that the
+/// expression being typed parses as a name or field access.
+pub const COMPLETION_MARKER: &str = "__dek_completion__";
+
+/// The texts to parse for completion at an offset, in order of preference, with the
+/// [`COMPLETION_MARKER`] inserted at the offset.
+///
+/// Code being typed is often incomplete, and does not parse at all, so the second text ends at the
+/// marker, and closes what was left open before it.
+pub fn completion_texts(text: &str, offset: usize) -> [String; 2] {
+    let (before, after) = text.split_at(offset);
+
+    let mut open = Vec::new();
+    for token in Token::lexer(before).flatten() {
+        match token {
+            Token::LParen => open.push(")"),
+            Token::LBracket => open.push("]"),
+            Token::LBrace => open.push("}"),
+            Token::Let => open.push(" in null"),
+            Token::RParen | Token::RBracket | Token::RBrace | Token::In => {
+                open.pop();
+            }
+            _ => {}
+        }
+    }
+    let closing: String = open.into_iter().rev().collect();
+
+    [
+        format!("{before}{COMPLETION_MARKER}{after}"),
+        format!("{before}{COMPLETION_MARKER}{closing}"),
+    ]
+}
+
+/// The text of a document, with its syntax tree.
+pub struct Document<'a> {
+    text: &'a str,
+    syntax: &'a SyntaxNode,
+}
+
+/// Where a name is defined.
+#[derive(Debug, PartialEq)]
+pub enum Definition {
+    /// A `let` binding or record field, with the range of its name.
+    Binding(Range<usize>),
+
+    /// An imported file, with its path as written in the import.
+    Import(String),
+}
+
+/// What to show when hovering over a name.
+#[derive(Debug, PartialEq)]
+pub struct Hover {
+    /// The range of the name.
+    pub range: Range<usize>,
+    pub name: String,
+
+    /// The range of the name where it is defined, if it is a `let` binding or a record field. Its
+    /// type is found by checking the document.
+    pub definition: Option<Range<usize>>,
+
+    /// What kind of value the name has, if it is known without checking the document: Functions of
+    /// the standard library.
+    pub kind: Option<&'static str>,
+
+    /// The comment right before the definition of the name, or the documentation of a function of
+    /// the standard library.
+    pub doc: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub is_field: bool,
+    pub kind: Option<&'static str>,
+}
+
+impl<'a> Document<'a> {
+    /// Wrap a syntax tree, with its [`NodeKind::Root`] node, and the text it was parsed from.
+    pub fn new(text: &'a str, syntax: &'a SyntaxNode) -> Self {
+        debug_assert_eq!(syntax.kind, NodeKind::Root);
+        Self { text, syntax }
+    }
+
+    /// The range of a span, relative to the start of the document.
+    pub fn range(&self, span: Span) -> Range<usize> {
+        let start = self.syntax.span.lo().to_u32();
+        (span.lo().to_u32() - start) as usize..(span.hi().to_u32() - start) as usize
+    }
+
+    fn slice(&self, span: Span) -> &'a str {
+        &self.text[self.range(span)]
+    }
+
+    /// The definition of the name, or the import, at an offset.
+    pub fn definition(&self, offset: usize) -> Option<Definition> {
+        let (path, token) = self.token_at(offset)?;
+        let parent = *path.last()?;
+        match (parent.kind, token.kind) {
+            (NodeKind::Import, TokenKind::String) => {
+                Some(Definition::Import(self.name(token)?.to_owned()))
+            }
+            _ => {
+                let binding = self.binding_of(&path, token)?;
+                Some(Definition::Binding(
+                    self.range(binding.tokens().next()?.span),
+                ))
+            }
+        }
+    }
+
+    pub fn hover(&self, offset: usize) -> Option<Hover> {
+        let (path, token) = self.token_at(offset)?;
+        let Some(binding) = self.binding_of(&path, token) else {
+            let native = self.native_of(&path, token)?;
+            return Some(Hover {
+                range: self.range(token.span),
+                name: native.name.to_owned(),
+                definition: None,
+                kind: Some("a function"),
+                doc: Some(native.help()),
+            });
+        };
+
+        let mut binding_path = self.path_to(binding)?;
+        binding_path.push(binding);
+
+        Some(Hover {
+            range: self.range(token.span),
+            name: self.name(token)?,
+            definition: Some(self.range(binding.tokens().next()?.span)),
+            kind: None,
+            doc: self.doc(&binding_path),
+        })
+    }
+
+    /// The names that can complete the one at the [`COMPLETION_MARKER`], which the document must
+    /// contain: Field names after a `.`, or the names in scope.
+    pub fn completions(&self) -> Vec<Completion> {
+        let Some(offset) = self.text.find(COMPLETION_MARKER) else {
+            return Vec::new();
+        };
+        let Some((path, token)) = self.token_at(offset) else {
+            return Vec::new();
+        };
+        let Some(&parent) = path.last() else {
+            return Vec::new();
+        };
+
+        let bindings: Vec<_> = match parent.kind {
+            NodeKind::Name => self.scope(&path),
+            NodeKind::Field if parent.tokens().last() == Some(token) => {
+                let Some(record) = parent.nodes().next() else {
+                    return Vec::new();
+                };
+                if let Some(module) = self.module_of(record, &path) {
+                    return module
+                        .natives
+                        .iter()
+                        .map(|native| Completion {
+                            label: native.name.to_owned(),
+                            is_field: true,
+                            kind: Some("a function"),
+                        })
+                        .collect();
+                }
+                self.record_of(record, &path)
+                    .map(|(record, _)| record.nodes().collect())
+                    .unwrap_or_default()
+            }
+            _ => return Vec::new(),
+        };
+
+        let mut completions: Vec<Completion> = Vec::new();
+        for binding in bindings.into_iter().rev() {
+            let Some(label) = binding.tokens().next().and_then(|name| self.name(name)) else {
+                continue;
+            };
+            if label.contains(COMPLETION_MARKER)
+                || completions.iter().any(|other| other.label == label)
+            {
+                continue;
+            }
+
+            let binding_path = self.path_to(binding).map(|mut path| {
+                path.push(binding);
+                path
+            });
+            completions.push(Completion {
+                label,
+                is_field: parent.kind == NodeKind::Field,
+                kind: binding
+                    .nodes()
+                    .last()
+                    .zip(binding_path)
+                    .and_then(|(value, path)| self.kind_of(value, &path)),
+            });
+        }
+
+        completions.reverse();
+        if parent.kind == NodeKind::Name {
+            completions.push(Completion {
+                label: ARGS.to_owned(),
+                is_field: false,
+                kind: Some("a record"),
+            });
+        }
+        completions
+    }
+
+    /// The token at an offset, which may also be right after the token, with the path of nodes
+    /// from the root to the token's parent.
+    fn token_at(&self, offset: usize) -> Option<(Vec<&'a SyntaxNode>, &'a SyntaxToken)> {
+        let mut path = vec![self.syntax];
+        let mut node = self.syntax;
+        loop {
+            // Between two elements, prefer the one that can be a name.
+            let child = node
+                .children
+                .iter()
+                .filter(|child| {
+                    let range = self.range(child.span());
+                    range.start <= offset && offset <= range.end
+                })
+                .filter_map(|child| match child {
+                    SyntaxElement::Node(_) => Some((child, true)),
+                    SyntaxElement::Token(token) if !token.kind.is_trivia() => Some((
+                        child,
+                        matches!(token.kind, TokenKind::Ident | TokenKind::String),
+                    )),
+                    SyntaxElement::Token(_) => None,
+                })
+                .reduce(|best, other| if other.1 && !best.1 { other } else { best })?
+                .0;
+
+            match child {
+                SyntaxElement::Token(token) => return Some((path, token)),
+                SyntaxElement::Node(child) => {
+                    path.push(child);
+                    node = child;
+                }
+            }
+        }
+    }
+
+    /// The path of nodes from the root to the parent of a node in the tree.
+    fn path_to(&self, target: &SyntaxNode) -> Option<Vec<&'a SyntaxNode>> {
+        let mut path = vec![self.syntax];
+        loop {
+            let node = *path.last()?;
+            let child = node.nodes().find(|child| {
+                child.span.lo() <= target.span.lo() && target.span.hi() <= child.span.hi()
+            })?;
+
+            if core::ptr::eq(child, target) {
+                return Some(path);
+            }
+            path.push(child);
+        }
+    }
+
+    /// The name of an identifier or string token.
+    fn name(&self, token: &SyntaxToken) -> Option<String> {
+        match token.kind {
+            TokenKind::Ident => Some(self.slice(token.span).to_owned()),
+            TokenKind::String => match Token::lexer(self.slice(token.span)).next() {
+                Some(Ok(Token::String(name))) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The binding a token refers to or defines, if it is a name, a binding or a field access.
+    fn binding_of(&self, path: &[&'a SyntaxNode], token: &SyntaxToken) -> Option<&'a SyntaxNode> {
+        let (&parent, outer) = path.split_last()?;
+        let name = self.name(token)?;
+
+        match parent.kind {
+            NodeKind::Name => self.lookup(path, &name),
+            NodeKind::Binding => Some(parent),
+            NodeKind::Field if parent.tokens().last() == Some(token) => {
+                let (record, _) = self.record_of(parent.nodes().next()?, outer)?;
+                self.field(record, &name)
+            }
+            _ => None,
+        }
+    }
+
+    /// The function of the standard library a token refers to, if it is a field access on an
+    /// imported module.
+    fn native_of(&self, path: &[&'a SyntaxNode], token: &SyntaxToken) -> Option<&'static Native> {
+        let (&parent, outer) = path.split_last()?;
+        if parent.kind != NodeKind::Field || parent.tokens().last() != Some(token) {
+            return None;
+        }
+        self.module_of(parent.nodes().next()?, outer)?
+            .native(&self.name(token)?)
+    }
+
+    /// The module of the standard library an expression evaluates to, if it is known without
+    /// evaluation. `path` leads to the expression.
+    fn module_of(&self, expr: &'a SyntaxNode, path: &[&'a SyntaxNode]) -> Option<&'static Module> {
+        let (value, _) = self.value_of(expr, path)?;
+        if value.kind != NodeKind::Import {
+            return None;
+        }
+        let token = value
+            .tokens()
+            .find(|token| token.kind == TokenKind::String)?;
+        builtins::module(self.name(token)?.strip_prefix(STD_PREFIX)?)
+    }
+
+    /// The last field of a record with the given name.
+    fn field(&self, record: &'a SyntaxNode, name: &str) -> Option<&'a SyntaxNode> {
+        record
+            .nodes()
+            .filter(|binding| binding.kind == NodeKind::Binding)
+            .filter(|binding| {
+                binding
+                    .tokens()
+                    .next()
+                    .and_then(|token| self.name(token))
+                    .as_deref()
+                    == Some(name)
+            })
+            .last()
+    }
+
+    /// The binding of a name, at the end of a path, that is in scope there.
+    fn lookup(&self, path: &[&'a SyntaxNode], name: &str) -> Option<&'a SyntaxNode> {
+        self.scope(path).into_iter().rev().find(|binding| {
+            binding
+                .tokens()
+                .next()
+                .and_then(|token| self.name(token))
+                .as_deref()
+                == Some(name)
+        })
+    }
+
+    /// The `let` bindings in scope at the end of a path, from the outermost to the innermost. Each
+    /// binding is in scope in the bindings after it, and in the body of its `let`.
+    fn scope(&self, path: &[&'a SyntaxNode]) -> Vec<&'a SyntaxNode> {
+        let mut scope = Vec::new();
+        for pair in path.windows(2) {
+            let [parent, child] = [pair[0], pair[1]];
+            if parent.kind != NodeKind::Let {
+                continue;
+            }
+
+            scope.extend(
+                parent
+                    .nodes()
+                    .take_while(|binding| !core::ptr::eq(*binding, child))
+                    .filter(|binding| binding.kind == NodeKind::Binding),
+            );
+        }
+        scope
+    }
+
+    /// The record literal an expression evaluates to, if that is known without evaluation, with
+    /// the path to it. `path` leads to the expression.
+    fn record_of(
+        &self,
+        expr: &'a SyntaxNode,
+        path: &[&'a SyntaxNode],
+    ) -> Option<(&'a SyntaxNode, Vec<&'a SyntaxNode>)> {
+        let (value, path) = self.value_of(expr, path)?;
+        (value.kind == NodeKind::Record).then_some((value, path))
+    }
+
+    /// The expression that gives an expression its value, looking through parentheses, type
+    /// annotations, names, field accesses and `let` bodies, with the path to it. `path` leads to the expression.
+    fn value_of(
+        &self,
+        expr: &'a SyntaxNode,
+        path: &[&'a SyntaxNode],
+    ) -> Option<(&'a SyntaxNode, Vec<&'a SyntaxNode>)> {
+        let mut inner = path.to_vec();
+        inner.push(expr);
+
+        match expr.kind {
+            NodeKind::Paren | NodeKind::Let => self.value_of(expr.nodes().last()?, &inner),
+            NodeKind::Annotated => self.value_of(expr.nodes().next()?, &inner),
+            NodeKind::Name => {
+                let binding = self.lookup(&inner, &self.name(expr.tokens().next()?)?)?;
+                let mut path = self.path_to(binding)?;
+                path.push(binding);
+                self.value_of(binding.nodes().last()?, &path)
+            }
+            NodeKind::Field => {
+                let (record, mut path) = self.record_of(expr.nodes().next()?, &inner)?;
+                let binding = self.field(record, &self.name(expr.tokens().last()?)?)?;
+                path.extend([record, binding]);
+                self.value_of(binding.nodes().last()?, &path)
+            }
+            _ => Some((expr, path.to_vec())),
+        }
+    }
+
+    /// What kind of value an expression has, if it is known without evaluation.
+    fn kind_of(&self, expr: &'a SyntaxNode, path: &[&'a SyntaxNode]) -> Option<&'static str> {
+        let (value, _) = self.value_of(expr, path)?;
+        match value.kind {
+            NodeKind::Literal => match value.tokens().next()?.kind {
+                TokenKind::Number => Some("a number"),
+                TokenKind::String => Some("a string"),
+                TokenKind::True | TokenKind::False => Some("a boolean"),
+                TokenKind::Null => Some("null"),
+                _ => None,
+            },
+            NodeKind::Record => Some("a record"),
+            NodeKind::List => Some("a list"),
+            NodeKind::Binary => match value.tokens().next()?.kind {
+                TokenKind::SlashSlash => Some("a record"),
+                _ => Some("a number"),
+            },
+            NodeKind::Unary => match value.tokens().next()?.kind {
+                TokenKind::Not => Some("a boolean"),
+                _ => Some("a number"),
+            },
+            _ => None,
+        }
+    }
+
+    /// The comment lines right before the last node of a path, without blank lines in between.
+    fn doc(&self, path: &[&'a SyntaxNode]) -> Option<String> {
+        let (&node, outer) = path.split_last()?;
+        let parent = outer.last()?;
+        let index = parent.children.iter().position(
+            |child| matches!(child, SyntaxElement::Node(child) if core::ptr::eq(child, node)),
+        )?;
+
+        let mut lines = Vec::new();
+        for child in parent.children[..index].iter().rev() {
+            let SyntaxElement::Token(token) = child else {
+                break;
+            };
+            let text = self.slice(token.span);
+            match token.kind {
+                TokenKind::Comment => {
+                    let line = text.trim_start_matches('#');
+                    lines.push(line.strip_prefix(' ').unwrap_or(line).trim_end());
+                }
+                TokenKind::Whitespace if text.matches('\n').count() <= 1 => {}
+                _ => break,
+            }
+        }
+
+        lines.reverse();
+        (!lines.is_empty()).then(|| lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Compiler;
+
+    /// Run a query at the position of the `$` in a source, which is removed.
+    fn at<T>(source: &str, query: impl FnOnce(&Document, usize) -> T) -> T {
+        let offset = source.find('$').expect("source has a `$`");
+        let text = source.replacen('$', "", 1);
+        let parsed = Compiler::new().parse_document("test", &text).unwrap();
+        query(&Document::new(&text, &parsed.syntax), offset)
+    }
+
+    fn definition(source: &str) -> Option<Definition> {
+        at(source, |document, offset| document.definition(offset))
+    }
+
+    fn completions(source: &str) -> Vec<String> {
+        let offset = source.find('$').expect("source has a `$`");
+        let text = source.replacen('$', "", 1);
+        for text in completion_texts(&text, offset) {
+            let parsed = Compiler::new().parse_document("test", &text).unwrap();
+            let completions = Document::new(&text, &parsed.syntax).completions();
+            if !completions.is_empty() {
+                return completions.into_iter().map(|c| c.label).collect();
+            }
+        }
+        Vec::new()
+    }
+
+    #[test]
+    fn names_are_defined_by_their_binding_in_scope() {
+        let binding = |range| Some(Definition::Binding(range));
+        assert_eq!(definition("let a = 1 in $a"), binding(4..5));
+        assert_eq!(definition("let a = 1 in a$"), binding(4..5));
+        assert_eq!(definition("let a = 1, a = a$ in a"), binding(4..5));
+        assert_eq!(definition("let a = 1, a = a in $a"), binding(11..12));
+        assert_eq!(definition("let a = 1 in let b = 2 in $a"), binding(4..5));
+        assert_eq!(definition("let $a = 1 in a"), binding(4..5));
+        assert_eq!(definition("let a = $a in a"), None);
+        assert_eq!(definition("[$b]"), None);
+    }
+
+    #[test]
+    fn fields_are_defined_by_their_binding_in_the_record() {
+        let binding = |range| Some(Definition::Binding(range));
+        assert_eq!(definition("let r = { x = 1 } in r.$x"), binding(10..11));
+        assert_eq!(definition("{ x = { y = 1 } }.x.$y"), binding(8..9));
+        assert_eq!(
+            definition("let r = { s = { t = 1 } }, u = r.s in u.$t"),
+            binding(16..17)
+        );
+        assert_eq!(definition("let r = [1] in r.$x"), None);
+    }
+
+    #[test]
+    fn imports_are_defined_by_their_file() {
+        assert_eq!(
+            definition(r#"let a = import $"data/b.json" in a"#),
+            Some(Definition::Import("data/b.json".to_owned()))
+        );
+    }
+
+    #[test]
+    fn hovers_show_definitions_and_comments() {
+        let source = "
+            let
+                # The number of copies.
+                # At least one.
+                replicas = 2,
+
+                # Not documentation, since a blank line follows.
+
+                name = \"api\",
+            in { a = replicas$, b = name }";
+        let hover = at(source, |document, offset| document.hover(offset)).unwrap();
+        assert_eq!(hover.name, "replicas");
+        let start = source.find("replicas =").unwrap();
+        assert_eq!(hover.definition, Some(start..start + "replicas".len()));
+        assert_eq!(
+            hover.doc.as_deref(),
+            Some("The number of copies.\nAt least one.")
+        );
+
+        let source = source
+            .replace("replicas$", "replicas")
+            .replace("= name", "= $name");
+        let hover = at(&source, |document, offset| document.hover(offset)).unwrap();
+        let start = source.find("name =").unwrap();
+        assert_eq!(hover.definition, Some(start..start + "name".len()));
+        assert_eq!(hover.doc, None);
+    }
+
+    #[test]
+    fn completions_are_names_in_scope_or_fields() {
+        assert_eq!(completions("let a = 1, b = 2 in $"), ["a", "b", "args"]);
+        assert_eq!(completions("let a = 1, b = $ in a"), ["a", "args"]);
+        assert_eq!(
+            completions("let a = 1 in let a = 2, c = a in [c, a$"),
+            ["a", "c", "args"]
+        );
+        assert_eq!(completions("let r = { x = 1, y = 2 } in r.$"), ["x", "y"]);
+        assert_eq!(completions("let r = { x = 1 } in r.x$"), ["x"]);
+        assert_eq!(completions("{ a = [1, (let r = { x = 1 } in r.$"), ["x"]);
+        assert_eq!(completions("{ x = 1 }.x.$"), Vec::<String>::new());
+        assert_eq!(completions("\"$\""), Vec::<String>::new());
+
+        let completions = completions(r#"let s = import "std:string" in s.$"#);
+        assert_eq!(
+            completions.len(),
+            builtins::module("string").unwrap().natives.len()
+        );
+        assert!(completions.contains(&"split".to_owned()));
+    }
+
+    #[test]
+    fn hovers_document_the_standard_library() {
+        let source = r#"let s = import "std:string" in s.pad_l$eft 3 "0" "7""#;
+        let hover = at(source, |document, offset| document.hover(offset)).unwrap();
+        assert_eq!(hover.name, "pad_left");
+        assert_eq!(hover.kind, Some("a function"));
+        let doc = hover.doc.unwrap();
+        assert!(
+            doc.starts_with("`pad_left width fill string`\n\nPad a string at the start"),
+            "{doc}"
+        );
+
+        let source = r#"(import "std:string").n$ope"#;
+        assert_eq!(at(source, |document, offset| document.hover(offset)), None);
+    }
+}
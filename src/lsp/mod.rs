@@ -0,0 +1,484 @@
+//! A language server, speaking the Language Server Protocol over a pair of streams.
+//!
+//! The server keeps the text of the open documents, and answers each request by parsing the
+//! document again: Diagnostics, definitions, hovers and completions all work on the syntax tree
+//! (see [`analysis`]), so that they are available while the document has errors. Hovers show the
+//! types of bindings too, when the document type checks.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+};
+
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Exit, Notification,
+        PublishDiagnostics,
+    },
+    request::{
+        Completion, Formatting, GotoDefinition, HoverRequest, Initialize, Request, Shutdown,
+    },
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticSeverity, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, DocumentFormattingParams, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InitializeResult, Location, MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, ServerInfo, TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit,
+    Url,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::compiler::{
+    source::{EntryContext, FileLoader, SourceContext, SourceLoader},
+    CompileError, Compiler, Entry,
+};
+
+use self::analysis::{Definition, Document};
+
+mod analysis;
+mod text;
+
+/// Serve requests read from `input`, writing responses and notifications to `output`, until the
+/// client asks the server to exit, or closes `input`. Imports are resolved relative to the
+/// importing document, then in `lib_paths`.
+pub fn run(input: impl BufRead, output: impl Write, lib_paths: &[PathBuf]) -> io::Result<()> {
+    let mut server = Server {
+        input,
+        output,
+        compiler: Compiler::with_loader(
+            lib_paths
+                .iter()
+                .fold(FileLoader::new("."), FileLoader::lib_path),
+        ),
+        lib_paths: lib_paths.to_vec(),
+        documents: HashMap::new(),
+        state: State::Uninitialized,
+    };
+    server.run()
+}
+
+// Error codes defined by JSON-RPC and the LSP specification.
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+const SERVER_NOT_INITIALIZED: i32 = -32002;
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Uninitialized,
+    Running,
+    ShutDown,
+}
+
+struct Server<I, O> {
+    input: I,
+    output: O,
+
+    /// Parses and formats documents. Each version of a document is a new source for the compiler.
+    compiler: Compiler,
+    lib_paths: Vec<PathBuf>,
+
+    /// The text of the open documents.
+    documents: HashMap<Url, String>,
+    state: State,
+}
+
+/// A JSON-RPC request, notification or response.
+#[derive(Deserialize)]
+struct Message {
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(default)]
+    method: Option<String>,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize)]
+struct ResponseError {
+    code: i32,
+    message: String,
+}
+
+impl ResponseError {
+    fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl<I: BufRead, O: Write> Server<I, O> {
+    fn run(&mut self) -> io::Result<()> {
+        while let Some(body) = self.read()? {
+            let message: Message = match serde_json::from_str(&body) {
+                Ok(message) => message,
+                Err(err) => {
+                    let error = ResponseError::new(PARSE_ERROR, err.to_string());
+                    self.respond(Value::Null, Err(error))?;
+                    continue;
+                }
+            };
+
+            match (message.id, message.method) {
+                (Some(id), Some(method)) => {
+                    let result = self.request(&method, message.params);
+                    self.respond(id, result)?;
+                }
+                (None, Some(method)) if method == Exit::METHOD => return Ok(()),
+                (None, Some(method)) => self.notification(&method, message.params)?,
+                // Responses to requests from the server: It sends none.
+                (_, None) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        match (self.state, method) {
+            (State::Uninitialized, Initialize::METHOD) => {
+                self.state = State::Running;
+                handle::<Initialize>(params, |_| initialize())
+            }
+            (State::Uninitialized, _) => Err(ResponseError::new(
+                SERVER_NOT_INITIALIZED,
+                "The server is not initialized",
+            )),
+            (State::ShutDown, _) => Err(ResponseError::new(
+                INVALID_REQUEST,
+                "The server is shut down",
+            )),
+            (_, Initialize::METHOD) => Err(ResponseError::new(
+                INVALID_REQUEST,
+                "The server is already initialized",
+            )),
+            (_, Shutdown::METHOD) => {
+                self.state = State::ShutDown;
+                Ok(Value::Null)
+            }
+            (_, GotoDefinition::METHOD) => {
+                handle::<GotoDefinition>(params, |params| self.definition(params))
+            }
+            (_, HoverRequest::METHOD) => {
+                handle::<HoverRequest>(params, |params| self.hover(params))
+            }
+            (_, Completion::METHOD) => {
+                handle::<Completion>(params, |params| self.completion(params))
+            }
+            (_, Formatting::METHOD) => {
+                handle::<Formatting>(params, |params| self.formatting(params))
+            }
+            _ => Err(ResponseError::new(
+                METHOD_NOT_FOUND,
+                format!("Unsupported request `{method}`"),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: Value) -> io::Result<()> {
+        // Notifications have no response to report errors in, so invalid ones are ignored.
+        if self.state != State::Running {
+            return Ok(());
+        }
+
+        let uri = match method {
+            DidOpenTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidOpenTextDocumentParams>(params) else {
+                    return Ok(());
+                };
+                let document = params.text_document;
+                self.documents.insert(document.uri.clone(), document.text);
+                document.uri
+            }
+            DidChangeTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidChangeTextDocumentParams>(params)
+                else {
+                    return Ok(());
+                };
+                // Documents are synchronized in full, so the last change has the whole text.
+                let Some(change) = params.content_changes.into_iter().last() else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents.insert(uri.clone(), change.text);
+                uri
+            }
+            DidCloseTextDocument::METHOD => {
+                let Ok(params) = serde_json::from_value::<DidCloseTextDocumentParams>(params)
+                else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                // Clear the diagnostics of the closed document.
+                return self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+                    uri,
+                    diagnostics: Vec::new(),
+                    version: None,
+                });
+            }
+            _ => return Ok(()),
+        };
+
+        let diagnostics = self.diagnostics(&uri);
+        self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        })
+    }
+
+    fn diagnostics(&mut self, uri: &Url) -> Vec<Diagnostic> {
+        let Some(text) = self.documents.get(uri) else {
+            return Vec::new();
+        };
+
+        let errors = match self.compiler.parse_document(uri.as_str(), text) {
+            Ok(parsed) if parsed.errors.is_empty() => {
+                match self.compiler.check_document(uri.as_str(), text) {
+                    Err(CompileError::Type(errors)) => return diagnostics(text, errors.errors()),
+                    _ => return Vec::new(),
+                }
+            }
+            Ok(parsed) => parsed.errors,
+            Err(err) => {
+                return vec![diagnostic(text::range(text, 0..0), err.to_string())];
+            }
+        };
+
+        diagnostics(text, &errors)
+    }
+
+    fn definition(&mut self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let text = self.documents.get(&uri)?;
+        let parsed = self.compiler.parse_document(uri.as_str(), text).ok()?;
+        let document = Document::new(text, &parsed.syntax);
+
+        let location = match document.definition(text::offset(text, position.position))? {
+            Definition::Binding(range) => Location::new(uri.clone(), text::range(text, range)),
+            Definition::Import(path) => {
+                let dir = uri.to_file_path().ok()?.parent()?.to_owned();
+                let mut loader = self
+                    .lib_paths
+                    .iter()
+                    .fold(FileLoader::new(dir), FileLoader::lib_path);
+
+                let mut cx = SourceContext::new();
+                cx.extensions_mut().insert(EntryContext);
+                let path = loader.resolve(&cx, &path).ok()?;
+
+                Location::new(Url::from_file_path(path).ok()?, Default::default())
+            }
+        };
+
+        Some(GotoDefinitionResponse::Scalar(location))
+    }
+
+    fn hover(&mut self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let text = self.documents.get(&uri)?;
+        let parsed = self.compiler.parse_document(uri.as_str(), text).ok()?;
+        let document = Document::new(text, &parsed.syntax);
+
+        let hover = document.hover(text::offset(text, position.position))?;
+        // Bindings have a type if the document has no errors.
+        let ty = hover.definition.and_then(|definition| {
+            let types = self.compiler.check_document(uri.as_str(), text).ok()?;
+            let (_, ty) = types
+                .bindings
+                .into_iter()
+                .find(|(range, _)| *range == definition)?;
+            Some(ty)
+        });
+        let mut value = match (ty, hover.kind) {
+            (Some(ty), _) => format!("`{} : {ty}`", hover.name),
+            (None, Some(kind)) => format!("`{}` is {kind}", hover.name),
+            (None, None) => format!("`{}`", hover.name),
+        };
+        if let Some(doc) = hover.doc {
+            value = format!("{value}\n\n{doc}");
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(text::range(text, hover.range)),
+        })
+    }
+
+    fn completion(&mut self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let uri = position.text_document.uri;
+        let text = self.documents.get(&uri)?;
+        let offset = text::offset(text, position.position);
+
+        for text in analysis::completion_texts(text, offset) {
+            let parsed = self.compiler.parse_document(uri.as_str(), &text).ok()?;
+            let completions = Document::new(&text, &parsed.syntax).completions();
+            if completions.is_empty() {
+                continue;
+            }
+
+            let items = completions
+                .into_iter()
+                .map(|completion| CompletionItem {
+                    label: completion.label,
+                    kind: Some(match completion.is_field {
+                        true => CompletionItemKind::FIELD,
+                        false => CompletionItemKind::VARIABLE,
+                    }),
+                    detail: completion.kind.map(str::to_owned),
+                    ..Default::default()
+                })
+                .collect();
+            return Some(CompletionResponse::Array(items));
+        }
+
+        None
+    }
+
+    /// Replace the whole document with its formatted text, with its comments and blank lines, unless
+    /// it has errors.
+    fn formatting(&mut self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let uri = params.text_document.uri;
+        let text = self.documents.get(&uri)?;
+        let entry = Entry::Inline {
+            name: uri.as_str(),
+            contents: text,
+        };
+        let formatted = self.compiler.format(entry).ok()?;
+
+        let edits = match formatted == *text {
+            true => Vec::new(),
+            false => vec![TextEdit::new(text::range(text, 0..text.len()), formatted)],
+        };
+        Some(edits)
+    }
+
+    fn notify<N: Notification>(&mut self, params: N::Params) -> io::Result<()> {
+        self.write(json!({
+            "jsonrpc": "2.0",
+            "method": N::METHOD,
+            "params": params,
+        }))
+    }
+
+    fn respond(&mut self, id: Value, result: Result<Value, ResponseError>) -> io::Result<()> {
+        self.write(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        })
+    }
+
+    /// Read the body of the next message, or `None` at the end of the input.
+    fn read(&mut self) -> io::Result<Option<String>> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut length = None;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+
+            let header = line.trim_end_matches(['\r', '\n']);
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    length = Some(
+                        value
+                            .trim()
+                            .parse::<usize>()
+                            .map_err(|_| invalid("Invalid Content-Length header"))?,
+                    );
+                }
+            }
+        }
+
+        let length = length.ok_or_else(|| invalid("Missing Content-Length header"))?;
+        let mut body = vec![0; length];
+        self.input.read_exact(&mut body)?;
+        String::from_utf8(body)
+            .map(Some)
+            .map_err(|_| invalid("Message is not UTF-8"))
+    }
+
+    fn write(&mut self, message: Value) -> io::Result<()> {
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.output.flush()
+    }
+}
+
+/// Run the handler of a request on its parameters.
+fn handle<R: Request>(
+    params: Value,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Result<Value, ResponseError> {
+    let params = serde_json::from_value(params)
+        .map_err(|err| ResponseError::new(INVALID_PARAMS, err.to_string()))?;
+    Ok(serde_json::to_value(handler(params)).expect("results serialize to JSON"))
+}
+
+fn initialize() -> InitializeResult {
+    InitializeResult {
+        capabilities: ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            definition_provider: Some(OneOf::Left(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec![".".to_owned()]),
+                ..Default::default()
+            }),
+            document_formatting_provider: Some(OneOf::Left(true)),
+            ..Default::default()
+        },
+        server_info: Some(ServerInfo {
+            name: "dek".to_owned(),
+            version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+        }),
+    }
+}
+
+/// Convert errors in a document, with their first label, and their help.
+fn diagnostics(text: &str, errors: &[impl miette::Diagnostic]) -> Vec<Diagnostic> {
+    errors
+        .iter()
+        .map(|error| {
+            let range = error
+                .labels()
+                .and_then(|mut labels| labels.next())
+                .map_or(0..0, |label| label.offset()..label.offset() + label.len());
+
+            let mut message = error.to_string();
+            if let Some(help) = error.help() {
+                message = format!("{message}\n{help}");
+            }
+
+            diagnostic(text::range(text, range), message)
+        })
+        .collect()
+}
+
+fn diagnostic(range: lsp_types::Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("dek".to_owned()),
+        message,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,60 @@
+//! Conversion between byte offsets and LSP positions, whose columns count UTF-16 code units.
+
+use core::ops::Range;
+
+use lsp_types::Position;
+
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+pub fn range(text: &str, range: Range<usize>) -> lsp_types::Range {
+    lsp_types::Range {
+        start: position(text, range.start),
+        end: position(text, range.end),
+    }
+}
+
+/// The byte offset of a position, clamped to the end of its line, or of the text.
+pub fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(index) => line_start += index + 1,
+            None => return text.len(),
+        }
+    }
+
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+    for (index, char) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + index;
+        }
+        units += char.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_count_utf16_code_units() {
+        let text = "a\n\"é𝄞\" b\nc";
+        let b = text.find('b').unwrap();
+        assert_eq!(position(text, b), Position::new(1, 6));
+        assert_eq!(offset(text, Position::new(1, 6)), b);
+        assert_eq!(offset(text, Position::new(2, 0)), text.len() - 1);
+
+        assert_eq!(offset(text, Position::new(0, 10)), 1);
+        assert_eq!(offset(text, Position::new(5, 0)), text.len());
+    }
+}
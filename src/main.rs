@@ -1,10 +1,532 @@
-use dek::compiler::Compiler;
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+    thread,
+    time::Duration,
+};
 
-fn main() -> miette::Result<()> {
+use clap::{Args, Parser, Subcommand};
+use dek::{
+    compiler::{
+        source::{FileLoader, SourceError},
+        CompileError, Compiler, Entry,
+    },
+    output::{
+        self,
+        explain::Explanation,
+        multi,
+        schema::{Schema, SchemaErrors},
+        Format, Options, OutputError,
+    },
+    repl::{Repl, Response},
+    vm::value::Value,
+};
+use miette::{miette, Report};
+
+/// Evaluate dek programs into data.
+///
+/// Exits with 1 when the program or its output has errors, 2 when the command line is invalid, and
+/// 3 when files cannot be read or written.
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Search DIR for imported files that are not found relative to the importing file. May be
+    /// given several times, in which case the directories are searched in order.
+    #[arg(short = 'L', long = "lib", value_name = "DIR", global = true)]
+    lib_paths: Vec<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Evaluate a program, and output the result
+    Eval(EvalArgs),
+
+    /// Evaluate a program, and show the definitions, overrides and merges that produced the value
+    /// at a path in the result
+    Explain(ExplainArgs),
+
+    /// Check a program for syntax, import and type errors, without evaluating it
+    Check(Input),
+
+    /// Evaluate a program again whenever it or a file it imports changes
+    Watch(WatchArgs),
+
+    /// Rewrite files in their canonical format
+    Fmt(FmtArgs),
+
+    /// Evaluate expressions interactively
+    Repl,
+
+    /// Run a language server, communicating over standard input and output
+    Lsp,
+}
+
+/// Where to read a program from.
+#[derive(Args)]
+struct Input {
+    /// The file to read the program from
+    #[arg(required_unless_present = "inline", conflicts_with = "inline")]
+    file: Option<String>,
+
+    /// Read the program from the given expression instead of a file
+    #[arg(short = 'e', long = "exec", value_name = "EXPR")]
+    inline: Option<String>,
+}
+
+impl Input {
+    fn entry(&self) -> Entry<'_> {
+        match (&self.file, &self.inline) {
+            (_, Some(contents)) => Entry::Inline {
+                name: "<exec>",
+                contents,
+            },
+            (Some(file), None) => Entry::File(file),
+            (None, None) => unreachable!("a file or an expression is required"),
+        }
+    }
+}
+
+/// The external arguments to evaluate a program with.
+#[derive(Args)]
+struct External {
+    /// Pass an external argument, reachable as `args.NAME`, with a string value
+    #[arg(long = "arg", value_name = "NAME=VALUE", value_parser = parse_arg)]
+    args: Vec<(String, String)>,
+
+    /// Pass an external argument, with the value of a dek expression
+    #[arg(long = "arg-expr", value_name = "NAME=EXPR", value_parser = parse_arg)]
+    arg_exprs: Vec<(String, String)>,
+
+    /// Pass an external argument, with the contents of a JSON, YAML or TOML file
+    #[arg(long = "arg-file", value_name = "NAME=PATH", value_parser = parse_arg)]
+    arg_files: Vec<(String, String)>,
+}
+
+impl External {
+    /// Supply the arguments to a compiler, in the order of their kinds: Strings, then expressions,
+    /// which can refer to the strings, then files.
+    fn supply(&self, compiler: &mut Compiler) -> Result<(), Failure> {
+        for (name, value) in &self.args {
+            compiler.arg(name, Value::string(value));
+        }
+        for (name, contents) in &self.arg_exprs {
+            let entry = Entry::Inline {
+                name: &format!("--arg-expr {name}"),
+                contents,
+            };
+            let value = compiler.eval(entry)?;
+            compiler.arg(name, value);
+        }
+        for (name, path) in &self.arg_files {
+            let value = compiler.import(path)?;
+            compiler.arg(name, value);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+struct EvalArgs {
+    #[command(flatten)]
+    input: Input,
+
+    #[command(flatten)]
+    external: External,
+
+    /// Output only the value at the given path, such as `services.api.ports[0]`
+    #[arg(long = "expr", value_name = "PATH", value_parser = parse_path)]
+    path: Option<output::Path>,
+
+    /// The output format: json, yaml or toml
+    #[arg(long, value_parser = parse_format, default_value = "json")]
+    format: Format,
+
+    /// Validate the output against a JSON Schema file. Violations point at the definitions of the
+    /// values that cause them
+    #[arg(long, value_name = "FILE")]
+    schema: Option<String>,
+
+    /// Round numbers with no exact decimal representation to PLACES decimal places, rather than
+    /// rejecting them
+    #[arg(long = "round", value_name = "PLACES")]
+    round_to: Option<u64>,
+
+    /// Write a record of files to DIR, rather than printing the result. The file names are
+    /// printed instead
+    #[arg(short = 'm', long = "multi", value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+
+    /// List the files that would be written to DIR, without writing them
+    #[arg(long, requires = "out_dir")]
+    dry_run: bool,
+}
+
+#[derive(Args)]
+struct ExplainArgs {
+    /// The file to read the program from
+    file: String,
+
+    /// The path of the value to explain, such as `services.api.replicas`
+    #[arg(value_parser = parse_path)]
+    path: output::Path,
+
+    #[command(flatten)]
+    external: External,
+}
+
+#[derive(Args)]
+struct WatchArgs {
+    /// The file to read the program from
+    file: String,
+
+    /// Write the output to FILE, rather than printing it
+    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// The output format: json, yaml or toml. Defaults to the format named by the extension of
+    /// the output file, or json
+    #[arg(long, value_parser = parse_format)]
+    format: Option<Format>,
+
+    /// Round numbers with no exact decimal representation to PLACES decimal places, rather than
+    /// rejecting them
+    #[arg(long = "round", value_name = "PLACES")]
+    round_to: Option<u64>,
+
+    /// How often to check the files for changes, in milliseconds
+    #[arg(long, value_name = "MS", default_value = "250")]
+    interval: u64,
+}
+
+#[derive(Args)]
+struct FmtArgs {
+    /// Fail if any file is not formatted, instead of rewriting it
+    #[arg(long)]
+    check: bool,
+
+    /// The files to format
+    #[arg(required = true)]
+    files: Vec<String>,
+}
+
+fn parse_format(name: &str) -> Result<Format, String> {
+    Format::from_name(name).ok_or_else(|| format!("unknown output format `{name}`"))
+}
+
+fn parse_arg(arg: &str) -> Result<(String, String), String> {
+    match arg.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_owned(), value.to_owned())),
+        _ => Err(format!("expected `NAME=VALUE`, found `{arg}`")),
+    }
+}
+
+fn parse_path(path: &str) -> Result<output::Path, String> {
+    output::Path::parse(path).ok_or_else(|| format!("invalid path `{path}`"))
+}
+
+/// The ways a command can fail, which exit with different codes.
+enum Failure {
+    /// The program or its output has errors.
+    Diagnostics(Report),
+
+    /// Files could not be read or written.
+    Io(Report),
+}
+
+impl Failure {
+    fn io(err: io::Error, path: &Path) -> Self {
+        Self::Io(miette!("I/O error for `{}`: {err}", path.display()))
+    }
+}
+
+impl From<CompileError> for Failure {
+    fn from(err: CompileError) -> Self {
+        match err {
+            CompileError::Source(SourceError::Loader(_)) => Self::Io(err.into()),
+            err => Self::Diagnostics(err.into()),
+        }
+    }
+}
+
+impl From<OutputError> for Failure {
+    fn from(err: OutputError) -> Self {
+        Self::Diagnostics(err.into())
+    }
+}
+
+fn main() -> ExitCode {
     miette::set_panic_hook();
 
-    let mut compiler = Compiler::new();
-    compiler.compile("main.dek")?;
+    let cli = Cli::parse();
+
+    let loader = cli
+        .lib_paths
+        .iter()
+        .fold(FileLoader::new("."), FileLoader::lib_path);
+    let mut compiler = Compiler::with_loader(loader);
+
+    let result = match &cli.command {
+        Command::Eval(args) => eval(&mut compiler, args),
+        Command::Explain(args) => explain(&mut compiler, args),
+        Command::Check(input) => compiler.check(input.entry()).map_err(Failure::from),
+        Command::Watch(args) => watch(&mut compiler, args),
+        Command::Fmt(args) => fmt(&mut compiler, args),
+        Command::Repl => repl(compiler),
+        Command::Lsp => dek::lsp::run(io::stdin().lock(), io::stdout().lock(), &cli.lib_paths)
+            .map_err(|err| Failure::Io(miette!("Language server I/O error: {err}"))),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(Failure::Diagnostics(report)) => {
+            eprintln!("{report:?}");
+            ExitCode::from(1)
+        }
+        Err(Failure::Io(report)) => {
+            eprintln!("{report:?}");
+            ExitCode::from(3)
+        }
+    }
+}
+
+/// Evaluate a program, and print the result, or write it to files with `-m` (see
+/// [`multi::files`]).
+fn eval(compiler: &mut Compiler, args: &EvalArgs) -> Result<(), Failure> {
+    let options = Options {
+        round_to: args.round_to,
+    };
+
+    args.external.supply(compiler)?;
+
+    // The schema is loaded first, so that the program is loaded after it, and the spans of the
+    // values it produces stay within its source.
+    let schema = match &args.schema {
+        Some(path) => {
+            let schema = compiler.import(path)?;
+            let schema =
+                Schema::new(path, &schema).map_err(|err| Failure::Diagnostics(err.into()))?;
+            Some((path, schema))
+        }
+        None => None,
+    };
+
+    let value = compiler.eval(args.input.entry())?;
+    let value = match &args.path {
+        Some(path) => path.select(&value).ok_or_else(|| {
+            Failure::Diagnostics(miette!("There is no value at {path} in the result"))
+        })?,
+        None => &value,
+    };
+
+    if let Some((path, schema)) = schema {
+        let violations = schema.validate(value);
+        if !violations.is_empty() {
+            let errors = SchemaErrors::new(compiler, path, value, violations);
+            return Err(Failure::Diagnostics(errors.into()));
+        }
+    }
+
+    let Some(out_dir) = &args.out_dir else {
+        print!("{}", args.format.render(value, &options)?);
+        return Ok(());
+    };
+
+    for file in multi::files(value, &options)? {
+        let path = out_dir.join(&file.path);
+        if !args.dry_run {
+            write_within(out_dir, &path, &file.contents)?;
+        }
+
+        println!("{}", path.display());
+    }
 
     Ok(())
 }
+
+/// Write an output file, whose path is lexically within the output directory (see
+/// [`multi::files`]). Symlinks are followed, so each directory on the way to the file is checked to
+/// be within the output directory too, before the next one is created, and the file itself may not
+/// be a symlink.
+fn write_within(out_dir: &Path, path: &Path, contents: &str) -> Result<(), Failure> {
+    let escapes = || {
+        Failure::Io(miette!(
+            "Refusing to write `{}`, which a symlink places outside of `{}`",
+            path.display(),
+            out_dir.display()
+        ))
+    };
+
+    fs::create_dir_all(out_dir).map_err(|err| Failure::io(err, out_dir))?;
+    let root = fs::canonicalize(out_dir).map_err(|err| Failure::io(err, out_dir))?;
+    let relative = path
+        .strip_prefix(out_dir)
+        .expect("output files are within the directory");
+    let mut dir = out_dir.to_owned();
+    for component in relative.parent().into_iter().flat_map(Path::components) {
+        dir.push(component);
+        match fs::canonicalize(&dir) {
+            Ok(canonical) if canonical.starts_with(&root) => {}
+            Ok(_) => return Err(escapes()),
+            // The directory is within the output directory if its parent is.
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                fs::create_dir(&dir).map_err(|err| Failure::io(err, &dir))?;
+            }
+            Err(err) => return Err(Failure::io(err, &dir)),
+        }
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => return Err(escapes()),
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(Failure::io(err, path)),
+    }
+
+    fs::write(path, contents).map_err(|err| Failure::io(err, path))
+}
+
+/// Evaluate a program, and print the steps that produced the value at a path in the result (see
+/// [`Explanation`]).
+fn explain(compiler: &mut Compiler, args: &ExplainArgs) -> Result<(), Failure> {
+    args.external.supply(compiler)?;
+
+    let value = compiler.eval(args.file.as_str())?;
+    let explanation = Explanation::new(compiler, &value, &args.path).ok_or_else(|| {
+        Failure::Diagnostics(miette!("There is no value at {} in the result", args.path))
+    })?;
+    print!("{explanation}");
+    Ok(())
+}
+
+/// Evaluate a program, and write the result, every time one of the files loaded for it changes.
+/// Errors are reported, and fixed on the next change. Only returns if the process is interrupted.
+///
+/// Files are checked for changes by loading them again (see [`Compiler::refresh`]). The ones that
+/// could not be loaded are not known yet, so evaluation is also retried after I/O errors.
+fn watch(compiler: &mut Compiler, args: &WatchArgs) -> Result<(), Failure> {
+    let format = args
+        .format
+        .or_else(|| {
+            let extension = args.output.as_ref()?.extension()?.to_str()?;
+            Format::from_extension(extension)
+        })
+        .unwrap_or(Format::Json);
+    let options = Options {
+        round_to: args.round_to,
+    };
+
+    // The last report, which is not repeated while retrying.
+    let mut last_report = None;
+    let mut retry = true;
+    loop {
+        let changed = compiler.refresh();
+        if !changed.is_empty() {
+            eprintln!("Changed: {}", changed.join(", "));
+        }
+
+        if retry || !changed.is_empty() {
+            let result = compiler
+                .eval(&args.file)
+                .map_err(Failure::from)
+                .and_then(|value| Ok(format.render(&value, &options)?))
+                .and_then(|output| match &args.output {
+                    Some(path) => {
+                        fs::write(path, output).map_err(|err| Failure::io(err, path))?;
+                        Ok(format!("Wrote {}", path.display()))
+                    }
+                    None => {
+                        print!("{output}");
+                        Ok(String::new())
+                    }
+                });
+
+            let report = match result {
+                Ok(message) => {
+                    retry = false;
+                    message
+                }
+                Err(Failure::Diagnostics(report)) => {
+                    retry = false;
+                    format!("{report:?}")
+                }
+                Err(Failure::Io(report)) => {
+                    retry = true;
+                    format!("{report:?}")
+                }
+            };
+
+            let repeated = retry && last_report.as_ref() == Some(&report);
+            if !repeated && !report.is_empty() {
+                eprintln!("{report}");
+            }
+            last_report = Some(report);
+        }
+
+        thread::sleep(Duration::from_millis(args.interval));
+    }
+}
+
+/// Rewrite files in their canonical format. With `--check`, the files are left untouched, and the
+/// command fails if any of them is not formatted.
+fn fmt(compiler: &mut Compiler, args: &FmtArgs) -> Result<(), Failure> {
+    let mut unformatted = false;
+    for file in &args.files {
+        let formatted = compiler.format(file)?;
+        let path = Path::new(file);
+        let current = fs::read_to_string(path).map_err(|err| Failure::io(err, path))?;
+        if current == formatted {
+            continue;
+        }
+
+        if args.check {
+            eprintln!("Not formatted: {file}");
+            unformatted = true;
+        } else {
+            fs::write(path, formatted).map_err(|err| Failure::io(err, path))?;
+        }
+    }
+
+    if unformatted {
+        return Err(Failure::Diagnostics(miette!(
+            "Some files are not formatted"
+        )));
+    }
+
+    Ok(())
+}
+
+/// Run an interactive session on standard input (see [`Repl`]).
+fn repl(compiler: Compiler) -> Result<(), Failure> {
+    let mut repl = Repl::new(compiler);
+    let mut stdin = io::stdin().lock();
+    let mut stdout = io::stdout();
+
+    let mut line = String::new();
+    loop {
+        print!("{}", if repl.is_continuing() { ". " } else { "> " });
+        stdout
+            .flush()
+            .map_err(|err| Failure::Io(miette!("Cannot write the prompt: {err}")))?;
+
+        line.clear();
+        let read = stdin
+            .read_line(&mut line)
+            .map_err(|err| Failure::Io(miette!("Cannot read the input: {err}")))?;
+        if read == 0 {
+            println!();
+            return Ok(());
+        }
+
+        match repl.line(line.trim_end_matches(['\n', '\r'])) {
+            Response::Continue => {}
+            Response::Output(output) if output.is_empty() => {}
+            Response::Output(output) => println!("{output}"),
+            Response::Error(report) => eprintln!("{report:?}"),
+            Response::Quit => return Ok(()),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Explanations of where the values in a result came from, as shown by `dek explain`.
+
+use core::fmt;
+
+use crate::{
+    compiler::Compiler,
+    vm::value::{Origin, Provenance, Value, ValueKind},
+};
+
+use super::Path;
+
+/// The chain of definitions, overrides and merges that produced the value at a path in a result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Explanation {
+    path: Path,
+
+    /// The value at the path, in dek syntax for single values, or described for records, lists
+    /// and functions.
+    value: String,
+
+    /// The path from the innermost value with a provenance to the explained one, if the explained
+    /// one has none of its own, such as a field of an imported data file.
+    rest: Path,
+
+    /// The steps that produced the value, earliest first.
+    steps: Vec<Step>,
+}
+
+/// A definition, override or merge, in a loaded source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub kind: StepKind,
+
+    /// The name of the source, or `None` if it is not loaded anymore, as inline entry points are
+    /// not once they are compiled.
+    pub source: Option<String>,
+
+    /// The line and column of the start of the step, from 1, with columns counted in Unicode
+    /// scalar values.
+    pub line: usize,
+    pub column: usize,
+
+    /// The line of source code the step starts on.
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    Defined,
+    Overridden,
+    Merged,
+}
+
+impl Explanation {
+    /// Explain the value at a path in a result, as evaluated by a compiler. Returns `None` if there
+    /// is no value at the path.
+    pub fn new(compiler: &Compiler, result: &Value, path: &Path) -> Option<Self> {
+        let value = path.select(result)?;
+        let value = match &*value.kind {
+            ValueKind::Record(_) | ValueKind::List(_) | ValueKind::Function(_) => {
+                value.kind.describe().to_owned()
+            }
+            _ => format!("{value:?}"),
+        };
+
+        let (provenance, rest) = path.locate(result);
+        let steps = provenance.map_or_else(Vec::new, |provenance| {
+            provenance
+                .history()
+                .into_iter()
+                .map(|step| Step::new(compiler, step))
+                .collect()
+        });
+
+        Some(Self {
+            path: path.clone(),
+            value,
+            rest,
+            steps,
+        })
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+}
+
+impl Step {
+    fn new(compiler: &Compiler, provenance: &Provenance) -> Self {
+        let kind = match provenance.origin {
+            Origin::Defined => StepKind::Defined,
+            Origin::Override(_) => StepKind::Overridden,
+            Origin::Merge(..) => StepKind::Merged,
+        };
+
+        let Some(source) = compiler.source_at(provenance.span.lo()) else {
+            return Self {
+                kind,
+                source: None,
+                line: 0,
+                column: 0,
+                snippet: String::new(),
+            };
+        };
+
+        let contents = source.contents();
+        let offset = provenance.span.lo().to_u32() - source.span().lo().to_u32();
+        let before = &contents[..offset as usize];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let line_end = contents[line_start..]
+            .find('\n')
+            .map_or(contents.len(), |index| line_start + index);
+
+        Self {
+            kind,
+            source: Some(source.name().to_owned()),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            snippet: contents[line_start..line_end].trim_end().to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = match self.path.segments() {
+            [] => "The result".to_owned(),
+            _ => self.path.to_string(),
+        };
+        writeln!(f, "{path} is {}", self.value)?;
+
+        if self.steps.is_empty() {
+            return writeln!(
+                f,
+                "It was not produced by dek code, but read from a data file or passed as an argument"
+            );
+        }
+        if !self.rest.segments().is_empty() {
+            writeln!(f, "It is at {} within the value produced by:", self.rest)?;
+        }
+
+        for step in &self.steps {
+            let kind = match step.kind {
+                StepKind::Defined => "Defined",
+                StepKind::Overridden => "Overridden",
+                StepKind::Merged => "Merged",
+            };
+            let Some(source) = &step.source else {
+                writeln!(f, "\n{kind} in an inline expression")?;
+                continue;
+            };
+
+            writeln!(f, "\n{kind} at {source}:{}:{}", step.line, step.column)?;
+            let number = step.line.to_string();
+            writeln!(f, "{number} │ {}", step.snippet)?;
+            writeln!(
+                f,
+                "{} │ {}^",
+                " ".repeat(number.len()),
+                " ".repeat(step.column - 1)
+            )?;
+        }
+
+        Ok(())
+    }
+}
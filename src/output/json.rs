@@ -0,0 +1,192 @@
+use crate::vm::value::{Value, ValueKind};
+
+use super::{decimal, write_quoted, Options, OutputError, Path, PathSegment};
+
+/// Convert a value to pretty-printed JSON.
+///
+/// Record fields are output in the order they were defined in. Numbers are output exactly: As
+/// integers, or as decimals if they have a terminating decimal expansion. Other numbers are
+/// rounded according to the [`Options`], or rejected.
+pub fn to_string(value: &Value, options: &Options) -> Result<String, OutputError> {
+    let mut writer = Writer {
+        out: String::new(),
+        options,
+        path: Path::root(),
+        indent: 0,
+    };
+
+    writer.value(value)?;
+    writer.out.push('\n');
+    Ok(writer.out)
+}
+
+struct Writer<'o> {
+    out: String,
+    options: &'o Options,
+    path: Path,
+    indent: usize,
+}
+
+impl Writer<'_> {
+    fn value(&mut self, value: &Value) -> Result<(), OutputError> {
+        match &*value.kind {
+            ValueKind::Null => self.out.push_str("null"),
+            ValueKind::Boolean(boolean) => self.out.push_str(&boolean.value.to_string()),
+            ValueKind::Number(number) => {
+                let decimal = decimal(number, self.options, &self.path)?;
+                self.out.push_str(&decimal);
+            }
+            ValueKind::String(string) => write_quoted(&mut self.out, &string.value),
+            ValueKind::Record(record) => {
+                self.nested('{', '}', &record.fields, |writer, (name, value)| {
+                    writer.path.push(PathSegment::Field(name.clone()));
+                    write_quoted(&mut writer.out, name);
+                    writer.out.push_str(": ");
+                    writer.value(value)?;
+                    writer.path.pop();
+                    Ok(())
+                })?;
+            }
+            ValueKind::List(list) => {
+                self.nested(
+                    '[',
+                    ']',
+                    list.items().iter().enumerate(),
+                    |writer, (i, item)| {
+                        writer.path.push(PathSegment::Index(i));
+                        writer.value(item)?;
+                        writer.path.pop();
+                        Ok(())
+                    },
+                )?;
+            }
+            ValueKind::Function(_) => {
+                return Err(OutputError::Function {
+                    path: self.path.clone(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the items between delimiters, one per line.
+    fn nested<I: IntoIterator>(
+        &mut self,
+        open: char,
+        close: char,
+        items: I,
+        mut item: impl FnMut(&mut Self, I::Item) -> Result<(), OutputError>,
+    ) -> Result<(), OutputError> {
+        self.out.push(open);
+        self.indent += 1;
+
+        let mut empty = true;
+        for next in items {
+            if !empty {
+                self.out.push(',');
+            }
+            empty = false;
+
+            self.newline();
+            item(self, next)?;
+        }
+
+        self.indent -= 1;
+        if !empty {
+            self.newline();
+        }
+        self.out.push(close);
+
+        Ok(())
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.out.extend(core::iter::repeat_n("  ", self.indent));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use malachite::{num::arithmetic::traits::Pow, Rational};
+
+    use super::*;
+
+    fn record<const N: usize>(fields: [(&str, Value); N]) -> Value {
+        Value::record(IndexMap::from_iter(
+            fields.map(|(name, value)| (name.to_owned(), value)),
+        ))
+    }
+
+    fn number(n: i64, d: i64) -> Value {
+        Value::number(Rational::from_signeds(n, d))
+    }
+
+    #[test]
+    fn pretty_prints_in_definition_order() {
+        let value = record([
+            ("zeta", Value::list(vec![number(1, 1), Value::null()])),
+            ("alpha", record([("nested", Value::boolean(true))])),
+            ("empty", Value::list(Vec::new())),
+            ("none", record([])),
+        ]);
+
+        assert_eq!(
+            to_string(&value, &Options::default()).unwrap(),
+            "{\n  \"zeta\": [\n    1,\n    null\n  ],\n  \"alpha\": {\n    \"nested\": true\n  },\n  \
+            \"empty\": [],\n  \"none\": {}\n}\n"
+        );
+    }
+
+    #[test]
+    fn numbers_are_exact() {
+        let big = Value::number(Rational::from(10u32).pow(40u64) + Rational::from(1));
+        assert_eq!(
+            to_string(&big, &Options::default()).unwrap(),
+            "10000000000000000000000000000000000000001\n"
+        );
+        assert_eq!(
+            to_string(&number(-1, 8), &Options::default()).unwrap(),
+            "-0.125\n"
+        );
+    }
+
+    #[test]
+    fn non_terminating_numbers_are_rejected_or_rounded() {
+        let value = record([("list", Value::list(vec![number(0, 1), number(2, 3)]))]);
+
+        match to_string(&value, &Options::default()) {
+            Err(err @ OutputError::NonTerminating { .. }) => assert_eq!(
+                err.to_string(),
+                "The number at `list[1]` has no exact decimal representation"
+            ),
+            result => panic!("unexpected result: {result:?}"),
+        }
+
+        let options = Options { round_to: Some(3) };
+        assert_eq!(to_string(&number(2, 3), &options).unwrap(), "0.667\n");
+        assert_eq!(to_string(&number(-1, 3), &options).unwrap(), "-0.333\n");
+    }
+
+    #[test]
+    fn functions_are_rejected() {
+        let value = record([("a", record([("f", Value::function(|v| Ok(v.clone())))]))]);
+
+        match to_string(&value, &Options::default()) {
+            Err(OutputError::Function { path }) => assert_eq!(path.to_string(), "`a.f`"),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        let value = record([("a \"quoted\"\n\\key\u{1}", Value::null())]);
+
+        assert_eq!(
+            to_string(&value, &Options::default()).unwrap(),
+            "{\n  \"a \\\"quoted\\\"\\n\\\\key\\u0001\": null\n}\n"
+        );
+    }
+}
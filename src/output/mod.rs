@@ -0,0 +1,370 @@
+//! Conversion of evaluated values into data formats.
+
+use core::fmt;
+use std::sync::Arc;
+
+use malachite::{
+    num::{arithmetic::traits::Pow, conversion::traits::RoundingFrom},
+    rounding_modes::RoundingMode,
+    Integer, Rational,
+};
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::vm::value::{Number, Provenance, Value, ValueKind};
+
+pub mod explain;
+pub mod json;
+pub mod multi;
+pub mod schema;
+pub mod toml;
+pub mod yaml;
+
+/// A data format values can be output in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    /// Look up a format by its name, as given on the command line.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(Self::Json),
+            "yaml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Look up the format for a file extension.
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        match extension {
+            "json" => Some(Self::Json),
+            "yaml" | "yml" => Some(Self::Yaml),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Convert a value to this format.
+    pub fn render(self, value: &Value, options: &Options) -> Result<String, OutputError> {
+        match self {
+            Self::Json => json::to_string(value, options),
+            Self::Yaml => yaml::to_string(value, options),
+            Self::Toml => toml::to_string(value, options),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// The number of decimal places to round numbers to, when they have no exact decimal
+    /// representation. If unset, such numbers are rejected.
+    pub round_to: Option<u64>,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum OutputError {
+    #[error("Cannot output the function at {path}")]
+    #[diagnostic(help("Only data can be output: Apply the function, or remove it"))]
+    Function { path: Path },
+
+    #[error("The number at {path} has no exact decimal representation")]
+    #[diagnostic(help("Numbers like this can be rounded to a fixed number of decimal places"))]
+    NonTerminating { path: Path },
+
+    #[error("Unknown format `{name}` at {path}")]
+    #[diagnostic(help("The supported formats are `json`, `yaml` and `toml`"))]
+    UnknownFormat { name: String, path: Path },
+
+    #[error("Multi-file output needs a record of files, found {found}")]
+    #[diagnostic(help(
+        "Map each file path to its contents, as in `{{ \"a.json\" = {{ ... }} }}`"
+    ))]
+    Files { found: &'static str },
+
+    #[error("Cannot write to `{path}`: {reason}")]
+    #[diagnostic(help("File paths must be relative to the output directory, and stay within it"))]
+    FilePath { path: String, reason: &'static str },
+
+    #[error("Cannot tell the format of `{path}`")]
+    #[diagnostic(help(
+        "Use a `.json`, `.yaml`, `.yml` or `.toml` extension, output a string, or set the format \
+        with `{{ format = \"...\", value = ... }}`"
+    ))]
+    FileFormat { path: String },
+
+    #[error("Cannot output `{path}`")]
+    #[diagnostic(forward(error))]
+    File {
+        path: String,
+
+        #[source]
+        error: Box<OutputError>,
+    },
+
+    #[error("Cannot output {found} as a TOML document")]
+    #[diagnostic(help("TOML documents must be records"))]
+    TomlDocument { found: &'static str },
+
+    #[error("Cannot output the null at {path} as TOML")]
+    #[diagnostic(help("TOML has no null value: Remove the field instead"))]
+    TomlNull { path: Path },
+
+    #[error("The list item at {path} is {found}, but the first item is {expected}")]
+    #[diagnostic(help("TOML lists must hold items of a single type"))]
+    TomlMixedList {
+        path: Path,
+        expected: &'static str,
+        found: &'static str,
+    },
+
+    #[error("The integer at {path} is too large for TOML")]
+    #[diagnostic(help("TOML integers are limited to 64 bits"))]
+    TomlIntegerOverflow { path: Path },
+}
+
+/// Format a number in decimal notation, rounding it to the nearest decimal with the configured
+/// number of places if its decimal expansion does not terminate.
+fn decimal(number: &Number, options: &Options, path: &Path) -> Result<String, OutputError> {
+    if let Some(decimal) = number.to_decimal() {
+        return Ok(decimal);
+    }
+
+    let places = options
+        .round_to
+        .ok_or_else(|| OutputError::NonTerminating { path: path.clone() })?;
+
+    let scale = Rational::from(10).pow(places);
+    let (rounded, _) = Integer::rounding_from(&number.value * &scale, RoundingMode::Nearest);
+    let rounded = Number {
+        value: Rational::from(rounded) / scale,
+    };
+
+    Ok(rounded.to_decimal().expect("rounded numbers terminate"))
+}
+
+/// Write a string in double quotes, escaping it as needed. The escapes are valid in JSON, YAML and
+/// TOML alike.
+fn write_quoted(out: &mut String, string: &str) {
+    out.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() || yaml::is_line_break(c) => {
+                out.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// The location of a value within the evaluated output, for use in messages.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Path(Vec<PathSegment>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    Field(String),
+    Index(usize),
+}
+
+impl Path {
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Parse a path written as in messages, without the backticks: Field names separated by dots,
+    /// each followed by any number of list indices, as in `a.b[0][1].c`. The empty path refers
+    /// to the top-level value.
+    pub fn parse(path: &str) -> Option<Self> {
+        let mut segments = Vec::new();
+        if path.is_empty() {
+            return Some(Self(segments));
+        }
+
+        for (i, part) in path.split('.').enumerate() {
+            let (name, mut indices) = part.split_at(part.find('[').unwrap_or(part.len()));
+            if !name.is_empty() {
+                segments.push(PathSegment::Field(name.to_owned()));
+            } else if i > 0 || indices.is_empty() {
+                return None;
+            }
+
+            while let Some(rest) = indices.strip_prefix('[') {
+                let (index, rest) = rest.split_once(']')?;
+                segments.push(PathSegment::Index(index.parse().ok()?));
+                indices = rest;
+            }
+
+            if !indices.is_empty() {
+                return None;
+            }
+        }
+
+        Some(Self(segments))
+    }
+
+    /// Find the value at this path within another value.
+    pub fn select<'v>(&self, value: &'v Value) -> Option<&'v Value> {
+        self.0
+            .iter()
+            .try_fold(value, |value, segment| match (segment, &*value.kind) {
+                (PathSegment::Field(name), ValueKind::Record(record)) => record.fields.get(name),
+                (PathSegment::Index(index), ValueKind::List(list)) => list.items().get(*index),
+                _ => None,
+            })
+    }
+
+    /// Find the provenance of the innermost value along this path within another value that has
+    /// one, and the rest of the path from that value.
+    pub fn locate<'v>(&self, value: &'v Value) -> (Option<&'v Arc<Provenance>>, Path) {
+        let mut provenance = value.provenance.as_ref();
+        let mut rest = Path::root();
+        let mut value = Some(value);
+        for segment in &self.0 {
+            value = value.and_then(|value| match (segment, &*value.kind) {
+                (PathSegment::Field(name), ValueKind::Record(record)) => record.fields.get(name),
+                (PathSegment::Index(index), ValueKind::List(list)) => list.items().get(*index),
+                _ => None,
+            });
+            rest.push(segment.clone());
+
+            if let Some(inner) = value.and_then(|value| value.provenance.as_ref()) {
+                provenance = Some(inner);
+                rest = Path::root();
+            }
+        }
+
+        (provenance, rest)
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    pub(crate) fn push(&mut self, segment: PathSegment) {
+        self.0.push(segment);
+    }
+
+    pub(crate) fn pop(&mut self) {
+        self.0.pop();
+    }
+}
+
+impl fmt::Display for Path {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("the top-level value");
+        }
+
+        f.write_str("`")?;
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Field(name) if i == 0 => f.write_str(name)?,
+                PathSegment::Field(name) => write!(f, ".{name}")?,
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        f.write_str("`")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use indexmap::IndexMap;
+
+    use crate::compiler::Compiler;
+
+    use super::*;
+
+    #[test]
+    fn paths_are_parsed_and_selected() {
+        for path in ["", "a", "a.b", "a[0]", "[1][2].b_c", "a.b[10].c"] {
+            let parsed = Path::parse(path).unwrap_or_else(|| panic!("cannot parse `{path}`"));
+            let expected = if path.is_empty() {
+                "the top-level value".to_owned()
+            } else {
+                format!("`{path}`")
+            };
+            assert_eq!(parsed.to_string(), expected);
+        }
+
+        for path in [".", "a.", ".a", "a..b", "a[", "a[x]", "a[0]b", "a.[0]"] {
+            assert_eq!(Path::parse(path), None, "`{path}` should not parse");
+        }
+
+        let list = Value::list(vec![Value::null(), Value::boolean(true)]);
+        let value = Value::record(IndexMap::from([("a".to_owned(), list)]));
+        let select = |path| {
+            Path::parse(path)
+                .unwrap()
+                .select(&value)
+                .map(|v| v.kind.describe())
+        };
+        assert_eq!(select(""), Some("a record"));
+        assert_eq!(select("a[1]"), Some("a boolean"));
+        assert_eq!(select("a[2]"), None);
+        assert_eq!(select("b"), None);
+        assert_eq!(select("a.b"), None);
+    }
+
+    /// Every `golden/*.dek` file is evaluated and output in each format, and compared against the
+    /// file of the same name with the format's extension. Failures are compared as `Error: ...`.
+    ///
+    /// Run with `DEK_UPDATE_GOLDEN=1` to write the current output to the expected files instead.
+    #[test]
+    fn golden() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/output/golden");
+        let update = env::var_os("DEK_UPDATE_GOLDEN").is_some();
+
+        let mut inputs: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "dek"))
+            .collect();
+        inputs.sort();
+        assert!(!inputs.is_empty(), "no golden files in {}", dir.display());
+
+        let mut mismatches = Vec::new();
+        for input in inputs {
+            let value = Compiler::new()
+                .eval(input.to_str().unwrap())
+                .unwrap_or_else(|err| panic!("cannot evaluate {}: {err:?}", input.display()));
+
+            for (format, extension) in [
+                (Format::Json, "json"),
+                (Format::Yaml, "yaml"),
+                (Format::Toml, "toml"),
+            ] {
+                let actual = match format.render(&value, &Options::default()) {
+                    Ok(output) => output,
+                    Err(err) => format!("Error: {err}\n"),
+                };
+
+                let expected_path = input.with_extension(extension);
+                if update {
+                    fs::write(&expected_path, &actual).unwrap();
+                } else if fs::read_to_string(&expected_path).ok().as_ref() != Some(&actual) {
+                    mismatches.push(format!("{}:\n{actual}", expected_path.display()));
+                }
+            }
+        }
+
+        assert!(
+            mismatches.is_empty(),
+            "unexpected output for:\n\n{}",
+            mismatches.join("\n")
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Output of many files from a single value.
+
+use std::path::{Component, PathBuf};
+
+use indexmap::IndexSet;
+
+use crate::vm::value::{Value, ValueKind};
+
+use super::{Format, Options, OutputError, Path, PathSegment};
+
+/// A file to write, with its path relative to the output directory.
+#[derive(Debug)]
+pub struct File {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+/// Convert a record mapping relative file paths to their contents into a list of files.
+///
+/// Strings are written as they are. Other values are converted to the format given by the
+/// extension of their file. The format can also be set explicitly, with a record of the form
+/// `{ format = "yaml", value = ... }`, in which case strings are converted too.
+///
+/// Paths must be relative, and may not contain `..`, so that all files end up within the output
+/// directory. This check is lexical: Symlinks in the output directory are checked when the files
+/// are written. Nothing is returned unless every file can be output.
+pub fn files(value: &Value, options: &Options) -> Result<Vec<File>, OutputError> {
+    let ValueKind::Record(record) = &*value.kind else {
+        return Err(OutputError::Files {
+            found: value.kind.describe(),
+        });
+    };
+
+    let mut paths = IndexSet::new();
+    let mut files = Vec::with_capacity(record.fields.len());
+    for (name, value) in &record.fields {
+        let path = relative_path(name)?;
+        if !paths.insert(path.clone()) {
+            return Err(OutputError::FilePath {
+                path: name.clone(),
+                reason: "another entry is written to the same file",
+            });
+        }
+
+        let contents = contents(name, value, options).map_err(|err| match err {
+            OutputError::FileFormat { .. } => err,
+            err => OutputError::File {
+                path: name.clone(),
+                error: Box::new(err),
+            },
+        })?;
+
+        files.push(File { path, contents });
+    }
+
+    Ok(files)
+}
+
+/// Check that a path stays within the output directory, and normalize it.
+fn relative_path(name: &str) -> Result<PathBuf, OutputError> {
+    let error = |reason| OutputError::FilePath {
+        path: name.to_owned(),
+        reason,
+    };
+
+    let mut path = PathBuf::new();
+    for component in std::path::Path::new(name).components() {
+        match component {
+            Component::Normal(component) => path.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => return Err(error("`..` is not allowed in file paths")),
+            Component::RootDir | Component::Prefix(_) => return Err(error("the path is absolute")),
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        return Err(error("the path names no file"));
+    }
+
+    Ok(path)
+}
+
+fn contents(name: &str, value: &Value, options: &Options) -> Result<String, OutputError> {
+    if let Some((format, value)) = tagged(value)? {
+        return format.render(value, options);
+    }
+
+    if let ValueKind::String(string) = &*value.kind {
+        return Ok(string.value.clone());
+    }
+
+    let format = std::path::Path::new(name)
+        .extension()
+        .and_then(|extension| Format::from_extension(extension.to_str()?))
+        .ok_or_else(|| OutputError::FileFormat {
+            path: name.to_owned(),
+        })?;
+
+    format.render(value, options)
+}
+
+/// Recognize a value tagged with its format: `{ format = "...", value = ... }`.
+fn tagged(value: &Value) -> Result<Option<(Format, &Value)>, OutputError> {
+    let ValueKind::Record(record) = &*value.kind else {
+        return Ok(None);
+    };
+
+    let (Some(format), Some(value), 2) = (
+        record.fields.get("format"),
+        record.fields.get("value"),
+        record.fields.len(),
+    ) else {
+        return Ok(None);
+    };
+
+    let ValueKind::String(name) = &*format.kind else {
+        return Ok(None);
+    };
+
+    match Format::from_name(&name.value) {
+        Some(format) => Ok(Some((format, value))),
+        None => Err(OutputError::UnknownFormat {
+            name: name.value.clone(),
+            path: Path(vec![PathSegment::Field("format".to_owned())]),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use malachite::Rational;
+
+    use super::*;
+
+    fn record<const N: usize>(fields: [(&str, Value); N]) -> Value {
+        Value::record(IndexMap::from_iter(
+            fields.map(|(name, value)| (name.to_owned(), value)),
+        ))
+    }
+
+    fn render(value: &Value) -> Result<Vec<(String, String)>, String> {
+        match files(value, &Options::default()) {
+            Ok(files) => Ok(files
+                .into_iter()
+                .map(|file| (file.path.display().to_string(), file.contents))
+                .collect()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    fn files_of(files: &[(&str, &str)]) -> Result<Vec<(String, String)>, String> {
+        Ok(files
+            .iter()
+            .map(|(path, contents)| (path.to_string(), contents.to_string()))
+            .collect())
+    }
+
+    #[test]
+    fn formats_follow_extensions() {
+        let data = record([("a", Value::number(Rational::from(1)))]);
+        let value = record([
+            ("a.json", data.clone()),
+            ("./dir/a.yml", data.clone()),
+            ("dir/sub/a.toml", data.clone()),
+            ("notes.txt", Value::string("verbatim\n")),
+            ("raw.json", Value::string("{}")),
+        ]);
+
+        assert_eq!(
+            render(&value),
+            files_of(&[
+                ("a.json", "{\n  \"a\": 1\n}\n"),
+                ("dir/a.yml", "a: 1\n"),
+                ("dir/sub/a.toml", "a = 1\n"),
+                ("notes.txt", "verbatim\n"),
+                ("raw.json", "{}"),
+            ])
+        );
+    }
+
+    #[test]
+    fn formats_can_be_tagged() {
+        let value = record([
+            (
+                "config",
+                record([("format", Value::string("yaml")), ("value", record([]))]),
+            ),
+            (
+                "string.json",
+                record([
+                    ("value", Value::string("a")),
+                    ("format", Value::string("json")),
+                ]),
+            ),
+        ]);
+
+        assert_eq!(
+            render(&value),
+            files_of(&[("config", "{}\n"), ("string.json", "\"a\"\n")])
+        );
+
+        let value = record([(
+            "a",
+            record([("format", Value::string("xml")), ("value", record([]))]),
+        )]);
+        assert_eq!(render(&value), Err("Cannot output `a`".to_owned()),);
+    }
+
+    #[test]
+    fn paths_stay_within_the_directory() {
+        for (path, reason) in [
+            ("../a.json", "`..` is not allowed in file paths"),
+            ("a/../../b.json", "`..` is not allowed in file paths"),
+            ("/etc/a.json", "the path is absolute"),
+            ("", "the path names no file"),
+            (".", "the path names no file"),
+        ] {
+            let value = record([(path, Value::string(""))]);
+            assert_eq!(
+                render(&value),
+                Err(format!("Cannot write to `{path}`: {reason}")),
+            );
+        }
+
+        let value = record([("a.txt", Value::string("")), ("./a.txt", Value::string(""))]);
+        assert_eq!(
+            render(&value),
+            Err("Cannot write to `./a.txt`: another entry is written to the same file".to_owned()),
+        );
+    }
+
+    #[test]
+    fn errors_name_the_file() {
+        assert_eq!(
+            render(&Value::list(Vec::new())),
+            Err("Multi-file output needs a record of files, found a list".to_owned())
+        );
+        assert_eq!(
+            render(&record([("a", record([]))])),
+            Err("Cannot tell the format of `a`".to_owned())
+        );
+
+        let value = record([("a.toml", record([("b", Value::null())]))]);
+        match files(&value, &Options::default()) {
+            Err(OutputError::File { path, error }) => {
+                assert_eq!(path, "a.toml");
+                assert_eq!(error.to_string(), "Cannot output the null at `b` as TOML");
+            }
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,787 @@
+//! Validation of values against JSON Schemas.
+//!
+//! A subset of [JSON Schema 2020-12](https://json-schema.org/draft/2020-12/json-schema-core) is
+//! supported:
+//!
+//! - `type`, with a name or a list of names;
+//! - `enum` and `const`;
+//! - `minimum`, `maximum`, `exclusiveMinimum` and `exclusiveMaximum`;
+//! - `minLength`, `maxLength` and `pattern`, with lengths counted in Unicode scalar values;
+//! - `items`, `minItems` and `maxItems`;
+//! - `properties`, `required` and `additionalProperties`;
+//! - `$ref`, to JSON pointers within the schema itself, such as `#/$defs/port`.
+//!
+//! Schemas may be `true` or `false`, and other keywords are ignored, as they are by validators
+//! that do not know them.
+
+use std::collections::HashMap;
+
+use indexmap::IndexMap;
+use malachite::Rational;
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use regex::Regex;
+use thiserror::Error;
+
+use crate::{
+    compiler::Compiler,
+    vm::value::{Value, ValueKind},
+};
+
+use super::{Path, PathSegment};
+
+/// A compiled JSON Schema.
+#[derive(Debug)]
+pub struct Schema {
+    /// The schemas making up the schema, the root first. References are resolved to indices.
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Default)]
+enum Node {
+    /// `true`, or an empty schema.
+    #[default]
+    Any,
+
+    /// `false`.
+    Nothing,
+
+    Keywords(Box<Keywords>),
+}
+
+#[derive(Debug, Default)]
+struct Keywords {
+    types: Option<Vec<JsonType>>,
+    values: Option<Vec<Value>>,
+
+    minimum: Option<Rational>,
+    maximum: Option<Rational>,
+    exclusive_minimum: Option<Rational>,
+    exclusive_maximum: Option<Rational>,
+
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<Regex>,
+
+    items: Option<usize>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+
+    properties: IndexMap<String, usize>,
+    required: Vec<String>,
+    additional_properties: Option<usize>,
+
+    reference: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonType {
+    Null,
+    Boolean,
+    Number,
+    Integer,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "null" => Some(Self::Null),
+            "boolean" => Some(Self::Boolean),
+            "number" => Some(Self::Number),
+            "integer" => Some(Self::Integer),
+            "string" => Some(Self::String),
+            "array" => Some(Self::Array),
+            "object" => Some(Self::Object),
+            _ => None,
+        }
+    }
+
+    /// The type as described in messages, in terms of dek values.
+    fn describe(self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Boolean => "a boolean",
+            Self::Number => "a number",
+            Self::Integer => "an integer",
+            Self::String => "a string",
+            Self::Array => "a list",
+            Self::Object => "a record",
+        }
+    }
+
+    fn matches(self, value: &Value) -> bool {
+        match (self, &*value.kind) {
+            (Self::Null, ValueKind::Null)
+            | (Self::Boolean, ValueKind::Boolean(_))
+            | (Self::Number, ValueKind::Number(_))
+            | (Self::String, ValueKind::String(_))
+            | (Self::Array, ValueKind::List(_))
+            | (Self::Object, ValueKind::Record(_)) => true,
+            (Self::Integer, ValueKind::Number(number)) => number.value.denominator_ref() == &1u32,
+            _ => false,
+        }
+    }
+}
+
+/// A schema that cannot be used for validation.
+#[derive(Debug, Error, Diagnostic)]
+#[error("Invalid schema `{name}`: {message}, at `#{pointer}`")]
+pub struct SchemaError {
+    name: String,
+    message: String,
+
+    /// The JSON pointer to the invalid part of the schema.
+    pointer: String,
+}
+
+impl Schema {
+    /// Compile a schema, as imported from a JSON file. The name is only used in messages.
+    pub fn new(name: &str, root: &Value) -> Result<Self, SchemaError> {
+        let mut builder = Builder {
+            root,
+            nodes: Vec::new(),
+            pointers: HashMap::new(),
+        };
+        let schema = builder
+            .node(root, String::new())
+            .and_then(|_| builder.check_references())
+            .map(|()| Schema {
+                nodes: builder.nodes,
+            });
+
+        schema.map_err(|(message, pointer)| SchemaError {
+            name: name.to_owned(),
+            message,
+            pointer,
+        })
+    }
+
+    /// Validate a value, returning every violation found.
+    pub fn validate(&self, value: &Value) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        self.validate_node(0, value, &mut Path::root(), &mut violations);
+        violations
+    }
+
+    fn validate_node(
+        &self,
+        node: usize,
+        value: &Value,
+        path: &mut Path,
+        violations: &mut Vec<Violation>,
+    ) {
+        let keywords = match &self.nodes[node] {
+            Node::Any => return,
+            Node::Nothing => {
+                let message = "No value is allowed here".to_owned();
+                violations.push(Violation::new(path, message));
+                return;
+            }
+            Node::Keywords(keywords) => keywords,
+        };
+        let mut violation = |message: String| violations.push(Violation::new(path, message));
+
+        if let Some(types) = &keywords.types {
+            if !types.iter().any(|ty| ty.matches(value)) {
+                let expected: Vec<_> = types.iter().map(|ty| ty.describe()).collect();
+                violation(format!(
+                    "Expected {}, found {}",
+                    expected.join(" or "),
+                    value.kind.describe()
+                ));
+                // The other keywords would only repeat the mismatch.
+                return;
+            }
+        }
+
+        if let Some(values) = &keywords.values {
+            if !values.iter().any(|allowed| equal(allowed, value)) {
+                let allowed: Vec<_> = values.iter().map(|value| format!("{value:?}")).collect();
+                violation(match allowed.as_slice() {
+                    [allowed] => format!("Expected {allowed}, found {value:?}"),
+                    _ => format!("Expected one of {}, found {value:?}", allowed.join(", ")),
+                });
+            }
+        }
+
+        match &*value.kind {
+            ValueKind::Number(number) => {
+                let number = &number.value;
+                let bounds: [(_, _, Comparison); 4] = [
+                    (&keywords.minimum, "at least", PartialOrd::ge),
+                    (&keywords.maximum, "at most", PartialOrd::le),
+                    (&keywords.exclusive_minimum, "greater than", PartialOrd::gt),
+                    (&keywords.exclusive_maximum, "less than", PartialOrd::lt),
+                ];
+                for (bound, relation, within) in bounds {
+                    match bound {
+                        Some(bound) if !within(number, bound) => violation(format!(
+                            "Expected a number {relation} {bound}, found {value:?}"
+                        )),
+                        _ => {}
+                    }
+                }
+            }
+            ValueKind::String(string) => {
+                let length = string.value.chars().count();
+                if let Some(min) = keywords.min_length.filter(|min| length < *min) {
+                    violation(format!(
+                        "Expected a string of at least {min} characters, found {length}"
+                    ));
+                }
+                if let Some(max) = keywords.max_length.filter(|max| length > *max) {
+                    violation(format!(
+                        "Expected a string of at most {max} characters, found {length}"
+                    ));
+                }
+                if let Some(regex) = &keywords.pattern {
+                    if !regex.is_match(&string.value) {
+                        violation(format!(
+                            "Expected a string matching `{}`, found {value:?}",
+                            regex.as_str()
+                        ));
+                    }
+                }
+            }
+            ValueKind::List(list) => {
+                let length = list.items().len();
+                if let Some(min) = keywords.min_items.filter(|min| length < *min) {
+                    violation(format!(
+                        "Expected a list of at least {min} items, found {length}"
+                    ));
+                }
+                if let Some(max) = keywords.max_items.filter(|max| length > *max) {
+                    violation(format!(
+                        "Expected a list of at most {max} items, found {length}"
+                    ));
+                }
+            }
+            ValueKind::Record(record) => {
+                for name in &keywords.required {
+                    if !record.fields.contains_key(name) {
+                        violation(format!("Missing field `{name}`"));
+                    }
+                }
+            }
+            ValueKind::Null | ValueKind::Boolean(_) | ValueKind::Function(_) => {}
+        }
+
+        match &*value.kind {
+            ValueKind::List(list) => {
+                if let Some(items) = keywords.items {
+                    for (index, item) in list.items().iter().enumerate() {
+                        path.push(PathSegment::Index(index));
+                        self.validate_node(items, item, path, violations);
+                        path.pop();
+                    }
+                }
+            }
+            ValueKind::Record(record) => {
+                for (name, field) in &record.fields {
+                    let schema = match keywords.properties.get(name) {
+                        Some(schema) => *schema,
+                        None => match keywords.additional_properties {
+                            Some(schema) => schema,
+                            None => continue,
+                        },
+                    };
+
+                    path.push(PathSegment::Field(name.clone()));
+                    if let (Node::Nothing, false) =
+                        (&self.nodes[schema], keywords.properties.contains_key(name))
+                    {
+                        violations.push(Violation::new(path, format!("Unexpected field `{name}`")));
+                    } else {
+                        self.validate_node(schema, field, path, violations);
+                    }
+                    path.pop();
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(reference) = keywords.reference {
+            self.validate_node(reference, value, path, violations);
+        }
+    }
+}
+
+/// Whether a number is within a bound.
+type Comparison = fn(&Rational, &Rational) -> bool;
+
+/// Compiles the nodes of a schema, with the JSON pointer to each of them.
+struct Builder<'a> {
+    root: &'a Value,
+    nodes: Vec<Node>,
+
+    /// The nodes compiled so far, by JSON pointer, for references.
+    pointers: HashMap<String, usize>,
+}
+
+/// A message and the JSON pointer to the part of the schema it is about.
+type BuildError = (String, String);
+
+impl<'a> Builder<'a> {
+    fn node(&mut self, schema: &Value, pointer: String) -> Result<usize, BuildError> {
+        if let Some(index) = self.pointers.get(&pointer) {
+            return Ok(*index);
+        }
+
+        // The node is reserved first, so that references back to it find it.
+        let index = self.nodes.len();
+        self.nodes.push(Node::Any);
+        self.pointers.insert(pointer.clone(), index);
+
+        let node = match &*schema.kind {
+            ValueKind::Boolean(boolean) if boolean.value => Node::Any,
+            ValueKind::Boolean(_) => Node::Nothing,
+            ValueKind::Record(record) => {
+                let mut keywords = Keywords::default();
+                for (keyword, value) in &record.fields {
+                    let at = format!("{pointer}/{}", escape(keyword));
+                    self.keyword(&mut keywords, keyword, value, at)?;
+                }
+                Node::Keywords(Box::new(keywords))
+            }
+            kind => {
+                let message = format!("Expected a schema, found {}", kind.describe());
+                return Err((message, pointer));
+            }
+        };
+
+        self.nodes[index] = node;
+        Ok(index)
+    }
+
+    fn keyword(
+        &mut self,
+        keywords: &mut Keywords,
+        keyword: &str,
+        value: &Value,
+        pointer: String,
+    ) -> Result<(), BuildError> {
+        let error = |expected: &str| {
+            let message = format!("`{keyword}` must be {expected}");
+            Err((message, pointer.clone()))
+        };
+
+        match (keyword, &*value.kind) {
+            ("type", ValueKind::String(name)) => match JsonType::from_name(&name.value) {
+                Some(ty) => keywords.types = Some(vec![ty]),
+                None => return error("a type name"),
+            },
+            ("type", ValueKind::List(names)) => {
+                let types = names.items().iter().map(|name| match &*name.kind {
+                    ValueKind::String(name) => JsonType::from_name(&name.value),
+                    _ => None,
+                });
+                match types.collect() {
+                    Some(types) => keywords.types = Some(types),
+                    None => return error("a list of type names"),
+                }
+            }
+            ("enum", ValueKind::List(values)) => keywords.values = Some(values.items().to_vec()),
+            ("const", _) => keywords.values = Some(vec![value.clone()]),
+            ("minimum" | "maximum" | "exclusiveMinimum" | "exclusiveMaximum", kind) => {
+                let ValueKind::Number(number) = kind else {
+                    return error("a number");
+                };
+                let bound = Some(number.value.clone());
+                match keyword {
+                    "minimum" => keywords.minimum = bound,
+                    "maximum" => keywords.maximum = bound,
+                    "exclusiveMinimum" => keywords.exclusive_minimum = bound,
+                    _ => keywords.exclusive_maximum = bound,
+                }
+            }
+            ("minLength" | "maxLength" | "minItems" | "maxItems", kind) => {
+                let count = match kind {
+                    ValueKind::Number(number) => usize::try_from(&number.value).ok(),
+                    _ => None,
+                };
+                let Some(count) = count else {
+                    return error("a non-negative integer");
+                };
+                match keyword {
+                    "minLength" => keywords.min_length = Some(count),
+                    "maxLength" => keywords.max_length = Some(count),
+                    "minItems" => keywords.min_items = Some(count),
+                    _ => keywords.max_items = Some(count),
+                }
+            }
+            ("pattern", ValueKind::String(pattern)) => match Regex::new(&pattern.value) {
+                Ok(regex) => keywords.pattern = Some(regex),
+                Err(_) => return error("a valid regular expression"),
+            },
+            ("items", _) => keywords.items = Some(self.node(value, pointer)?),
+            ("properties", ValueKind::Record(properties)) => {
+                for (name, schema) in &properties.fields {
+                    let at = format!("{pointer}/{}", escape(name));
+                    let node = self.node(schema, at)?;
+                    keywords.properties.insert(name.clone(), node);
+                }
+            }
+            ("required", ValueKind::List(names)) => {
+                for name in names.items() {
+                    let ValueKind::String(name) = &*name.kind else {
+                        return error("a list of field names");
+                    };
+                    keywords.required.push(name.value.clone());
+                }
+            }
+            ("additionalProperties", _) => {
+                keywords.additional_properties = Some(self.node(value, pointer)?);
+            }
+            ("$ref", ValueKind::String(reference)) => {
+                let Some(target) = reference.value.strip_prefix('#') else {
+                    return error("a reference within the schema, starting with `#`");
+                };
+                let Some(schema) = self.resolve(target) else {
+                    let message = format!("`{}` refers to nothing", reference.value);
+                    return Err((message, pointer));
+                };
+                keywords.reference = Some(self.node(schema, target.to_owned())?);
+            }
+            ("type" | "enum" | "pattern" | "properties" | "required" | "$ref", _) => {
+                let expected = match keyword {
+                    "type" => "a type name, or a list of them",
+                    "enum" | "required" => "a list",
+                    "pattern" | "$ref" => "a string",
+                    _ => "a record",
+                };
+                return error(expected);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// The part of the schema a JSON pointer refers to.
+    fn resolve(&self, pointer: &str) -> Option<&'a Value> {
+        if pointer.is_empty() {
+            return Some(self.root);
+        }
+
+        let tokens = pointer.strip_prefix('/')?.split('/');
+        tokens
+            .map(unescape)
+            .try_fold(self.root, |value, token| match &*value.kind {
+                ValueKind::Record(record) => record.fields.get(&token),
+                ValueKind::List(list) => list.items().get(token.parse::<usize>().ok()?),
+                _ => None,
+            })
+    }
+
+    /// Check that no chain of references leads back to its start, which would make validation
+    /// loop without ever reaching a value.
+    fn check_references(&self) -> Result<(), BuildError> {
+        let reference = |node: usize| match &self.nodes[node] {
+            Node::Keywords(keywords) => keywords.reference,
+            Node::Any | Node::Nothing => None,
+        };
+
+        for start in 0..self.nodes.len() {
+            let mut node = start;
+            for _ in 0..self.nodes.len() {
+                match reference(node) {
+                    Some(next) if next == start => {
+                        let pointer = self
+                            .pointers
+                            .iter()
+                            .find(|(_, index)| **index == start)
+                            .map(|(pointer, _)| pointer.clone())
+                            .unwrap_or_default();
+                        return Err(("`$ref` refers back to itself".to_owned(), pointer));
+                    }
+                    Some(next) => node = next,
+                    None => break,
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Escape a name as a JSON pointer token.
+fn escape(name: &str) -> String {
+    name.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape(token: &str) -> String {
+    token.replace("~1", "/").replace("~0", "~")
+}
+
+/// Whether two values are equal as JSON values: Records are equal regardless of the order of
+/// their fields.
+fn equal(a: &Value, b: &Value) -> bool {
+    match (&*a.kind, &*b.kind) {
+        (ValueKind::Null, ValueKind::Null) => true,
+        (ValueKind::Boolean(a), ValueKind::Boolean(b)) => a.value == b.value,
+        (ValueKind::Number(a), ValueKind::Number(b)) => a.value == b.value,
+        (ValueKind::String(a), ValueKind::String(b)) => a.value == b.value,
+        (ValueKind::List(a), ValueKind::List(b)) => {
+            a.items().len() == b.items().len()
+                && a.items().iter().zip(b.items()).all(|(a, b)| equal(a, b))
+        }
+        (ValueKind::Record(a), ValueKind::Record(b)) => {
+            a.fields.len() == b.fields.len()
+                && a.fields
+                    .iter()
+                    .all(|(name, a)| b.fields.get(name).is_some_and(|b| equal(a, b)))
+        }
+        _ => false,
+    }
+}
+
+/// A part of a value that does not satisfy a schema.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub path: Path,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(path: &Path, message: String) -> Self {
+        Self {
+            path: path.clone(),
+            message,
+        }
+    }
+}
+
+/// The violations of a schema by a value, pointing at the source code the parts of the value that
+/// violate it were defined in.
+#[derive(Debug, Error, Diagnostic)]
+#[error("The result does not match the schema `{schema}`")]
+pub struct SchemaErrors {
+    schema: String,
+
+    #[related]
+    errors: Vec<SchemaViolation>,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("{message}")]
+pub struct SchemaViolation {
+    message: String,
+
+    #[source_code]
+    source_code: NamedSource,
+
+    #[label("{label}")]
+    span: Option<SourceSpan>,
+    label: String,
+
+    #[help]
+    help: Option<String>,
+}
+
+impl SchemaErrors {
+    /// Report the violations of a schema by a value, as evaluated by a compiler.
+    pub fn new(
+        compiler: &Compiler,
+        schema: &str,
+        value: &Value,
+        violations: Vec<Violation>,
+    ) -> Self {
+        let errors = violations
+            .into_iter()
+            .map(|violation| {
+                let help = (!violation.path.segments().is_empty())
+                    .then(|| format!("At {} in the result", violation.path));
+
+                let (provenance, rest) = violation.path.locate(value);
+                let span = provenance.map(|provenance| provenance.span);
+                let source = span.and_then(|span| Some((span, compiler.source_at(span.lo())?)));
+                let Some((span, source)) = source else {
+                    return SchemaViolation {
+                        message: violation.message,
+                        source_code: NamedSource::new(schema, String::new()),
+                        span: None,
+                        label: String::new(),
+                        help,
+                    };
+                };
+
+                let label = match rest.segments() {
+                    [] => "This value".to_owned(),
+                    _ => format!("At {rest} in this value"),
+                };
+                SchemaViolation {
+                    message: violation.message,
+                    source_code: NamedSource::new(source.name(), source.contents().to_owned()),
+                    span: Some(source.local_span(span.lo().to_u32(), span.hi().to_u32())),
+                    label,
+                    help,
+                }
+            })
+            .collect();
+
+        Self {
+            schema: schema.to_owned(),
+            errors,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::Entry;
+
+    /// Validate the value of a dek expression against a schema written in dek, returning the
+    /// violations as `path: message`.
+    fn validate(schema: &str, source: &str) -> Result<Vec<String>, SchemaError> {
+        let mut compiler = Compiler::new();
+        let mut eval = |contents| {
+            let entry = Entry::Inline {
+                name: "<test>",
+                contents,
+            };
+            compiler.eval(entry).unwrap()
+        };
+
+        let schema = Schema::new("<schema>", &eval(schema))?;
+        let violations = schema.validate(&eval(source));
+        Ok(violations
+            .into_iter()
+            .map(|violation| match violation.path.segments() {
+                [] => violation.message,
+                _ => format!("{}: {}", violation.path, violation.message),
+            })
+            .collect())
+    }
+
+    #[track_caller]
+    fn assert_violations(schema: &str, source: &str, expected: &[&str]) {
+        match validate(schema, source) {
+            Ok(violations) => assert_eq!(violations, expected, "value: `{source}`"),
+            Err(err) => panic!("invalid schema: {err}"),
+        }
+    }
+
+    #[test]
+    fn types_and_enums() {
+        let schema = r#"{ type = ["string", "null"], enum = ["a", "b", null] }"#;
+        assert_violations(schema, r#""a""#, &[]);
+        assert_violations(schema, "null", &[]);
+        assert_violations(schema, "1", &["Expected a string or null, found a number"]);
+        assert_violations(
+            schema,
+            r#""c""#,
+            &[r#"Expected one of "a", "b", null, found "c""#],
+        );
+
+        assert_violations(r#"{ type = "integer" }"#, "2", &[]);
+        assert_violations(
+            r#"{ type = "integer" }"#,
+            "2.5",
+            &["Expected an integer, found a number"],
+        );
+        assert_violations(
+            "{ const = { a = [1] } }",
+            "{ a = [2] }",
+            &["Expected {a = [1]}, found {a = [2]}"],
+        );
+        assert_violations("true", "1", &[]);
+        assert_violations("false", "1", &["No value is allowed here"]);
+    }
+
+    #[test]
+    fn numbers_strings_and_lists() {
+        let schema = "{ minimum = 1, exclusiveMaximum = 10 }";
+        assert_violations(schema, "1", &[]);
+        assert_violations(schema, "0.5", &["Expected a number at least 1, found 1/2"]);
+        assert_violations(schema, "10", &["Expected a number less than 10, found 10"]);
+
+        let schema = r#"{ minLength = 2, maxLength = 3, pattern = "^[a-zé]+$" }"#;
+        assert_violations(schema, r#""éé""#, &[]);
+        assert_violations(
+            schema,
+            r#""Abcd""#,
+            &[
+                "Expected a string of at most 3 characters, found 4",
+                r#"Expected a string matching `^[a-zé]+$`, found "Abcd""#,
+            ],
+        );
+
+        let schema = r#"{ items = { type = "number" }, maxItems = 2 }"#;
+        assert_violations(
+            schema,
+            "[1, true, 3]",
+            &[
+                "Expected a list of at most 2 items, found 3",
+                "`[1]`: Expected a number, found a boolean",
+            ],
+        );
+    }
+
+    #[test]
+    fn records() {
+        let schema = r#"{
+            type = "object",
+            required = ["name", "port"],
+            properties = { name = { type = "string" }, port = { maximum = 65535 } },
+            additionalProperties = false,
+        }"#;
+        assert_violations(schema, r#"{ name = "api", port = 80 }"#, &[]);
+        assert_violations(
+            schema,
+            "{ port = 70000, debug = true }",
+            &[
+                "Missing field `name`",
+                "`port`: Expected a number at most 65535, found 70000",
+                "`debug`: Unexpected field `debug`",
+            ],
+        );
+
+        let schema = r#"{ additionalProperties = { type = "number" } }"#;
+        assert_violations(
+            schema,
+            r#"{ a = 1, b = "2" }"#,
+            &["`b`: Expected a number, found a string"],
+        );
+    }
+
+    #[test]
+    fn references_are_local() {
+        let schema = r##"{
+            "$defs" = {
+                port = { type = "integer", minimum = 1 },
+                tree = { type = "object", properties = { children = { items = { "$ref" = "#/$defs/tree" } } } },
+            },
+            properties = { port = { "$ref" = "#/$defs/port" }, tree = { "$ref" = "#/$defs/tree" } },
+        }"##;
+        assert_violations(
+            schema,
+            "{ port = 0, tree = { children = [{ children = [1] }] } }",
+            &[
+                "`port`: Expected a number at least 1, found 0",
+                "`tree.children[0].children[0]`: Expected a record, found a number",
+            ],
+        );
+
+        let err = validate(r##"{ "$ref" = "#/$defs/a" }"##, "1").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid schema `<schema>`: `#/$defs/a` refers to nothing, at `#/$ref`"
+        );
+
+        let err = validate(
+            r##"{ "$defs" = { a = { "$ref" = "#" } }, "$ref" = "#/$defs/a" }"##,
+            "1",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid schema `<schema>`: `$ref` refers back to itself, at `#`"
+        );
+
+        let err = validate(r##"{ "$ref" = "other.json" }"##, "1").unwrap_err();
+        assert!(err.to_string().contains("a reference within the schema"));
+    }
+}
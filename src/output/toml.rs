@@ -0,0 +1,314 @@
+use core::mem;
+
+use malachite::{num::conversion::traits::ConvertibleFrom, Integer};
+
+use crate::vm::value::{Record, Value, ValueKind};
+
+use super::{decimal, write_quoted, Options, OutputError, Path, PathSegment};
+
+/// Convert a record to a TOML document.
+///
+/// Fields holding records become tables, and fields holding lists of records become arrays of
+/// tables. Since a table cannot be continued after a nested table starts, the other fields of a
+/// record are output before its tables, but otherwise definition order is kept. Records and lists
+/// nested in other lists are output inline.
+///
+/// TOML is stricter than the values it is converted from: The document must be a record, there
+/// is no `null`, integers must fit in 64 bits, and the items of a list must all be of the same type.
+/// Numbers otherwise follow the same rules as [JSON](super::json::to_string).
+pub fn to_string(value: &Value, options: &Options) -> Result<String, OutputError> {
+    let ValueKind::Record(record) = &*value.kind else {
+        return Err(OutputError::TomlDocument {
+            found: value.kind.describe(),
+        });
+    };
+
+    let mut writer = Writer {
+        out: String::new(),
+        options,
+        path: Path::root(),
+        keys: Vec::new(),
+    };
+
+    writer.table(record)?;
+    Ok(writer.out)
+}
+
+struct Writer<'o> {
+    out: String,
+    options: &'o Options,
+    path: Path,
+
+    /// The keys of the table being written, formatted for use in headers.
+    keys: Vec<String>,
+}
+
+impl Writer<'_> {
+    /// Write the fields of a table, after its header.
+    fn table(&mut self, record: &Record) -> Result<(), OutputError> {
+        let (tables, fields): (Vec<_>, Vec<_>) =
+            record.fields.iter().partition(|(_, value)| is_table(value));
+
+        for (name, value) in fields {
+            self.path.push(PathSegment::Field(name.clone()));
+            write_key(&mut self.out, name);
+            self.out.push_str(" = ");
+            self.inline(value)?;
+            self.out.push('\n');
+            self.path.pop();
+        }
+
+        for (name, value) in tables {
+            self.path.push(PathSegment::Field(name.clone()));
+            let mut key = String::new();
+            write_key(&mut key, name);
+            self.keys.push(key);
+
+            match &*value.kind {
+                ValueKind::Record(record) => {
+                    self.header("[", "]");
+                    self.table(record)?;
+                }
+                ValueKind::List(list) => {
+                    self.homogeneous(list.items())?;
+                    for (i, item) in list.items().iter().enumerate() {
+                        let ValueKind::Record(record) = &*item.kind else {
+                            unreachable!("arrays of tables hold records");
+                        };
+
+                        self.path.push(PathSegment::Index(i));
+                        self.header("[[", "]]");
+                        self.table(record)?;
+                        self.path.pop();
+                    }
+                }
+                _ => unreachable!("tables are records or lists"),
+            }
+
+            self.keys.pop();
+            self.path.pop();
+        }
+
+        Ok(())
+    }
+
+    fn header(&mut self, open: &str, close: &str) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+
+        self.out.push_str(open);
+        self.out.push_str(&self.keys.join("."));
+        self.out.push_str(close);
+        self.out.push('\n');
+    }
+
+    fn inline(&mut self, value: &Value) -> Result<(), OutputError> {
+        match &*value.kind {
+            ValueKind::Null => {
+                return Err(OutputError::TomlNull {
+                    path: self.path.clone(),
+                })
+            }
+            ValueKind::Boolean(boolean) => self.out.push_str(&boolean.value.to_string()),
+            ValueKind::Number(number) => {
+                if let Ok(integer) = Integer::try_from(&number.value) {
+                    if !i64::convertible_from(&integer) {
+                        return Err(OutputError::TomlIntegerOverflow {
+                            path: self.path.clone(),
+                        });
+                    }
+                }
+
+                let decimal = decimal(number, self.options, &self.path)?;
+                self.out.push_str(&decimal);
+            }
+            ValueKind::String(string) => write_quoted(&mut self.out, &string.value),
+            ValueKind::Record(record) if record.fields.is_empty() => self.out.push_str("{}"),
+            ValueKind::Record(record) => {
+                self.out.push_str("{ ");
+                for (i, (name, value)) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+
+                    self.path.push(PathSegment::Field(name.clone()));
+                    write_key(&mut self.out, name);
+                    self.out.push_str(" = ");
+                    self.inline(value)?;
+                    self.path.pop();
+                }
+                self.out.push_str(" }");
+            }
+            ValueKind::List(list) => {
+                self.homogeneous(list.items())?;
+
+                self.out.push('[');
+                for (i, item) in list.items().iter().enumerate() {
+                    if i > 0 {
+                        self.out.push_str(", ");
+                    }
+
+                    self.path.push(PathSegment::Index(i));
+                    self.inline(item)?;
+                    self.path.pop();
+                }
+                self.out.push(']');
+            }
+            ValueKind::Function(_) => {
+                return Err(OutputError::Function {
+                    path: self.path.clone(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ensure that all the items of a list are of the same type as the first one.
+    fn homogeneous(&mut self, items: &[Value]) -> Result<(), OutputError> {
+        let Some(first) = items.first() else {
+            return Ok(());
+        };
+
+        let mismatch = items
+            .iter()
+            .position(|item| mem::discriminant(&*item.kind) != mem::discriminant(&*first.kind));
+
+        match mismatch {
+            Some(i) => {
+                self.path.push(PathSegment::Index(i));
+                Err(OutputError::TomlMixedList {
+                    path: self.path.clone(),
+                    expected: first.kind.describe(),
+                    found: items[i].kind.describe(),
+                })
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Whether a field's value is output as a table, rather than inline: Records, and lists starting
+/// with a record, are tables.
+fn is_table(value: &Value) -> bool {
+    match &*value.kind {
+        ValueKind::Record(_) => true,
+        ValueKind::List(list) => list
+            .items()
+            .first()
+            .is_some_and(|item| matches!(&*item.kind, ValueKind::Record(_))),
+        _ => false,
+    }
+}
+
+/// Write a key, bare if possible, and quoted otherwise.
+fn write_key(out: &mut String, name: &str) {
+    let bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    if bare {
+        out.push_str(name);
+    } else {
+        write_quoted(out, name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use malachite::{num::arithmetic::traits::Pow, Rational};
+
+    use super::*;
+
+    fn record<const N: usize>(fields: [(&str, Value); N]) -> Value {
+        Value::record(IndexMap::from_iter(
+            fields.map(|(name, value)| (name.to_owned(), value)),
+        ))
+    }
+
+    fn number(n: i64) -> Value {
+        Value::number(Rational::from(n))
+    }
+
+    fn error(value: &Value) -> String {
+        match to_string(value, &Options::default()) {
+            Ok(toml) => panic!("unexpected success:\n{toml}"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn fields_come_before_tables() {
+        let value = record([
+            ("table", record([("a", number(1))])),
+            ("b", Value::list(vec![record([("c", number(2))])])),
+            ("inline", Value::list(vec![Value::list(vec![record([])])])),
+            ("d", Value::boolean(true)),
+        ]);
+
+        assert_eq!(
+            to_string(&value, &Options::default()).unwrap(),
+            "inline = [[{}]]\nd = true\n\n[table]\na = 1\n\n[[b]]\nc = 2\n"
+        );
+    }
+
+    #[test]
+    fn keys_are_quoted_when_needed() {
+        let value = record([("a b", record([("c.d", number(1)), ("e-f", number(2))]))]);
+
+        assert_eq!(
+            to_string(&value, &Options::default()).unwrap(),
+            "[\"a b\"]\n\"c.d\" = 1\ne-f = 2\n"
+        );
+    }
+
+    #[test]
+    fn documents_are_records() {
+        assert_eq!(
+            error(&Value::list(Vec::new())),
+            "Cannot output a list as a TOML document"
+        );
+    }
+
+    #[test]
+    fn null_is_rejected() {
+        let value = record([("a", Value::list(vec![record([("b", Value::null())])]))]);
+        assert_eq!(error(&value), "Cannot output the null at `a[0].b` as TOML");
+    }
+
+    #[test]
+    fn mixed_lists_are_rejected() {
+        let value = record([(
+            "a",
+            record([("b", Value::list(vec![number(1), number(2), record([])]))]),
+        )]);
+        assert_eq!(
+            error(&value),
+            "The list item at `a.b[2]` is a record, but the first item is a number"
+        );
+
+        let value = record([("a", Value::list(vec![record([]), number(1)]))]);
+        assert_eq!(
+            error(&value),
+            "The list item at `a[1]` is a number, but the first item is a record"
+        );
+    }
+
+    #[test]
+    fn integers_are_64_bit() {
+        let max = Value::number(Rational::from(i64::MAX));
+        assert_eq!(
+            to_string(&record([("a", max)]), &Options::default()).unwrap(),
+            "a = 9223372036854775807\n"
+        );
+
+        let big = Value::number(Rational::from(2).pow(64u64));
+        assert_eq!(
+            error(&record([("a", big)])),
+            "The integer at `a` is too large for TOML"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use crate::vm::value::{Value, ValueKind};
+
+use super::{decimal, write_quoted, Options, OutputError, Path, PathSegment};
+
+/// Convert a value to block-style YAML.
+///
+/// Records and lists are output one entry per line, with nested entries indented by two spaces.
+/// Only empty records and lists use the flow style (`{}` and `[]`). Strings and field names are
+/// quoted when they could otherwise be mistaken for another value or for YAML syntax. Numbers
+/// follow the same rules as [JSON](super::json::to_string).
+pub fn to_string(value: &Value, options: &Options) -> Result<String, OutputError> {
+    let mut writer = Writer {
+        out: String::new(),
+        options,
+        path: Path::root(),
+    };
+
+    writer.block(value, 0)?;
+    writer.out.push('\n');
+    Ok(writer.out)
+}
+
+struct Writer<'o> {
+    out: String,
+    options: &'o Options,
+    path: Path,
+}
+
+impl Writer<'_> {
+    /// Write a value as a block at the given indentation level. The first entry of a block is
+    /// written on the current line.
+    fn block(&mut self, value: &Value, indent: usize) -> Result<(), OutputError> {
+        match &*value.kind {
+            ValueKind::Record(record) if !record.fields.is_empty() => {
+                for (i, (name, value)) in record.fields.iter().enumerate() {
+                    if i > 0 {
+                        self.newline(indent);
+                    }
+
+                    self.path.push(PathSegment::Field(name.clone()));
+                    self.string(name);
+                    self.out.push(':');
+                    self.nested(value, indent, false)?;
+                    self.path.pop();
+                }
+            }
+            ValueKind::List(list) if !list.items().is_empty() => {
+                for (i, item) in list.items().iter().enumerate() {
+                    if i > 0 {
+                        self.newline(indent);
+                    }
+
+                    self.path.push(PathSegment::Index(i));
+                    self.out.push('-');
+                    self.nested(item, indent, true)?;
+                    self.path.pop();
+                }
+            }
+            _ => self.scalar(value)?,
+        }
+
+        Ok(())
+    }
+
+    /// Write a value following a `key:` or `-` indicator. Blocks nested in list items start on the
+    /// same line as their `-`, while blocks nested in records start on the next line.
+    fn nested(&mut self, value: &Value, indent: usize, item: bool) -> Result<(), OutputError> {
+        let is_block = match &*value.kind {
+            ValueKind::Record(record) => !record.fields.is_empty(),
+            ValueKind::List(list) => !list.items().is_empty(),
+            _ => false,
+        };
+
+        if is_block && !item {
+            self.newline(indent + 1);
+        } else {
+            self.out.push(' ');
+        }
+
+        self.block(value, indent + 1)
+    }
+
+    fn scalar(&mut self, value: &Value) -> Result<(), OutputError> {
+        match &*value.kind {
+            ValueKind::Null => self.out.push_str("null"),
+            ValueKind::Boolean(boolean) => self.out.push_str(&boolean.value.to_string()),
+            ValueKind::Number(number) => {
+                let decimal = decimal(number, self.options, &self.path)?;
+                self.out.push_str(&decimal);
+            }
+            ValueKind::String(string) => self.string(&string.value),
+            ValueKind::Record(_) => self.out.push_str("{}"),
+            ValueKind::List(_) => self.out.push_str("[]"),
+            ValueKind::Function(_) => {
+                return Err(OutputError::Function {
+                    path: self.path.clone(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a field name or a string, quoted if needed.
+    fn string(&mut self, name: &str) {
+        if needs_quotes(name) {
+            write_quoted(&mut self.out, name);
+        } else {
+            self.out.push_str(name);
+        }
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.out.extend(core::iter::repeat_n("  ", indent));
+    }
+}
+
+/// Whether a string must be quoted to be read back as the same string.
+///
+/// This errs on the side of quoting: Besides YAML syntax, it covers everything YAML 1.1 parsers
+/// would read as a boolean, null or number, such as `yes`, `off` or `1e3`.
+fn needs_quotes(string: &str) -> bool {
+    const RESERVED: &[&str] = &[
+        "null", "~", "true", "false", "yes", "no", "on", "off", "y", "n", "<<",
+    ];
+
+    let Some(first) = string.chars().next() else {
+        return true;
+    };
+
+    RESERVED
+        .iter()
+        .any(|word| string.eq_ignore_ascii_case(word))
+        || first.is_ascii_digit()
+        || "-+.?:,[]{}#&*!|>'\"%@`".contains(first)
+        || first.is_whitespace()
+        || string.ends_with(char::is_whitespace)
+        || string.ends_with(':')
+        || string.contains(": ")
+        || string.contains(" #")
+        || string.contains(|c: char| c.is_control() || is_line_break(c))
+}
+
+/// Whether a character is one of the line breaks YAML knows besides `\n` and `\r`: Next line,
+/// line separator and paragraph separator.
+pub(super) fn is_line_break(c: char) -> bool {
+    matches!(c, '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use malachite::Rational;
+
+    use super::*;
+
+    fn record<const N: usize>(fields: [(&str, Value); N]) -> Value {
+        Value::record(IndexMap::from_iter(
+            fields.map(|(name, value)| (name.to_owned(), value)),
+        ))
+    }
+
+    fn number(n: i64) -> Value {
+        Value::number(Rational::from(n))
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let value = record([
+            (
+                "a",
+                record([("b", number(1)), ("c", Value::list(Vec::new()))]),
+            ),
+            (
+                "list",
+                Value::list(vec![
+                    record([("x", number(1)), ("w", record([("z", Value::null())]))]),
+                    Value::list(vec![number(2), Value::list(vec![number(3)])]),
+                    record([]),
+                ]),
+            ),
+        ]);
+
+        assert_eq!(
+            to_string(&value, &Options::default()).unwrap(),
+            "a:\n  b: 1\n  c: []\nlist:\n  - x: 1\n    w:\n      z: null\n  - - 2\n    - - 3\n  - {}\n"
+        );
+    }
+
+    #[test]
+    fn scalar_documents() {
+        assert_eq!(to_string(&number(-3), &Options::default()).unwrap(), "-3\n");
+        assert_eq!(to_string(&record([]), &Options::default()).unwrap(), "{}\n");
+    }
+
+    #[test]
+    fn keys_are_quoted_when_ambiguous() {
+        for plain in [
+            "name",
+            "snake_case",
+            "with space",
+            "a-b",
+            "x:y",
+            "a#b",
+            "Ünïcode",
+        ] {
+            assert!(!needs_quotes(plain), "`{plain}` should not be quoted");
+        }
+
+        for quoted in [
+            "",
+            "yes",
+            "No",
+            "ON",
+            "y",
+            "null",
+            "~",
+            "<<",
+            "1e3",
+            "0x10",
+            ".5",
+            "-a",
+            "? a",
+            "a: b",
+            "a #b",
+            "[a]",
+            "{a}",
+            "*ref",
+            "&anchor",
+            "!tag",
+            "'a'",
+            "\"a\"",
+            "a:",
+            " a",
+            "a ",
+            "a\nb",
+            "%a",
+            "@a",
+            "`a",
+            "a\u{85}b",
+            "a\u{2028}b",
+            "a\u{2029}b",
+        ] {
+            assert!(needs_quotes(quoted), "`{quoted}` should be quoted");
+        }
+
+        let value = record([("yes", number(1)), ("a: b\n", number(2))]);
+        assert_eq!(
+            to_string(&value, &Options::default()).unwrap(),
+            "\"yes\": 1\n\"a: b\\n\": 2\n"
+        );
+
+        let value = Value::list(vec![Value::string("a\u{2028}b\u{85}")]);
+        assert_eq!(
+            to_string(&value, &Options::default()).unwrap(),
+            "- \"a\\u2028b\\u0085\"\n"
+        );
+    }
+
+    #[test]
+    fn functions_are_rejected() {
+        let value = record([("a", Value::list(vec![Value::function(|v| Ok(v.clone()))]))]);
+
+        match to_string(&value, &Options::default()) {
+            Err(OutputError::Function { path }) => assert_eq!(path.to_string(), "`a[0]`"),
+            result => panic!("unexpected result: {result:?}"),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! An interactive session, reading expressions one line at a time.
+
+use miette::{miette, Report};
+
+use crate::{
+    compiler::{CompileError, Compiler, Entry},
+    vm::{
+        builtins::{self, STD_PREFIX},
+        value::ValueKind,
+    },
+};
+
+const HELP: &str = "\
+Enter an expression to evaluate it, or `let name = value, ...` to bind names for the rest of the
+session. Input that ends before the expression is complete continues on the next line, until a
+blank line.
+
+Commands:
+  :type EXPR    Show the type of an expression, without evaluating it
+  :ast EXPR     Show the syntax tree of an expression
+  :load FILE    Evaluate a file, and bind the fields of the resulting record
+  :help [NAME]  Show this message, or the documentation of a module or function of the standard
+                library, such as `std:string` or `std:string.split`
+  :quit         End the session";
+
+/// A REPL session: Expressions are evaluated in an environment that `let` lines add to.
+pub struct Repl {
+    compiler: Compiler,
+
+    /// The lines of an incomplete input, waiting for more.
+    pending: String,
+}
+
+/// The reply to a line of input.
+#[derive(Debug)]
+pub enum Response {
+    /// The input is incomplete, and continues on the next line.
+    Continue,
+
+    /// The input was handled, with the given text to show, if any.
+    Output(String),
+
+    /// The input failed.
+    Error(Report),
+
+    /// The session is over.
+    Quit,
+}
+
+impl Repl {
+    pub fn new(compiler: Compiler) -> Self {
+        Self {
+            compiler,
+            pending: String::new(),
+        }
+    }
+
+    /// Whether the previous lines were an incomplete input, which the next line continues.
+    pub fn is_continuing(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Handle a line of input.
+    pub fn line(&mut self, line: &str) -> Response {
+        if !self.is_continuing() {
+            let line = line.trim();
+            if line.is_empty() {
+                return Response::Output(String::new());
+            }
+
+            if let Some(command) = line.strip_prefix(':') {
+                return self.command(command);
+            }
+        } else if line.trim().is_empty() {
+            // A blank line gives up on an incomplete input, and reports why it is incomplete.
+            let input = std::mem::take(&mut self.pending);
+            return match self.compiler.check(entry(&input)) {
+                Ok(()) => unreachable!("incomplete inputs do not parse"),
+                Err(err) => Response::Error(err.into()),
+            };
+        }
+
+        self.pending.push_str(line);
+        self.pending.push('\n');
+
+        let input = std::mem::take(&mut self.pending);
+        match self.input(&input) {
+            Some(response) => response,
+            None => {
+                self.pending = input;
+                Response::Continue
+            }
+        }
+    }
+
+    /// Evaluate an input, or bind the names it defines. Returns `None` if the input is incomplete.
+    fn input(&mut self, input: &str) -> Option<Response> {
+        match self.compiler.eval(entry(input)) {
+            Ok(value) => Some(Response::Output(format!("{value:?}"))),
+            Err(CompileError::Syntax(err)) if err.is_incomplete() => self.definition(input),
+            Err(err) => Some(Response::Error(err.into())),
+        }
+    }
+
+    /// Bind the names of a `let` with no body, which would otherwise be an incomplete expression.
+    /// Returns `None` if the input is not such a definition.
+    fn definition(&mut self, input: &str) -> Option<Response> {
+        if !input.trim_start().starts_with("let") {
+            return None;
+        }
+
+        match self.compiler.define(entry(&format!("{input}in null"))) {
+            Ok(bound) if !bound.is_empty() => {
+                let lines: Vec<_> = bound
+                    .iter()
+                    .map(|(name, value)| format!("{name} = {value:?}"))
+                    .collect();
+                Some(Response::Output(lines.join("\n")))
+            }
+            Err(
+                err @ (CompileError::Runtime(_)
+                | CompileError::Eval(_)
+                | CompileError::Contract(_)
+                | CompileError::Merge(_)
+                | CompileError::Import(_)
+                | CompileError::Type(_)),
+            ) => Some(Response::Error(err.into())),
+            Ok(_) | Err(_) => None,
+        }
+    }
+
+    fn command(&mut self, command: &str) -> Response {
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .map_or((command, ""), |(name, arg)| (name, arg.trim()));
+
+        let result = match name {
+            "type" | "t" => self
+                .compiler
+                .type_of(entry(arg))
+                .map(|ty| ty.to_string())
+                .map_err(Report::from),
+            "ast" => self.compiler.ast(entry(arg)).map_err(Report::from),
+            "load" | "l" => self.load(arg),
+            "help" | "h" | "?" if arg.is_empty() => Ok(HELP.to_owned()),
+            "help" | "h" | "?" => help(arg),
+            "quit" | "q" => return Response::Quit,
+            _ => Err(miette!("Unknown command `:{name}`, see `:help`")),
+        };
+
+        match result {
+            Ok(output) => Response::Output(output),
+            Err(err) => Response::Error(err),
+        }
+    }
+
+    fn load(&mut self, path: &str) -> Result<String, Report> {
+        if path.is_empty() {
+            return Err(miette!("Missing file to load"));
+        }
+
+        let value = self.compiler.eval(path)?;
+        let ValueKind::Record(record) = &*value.kind else {
+            return Err(miette!(
+                "Only records can be loaded, but `{path}` is {}",
+                value.kind.describe()
+            ));
+        };
+
+        for (name, value) in &record.fields {
+            self.compiler.bind(name, value.clone());
+        }
+
+        let names: Vec<_> = record.fields.keys().map(String::as_str).collect();
+        Ok(format!("Bound {}", names.join(", ")))
+    }
+}
+
+/// The documentation of a module of the standard library, with a summary of each of its
+/// functions, or of one of those functions.
+fn help(name: &str) -> Result<String, Report> {
+    let unknown = || miette!("Unknown module or function `{name}`, such as `{STD_PREFIX}string`");
+    let path = name.strip_prefix(STD_PREFIX).ok_or_else(unknown)?;
+    let (module, native) = match path.split_once('.') {
+        Some((module, native)) => (module, Some(native)),
+        None => (path, None),
+    };
+    let module = builtins::module(module).ok_or_else(unknown)?;
+
+    if let Some(native) = native {
+        return Ok(module.native(native).ok_or_else(unknown)?.help());
+    }
+
+    let width = module
+        .natives
+        .iter()
+        .map(|native| native.usage().len())
+        .max();
+    let lines: Vec<_> = module
+        .natives
+        .iter()
+        .map(|native| {
+            let usage = native.usage();
+            format!(
+                "  {usage:width$}  {}",
+                native.summary(),
+                width = width.unwrap_or(0)
+            )
+        })
+        .collect();
+    Ok(format!(
+        "Import with `import \"{STD_PREFIX}{}\"`:\n{}",
+        module.name,
+        lines.join("\n")
+    ))
+}
+
+fn entry(contents: &str) -> Entry<'_> {
+    Entry::Inline {
+        name: "<repl>",
+        contents,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::compiler::source::FileLoader;
+
+    use super::*;
+
+    fn repl() -> Repl {
+        let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/output/golden");
+        Repl::new(Compiler::with_loader(FileLoader::new(golden)))
+    }
+
+    #[track_caller]
+    fn assert_output(repl: &mut Repl, line: &str, expected: &str) {
+        match repl.line(line) {
+            Response::Output(output) => assert_eq!(output, expected, "line: `{line}`"),
+            response => panic!("unexpected response to `{line}`: {response:?}"),
+        }
+    }
+
+    #[track_caller]
+    fn assert_error(repl: &mut Repl, line: &str, expected: &str) {
+        match repl.line(line) {
+            Response::Error(err) => assert_eq!(err.to_string(), expected, "line: `{line}`"),
+            response => panic!("unexpected response to `{line}`: {response:?}"),
+        }
+    }
+
+    #[test]
+    fn let_bindings_persist() {
+        let mut repl = repl();
+        assert_output(&mut repl, "let a = 1, b = a + 1", "a = 1\nb = 2");
+        assert_output(&mut repl, "[a, b]", "[1, 2]");
+        assert_output(&mut repl, "let a = a * 10", "a = 10");
+        assert_output(&mut repl, "let c = 5 in a + c", "15");
+        assert_error(&mut repl, "c", "Type errors in `<repl>`");
+        assert_error(&mut repl, "let d = 1 / 0", "Division by zero");
+        assert_error(&mut repl, "let d = 1 + d", "Type errors in `<repl>`");
+        assert_error(&mut repl, "d", "Type errors in `<repl>`");
+    }
+
+    #[test]
+    fn incomplete_input_continues() {
+        let mut repl = repl();
+        assert!(matches!(repl.line("{ a = 1,"), Response::Continue));
+        assert!(repl.is_continuing());
+        assert!(matches!(repl.line("b = [2"), Response::Continue));
+        assert_output(&mut repl, "] }", "{a = 1, b = [2]}");
+        assert!(!repl.is_continuing());
+
+        assert!(matches!(repl.line("let x ="), Response::Continue));
+        assert_output(&mut repl, "  { y = 1 }", "x = {y = 1}");
+        assert_output(&mut repl, "x.y", "1");
+
+        assert!(matches!(repl.line("[1,"), Response::Continue));
+        assert_error(&mut repl, "", "Could not parse `<repl>`");
+        assert!(!repl.is_continuing());
+    }
+
+    #[test]
+    fn commands() {
+        let mut repl = repl();
+        assert_output(&mut repl, ":type { a = 1 }", "{a: Number}");
+        assert_output(&mut repl, ":type args.a + 1", "Number");
+        assert_error(&mut repl, ":type 1 + true", "Type errors in `<repl>`");
+        assert_output(&mut repl, ":ast let x = 1 in x.y", "(let [x = 1] (x.y))");
+        assert_error(&mut repl, ":ast (", "Could not parse `<repl>`");
+        assert_error(&mut repl, ":nope", "Unknown command `:nope`, see `:help`");
+        assert!(matches!(repl.line(":quit"), Response::Quit));
+    }
+
+    #[test]
+    fn help_documents_the_standard_library() {
+        let mut repl = repl();
+        let Response::Output(help) = repl.line(":help std:string") else {
+            panic!("no help for `std:string`");
+        };
+        let mut lines = help.lines();
+        assert_eq!(lines.next(), Some(r#"Import with `import "std:string"`:"#));
+        assert_eq!(
+            lines.next(),
+            Some("  split separator string              Split a string at each occurrence of a separator, which must not be empty.")
+        );
+
+        assert_output(
+            &mut repl,
+            ":help std:string.length",
+            "`length string`\n\nThe length of a string, in Unicode scalar values.\n\n`length \"héllo\"` is `5`.",
+        );
+        assert_error(
+            &mut repl,
+            ":help std:string.nope",
+            "Unknown module or function `std:string.nope`, such as `std:string`",
+        );
+        assert_error(
+            &mut repl,
+            ":help split",
+            "Unknown module or function `split`, such as `std:string`",
+        );
+    }
+
+    #[test]
+    fn files_are_loaded_into_scope() {
+        let mut repl = repl();
+        assert_output(&mut repl, ":load nullable.dek", "Bound a, b");
+        assert_output(&mut repl, "[a, b.c]", "[1, null]");
+        assert_error(
+            &mut repl,
+            ":load scalar.dek",
+            "Only records can be loaded, but `scalar.dek` is a number",
+        );
+    }
+}
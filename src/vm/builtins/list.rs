@@ -0,0 +1,380 @@
+//! `std:list`: Functions on lists. Those that search a list stop calling the function they are
+//! given once the result is known, so that the rest of the list may hold items it fails on. The
+//! items of the lists that `map`, `take` and `zip` return are only evaluated once they are used, so
+//! that `find (f) (map g list)` only calls `g` on the items up to the one found.
+
+use core::cmp::Ordering;
+
+use malachite::Rational;
+
+use super::*;
+
+natives! {
+    /// Apply a function to each item of a list.
+    ///
+    /// `map string.to_upper ["a", "b"]` is `["A", "B"]`.
+    fn map(f, list) {
+        if !matches!(&*f.kind, ValueKind::Function(_)) {
+            return Err(mismatch("a function", &f.kind));
+        }
+        let items = thunks(list)?.into_iter().map(|item| {
+            let f = f.clone();
+            Thunk::new(move || call(&f, item.force()?))
+        });
+        Ok(Value::lazy_list(items.collect()))
+    }
+
+    /// The first `n` items of a list, or all of them if it is shorter.
+    ///
+    /// `take 2 ["a", "b", "c"]` is `["a", "b"]`.
+    fn take(n, list) {
+        let mut items = thunks(list)?;
+        items.truncate(count(n)?);
+        Ok(Value::lazy_list(items))
+    }
+
+    /// The items of a list that a predicate returns `true` for.
+    ///
+    /// `filter (string.starts_with "a") ["ab", "b", "ac"]` is `["ab", "ac"]`.
+    fn filter(predicate, list) {
+        let mut items = Vec::new();
+        for item in super::list(list)? {
+            if boolean(&call(predicate, item)?)? {
+                items.push(item.clone());
+            }
+        }
+        Ok(Value::list(items))
+    }
+
+    /// Combine the items of a list into one value, from the first to the last: `f` is given the
+    /// value combined so far, starting with `initial`, and the next item.
+    ///
+    /// `fold f initial [a, b]` is `f (f initial a) b`.
+    fn fold(f, initial, list) {
+        super::list(list)?
+            .iter()
+            .try_fold(initial.clone(), |acc, item| call(&call(f, &acc)?, item))
+    }
+
+    /// Apply a function that returns lists to each item of a list, and concatenate the results.
+    ///
+    /// `flat_map (string.split ",") ["a,b", "c"]` is `["a", "b", "c"]`.
+    fn flat_map(f, list) {
+        let mut items = Vec::new();
+        for item in super::list(list)? {
+            items.extend(super::list(&call(f, item)?)?.iter().cloned());
+        }
+        Ok(Value::list(items))
+    }
+
+    /// Sort a list by a key computed for each item, which must be numbers for all items, or
+    /// strings for all items. Items with equal keys keep their order.
+    ///
+    /// `sort_by string.length ["abc", "a", "ab"]` is `["a", "ab", "abc"]`.
+    fn sort_by(key, list) {
+        let items = super::list(list)?;
+        let keys = items
+            .iter()
+            .map(|item| call(key, item))
+            .collect::<Result<Vec<_>, _>>()?;
+        let keys = match keys.first().map(|key| &*key.kind) {
+            None => return Ok(list.clone()),
+            Some(ValueKind::Number(_)) => Keys::Numbers(
+                keys.iter().map(number).collect::<Result<_, _>>()?,
+            ),
+            Some(ValueKind::String(_)) => Keys::Strings(
+                keys.iter().map(super::string).collect::<Result<_, _>>()?,
+            ),
+            Some(kind) => return Err(mismatch("a number or a string", kind)),
+        };
+
+        let mut order: Vec<usize> = (0..items.len()).collect();
+        order.sort_by(|&a, &b| keys.compare(a, b));
+        Ok(Value::list(order.into_iter().map(|i| items[i].clone()).collect()))
+    }
+
+    /// The items of a list without those equal to an earlier one.
+    ///
+    /// `unique [1, 2, 1, 3, 2]` is `[1, 2, 3]`.
+    fn unique(list) {
+        let mut items: Vec<Value> = Vec::new();
+        for item in super::list(list)? {
+            item.force()?;
+            if !items.iter().any(|other| equal(item, other)) {
+                items.push(item.clone());
+            }
+        }
+        Ok(Value::list(items))
+    }
+
+    /// Pair the items of two lists, in order, as lists of two items. The longer list is cut to
+    /// the length of the shorter one.
+    ///
+    /// `zip ["a", "b"] [1, 2, 3]` is `[["a", 1], ["b", 2]]`.
+    fn zip(left, right) {
+        let pairs = thunks(left)?
+            .into_iter()
+            .zip(thunks(right)?)
+            .map(|(left, right)| Value::lazy_list(vec![left, right]));
+        Ok(Value::list(pairs.collect()))
+    }
+
+    /// The integers from `start`, up to but not including `end`.
+    ///
+    /// `range 1 4` is `[1, 2, 3]`.
+    fn range(start, end) {
+        let (start, end) = (integer(start)?, integer(end)?);
+        if i128::from(end) - i128::from(start) > MAX_SIZE as i128 {
+            return Err(RuntimeError::InvalidArgument {
+                message: format!("The range would have more than {MAX_SIZE} items"),
+            });
+        }
+        Ok(Value::list((start..end).map(|i| Value::number(i.into())).collect()))
+    }
+
+    /// Whether a predicate returns `true` for any item of a list.
+    ///
+    /// `any (string.starts_with "a") ["b", "ab"]` is `true`.
+    fn any(predicate, list) {
+        for item in thunks(list)? {
+            if boolean(&call(predicate, item.force()?)?)? {
+                return Ok(Value::boolean(true));
+            }
+        }
+        Ok(Value::boolean(false))
+    }
+
+    /// Whether a predicate returns `true` for every item of a list.
+    ///
+    /// `all (string.starts_with "a") ["b", "ab"]` is `false`.
+    fn all(predicate, list) {
+        for item in thunks(list)? {
+            if !boolean(&call(predicate, item.force()?)?)? {
+                return Ok(Value::boolean(false));
+            }
+        }
+        Ok(Value::boolean(true))
+    }
+
+    /// The first item of a list that a predicate returns `true` for, or `null` if there is none.
+    ///
+    /// `find (string.starts_with "a") ["b", "ab", "ac"]` is `"ab"`.
+    fn find(predicate, list) {
+        for item in thunks(list)? {
+            let item = item.force()?;
+            if boolean(&call(predicate, item)?)? {
+                return Ok(item.clone());
+            }
+        }
+        Ok(Value::null())
+    }
+
+    /// Group the items of a list by a key computed for each item, which must be a string: A
+    /// record with a field for each key, in the order they first occur, listing the items with
+    /// that key in order.
+    ///
+    /// `group_by string.to_lower ["a", "B", "A"]` is `{a = ["a", "A"], b = ["B"]}`.
+    fn group_by(key, list) {
+        let mut groups: IndexMap<String, Vec<Value>> = IndexMap::new();
+        for item in super::list(list)? {
+            let key = call(key, item)?;
+            groups
+                .entry(super::string(&key)?.to_owned())
+                .or_default()
+                .push(item.clone());
+        }
+        Ok(Value::record(
+            groups
+                .into_iter()
+                .map(|(key, items)| (key, Value::list(items)))
+                .collect(),
+        ))
+    }
+}
+
+/// The keys to sort a list by.
+enum Keys<'a> {
+    Numbers(Vec<&'a Rational>),
+    Strings(Vec<&'a str>),
+}
+
+impl Keys<'_> {
+    fn compare(&self, a: usize, b: usize) -> Ordering {
+        match self {
+            Self::Numbers(keys) => keys[a].cmp(keys[b]),
+            Self::Strings(keys) => keys[a].cmp(keys[b]),
+        }
+    }
+}
+
+/// Whether two values are equal: Records are equal regardless of the order of their fields, and
+/// functions are never equal. The values must be [forced](Value::force).
+fn equal(a: &Value, b: &Value) -> bool {
+    match (&*a.kind, &*b.kind) {
+        (ValueKind::Null, ValueKind::Null) => true,
+        (ValueKind::Boolean(a), ValueKind::Boolean(b)) => a.value == b.value,
+        (ValueKind::Number(a), ValueKind::Number(b)) => a.value == b.value,
+        (ValueKind::String(a), ValueKind::String(b)) => a.value == b.value,
+        (ValueKind::List(a), ValueKind::List(b)) => {
+            a.len() == b.len() && a.items().iter().zip(b.items()).all(|(a, b)| equal(a, b))
+        }
+        (ValueKind::Record(a), ValueKind::Record(b)) => {
+            a.fields.len() == b.fields.len()
+                && a.fields
+                    .iter()
+                    .all(|(name, a)| b.fields.get(name).is_some_and(|b| equal(a, b)))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::builtins::tests::{assert_evals, assert_fails};
+
+    #[test]
+    fn map() {
+        assert_evals(r#"list.map string.to_upper ["a", "b"]"#, r#"["A", "B"]"#);
+        assert_evals("list.map (args.add 1) []", "[]");
+        assert_fails("list.map 1 [1]", "Expected a function, found a number");
+        assert_fails("list.map (number.div 1) [1, 0]", "Division by zero");
+    }
+
+    #[test]
+    fn take() {
+        assert_evals(r#"list.take 2 ["a", "b", "c"]"#, r#"["a", "b"]"#);
+        assert_evals("list.take 2 [1]", "[1]");
+        assert_evals("list.take 1 (list.map (number.div 1) [1, 0])", "[1]");
+        assert_fails("list.take (0 - 1) [1]", "-1 is not a count");
+    }
+
+    #[test]
+    fn filter() {
+        assert_evals(
+            r#"list.filter (string.starts_with "a") ["ab", "b", "ac"]"#,
+            r#"["ab", "ac"]"#,
+        );
+        assert_fails(
+            "list.filter (args.add 1) [1]",
+            "Expected a boolean, found a number",
+        );
+    }
+
+    #[test]
+    fn fold() {
+        assert_evals("list.fold args.add 0 [1, 2, 3]", "6");
+        assert_evals("list.fold args.add 0 []", "0");
+        assert_fails(
+            "list.fold args.add 0 [[1]]",
+            "Expected a number, found a list",
+        );
+    }
+
+    #[test]
+    fn flat_map() {
+        assert_evals(
+            r#"list.flat_map (string.split ",") ["a,b", "c"]"#,
+            r#"["a", "b", "c"]"#,
+        );
+        assert_fails(
+            "list.flat_map (args.add 1) [1]",
+            "Expected a list, found a number",
+        );
+    }
+
+    #[test]
+    fn sort_by() {
+        assert_evals(
+            r#"list.sort_by string.length ["abc", "a", "ab", "b"]"#,
+            r#"["a", "b", "ab", "abc"]"#,
+        );
+        assert_evals(
+            r#"list.sort_by string.to_lower ["b", "A", "c"]"#,
+            r#"["A", "b", "c"]"#,
+        );
+        assert_evals(
+            "list.sort_by (args.add 0) [2, 1 / 2, 0 - 1]",
+            "[-1, 1/2, 2]",
+        );
+        assert_evals("list.sort_by (args.add 0) []", "[]");
+        assert_fails(
+            r#"list.sort_by (list.find (string.starts_with "a")) [["b"], ["a"]]"#,
+            "Expected a number or a string, found null",
+        );
+    }
+
+    #[test]
+    fn unique() {
+        assert_evals("list.unique [1, 2, 1, 3, 2]", "[1, 2, 3]");
+        assert_evals(
+            "list.unique [{ a = 1, b = 2 }, { b = 2, a = 1 }, [1], [1]]",
+            "[{a = 1, b = 2}, [1]]",
+        );
+    }
+
+    #[test]
+    fn zip() {
+        assert_evals(
+            r#"list.zip ["a", "b"] [1, 2, 3]"#,
+            r#"[["a", 1], ["b", 2]]"#,
+        );
+        assert_evals("list.zip [] [1]", "[]");
+        assert_evals(
+            "list.map (list.take 1) (list.zip [1, 2] (list.map (number.div 1) [1, 0]))",
+            "[[1], [2]]",
+        );
+    }
+
+    #[test]
+    fn range() {
+        assert_evals("list.range 1 4", "[1, 2, 3]");
+        assert_evals("list.range (0 - 1) 1", "[-1, 0]");
+        assert_evals("list.range 3 1", "[]");
+        assert_fails("list.range 0 (1 / 2)", "1/2 is not an integer");
+        assert_fails(
+            "list.range 0 100000000000",
+            "The range would have more than 1048576 items",
+        );
+    }
+
+    #[test]
+    fn any() {
+        assert_evals(r#"list.any (string.starts_with "a") ["b", "ab"]"#, "true");
+        assert_evals(r#"list.any (string.starts_with "a") []"#, "false");
+        // The predicate is not called on the items after the first match.
+        assert_evals(r#"list.any (string.starts_with "a") ["ab", 1]"#, "true");
+    }
+
+    #[test]
+    fn all() {
+        assert_evals(r#"list.all (string.starts_with "a") ["ab", "ac"]"#, "true");
+        assert_evals(r#"list.all (string.starts_with "a") []"#, "true");
+        assert_evals(r#"list.all (string.starts_with "a") ["b", 1]"#, "false");
+    }
+
+    #[test]
+    fn find() {
+        assert_evals(
+            r#"list.find (string.starts_with "a") ["b", "ab", "ac", 1]"#,
+            r#""ab""#,
+        );
+        assert_evals(r#"list.find (string.starts_with "a") ["b"]"#, "null");
+        // The items after the one found are not evaluated.
+        assert_evals(
+            r#"list.find (string.starts_with "a") (list.map string.to_lower ["B", "A", 1])"#,
+            r#""a""#,
+        );
+    }
+
+    #[test]
+    fn group_by() {
+        assert_evals(
+            r#"list.group_by string.to_lower ["a", "B", "A"]"#,
+            r#"{a = ["a", "A"], b = ["B"]}"#,
+        );
+        assert_fails(
+            "list.group_by (args.add 1) [1]",
+            "Expected a string, found a number",
+        );
+    }
+}
@@ -0,0 +1,338 @@
+//! The functions of the standard library, implemented natively.
+//!
+//! Natives are grouped into modules, which programs import as records of functions, such as
+//! `import "std:string"`. Each native takes its arguments one at a time, as dek functions do, and
+//! is only called once it has all of them. The doc comments of natives are kept for hovers and
+//! help output.
+
+use indexmap::IndexMap;
+use malachite::Rational;
+
+use super::{
+    value::{Record, Thunk, Value, ValueKind},
+    RuntimeError,
+};
+
+mod list;
+mod number;
+mod record;
+mod string;
+
+/// The prefix of imports that refer to a module of the standard library rather than a file.
+pub const STD_PREFIX: &str = "std:";
+
+/// The most items, characters or digits in a value that natives build from numbers they are given,
+/// so that a mistaken argument fails instead of exhausting memory.
+const MAX_SIZE: usize = 1 << 20;
+
+/// A module of the standard library.
+pub struct Module {
+    pub name: &'static str,
+    pub natives: &'static [Native],
+}
+
+/// A function of the standard library.
+pub struct Native {
+    pub name: &'static str,
+
+    /// The names of the parameters, in the order the arguments are given in.
+    pub params: &'static [&'static str],
+
+    /// The lines of the doc comment.
+    docs: &'static [&'static str],
+
+    call: fn(&[Value]) -> Result<Value, RuntimeError>,
+}
+
+pub static MODULES: &[Module] = &[
+    Module {
+        name: "list",
+        natives: list::NATIVES,
+    },
+    Module {
+        name: "number",
+        natives: number::NATIVES,
+    },
+    Module {
+        name: "record",
+        natives: record::NATIVES,
+    },
+    Module {
+        name: "string",
+        natives: string::NATIVES,
+    },
+];
+
+/// The module of the standard library with the given name, without the [`STD_PREFIX`].
+pub fn module(name: &str) -> Option<&'static Module> {
+    MODULES.iter().find(|module| module.name == name)
+}
+
+impl Module {
+    pub fn native(&self, name: &str) -> Option<&'static Native> {
+        self.natives.iter().find(|native| native.name == name)
+    }
+
+    /// The module as a record of functions, as it is imported.
+    pub fn value(&self) -> Value {
+        Value::record(
+            self.natives
+                .iter()
+                .map(|native| (native.name.to_owned(), native.value()))
+                .collect::<IndexMap<_, _>>(),
+        )
+    }
+}
+
+impl Native {
+    /// The doc comment of the native, in Markdown.
+    pub fn doc(&self) -> String {
+        let lines: Vec<_> = self
+            .docs
+            .iter()
+            .map(|line| line.strip_prefix(' ').unwrap_or(line))
+            .collect();
+        lines.join("\n")
+    }
+
+    /// The first paragraph of the doc comment.
+    pub fn summary(&self) -> String {
+        let doc = self.doc();
+        let summary = doc.split("\n\n").next().unwrap_or_default();
+        summary.replace('\n', " ")
+    }
+
+    /// How the native is called, such as `split separator string`.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_owned();
+        for param in self.params {
+            usage.push(' ');
+            usage.push_str(param);
+        }
+        usage
+    }
+
+    /// How the native is called, followed by its doc comment, as shown by hovers and help output.
+    pub fn help(&self) -> String {
+        format!("`{}`\n\n{}", self.usage(), self.doc())
+    }
+
+    /// The native as a function value.
+    pub fn value(&'static self) -> Value {
+        self.applied(Vec::new())
+    }
+
+    /// The native with some of its arguments given, as a function taking the next one.
+    fn applied(&'static self, args: Vec<Value>) -> Value {
+        Value::function(move |arg| {
+            let mut args = args.clone();
+            args.push(arg.clone());
+            if args.len() == self.params.len() {
+                (self.call)(&args)
+            } else {
+                Ok(self.applied(args))
+            }
+        })
+    }
+}
+
+/// Define natives as Rust functions taking their arguments as values, along with a `NATIVES` table
+/// of them. Their doc comments are both Rust and dek documentation. Natives named after Rust
+/// keywords are defined with raw identifiers, such as `r#mod`.
+macro_rules! natives {
+    ($(
+        $(#[doc = $doc:literal])*
+        fn $name:ident($($param:ident),+ $(,)?) $body:block
+    )*) => {
+        $(
+            $(#[doc = $doc])*
+            fn $name($($param: &Value),+) -> Result<Value, RuntimeError> $body
+        )*
+
+        pub(super) static NATIVES: &[Native] = &[$(Native {
+            name: unraw(stringify!($name)),
+            params: &[$(stringify!($param)),+],
+            docs: &[$($doc),*],
+            call: |args| match args {
+                [$($param),+] => $name($($param),+),
+                _ => unreachable!("natives are called with all their arguments"),
+            },
+        }),*];
+    };
+}
+
+use natives;
+
+/// The name of a native, without the `r#` of a raw identifier.
+const fn unraw(name: &'static str) -> &'static str {
+    match name.as_bytes() {
+        [b'r', b'#', rest @ ..] => match core::str::from_utf8(rest) {
+            Ok(name) => name,
+            Err(_) => unreachable!(),
+        },
+        _ => name,
+    }
+}
+
+/// Call a function value, as given to natives that take functions.
+fn call(fun: &Value, arg: &Value) -> Result<Value, RuntimeError> {
+    match &*fun.kind {
+        ValueKind::Function(fun) => (fun.body)(arg),
+        kind => Err(mismatch("a function", kind)),
+    }
+}
+
+fn boolean(value: &Value) -> Result<bool, RuntimeError> {
+    match &*value.kind {
+        ValueKind::Boolean(boolean) => Ok(boolean.value),
+        kind => Err(mismatch("a boolean", kind)),
+    }
+}
+
+fn number(value: &Value) -> Result<&Rational, RuntimeError> {
+    match &*value.kind {
+        ValueKind::Number(number) => Ok(&number.value),
+        kind => Err(mismatch("a number", kind)),
+    }
+}
+
+/// A number that must be an integer of at most 64 bits.
+fn integer(value: &Value) -> Result<i64, RuntimeError> {
+    let number = number(value)?;
+    i64::try_from(number).map_err(|_| RuntimeError::InvalidArgument {
+        message: if *number.denominator_ref() == 1u32 {
+            format!(
+                "{number:?} is out of range, as integers must be between {} and {}",
+                i64::MIN,
+                i64::MAX
+            )
+        } else {
+            format!("{number:?} is not an integer")
+        },
+    })
+}
+
+fn string(value: &Value) -> Result<&str, RuntimeError> {
+    match &*value.kind {
+        ValueKind::String(string) => Ok(&string.value),
+        kind => Err(mismatch("a string", kind)),
+    }
+}
+
+/// The items of a list, all evaluated.
+fn list(value: &Value) -> Result<&[Value], RuntimeError> {
+    match &*value.kind {
+        ValueKind::List(list) => list.force(),
+        kind => Err(mismatch("a list", kind)),
+    }
+}
+
+/// The items of a list, each evaluated on first use.
+fn thunks(value: &Value) -> Result<Vec<Thunk>, RuntimeError> {
+    match &*value.kind {
+        ValueKind::List(list) => Ok(list.thunks()),
+        kind => Err(mismatch("a list", kind)),
+    }
+}
+
+/// A record, which must not have left out late-bound fields: What natives build from its fields
+/// would silently lose them.
+fn record(value: &Value) -> Result<&Record, RuntimeError> {
+    match &*value.kind {
+        ValueKind::Record(record) => match record.unresolved.first() {
+            Some(unresolved) => Err(RuntimeError::Unresolved(unresolved.clone())),
+            None => Ok(record),
+        },
+        kind => Err(mismatch("a record", kind)),
+    }
+}
+
+/// A number that must be a non-negative integer which fits in memory, such as a length.
+fn count(value: &Value) -> Result<usize, RuntimeError> {
+    match &*value.kind {
+        ValueKind::Number(number) => {
+            let number = &number.value;
+            usize::try_from(number).map_err(|_| RuntimeError::InvalidArgument {
+                message: if *number > 0u32 && *number.denominator_ref() == 1u32 {
+                    format!(
+                        "{number:?} is out of range, as counts must be at most {}",
+                        usize::MAX
+                    )
+                } else {
+                    format!("{number:?} is not a count")
+                },
+            })
+        }
+        kind => Err(mismatch("a number", kind)),
+    }
+}
+
+fn mismatch(expected: &'static str, found: &ValueKind) -> RuntimeError {
+    RuntimeError::TypeMismatch {
+        expected,
+        found: found.describe(),
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+    use crate::compiler::{CompileError, Compiler, Entry};
+
+    /// Evaluate a source with every module of the standard library bound to its name, and a
+    /// function adding two numbers as `args.add`.
+    fn eval(source: &str) -> Result<String, CompileError> {
+        let modules: Vec<_> = MODULES
+            .iter()
+            .map(|module| format!("{0} = import \"{STD_PREFIX}{0}\"", module.name))
+            .collect();
+        let contents = format!("let {} in {source}", modules.join(", "));
+        let entry = Entry::Inline {
+            name: "test",
+            contents: &contents,
+        };
+        let add = Value::function(|a| {
+            let a = a.clone();
+            Ok(Value::function(move |b| match (&*a.kind, &*b.kind) {
+                (ValueKind::Number(a), ValueKind::Number(b)) => {
+                    Ok(Value::number(&a.value + &b.value))
+                }
+                _ => Err(mismatch("a number", &b.kind)),
+            }))
+        });
+
+        let mut compiler = Compiler::new();
+        compiler.arg("add", add);
+        compiler.eval(entry).map(|value| format!("{value:?}"))
+    }
+
+    #[track_caller]
+    pub(super) fn assert_evals(source: &str, expected: &str) {
+        match eval(source) {
+            Ok(value) => assert_eq!(value, expected, "source: {source}"),
+            Err(err) => panic!("cannot evaluate {source}: {err:?}"),
+        }
+    }
+
+    #[track_caller]
+    pub(super) fn assert_fails(source: &str, message: &str) {
+        match eval(source) {
+            Ok(value) => panic!("unexpected success for {source}: {value}"),
+            Err(err) => assert_eq!(err.to_string(), message, "source: {source}"),
+        }
+    }
+
+    #[test]
+    fn natives_are_documented() {
+        for module in MODULES {
+            for native in module.natives {
+                assert!(
+                    !native.summary().is_empty(),
+                    "{}.{} has no doc comment",
+                    module.name,
+                    native.name
+                );
+            }
+        }
+    }
+}
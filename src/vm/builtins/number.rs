@@ -0,0 +1,362 @@
+//! `std:number`: Functions on numbers, which are exact fractions. Functions that round give the
+//! nearest integer in the direction they are named after.
+
+use core::cmp::Ordering;
+
+use malachite::{
+    num::{
+        arithmetic::traits::{Abs, DivMod, Pow},
+        conversion::{string::options::ToSciOptions, traits::RoundingFrom, traits::ToSci},
+        logic::traits::SignificantBits,
+    },
+    rounding_modes::RoundingMode,
+    Integer,
+};
+
+use super::*;
+
+/// The rounding modes of `round`, by name.
+const MODES: &[&str] = &[
+    "up",
+    "down",
+    "ceiling",
+    "floor",
+    "half_up",
+    "half_down",
+    "half_even",
+];
+
+natives! {
+    /// The greatest integer less than or equal to a number.
+    ///
+    /// `floor (-5 / 2)` is `-3`.
+    fn floor(n) {
+        Ok(rounded(number(n)?, RoundingMode::Floor))
+    }
+
+    /// The least integer greater than or equal to a number.
+    ///
+    /// `ceil (5 / 2)` is `3`.
+    fn ceil(n) {
+        Ok(rounded(number(n)?, RoundingMode::Ceiling))
+    }
+
+    /// A number without its fractional part: The nearest integer towards zero.
+    ///
+    /// `trunc (-5 / 2)` is `-2`.
+    fn trunc(n) {
+        Ok(rounded(number(n)?, RoundingMode::Down))
+    }
+
+    /// Round a number to an integer, in the mode with the given name.
+    ///
+    /// - `"up"` and `"down"`: Away from and towards zero.
+    /// - `"ceiling"` and `"floor"`: Towards positive and negative infinity.
+    /// - `"half_up"`, `"half_down"` and `"half_even"`: To the nearest integer, and halfway between
+    ///   two integers away from zero, towards zero, or to the even one.
+    ///
+    /// `round "half_up" (5 / 2)` is `3`, and `round "half_even" (5 / 2)` is `2`.
+    fn round(mode, n) {
+        let n = number(n)?;
+        let rounding = match string(mode)? {
+            "up" => RoundingMode::Up,
+            "down" => RoundingMode::Down,
+            "ceiling" => RoundingMode::Ceiling,
+            "floor" => RoundingMode::Floor,
+            "half_even" => RoundingMode::Nearest,
+            mode @ ("half_up" | "half_down") => {
+                let floor = Integer::rounding_from(n, RoundingMode::Floor).0;
+                let fraction = n - Rational::from(floor.clone());
+                let up = match fraction.cmp(&Rational::from_signeds(1, 2)) {
+                    Ordering::Less => false,
+                    Ordering::Greater => true,
+                    Ordering::Equal => (mode == "half_up") == (*n > 0),
+                };
+                return Ok(Value::number(Rational::from(floor + Integer::from(up))));
+            }
+            mode => {
+                let modes: Vec<_> = MODES.iter().map(|mode| format!("{mode:?}")).collect();
+                return Err(RuntimeError::InvalidArgument {
+                    message: format!(
+                        "Unknown rounding mode {mode:?}, expected one of {}",
+                        modes.join(", ")
+                    ),
+                });
+            }
+        };
+        Ok(rounded(n, rounding))
+    }
+
+    /// Divide two integers, rounding the quotient towards negative infinity, so that
+    /// `a = b * div a b + mod a b`.
+    ///
+    /// `div 7 2` is `3`, and `div (-7) 2` is `-4`.
+    fn div(a, b) {
+        let (quotient, _) = divide(a, b)?;
+        Ok(Value::number(Rational::from(quotient)))
+    }
+
+    /// The remainder of dividing two integers, as by `div`. It has the sign of the divisor.
+    ///
+    /// `mod 7 2` is `1`, and `mod (-7) 2` is `1`.
+    fn r#mod(a, b) {
+        let (_, remainder) = divide(a, b)?;
+        Ok(Value::number(Rational::from(remainder)))
+    }
+
+    /// Raise a number to an integer power. Zero has no negative powers.
+    ///
+    /// `pow 2 10` is `1024`, and `pow 2 (-2)` is `1/4`.
+    fn pow(base, exponent) {
+        let (base, exponent) = (number(base)?, integer(exponent)?);
+        if *base == 0 && exponent < 0 {
+            return Err(RuntimeError::DivisionByZero);
+        }
+
+        // The numerator and denominator of the result have at least this many bits in all.
+        let bits = [base.numerator_ref(), base.denominator_ref()]
+            .map(|part| u128::from(part.significant_bits().saturating_sub(1)))
+            .iter()
+            .sum::<u128>()
+            * u128::from(exponent.unsigned_abs());
+        if bits * 30103 / 100_000 > MAX_SIZE as u128 {
+            return Err(RuntimeError::InvalidArgument {
+                message: format!("The result would have more than {MAX_SIZE} digits"),
+            });
+        }
+        Ok(Value::number(base.pow(exponent)))
+    }
+
+    /// The absolute value of a number.
+    ///
+    /// `abs (-3)` is `3`.
+    fn abs(n) {
+        Ok(Value::number(number(n)?.abs()))
+    }
+
+    /// The lesser of two numbers.
+    ///
+    /// `min 1 2` is `1`.
+    fn min(a, b) {
+        Ok(if number(b)? < number(a)? { b } else { a }.clone())
+    }
+
+    /// The greater of two numbers.
+    ///
+    /// `max 1 2` is `2`.
+    fn max(a, b) {
+        Ok(if number(b)? > number(a)? { b } else { a }.clone())
+    }
+
+    /// The numerator of a number as a fraction in lowest terms, which has the sign of the number.
+    ///
+    /// `numerator (-6 / 4)` is `-3`.
+    fn numerator(n) {
+        let n = number(n)?;
+        let numerator = Rational::from(n.to_numerator());
+        Ok(Value::number(if *n < 0 { -numerator } else { numerator }))
+    }
+
+    /// The denominator of a number as a fraction in lowest terms, which is positive.
+    ///
+    /// `denominator (-6 / 4)` is `2`.
+    fn denominator(n) {
+        Ok(Value::number(Rational::from(number(n)?.to_denominator())))
+    }
+
+    /// Whether a number is an integer.
+    ///
+    /// `is_integer (4 / 2)` is `true`.
+    fn is_integer(n) {
+        Ok(Value::boolean(*number(n)?.denominator_ref() == 1u32))
+    }
+
+    /// Format a number in decimal notation, with exactly `places` digits after the point. The last
+    /// digit is rounded to the nearest, and halfway between two digits to the even one. Numbers
+    /// that round to zero have no sign.
+    ///
+    /// `to_decimal (2 / 3) 2` is `"0.67"`, and `to_decimal 1 2` is `"1.00"`.
+    fn to_decimal(n, places) {
+        let (n, places) = (number(n)?, count(places)?);
+        if places > MAX_SIZE {
+            return Err(RuntimeError::InvalidArgument {
+                message: format!("Cannot format with more than {MAX_SIZE} places"),
+            });
+        }
+
+        let mut options = ToSciOptions::default();
+        options.set_scale(places as u64);
+        options.set_rounding_mode(RoundingMode::Nearest);
+        options.set_include_trailing_zeros(true);
+        options.set_neg_exp_threshold(i64::MIN);
+        let decimal = n.to_sci_with_options(options).to_string();
+        Ok(Value::string(match decimal.strip_prefix('-') {
+            Some(zero) if zero.bytes().all(|b| matches!(b, b'0' | b'.')) => zero,
+            _ => &decimal,
+        }))
+    }
+}
+
+/// A number rounded to an integer.
+fn rounded(n: &Rational, mode: RoundingMode) -> Value {
+    let (integer, _) = Integer::rounding_from(n, mode);
+    Value::number(Rational::from(integer))
+}
+
+/// The quotient and remainder of dividing two integers, as by `div` and `mod`.
+fn divide(a: &Value, b: &Value) -> Result<(Integer, Integer), RuntimeError> {
+    let (a, b) = (whole(a)?, whole(b)?);
+    if b == 0 {
+        return Err(RuntimeError::DivisionByZero);
+    }
+    Ok(a.div_mod(b))
+}
+
+/// A number that must be an integer, of any size.
+fn whole(value: &Value) -> Result<Integer, RuntimeError> {
+    let number = number(value)?;
+    Integer::try_from(number).map_err(|_| RuntimeError::InvalidArgument {
+        message: format!("{number:?} is not an integer"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::builtins::tests::{assert_evals, assert_fails};
+
+    #[test]
+    fn floor() {
+        assert_evals("number.floor (5 / 2)", "2");
+        assert_evals("number.floor (-5 / 2)", "-3");
+        assert_evals("number.floor 4", "4");
+        assert_fails("number.floor \"1\"", "Expected a number, found a string");
+    }
+
+    #[test]
+    fn ceil() {
+        assert_evals("number.ceil (5 / 2)", "3");
+        assert_evals("number.ceil (-5 / 2)", "-2");
+    }
+
+    #[test]
+    fn trunc() {
+        assert_evals("number.trunc (5 / 2)", "2");
+        assert_evals("number.trunc (-5 / 2)", "-2");
+    }
+
+    #[test]
+    fn round() {
+        let round = |mode: &str| {
+            format!("list.map (number.round \"{mode}\") [5 / 2, 7 / 2, -5 / 2, 13 / 5, -13 / 5, 3]")
+        };
+        assert_evals(&round("up"), "[3, 4, -3, 3, -3, 3]");
+        assert_evals(&round("down"), "[2, 3, -2, 2, -2, 3]");
+        assert_evals(&round("ceiling"), "[3, 4, -2, 3, -2, 3]");
+        assert_evals(&round("floor"), "[2, 3, -3, 2, -3, 3]");
+        assert_evals(&round("half_up"), "[3, 4, -3, 3, -3, 3]");
+        assert_evals(&round("half_down"), "[2, 3, -2, 3, -3, 3]");
+        assert_evals(&round("half_even"), "[2, 4, -2, 3, -3, 3]");
+        assert_fails(
+            "number.round \"nearest\" 1",
+            "Unknown rounding mode \"nearest\", expected one of \"up\", \"down\", \"ceiling\", \
+             \"floor\", \"half_up\", \"half_down\", \"half_even\"",
+        );
+    }
+
+    #[test]
+    fn div() {
+        assert_evals("number.div 7 2", "3");
+        assert_evals("number.div (-7) 2", "-4");
+        assert_evals("number.div 7 (-2)", "-4");
+        assert_fails("number.div 7 0", "Division by zero");
+        assert_fails("number.div (7 / 2) 1", "7/2 is not an integer");
+    }
+
+    #[test]
+    fn r#mod() {
+        assert_evals("number.mod 7 2", "1");
+        assert_evals("number.mod (-7) 2", "1");
+        assert_evals("number.mod 7 (-2)", "-1");
+        assert_fails("number.mod 7 0", "Division by zero");
+        assert_fails("number.mod 7 (1 / 2)", "1/2 is not an integer");
+    }
+
+    #[test]
+    fn pow() {
+        assert_evals("number.pow 2 10", "1024");
+        assert_evals("number.pow (2 / 3) 2", "4/9");
+        assert_evals("number.pow 2 (-2)", "1/4");
+        assert_evals("number.pow 0 0", "1");
+        assert_fails("number.pow 0 (-1)", "Division by zero");
+        assert_fails("number.pow 2 (1 / 2)", "1/2 is not an integer");
+        assert_evals("number.pow 1 4000000000000", "1");
+        assert_evals("number.pow (0 - 1) 4000000000001", "-1");
+        assert_fails(
+            "number.pow 10 4000000000000",
+            "The result would have more than 1048576 digits",
+        );
+        assert_fails(
+            "number.pow (1 / 10) (0 - 4000000000000)",
+            "The result would have more than 1048576 digits",
+        );
+        assert_fails(
+            "number.pow 2 9223372036854775808",
+            "9223372036854775808 is out of range, as integers must be between \
+             -9223372036854775808 and 9223372036854775807",
+        );
+    }
+
+    #[test]
+    fn abs() {
+        assert_evals("number.abs (-3 / 2)", "3/2");
+        assert_evals("number.abs 3", "3");
+    }
+
+    #[test]
+    fn min() {
+        assert_evals("number.min 1 2", "1");
+        assert_evals("number.min 2 (-1)", "-1");
+        assert_fails("number.min 1 null", "Expected a number, found null");
+    }
+
+    #[test]
+    fn max() {
+        assert_evals("number.max 1 2", "2");
+        assert_evals("list.fold number.max 0 [3, 1 / 2, 7, 2]", "7");
+    }
+
+    #[test]
+    fn numerator() {
+        assert_evals("number.numerator (-6 / 4)", "-3");
+        assert_evals("number.numerator 5", "5");
+    }
+
+    #[test]
+    fn denominator() {
+        assert_evals("number.denominator (-6 / 4)", "2");
+        assert_evals("number.denominator 5", "1");
+    }
+
+    #[test]
+    fn is_integer() {
+        assert_evals("number.is_integer (4 / 2)", "true");
+        assert_evals("number.is_integer (-1 / 2)", "false");
+    }
+
+    #[test]
+    fn to_decimal() {
+        assert_evals("number.to_decimal (2 / 3) 2", r#""0.67""#);
+        assert_evals("number.to_decimal 1 2", r#""1.00""#);
+        assert_evals("number.to_decimal (5 / 2) 0", r#""2""#);
+        assert_evals("number.to_decimal (-1 / 4) 1", r#""-0.2""#);
+        assert_evals("number.to_decimal 1234 3", r#""1234.000""#);
+        assert_fails("number.to_decimal 1 (-1)", "-1 is not a count");
+        assert_evals("number.to_decimal (0 - 1 / 20) 1", r#""0.0""#);
+        assert_evals("number.to_decimal (0 - 1 / 3) 0", r#""0""#);
+        assert_evals("number.to_decimal (0 - 1 / 10) 1", r#""-0.1""#);
+        assert_fails(
+            "number.to_decimal 1 100000000000",
+            "Cannot format with more than 1048576 places",
+        );
+    }
+}
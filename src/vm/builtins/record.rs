@@ -0,0 +1,206 @@
+//! `std:record`: Functions on records. The records they return are plain data: Their fields merge
+//! by default, and fields that were late-bound keep the values they had.
+
+use super::*;
+
+natives! {
+    /// The names of the fields of a record, in order.
+    ///
+    /// `keys { a = 1, b = 2 }` is `["a", "b"]`.
+    fn keys(record) {
+        let names = super::record(record)?.fields.keys().map(Value::string);
+        Ok(Value::list(names.collect()))
+    }
+
+    /// The values of the fields of a record, in order.
+    ///
+    /// `values { a = 1, b = 2 }` is `[1, 2]`.
+    fn values(record) {
+        Ok(Value::list(super::record(record)?.fields.values().cloned().collect()))
+    }
+
+    /// The fields of a record, in order, as records with a `name` and a `value`.
+    ///
+    /// `entries { a = 1 }` is `[{name = "a", value = 1}]`.
+    fn entries(record) {
+        let entries = super::record(record)?.fields.iter().map(|(name, value)| {
+            Value::record(IndexMap::from([
+                ("name".to_owned(), Value::string(name)),
+                ("value".to_owned(), value.clone()),
+            ]))
+        });
+        Ok(Value::list(entries.collect()))
+    }
+
+    /// The record with the fields of a list of records with a `name` and a `value`, as returned by
+    /// `entries`. The names must be distinct.
+    ///
+    /// `from_entries [{ name = "a", value = 1 }]` is `{a = 1}`.
+    fn from_entries(entries) {
+        let mut fields = IndexMap::new();
+        for entry in list(entries)? {
+            let entry = super::record(entry)?;
+            let field = |name: &str| {
+                entry.fields.get(name).ok_or_else(|| RuntimeError::MissingField {
+                    name: name.to_owned(),
+                })
+            };
+            let name = super::string(field("name")?)?;
+            if fields.insert(name.to_owned(), field("value")?.clone()).is_some() {
+                return Err(RuntimeError::DuplicateField {
+                    name: name.to_owned(),
+                });
+            }
+        }
+        Ok(Value::record(fields))
+    }
+
+    /// Apply a function to the value of each field of a record.
+    ///
+    /// `map_values string.length { a = "abc" }` is `{a = 3}`.
+    fn map_values(f, record) {
+        let fields = super::record(record)?
+            .fields
+            .iter()
+            .map(|(name, value)| Ok((name.clone(), call(f, value)?)));
+        Ok(Value::record(fields.collect::<Result<_, _>>()?))
+    }
+
+    /// Whether a record has a field.
+    ///
+    /// `has "a" { a = 1 }` is `true`.
+    fn has(name, record) {
+        let name = super::string(name)?;
+        Ok(Value::boolean(super::record(record)?.fields.contains_key(name)))
+    }
+
+    /// The value of a field of a record, or a default if the record has no such field.
+    ///
+    /// `get_or "b" 0 { a = 1 }` is `0`.
+    fn get_or(name, default, record) {
+        let name = super::string(name)?;
+        let value = super::record(record)?.fields.get(name).unwrap_or(default);
+        Ok(value.clone())
+    }
+
+    /// A record without one of its fields, if it has it.
+    ///
+    /// `remove "a" { a = 1, b = 2 }` is `{b = 2}`.
+    fn remove(name, record) {
+        let name = super::string(name)?;
+        let mut fields = super::record(record)?.fields.clone();
+        fields.shift_remove(name);
+        Ok(Value::record(fields))
+    }
+
+    /// Rename a field of a record, which must have it, keeping its place. No other field may have
+    /// the new name.
+    ///
+    /// `rename "a" "c" { a = 1, b = 2 }` is `{c = 1, b = 2}`.
+    fn rename(from, to, record) {
+        let (from, to) = (super::string(from)?, super::string(to)?);
+        let record = super::record(record)?;
+        if !record.fields.contains_key(from) {
+            return Err(RuntimeError::MissingField {
+                name: from.to_owned(),
+            });
+        }
+        if from != to && record.fields.contains_key(to) {
+            return Err(RuntimeError::DuplicateField { name: to.to_owned() });
+        }
+
+        let fields = record.fields.iter().map(|(name, value)| {
+            let name = if name == from { to } else { name };
+            (name.to_owned(), value.clone())
+        });
+        Ok(Value::record(fields.collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::builtins::tests::{assert_evals, assert_fails};
+
+    #[test]
+    fn keys() {
+        assert_evals("record.keys { b = 1, a = 2 }", r#"["b", "a"]"#);
+        assert_fails("record.keys [1]", "Expected a record, found a list");
+        assert_fails(
+            "record.keys { a = 1, b = self.x }",
+            "Field `b` depends on `self.x`, which is missing",
+        );
+    }
+
+    #[test]
+    fn values() {
+        assert_evals("record.values { b = 1, a = 2 }", "[1, 2]");
+    }
+
+    #[test]
+    fn entries() {
+        assert_evals(
+            "record.entries { a = 1, b = 2 }",
+            r#"[{name = "a", value = 1}, {name = "b", value = 2}]"#,
+        );
+    }
+
+    #[test]
+    fn from_entries() {
+        assert_evals(
+            "record.from_entries (record.entries { a = 1, b = 2 })",
+            "{a = 1, b = 2}",
+        );
+        assert_fails(
+            r#"record.from_entries [{ name = "a", value = 1 }, { name = "a", value = 2 }]"#,
+            "Duplicate field `a`",
+        );
+        assert_fails(
+            r#"record.from_entries [{ name = "a" }]"#,
+            "Missing field `value`",
+        );
+    }
+
+    #[test]
+    fn map_values() {
+        assert_evals(
+            r#"record.map_values string.length { a = "abc", b = "" }"#,
+            "{a = 3, b = 0}",
+        );
+    }
+
+    #[test]
+    fn has() {
+        assert_evals(r#"record.has "a" { a = null }"#, "true");
+        assert_evals(r#"record.has "b" { a = 1 }"#, "false");
+    }
+
+    #[test]
+    fn get_or() {
+        assert_evals(r#"record.get_or "a" 0 { a = 1 }"#, "1");
+        assert_evals(r#"record.get_or "b" 0 { a = 1 }"#, "0");
+    }
+
+    #[test]
+    fn remove() {
+        assert_evals(r#"record.remove "a" { a = 1, b = 2 }"#, "{b = 2}");
+        assert_evals(r#"record.remove "c" { a = 1 }"#, "{a = 1}");
+        assert_fails(
+            r#"record.remove "z" { a = 1, b = super.x }"#,
+            "Field `b` depends on `super`, which is missing",
+        );
+    }
+
+    #[test]
+    fn rename() {
+        assert_evals(
+            r#"record.rename "a" "c" { a = 1, b = 2 }"#,
+            "{c = 1, b = 2}",
+        );
+        assert_evals(r#"record.rename "a" "a" { a = 1 }"#, "{a = 1}");
+        assert_fails(r#"record.rename "c" "d" { a = 1 }"#, "Missing field `c`");
+        assert_fails(
+            r#"record.rename "a" "b" { a = 1, b = 2 }"#,
+            "Duplicate field `b`",
+        );
+    }
+}
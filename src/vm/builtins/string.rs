@@ -0,0 +1,292 @@
+//! `std:string`: Functions on strings. Lengths and positions count Unicode scalar values.
+
+use super::*;
+
+natives! {
+    /// Split a string at each occurrence of a separator, which must not be empty.
+    ///
+    /// `split "," "a,b,,c"` is `["a", "b", "", "c"]`.
+    fn split(separator, string) {
+        let separator = non_empty(super::string(separator)?, "split at")?;
+        let parts = super::string(string)?.split(separator).map(Value::string);
+        Ok(Value::list(parts.collect()))
+    }
+
+    /// Join a list of strings into one, with a separator between each of them.
+    ///
+    /// `join ", " ["a", "b"]` is `"a, b"`.
+    fn join(separator, strings) {
+        let separator = super::string(separator)?;
+        let strings = list(strings)?
+            .iter()
+            .map(super::string)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Value::string(strings.join(separator)))
+    }
+
+    /// Remove the whitespace at the start and end of a string.
+    ///
+    /// `trim "  a b "` is `"a b"`.
+    fn trim(string) {
+        Ok(Value::string(super::string(string)?.trim()))
+    }
+
+    /// Replace each occurrence of a pattern in a string, which must not be empty.
+    ///
+    /// `replace "-" "_" "a-b-c"` is `"a_b_c"`.
+    fn replace(pattern, replacement, string) {
+        let pattern = non_empty(super::string(pattern)?, "replace")?;
+        let replacement = super::string(replacement)?;
+        Ok(Value::string(super::string(string)?.replace(pattern, replacement)))
+    }
+
+    /// Whether a string starts with a prefix.
+    ///
+    /// `starts_with "http" "https://example.com"` is `true`.
+    fn starts_with(prefix, string) {
+        Ok(Value::boolean(super::string(string)?.starts_with(super::string(prefix)?)))
+    }
+
+    /// Whether a string ends with a suffix.
+    ///
+    /// `ends_with ".json" "data.json"` is `true`.
+    fn ends_with(suffix, string) {
+        Ok(Value::boolean(super::string(string)?.ends_with(super::string(suffix)?)))
+    }
+
+    /// Convert a string to upper case, following Unicode.
+    ///
+    /// `to_upper "straße"` is `"STRASSE"`.
+    fn to_upper(string) {
+        Ok(Value::string(super::string(string)?.to_uppercase()))
+    }
+
+    /// Convert a string to lower case, following Unicode.
+    ///
+    /// `to_lower "ÄB"` is `"äb"`.
+    fn to_lower(string) {
+        Ok(Value::string(super::string(string)?.to_lowercase()))
+    }
+
+    /// Pad a string at the start with a fill character, a string of one Unicode scalar value,
+    /// until it is at least `width` long.
+    ///
+    /// `pad_left 3 "0" "7"` is `"007"`.
+    fn pad_left(width, fill, string) {
+        let width = count(width)?;
+        if width > MAX_SIZE {
+            return Err(RuntimeError::InvalidArgument {
+                message: format!("Cannot pad to more than {MAX_SIZE} characters"),
+            });
+        }
+        let fill = super::string(fill)?;
+        let mut chars = fill.chars();
+        let (Some(fill), None) = (chars.next(), chars.next()) else {
+            return Err(RuntimeError::InvalidArgument {
+                message: format!("Cannot pad with {fill:?}, which is not a single character"),
+            });
+        };
+
+        let string = super::string(string)?;
+        let padding = width.saturating_sub(string.chars().count());
+        let mut padded: String = core::iter::repeat_n(fill, padding).collect();
+        padded.push_str(string);
+        Ok(Value::string(padded))
+    }
+
+    /// Fill in the `{}` placeholders of a template with a list of values, in order. The values
+    /// must be strings, numbers, booleans or null, and there must be one for each placeholder.
+    /// `{{` and `}}` stand for `{` and `}`.
+    ///
+    /// `format "{}:{}" ["localhost", 8080]` is `"localhost:8080"`.
+    fn format(template, values) {
+        let template = super::string(template)?;
+        let mut values = list(values)?.iter();
+        let invalid = |message: &str| RuntimeError::InvalidArgument {
+            message: format!("Cannot format {template:?}: {message}"),
+        };
+
+        let mut formatted = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('{', Some('{')) | ('}', Some('}')) => {
+                    chars.next();
+                    formatted.push(c);
+                }
+                ('{', Some('}')) => {
+                    chars.next();
+                    let value = values
+                        .next()
+                        .ok_or_else(|| invalid("There are more placeholders than values"))?;
+                    display(value, &mut formatted)?;
+                }
+                ('{' | '}', _) => {
+                    return Err(invalid("Braces must be `{}`, or doubled to stand for themselves"))
+                }
+                _ => formatted.push(c),
+            }
+        }
+
+        if values.next().is_some() {
+            return Err(invalid("There are more values than placeholders"));
+        }
+        Ok(Value::string(formatted))
+    }
+
+    /// The length of a string, in Unicode scalar values.
+    ///
+    /// `length "héllo"` is `5`.
+    fn length(string) {
+        let length = super::string(string)?.chars().count();
+        Ok(Value::number(length.into()))
+    }
+}
+
+/// A string argument that must not be empty, for the given purpose.
+fn non_empty<'a>(string: &'a str, purpose: &str) -> Result<&'a str, RuntimeError> {
+    if string.is_empty() {
+        return Err(RuntimeError::InvalidArgument {
+            message: format!("Cannot {purpose} an empty string"),
+        });
+    }
+    Ok(string)
+}
+
+/// Write a value as it is shown in formatted strings: Strings without quotes, and numbers in
+/// decimal notation where their expansion terminates.
+fn display(value: &Value, formatted: &mut String) -> Result<(), RuntimeError> {
+    match &*value.kind {
+        ValueKind::String(string) => formatted.push_str(&string.value),
+        ValueKind::Number(number) => match number.to_decimal() {
+            Some(decimal) => formatted.push_str(&decimal),
+            None => formatted.push_str(&format!("{:?}", number.value)),
+        },
+        ValueKind::Null | ValueKind::Boolean(_) => formatted.push_str(&format!("{value:?}")),
+        kind => return Err(mismatch("a string, number, boolean or null", kind)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vm::builtins::tests::{assert_evals, assert_fails};
+
+    #[test]
+    fn split() {
+        assert_evals(r#"string.split "," "a,b,,c""#, r#"["a", "b", "", "c"]"#);
+        assert_evals(r#"string.split ", " "a""#, r#"["a"]"#);
+        assert_evals(r#"string.split "," """#, r#"[""]"#);
+        assert_fails(r#"string.split "" "a""#, "Cannot split at an empty string");
+    }
+
+    #[test]
+    fn join() {
+        assert_evals(r#"string.join ", " ["a", "b"]"#, r#""a, b""#);
+        assert_evals(r#"string.join ", " []"#, r#""""#);
+        assert_fails(
+            r#"string.join ", " ["a", 1]"#,
+            "Expected a string, found a number",
+        );
+    }
+
+    #[test]
+    fn trim() {
+        assert_evals(r#"string.trim "  a b \n""#, r#""a b""#);
+        assert_evals(r#"string.trim "ab""#, r#""ab""#);
+    }
+
+    #[test]
+    fn replace() {
+        assert_evals(r#"string.replace "-" "_" "a-b-c""#, r#""a_b_c""#);
+        assert_evals(r#"string.replace "ab" "" "abcab""#, r#""c""#);
+        assert_fails(
+            r#"string.replace "" "_" "a""#,
+            "Cannot replace an empty string",
+        );
+    }
+
+    #[test]
+    fn starts_with() {
+        assert_evals(r#"string.starts_with "ht" "http""#, "true");
+        assert_evals(r#"string.starts_with "tp" "http""#, "false");
+        assert_evals(r#"string.starts_with "" "http""#, "true");
+    }
+
+    #[test]
+    fn ends_with() {
+        assert_evals(r#"string.ends_with ".json" "a.json""#, "true");
+        assert_evals(r#"string.ends_with ".json" "a.yaml""#, "false");
+    }
+
+    #[test]
+    fn to_upper() {
+        assert_evals(r#"string.to_upper "straße""#, r#""STRASSE""#);
+    }
+
+    #[test]
+    fn to_lower() {
+        assert_evals(r#"string.to_lower "ÄB""#, r#""äb""#);
+    }
+
+    #[test]
+    fn pad_left() {
+        assert_evals(r#"string.pad_left 3 "0" "7""#, r#""007""#);
+        assert_evals(r#"string.pad_left 3 "·" "é""#, r#""··é""#);
+        assert_evals(r#"string.pad_left 1 " " "abc""#, r#""abc""#);
+        assert_fails(
+            r#"string.pad_left 3 "ab" "c""#,
+            r#"Cannot pad with "ab", which is not a single character"#,
+        );
+        assert_fails(r#"string.pad_left (0 - 1) " " "c""#, "-1 is not a count");
+        assert_fails(r#"string.pad_left (1 / 2) " " "c""#, "1/2 is not a count");
+        assert_fails(
+            r#"string.pad_left 100000000000 " " "c""#,
+            "Cannot pad to more than 1048576 characters",
+        );
+    }
+
+    #[test]
+    fn format() {
+        assert_evals(
+            r#"string.format "{}:{}" ["localhost", 8080]"#,
+            r#""localhost:8080""#,
+        );
+        assert_evals(
+            r#"string.format "{} {} {} {}" [1 / 4, 1 / 3, true, null]"#,
+            r#""0.25 1/3 true null""#,
+        );
+        assert_evals(r#"string.format "{{{}}}" ["a"]"#, r#""{a}""#);
+        assert_fails(
+            r#"string.format "{} {}" ["a"]"#,
+            r#"Cannot format "{} {}": There are more placeholders than values"#,
+        );
+        assert_fails(
+            r#"string.format "{}" ["a", "b"]"#,
+            r#"Cannot format "{}": There are more values than placeholders"#,
+        );
+        assert_fails(
+            r#"string.format "{a}" []"#,
+            r#"Cannot format "{a}": Braces must be `{}`, or doubled to stand for themselves"#,
+        );
+        assert_fails(
+            r#"string.format "{}" [[1]]"#,
+            "Expected a string, number, boolean or null, found a list",
+        );
+    }
+
+    #[test]
+    fn length() {
+        assert_evals(r#"string.length "héllo""#, "5");
+        assert_evals(r#"string.length """#, "0");
+        assert_fails("string.length 1", "Expected a string, found a number");
+    }
+
+    #[test]
+    fn natives_are_curried() {
+        assert_evals(
+            r#"let comma = string.split "," in [comma "a,b", comma "c"]"#,
+            r#"[["a", "b"], ["c"]]"#,
+        );
+    }
+}
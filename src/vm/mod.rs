@@ -1 +1,96 @@
+use miette::Diagnostic;
+use thiserror::Error;
+
+use crate::compiler::{contract::Violation, merge::Conflict, source::Span};
+
+pub mod builtins;
 pub mod value;
+
+#[derive(Debug, Error, Diagnostic)]
+pub enum RuntimeError {
+    #[error("Unbound variable `{name}`")]
+    UnboundVariable { name: String },
+
+    #[error("Expected {expected}, found {found}")]
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("{message}")]
+    InvalidArgument { message: String },
+
+    #[error("Duplicate field `{name}`")]
+    DuplicateField { name: String },
+
+    #[error("Missing field `{name}`")]
+    MissingField { name: String },
+
+    /// A late-bound field that depends on its own value, defined with its name at `span`.
+    #[error("Field `{name}` depends on its own value")]
+    RecursiveField { name: String, span: Span },
+
+    #[error("Field `{}` depends on `{}`, which is missing", .0.field, .0.dependency)]
+    Unresolved(Unresolved),
+
+    #[error("{}", .0.message)]
+    Contract(Box<Violation>),
+
+    #[error("{}", .0.message)]
+    Merge(Box<Conflict>),
+
+    /// An error raised while evaluating the expression at `span`, the innermost one it is known
+    /// for. Errors that point at definitions of their own, such as contract violations, are not
+    /// wrapped.
+    #[error("{error}")]
+    Located {
+        error: Box<RuntimeError>,
+        span: Span,
+    },
+}
+
+/// A late-bound field that depends on a field `self` or `super` does not have, or on `super` in a
+/// record that is not merged into another.
+#[derive(Debug, Clone)]
+pub struct Unresolved {
+    pub field: String,
+
+    /// What the field depends on, such as `self.name`.
+    pub dependency: String,
+
+    /// The span of the name of the field, where it is defined.
+    pub span: Span,
+}
+
+impl RuntimeError {
+    /// The error, raised by the expression at `span` unless it is located already.
+    pub fn at(self, span: Span) -> Self {
+        match self {
+            Self::UnboundVariable { .. }
+            | Self::TypeMismatch { .. }
+            | Self::DivisionByZero
+            | Self::InvalidArgument { .. }
+            | Self::DuplicateField { .. }
+            | Self::MissingField { .. } => Self::Located {
+                error: Box::new(self),
+                span,
+            },
+            Self::RecursiveField { .. }
+            | Self::Unresolved(_)
+            | Self::Contract(_)
+            | Self::Merge(_)
+            | Self::Located { .. } => self,
+        }
+    }
+
+    /// The error, without the span it was located at, if any.
+    pub fn unlocated(self) -> Self {
+        match self {
+            Self::Located { error, .. } => *error,
+            err => err,
+        }
+    }
+}
@@ -1,19 +1,79 @@
-use core::fmt;
-use std::sync::Arc;
+use core::{any::Any, fmt};
+use std::sync::{Arc, OnceLock};
 
-use malachite::Rational;
+use indexmap::IndexMap;
+use malachite::{
+    num::conversion::{string::options::ToSciOptions, traits::ToSci},
+    Rational,
+};
 
+use crate::compiler::source::Span;
+
+use super::{RuntimeError, Unresolved};
+
+#[derive(Clone)]
 pub struct Value {
     pub kind: Arc<ValueKind>,
+
+    /// Where the value came from, if it was produced by evaluating source code. The parts of a
+    /// value that were produced along with it, such as the contents of an imported file, have no
+    /// provenance of their own.
+    pub provenance: Option<Arc<Provenance>>,
 }
 
 impl Value {
     pub fn new(kind: ValueKind) -> Self {
         Self {
             kind: Arc::new(kind),
+            provenance: None,
+        }
+    }
+
+    /// Give the value the provenance of having been produced by the expression at `span`.
+    pub fn with_span(self, span: Span) -> Self {
+        self.with_provenance(Provenance::new(span))
+    }
+
+    pub fn with_provenance(self, provenance: Provenance) -> Self {
+        Self {
+            provenance: Some(Arc::new(provenance)),
+            ..self
         }
     }
 
+    /// The value, with the provenance of it and of its parts removed, for values that outlive the
+    /// source they were defined in. Its records lose their [templates](Record::template) too, as
+    /// those are made of source code: Their late-bound fields keep the values they have.
+    pub fn detached(&self) -> Self {
+        let kind = match &*self.kind {
+            ValueKind::Record(record) => ValueKind::Record(Record {
+                fields: record
+                    .fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.detached()))
+                    .collect(),
+                merges: record.merges.clone(),
+                template: None,
+                unresolved: record.unresolved.clone(),
+            }),
+            ValueKind::List(list) => {
+                ValueKind::List(List::new(list.items().iter().map(Self::detached).collect()))
+            }
+            _ => {
+                return Self {
+                    kind: Arc::clone(&self.kind),
+                    provenance: None,
+                }
+            }
+        };
+        Self::new(kind)
+    }
+
+    /// The span of the expression that produced the value, if any.
+    pub fn span(&self) -> Option<Span> {
+        self.provenance.as_ref().map(|provenance| provenance.span)
+    }
+
     pub fn boolean(value: bool) -> Self {
         Self::new(ValueKind::Boolean(Boolean { value }))
     }
@@ -22,9 +82,149 @@ impl Value {
         Self::new(ValueKind::Number(Number { value }))
     }
 
+    pub fn string(value: impl Into<String>) -> Self {
+        Self::new(ValueKind::String(Str {
+            value: value.into(),
+        }))
+    }
+
     pub fn null() -> Self {
         Self::new(ValueKind::Null)
     }
+
+    pub fn record(fields: IndexMap<String, Value>) -> Self {
+        Self::new(ValueKind::Record(Record {
+            fields,
+            merges: IndexMap::new(),
+            template: None,
+            unresolved: Vec::new(),
+        }))
+    }
+
+    pub fn list(items: Vec<Value>) -> Self {
+        Self::new(ValueKind::List(List::new(items)))
+    }
+
+    /// A list whose items are evaluated on first use.
+    pub fn lazy_list(items: Vec<Thunk>) -> Self {
+        Self::new(ValueKind::List(List::lazy(items)))
+    }
+
+    pub fn function(
+        body: impl Fn(&Value) -> Result<Value, RuntimeError> + Send + Sync + 'static,
+    ) -> Self {
+        Self::new(ValueKind::Function(Function {
+            body: Box::new(body),
+        }))
+    }
+
+    /// Evaluate the items of every list in the value, which are otherwise evaluated on first use,
+    /// as results must not fail once they are produced. Errors are raised at the innermost part of
+    /// the value that has a span.
+    pub fn force(&self) -> Result<(), RuntimeError> {
+        let result = match &*self.kind {
+            ValueKind::Record(record) => record.fields.values().try_for_each(Self::force),
+            ValueKind::List(list) => list
+                .force()
+                .and_then(|items| items.iter().try_for_each(Self::force)),
+            _ => Ok(()),
+        };
+        match self.span() {
+            Some(span) => result.map_err(|err| err.at(span)),
+            None => result,
+        }
+    }
+}
+
+/// Where a value came from: The expression that produced it, and the earlier values it replaced or
+/// was merged from on the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// The span of the expression that produced the value.
+    pub span: Span,
+    pub origin: Origin,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// The value was defined by the expression.
+    Defined,
+
+    /// The value was defined by the expression, in place of an earlier value.
+    Override(Arc<Provenance>),
+
+    /// The value was combined from two earlier values by the expression, such as two records
+    /// merged field by field, or two lists appended.
+    Merge(Arc<Provenance>, Arc<Provenance>),
+}
+
+impl Provenance {
+    pub fn new(span: Span) -> Self {
+        Self {
+            span,
+            origin: Origin::Defined,
+        }
+    }
+
+    /// The provenance of a value defined at `span` in place of a value with the given provenance,
+    /// if it had one.
+    pub fn overriding(span: Span, previous: Option<Arc<Provenance>>) -> Self {
+        Self {
+            span,
+            origin: previous.map_or(Origin::Defined, Origin::Override),
+        }
+    }
+
+    /// The provenance of a value combined at `span` from values with the given provenances, if
+    /// they had them. If only one of them had one, the value overrides it.
+    pub fn merging(
+        span: Span,
+        left: Option<Arc<Provenance>>,
+        right: Option<Arc<Provenance>>,
+    ) -> Self {
+        let origin = match (left, right) {
+            (Some(left), Some(right)) => Origin::Merge(left, right),
+            (Some(previous), None) | (None, Some(previous)) => Origin::Override(previous),
+            (None, None) => Origin::Defined,
+        };
+        Self { span, origin }
+    }
+
+    /// This provenance, with the earliest definition it goes back to overriding a value with the
+    /// given provenance: The provenance of a value that replaced another.
+    pub fn after(&self, previous: Arc<Provenance>) -> Self {
+        let origin = match &self.origin {
+            Origin::Defined => Origin::Override(previous),
+            Origin::Override(earlier) => Origin::Override(Arc::new(earlier.after(previous))),
+            Origin::Merge(left, right) => {
+                Origin::Merge(Arc::new(left.after(previous)), right.clone())
+            }
+        };
+        Self {
+            span: self.span,
+            origin,
+        }
+    }
+
+    /// The provenance of every definition, override and merge that went into the value, earliest
+    /// first, ending with this one.
+    pub fn history(&self) -> Vec<&Provenance> {
+        let mut history = Vec::new();
+        self.collect(&mut history);
+        history
+    }
+
+    fn collect<'a>(&'a self, history: &mut Vec<&'a Provenance>) {
+        match &self.origin {
+            Origin::Defined => {}
+            Origin::Override(previous) => previous.collect(history),
+            Origin::Merge(left, right) => {
+                left.collect(history);
+                right.collect(history);
+            }
+        }
+        history.push(self);
+    }
 }
 
 impl fmt::Debug for Value {
@@ -37,15 +237,36 @@ pub enum ValueKind {
     Null,
     Boolean(Boolean),
     Number(Number),
+    String(Str),
+    Record(Record),
+    List(List),
     Function(Function),
 }
 
+impl ValueKind {
+    /// A short description of the kind of value, for use in messages.
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Null => "null",
+            Self::Boolean(_) => "a boolean",
+            Self::Number(_) => "a number",
+            Self::String(_) => "a string",
+            Self::Record(_) => "a record",
+            Self::List(_) => "a list",
+            Self::Function(_) => "a function",
+        }
+    }
+}
+
 impl fmt::Debug for ValueKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Null => write!(f, "null"),
             Self::Boolean(v) => fmt::Debug::fmt(&v, f),
             Self::Number(v) => fmt::Debug::fmt(&v, f),
+            Self::String(v) => fmt::Debug::fmt(&v, f),
+            Self::Record(v) => fmt::Debug::fmt(&v, f),
+            Self::List(v) => fmt::Debug::fmt(&v, f),
             Self::Function(v) => fmt::Debug::fmt(&v, f),
         }
     }
@@ -55,14 +276,274 @@ pub struct Number {
     pub value: Rational,
 }
 
+impl Number {
+    /// Format the number in decimal notation, without an exponent. Returns `None` if the decimal
+    /// expansion of the number does not terminate.
+    pub fn to_decimal(&self) -> Option<String> {
+        let scale = self.value.length_after_point_in_small_base(10)?;
+
+        let mut options = ToSciOptions::default();
+        options.set_scale(scale);
+        options.set_neg_exp_threshold(i64::MIN);
+
+        Some(self.value.to_sci_with_options(options).to_string())
+    }
+}
+
 impl fmt::Debug for Number {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Debug::fmt(&self.value, f)
     }
 }
 
+pub struct Record {
+    /// The fields of the record, in the order they were defined in.
+    pub fields: IndexMap<String, Value>,
+
+    /// How the fields of the record merge into the fields of another record, on the right of
+    /// `//`, for those that do not [merge by default](Merge::Deep). Deleted fields are only found
+    /// here.
+    pub merges: IndexMap<String, Merge>,
+
+    /// How to evaluate the fields of the record again once it is merged, if some of them are
+    /// late-bound: Bound to values that depend on `self` or `super`.
+    pub template: Option<Arc<Template>>,
+
+    /// The late-bound fields left out of the record, as they depend on fields that `self` or
+    /// `super` do not have. Merges may bind those, but results must not have any.
+    pub unresolved: Vec<Unresolved>,
+}
+
+/// How to evaluate the fields of a record with late-bound fields again.
+pub enum Template {
+    /// A record literal. Its other fields have the values the record has.
+    Literal {
+        /// The names of all the fields of the literal, in order. Those that depend on `super` are
+        /// left out of the record until it is merged into another.
+        names: Vec<String>,
+
+        /// The definitions of the late-bound fields. Only the evaluator that made the record knows
+        /// what they are made of, and evaluates them.
+        definitions: Arc<dyn Any + Send + Sync>,
+    },
+
+    /// The records merged by the `//` expression at `span`.
+    Merge {
+        left: Value,
+        right: Value,
+        span: Span,
+    },
+}
+
+impl Template {
+    /// A record made of two others, whose fields are evaluated once it is instantiated.
+    pub fn merge(left: &Value, right: &Value, span: Span) -> Value {
+        Value::new(ValueKind::Record(Record {
+            fields: IndexMap::new(),
+            merges: IndexMap::new(),
+            unresolved: Vec::new(),
+            template: Some(Arc::new(Template::Merge {
+                left: left.clone(),
+                right: right.clone(),
+                span,
+            })),
+        }))
+    }
+}
+
+impl Record {
+    pub fn merge(&self, name: &str) -> Merge {
+        self.merges.get(name).copied().unwrap_or_default()
+    }
+}
+
+impl fmt::Debug for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("{")?;
+        for (i, (name, value)) in self.fields.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{name} {} {value:?}", self.merge(name).operator())?;
+        }
+        let deleted = self
+            .merges
+            .iter()
+            .filter(|(_, merge)| **merge == Merge::Delete);
+        for (i, (name, _)) in deleted.enumerate() {
+            if i > 0 || !self.fields.is_empty() {
+                f.write_str(", ")?;
+            }
+            write!(f, "-{name}")?;
+        }
+        f.write_str("}")
+    }
+}
+
+/// How a field on the right of `//` merges into the field of the same name on the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Merge {
+    /// `name = value`: Records are merged field by field, and other values replace values of the
+    /// same kind.
+    #[default]
+    Deep,
+
+    /// `name := value`: The value replaces the field, whatever it is.
+    Replace,
+
+    /// `name += value`: The value, a list, is appended to the field, a list.
+    Append,
+
+    /// `-name`: The field is deleted.
+    Delete,
+}
+
+impl Merge {
+    /// The operator binding a field that merges this way, or `-` for deletions.
+    pub fn operator(self) -> &'static str {
+        match self {
+            Self::Deep => "=",
+            Self::Replace => ":=",
+            Self::Append => "+=",
+            Self::Delete => "-",
+        }
+    }
+}
+
+/// A list. The items of lists made by natives, such as `std:list.map`, are only evaluated once they
+/// are used, so that the items a program never uses cannot fail it.
+pub struct List {
+    /// The items, once they are all evaluated.
+    items: OnceLock<Vec<Value>>,
+
+    /// The items of a list whose items are evaluated on first use. Empty for other lists.
+    lazy: Vec<Thunk>,
+}
+
+impl List {
+    pub fn new(items: Vec<Value>) -> Self {
+        Self {
+            items: OnceLock::from(items),
+            lazy: Vec::new(),
+        }
+    }
+
+    pub fn lazy(items: Vec<Thunk>) -> Self {
+        Self {
+            items: OnceLock::new(),
+            lazy: items,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.get().map_or(self.lazy.len(), Vec::len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Evaluate the items of the list, if they were not yet.
+    pub fn force(&self) -> Result<&[Value], RuntimeError> {
+        if let Some(items) = self.items.get() {
+            return Ok(items);
+        }
+        let items = self
+            .lazy
+            .iter()
+            .map(|item| item.force().cloned())
+            .collect::<Result<_, _>>()?;
+        Ok(self.items.get_or_init(|| items))
+    }
+
+    /// The items of the list.
+    ///
+    /// # Panics
+    ///
+    /// If an item fails to evaluate. Results are [forced](Value::force) before they are used, so
+    /// that their items do not fail.
+    pub fn items(&self) -> &[Value] {
+        self.force()
+            .expect("lists are forced before their items are used")
+    }
+
+    /// The items of the list, each evaluated on first use.
+    pub fn thunks(&self) -> Vec<Thunk> {
+        match self.items.get() {
+            Some(items) if self.lazy.is_empty() => {
+                items.iter().cloned().map(Thunk::value).collect()
+            }
+            _ => self.lazy.clone(),
+        }
+    }
+}
+
+impl fmt::Debug for List {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.items.get() {
+            Some(items) => f.debug_list().entries(items).finish(),
+            None => f.debug_list().entries(&self.lazy).finish(),
+        }
+    }
+}
+
+/// A value evaluated on first use, and kept from then on. Failures are not kept: Forcing the thunk
+/// again evaluates it again.
+#[derive(Clone)]
+pub struct Thunk(Arc<ThunkInner>);
+
+/// How a thunk evaluates to its value, once it has to.
+type Evaluate = dyn Fn() -> Result<Value, RuntimeError> + Send + Sync;
+
+struct ThunkInner {
+    value: OnceLock<Value>,
+    evaluate: Option<Box<Evaluate>>,
+}
+
+impl Thunk {
+    pub fn new(evaluate: impl Fn() -> Result<Value, RuntimeError> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(ThunkInner {
+            value: OnceLock::new(),
+            evaluate: Some(Box::new(evaluate)),
+        }))
+    }
+
+    /// A thunk that is already evaluated.
+    pub fn value(value: Value) -> Self {
+        Self(Arc::new(ThunkInner {
+            value: OnceLock::from(value),
+            evaluate: None,
+        }))
+    }
+
+    pub fn force(&self) -> Result<&Value, RuntimeError> {
+        if let Some(value) = self.0.value.get() {
+            return Ok(value);
+        }
+        let evaluate = self
+            .0
+            .evaluate
+            .as_ref()
+            .expect("thunks without values evaluate");
+        let value = evaluate()?;
+        Ok(self.0.value.get_or_init(|| value))
+    }
+}
+
+impl fmt::Debug for Thunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.value.get() {
+            Some(value) => fmt::Debug::fmt(value, f),
+            None => f.write_str("<unevaluated>"),
+        }
+    }
+}
+
+/// The body of a [`Function`]: Given an argument, produce the result of the call.
+pub type FunctionBody = dyn Fn(&Value) -> Result<Value, RuntimeError> + Send + Sync;
+
 pub struct Function {
-    pub body: Box<dyn Fn(&Value) -> Value + Send + Sync>,
+    pub body: Box<FunctionBody>,
 }
 
 impl fmt::Debug for Function {
@@ -71,6 +552,16 @@ impl fmt::Debug for Function {
     }
 }
 
+pub struct Str {
+    pub value: String,
+}
+
+impl fmt::Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.value, f)
+    }
+}
+
 pub struct Boolean {
     pub value: bool,
 }
@@ -83,7 +574,57 @@ impl fmt::Debug for Boolean {
 
 #[cfg(test)]
 mod tests {
+    use malachite::num::arithmetic::traits::Pow;
+
     use super::*;
+    use crate::compiler::source::BytePos;
 
     static_assertions::assert_impl_all!(Value: Send, Sync);
+
+    fn decimal(value: Rational) -> Option<String> {
+        Number { value }.to_decimal()
+    }
+
+    #[test]
+    fn decimal_formatting() {
+        assert_eq!(decimal(Rational::from(42)).as_deref(), Some("42"));
+        assert_eq!(
+            decimal(Rational::from_signeds(-5, 2)).as_deref(),
+            Some("-2.5")
+        );
+        assert_eq!(
+            decimal(Rational::from_signeds(1, 1000)).as_deref(),
+            Some("0.001")
+        );
+        assert_eq!(
+            decimal(Rational::from(10).pow(30u64)).as_deref(),
+            Some("1000000000000000000000000000000")
+        );
+        assert_eq!(decimal(Rational::from_signeds(1, 3)), None);
+    }
+
+    #[test]
+    fn provenance_history() {
+        let span = |lo| Span::new(BytePos::new(lo), BytePos::new(lo + 1));
+        let spans = |provenance: &Provenance| -> Vec<Span> {
+            provenance.history().iter().map(|step| step.span).collect()
+        };
+        let defined = |lo| Some(Arc::new(Provenance::new(span(lo))));
+
+        let overridden = Provenance::overriding(span(2), defined(1));
+        assert_eq!(spans(&overridden), [span(1), span(2)]);
+
+        let merged = Provenance::merging(span(4), Some(Arc::new(overridden)), defined(3));
+        assert_eq!(spans(&merged), [span(1), span(2), span(3), span(4)]);
+
+        let replaced = Provenance::merging(span(6), defined(5), None).after(Arc::new(merged));
+        assert_eq!(
+            spans(&replaced),
+            [span(1), span(2), span(3), span(4), span(5), span(6)]
+        );
+
+        let merged = Provenance::merging(span(2), None, defined(1));
+        assert_eq!(merged.origin, Origin::Override(defined(1).unwrap()));
+        assert_eq!(spans(&Provenance::overriding(span(1), None)), [span(1)]);
+    }
 }
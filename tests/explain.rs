@@ -0,0 +1,49 @@
+//! Run `dek explain` in a temporary directory.
+
+use std::{fs, process::Command};
+
+#[test]
+fn explanations_show_definitions() {
+    let dir = std::env::temp_dir().join(format!("dek-explain-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("data.json"), r#"{ "limits": { "cpu": 2 } }"#).unwrap();
+    fs::write(
+        dir.join("main.dek"),
+        "let\n    base = {\n        replicas = 3,\n    },\n    data = import \"data.json\",\nin { replicas = base.replicas, data = data }\n",
+    )
+    .unwrap();
+
+    let explain = |path: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_dek"))
+            .args(["explain", "main.dek", path])
+            .env("NO_COLOR", "1")
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    // Values referred to by name keep the definitions they were produced by.
+    let (code, stdout, stderr) = explain("replicas");
+    assert_eq!(code, Some(0), "{stderr}");
+    assert_eq!(
+        stdout,
+        "`replicas` is 3\n\nDefined at main.dek:3:20\n3 │         replicas = 3,\n  │                    ^\n"
+    );
+
+    // The parts of imported data are explained by the import.
+    let (code, stdout, stderr) = explain("data.limits.cpu");
+    assert_eq!(code, Some(0), "{stderr}");
+    assert!(stdout.starts_with("`data.limits.cpu` is 2\nIt is at `limits.cpu` within"));
+    assert!(stdout.contains("Defined at main.dek:5:12\n"), "{stdout}");
+
+    let (code, _, stderr) = explain("replicas.count");
+    assert_eq!(code, Some(1));
+    assert!(stderr.contains("There is no value at `replicas.count` in the result"));
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,220 @@
+//! Drive `dek lsp` over pipes with a scripted session, as an editor would.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read, Write},
+    process::{ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+struct Client {
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+}
+
+impl Client {
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            self.stdout.read_line(&mut line).unwrap();
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    /// Send a request, and return its response.
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+
+        let response = self.receive();
+        assert_eq!(response["id"], id, "response: {response}");
+        response
+    }
+
+    /// Receive the diagnostics published for a document.
+    fn diagnostics(&mut self) -> Vec<Value> {
+        let notification = self.receive();
+        assert_eq!(
+            notification["method"], "textDocument/publishDiagnostics",
+            "notification: {notification}"
+        );
+        notification["params"]["diagnostics"]
+            .as_array()
+            .unwrap()
+            .clone()
+    }
+}
+
+fn position(uri: &str, line: u32, character: u32) -> Value {
+    json!({
+        "textDocument": { "uri": uri },
+        "position": { "line": line, "character": character },
+    })
+}
+
+#[test]
+fn scripted_session() {
+    let dir = std::env::temp_dir().join(format!("dek-lsp-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("data.json"), "{}").unwrap();
+    let path = dir.join("main.dek");
+    let uri = format!("file://{}", path.display());
+    let data_uri = format!("file://{}", dir.join("data.json").display());
+
+    let mut server = Command::new(env!("CARGO_BIN_EXE_dek"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut client = Client {
+        stdin: server.stdin.take().unwrap(),
+        stdout: BufReader::new(server.stdout.take().unwrap()),
+        next_id: 0,
+    };
+
+    let response = client.request("textDocument/hover", position(&uri, 0, 0));
+    assert_eq!(response["error"]["code"], -32002);
+
+    let response = client.request("initialize", json!({ "capabilities": {} }));
+    let capabilities = &response["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(
+        capabilities["completionProvider"]["triggerCharacters"],
+        json!(["."])
+    );
+    client.notify("initialized", json!({}));
+
+    let text = "let\n    # The server.\n    server = { host = \"a\", port = 1 },\n    data = import \"data.json\",\nin [server.port, +]\n";
+    client.notify(
+        "textDocument/didOpen",
+        json!({
+            "textDocument": { "uri": uri, "languageId": "dek", "version": 1, "text": text },
+        }),
+    );
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "diagnostics: {diagnostics:?}");
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({ "start": { "line": 4, "character": 17 }, "end": { "line": 4, "character": 18 } })
+    );
+    assert!(diagnostics[0]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("Unexpected token"));
+
+    let text = "let\n    # The server.\n    server = { host = \"a\", port = 1 },\n    data = import \"data.json\",\nin [server.port, server.name]\n";
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": uri, "version": 2 },
+            "contentChanges": [{ "text": text }],
+        }),
+    );
+    let diagnostics = client.diagnostics();
+    assert_eq!(diagnostics.len(), 1, "diagnostics: {diagnostics:?}");
+    assert_eq!(
+        diagnostics[0]["range"],
+        json!({ "start": { "line": 4, "character": 17 }, "end": { "line": 4, "character": 23 } })
+    );
+    assert_eq!(diagnostics[0]["message"], "Missing field `name`");
+
+    let text = "let\n    # The server.\n    server = { host = \"a\", port = 1 },\n    data = import \"data.json\",\nin [server.port,   data]\n";
+    client.notify(
+        "textDocument/didChange",
+        json!({
+            "textDocument": { "uri": uri, "version": 3 },
+            "contentChanges": [{ "text": text }],
+        }),
+    );
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+
+    // `server` in `server.port`.
+    let response = client.request("textDocument/definition", position(&uri, 4, 5));
+    assert_eq!(
+        response["result"],
+        json!({
+            "uri": uri,
+            "range": { "start": { "line": 2, "character": 4 }, "end": { "line": 2, "character": 10 } },
+        })
+    );
+
+    // `port` in `server.port`.
+    let response = client.request("textDocument/definition", position(&uri, 4, 12));
+    assert_eq!(response["result"]["range"]["start"]["character"], 27);
+
+    let response = client.request("textDocument/definition", position(&uri, 3, 20));
+    assert_eq!(response["result"]["uri"], data_uri);
+
+    let response = client.request("textDocument/hover", position(&uri, 4, 5));
+    assert_eq!(
+        response["result"]["contents"]["value"],
+        "`server : {host: String, port: Number}`\n\nThe server."
+    );
+
+    let sum_uri = format!("file://{}", dir.join("sum.dek").display());
+    client.notify(
+        "textDocument/didOpen",
+        json!({
+            "textDocument": { "uri": sum_uri, "languageId": "dek", "version": 1, "text": "let x = 1 + 2 in x\n" },
+        }),
+    );
+    assert_eq!(client.diagnostics(), Vec::<Value>::new());
+    // `x` in the body.
+    let response = client.request("textDocument/hover", position(&sum_uri, 0, 17));
+    assert_eq!(response["result"]["contents"]["value"], "`x : Number`");
+
+    // After `server.`.
+    let response = client.request("textDocument/completion", position(&uri, 4, 11));
+    let labels: Vec<_> = response["result"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["label"].as_str().unwrap())
+        .collect();
+    assert_eq!(labels, ["host", "port"]);
+
+    let response = client.request(
+        "textDocument/formatting",
+        json!({
+            "textDocument": { "uri": uri },
+            "options": { "tabSize": 4, "insertSpaces": true },
+        }),
+    );
+    let edits = response["result"].as_array().unwrap();
+    assert_eq!(edits.len(), 1);
+    let formatted = edits[0]["newText"].as_str().unwrap();
+    assert!(formatted.contains("    # The server.\n"));
+    assert!(formatted.ends_with("in [server.port, data]\n"));
+
+    let response = client.request("shutdown", Value::Null);
+    assert_eq!(response["result"], Value::Null);
+    client.notify("exit", Value::Null);
+
+    assert!(server.wait().unwrap().success());
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,46 @@
+//! Run `dek eval --multi` in a temporary directory.
+
+use std::{fs, process::Command};
+
+#[cfg(unix)]
+#[test]
+fn symlinks_do_not_escape_the_output_directory() {
+    let dir = std::env::temp_dir().join(format!("dek-output-{}", std::process::id()));
+    let (out, outside) = (dir.join("out"), dir.join("outside"));
+    fs::create_dir_all(&out).unwrap();
+    fs::create_dir_all(&outside).unwrap();
+    std::os::unix::fs::symlink(&outside, out.join("link")).unwrap();
+    std::os::unix::fs::symlink(outside.join("file.txt"), out.join("file.txt")).unwrap();
+
+    let eval = |source: &str| {
+        fs::write(dir.join("main.dek"), source).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_dek"))
+            .args(["eval", "main.dek", "--multi", "out"])
+            .env("NO_COLOR", "1")
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let sources = [
+        r#"{ "link/a.txt" = "a" }"#,
+        r#"{ "link/new/dir/a.txt" = "a" }"#,
+        r#"{ "file.txt" = "a" }"#,
+    ];
+    for source in sources {
+        let (code, stderr) = eval(source);
+        assert_ne!(code, Some(0), "{source}: {stderr}");
+        assert!(stderr.contains("Refusing to write"), "{source}: {stderr}");
+    }
+    assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+
+    let (code, stderr) = eval(r#"{ "sub/a.txt" = "a" }"#);
+    assert_eq!(code, Some(0), "{stderr}");
+    assert_eq!(fs::read_to_string(out.join("sub/a.txt")).unwrap(), "a");
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,76 @@
+//! Run `dek eval --schema` in a temporary directory.
+
+use std::{fs, process::Command};
+
+#[test]
+fn violations_point_at_definitions() {
+    let dir = std::env::temp_dir().join(format!("dek-schema-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        dir.join("schema.json"),
+        r##"{
+            "type": "object",
+            "required": ["name"],
+            "properties": { "server": { "$ref": "#/$defs/server" } },
+            "$defs": {
+                "server": {
+                    "properties": {
+                        "port": { "type": "integer", "maximum": 65535 },
+                        "env": { "enum": ["dev", "prod"] }
+                    }
+                }
+            }
+        }"##,
+    )
+    .unwrap();
+    fs::write(dir.join("data.json"), r#"{ "env": "test" }"#).unwrap();
+    fs::write(
+        dir.join("main.dek"),
+        "let\n    port = 70000,\n    data = import \"data.json\",\nin { server = { port = port, env = data.env } }\n",
+    )
+    .unwrap();
+
+    let eval = |file: &str| {
+        let output = Command::new(env!("CARGO_BIN_EXE_dek"))
+            .args(["eval", file, "--schema", "schema.json"])
+            .env("NO_COLOR", "1")
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        (
+            output.status.code(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    let (code, stderr) = eval("main.dek");
+    assert_eq!(code, Some(1), "{stderr}");
+    assert!(stderr.contains("The result does not match the schema `schema.json`"));
+    assert!(stderr.contains("Missing field `name`"), "{stderr}");
+
+    // Each violation is labelled at the expression that produced the value.
+    let messages = [
+        (
+            "Expected a number at most 65535, found 70000",
+            "2 │     port = 70000,",
+        ),
+        (
+            r#"Expected one of "dev", "prod", found "test""#,
+            "4 │ in { server = { port = port, env = data.env } }",
+        ),
+    ];
+    for (message, line) in messages {
+        let (_, report) = stderr.split_once(message).expect(message);
+        assert!(report.contains(line), "{message}: {report}");
+    }
+
+    fs::write(
+        dir.join("valid.dek"),
+        r#"{ name = "api", server = { port = 80, env = "dev" } }"#,
+    )
+    .unwrap();
+    let (code, stderr) = eval("valid.dek");
+    assert_eq!(code, Some(0), "{stderr}");
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,85 @@
+//! Run `dek watch` in a temporary directory, and edit the files it watches.
+
+use std::{
+    fs,
+    io::{BufRead, BufReader},
+    path::Path,
+    process::{Command, Stdio},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Wait until `condition` holds, or fail after [`TIMEOUT`].
+#[track_caller]
+fn wait_for(what: &str, mut condition: impl FnMut() -> bool) {
+    let start = Instant::now();
+    while !condition() {
+        assert!(start.elapsed() < TIMEOUT, "timed out waiting for {what}");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+#[test]
+fn output_is_regenerated_on_changes() {
+    let dir = std::env::temp_dir().join(format!("dek-watch-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.dek"), r#"{ value = import "data.json" }"#).unwrap();
+    fs::write(dir.join("data.json"), r#"{ "a": 1 }"#).unwrap();
+    let out = dir.join("out.json");
+
+    let mut watch = Command::new(env!("CARGO_BIN_EXE_dek"))
+        .args(["watch", "main.dek", "-o", "out.json", "--interval", "20"])
+        .current_dir(&dir)
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (sender, messages) = mpsc::channel();
+    let stderr = BufReader::new(watch.stderr.take().unwrap());
+    thread::spawn(move || {
+        for line in stderr.lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let mut stderr = String::new();
+    let mut wait_for_message = |message: &str| {
+        wait_for(message, || {
+            stderr.extend(messages.try_iter().map(|line| line + "\n"));
+            stderr.contains(message)
+        });
+        stderr.clear();
+    };
+
+    wait_for("the first output", || read(&out).contains(r#""a": 1"#));
+    wait_for_message("Wrote out.json");
+
+    fs::write(dir.join("data.json"), r#"{ "a": 2 }"#).unwrap();
+    wait_for("a changed import", || read(&out).contains(r#""a": 2"#));
+    wait_for_message("Changed: data.json");
+
+    // Errors are reported, and the previous output is left as it is.
+    fs::write(dir.join("main.dek"), "{ value = }").unwrap();
+    wait_for_message("Could not parse `main.dek`");
+    assert!(read(&out).contains(r#""a": 2"#));
+
+    // Files that do not exist yet are picked up when they are created.
+    fs::write(dir.join("main.dek"), r#"[import "new.json"]"#).unwrap();
+    wait_for_message("new.json");
+    fs::write(dir.join("new.json"), "3").unwrap();
+    wait_for("a new import", || read(&out) == "[\n  3\n]\n");
+
+    assert!(watch.try_wait().unwrap().is_none(), "watch exited");
+    watch.kill().unwrap();
+    watch.wait().unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}